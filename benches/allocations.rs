@@ -0,0 +1,134 @@
+//! Counts the allocations per packet once the buffer pools are warmed up.
+//!
+//! Run with `cargo bench --bench allocations`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use laminar::{Config, ConnectionManager, Packet};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const PACKETS: usize = 10_000;
+
+type CreatePacket = fn(Vec<u8>) -> Packet;
+
+fn client_address() -> SocketAddr {
+    "127.0.0.1:10001".parse().unwrap()
+}
+
+fn server_address() -> SocketAddr {
+    "127.0.0.1:10002".parse().unwrap()
+}
+
+/// Returns the average number of allocations per packet to send it, and to receive it.
+fn count_allocations(create_packet: impl Fn(Vec<u8>) -> Packet) -> (f64, f64) {
+    let mut client = ConnectionManager::new(Config::default());
+    let mut server = ConnectionManager::new(Config::default());
+    let packets: Vec<Packet> = (0..2 * PACKETS)
+        .map(|_| create_packet(vec![1; 500]))
+        .collect();
+    let mut packets = packets.into_iter();
+
+    let mut exchange = |count: usize| {
+        let (mut sending, mut receiving) = (0, 0);
+        for packet in packets.by_ref().take(count) {
+            let start = ALLOCATIONS.load(Ordering::Relaxed);
+            client.send(packet, Instant::now()).unwrap();
+            let (addr, datagram) = client.poll_transmit().unwrap();
+            sending += ALLOCATIONS.load(Ordering::Relaxed) - start;
+
+            let start = ALLOCATIONS.load(Ordering::Relaxed);
+            server
+                .handle_payload(client_address(), datagram, Instant::now())
+                .unwrap();
+            while server.poll_event().is_some() {}
+            receiving += ALLOCATIONS.load(Ordering::Relaxed) - start;
+            assert_eq!(addr, server_address());
+
+            // Acknowledge the packets, so they are not kept for resending
+            server
+                .send(
+                    Packet::reliable_unordered(client_address(), vec![]),
+                    Instant::now(),
+                )
+                .unwrap();
+            while let Some((_, datagram)) = server.poll_transmit() {
+                client
+                    .handle_payload(server_address(), datagram, Instant::now())
+                    .unwrap();
+            }
+            while client.poll_event().is_some() {}
+        }
+        (sending, receiving)
+    };
+
+    // Warm up the pools, connections and channels
+    exchange(PACKETS);
+    let (sending, receiving) = exchange(PACKETS);
+
+    (
+        sending as f64 / PACKETS as f64,
+        receiving as f64 / PACKETS as f64,
+    )
+}
+
+fn main() {
+    let cases: [(&str, CreatePacket); 3] = [
+        ("unreliable", |payload| {
+            Packet::unreliable(server_address(), payload)
+        }),
+        ("reliable unordered", |payload| {
+            Packet::reliable_unordered(server_address(), payload)
+        }),
+        ("reliable ordered", |payload| {
+            Packet::reliable_ordered(server_address(), payload, None)
+        }),
+    ];
+
+    for (name, create_packet) in cases.iter() {
+        let (sending, receiving) = count_allocations(create_packet);
+        println!(
+            "{}: {:.2} allocations per packet sent, {:.2} per packet received",
+            name, sending, receiving
+        );
+
+        // Only the header of a datagram is allocated when it is built
+        assert!(
+            sending < 1.1,
+            "Sending {} packets allocates {:.2} times per packet",
+            name,
+            sending
+        );
+        // Only the event channel allocates, once for a block of events
+        assert!(
+            receiving < 0.1,
+            "Receiving {} packets allocates {:.2} times per packet",
+            name,
+            receiving
+        );
+    }
+}
@@ -1,85 +1,105 @@
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::time::Instant;
 
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-
-use laminar::{Config, DeliveryMethod, ProtocolVersion, VirtualConnection};
+use laminar::{Config, Packet, Socket};
 
 use criterion::{criterion_group, criterion_main, Criterion};
 
 const SERVER_ADDR: &str = "127.0.0.1:12345";
 const CLIENT_ADDR: &str = "127.0.0.1:12346";
 
-fn process_packet_before_send(
-    connection: &mut VirtualConnection,
-    _config: &Config,
-    delivery_method: DeliveryMethod,
-) {
-    let payload = vec![1, 2, 3, 4, 5];
-
-    let _packet_data = connection
-        .process_outgoing(&payload, delivery_method)
-        .unwrap();
+fn server_address() -> SocketAddr {
+    SERVER_ADDR.parse().unwrap()
 }
 
-fn process_packet_when_received(connection: &mut VirtualConnection, data: &[u8]) {
-    connection.process_incoming(&data).unwrap().unwrap();
+fn client_address() -> SocketAddr {
+    CLIENT_ADDR.parse().unwrap()
 }
 
-/// This is mimicking the `HeaderParser for StandardHeader` implementation which is no longer
-/// visible externally
-fn standard_header_bytes(delivery_method: DeliveryMethod) -> Vec<u8> {
-    let mut buffer = Vec::new();
-    buffer.write_u16::<BigEndian>(ProtocolVersion::get_crc16());
-    // Represents a standard `Packet`
-    buffer.write_u8(0);
-    buffer.write_u8(delivery_method as u8);
-    buffer
-}
+/// Sends the given packet from the client to the server and drains every event the server
+/// produced while processing it.
+fn process_packet_round(client: &mut Socket, server: &mut Socket, packet: Packet) {
+    client.send(packet).unwrap();
+    client.manual_poll(Instant::now());
+    server.manual_poll(Instant::now());
 
-/// This is mimicking the `HeaderParser for AckedPacketHeader` implementation which is no longer
-/// visible externally
-fn acked_header_bytes(
-    delivery_method: DeliveryMethod,
-    seq: u16,
-    ack_seq: u16,
-    ack_field: u32,
-) -> Vec<u8> {
-    let mut buffer = standard_header_bytes(delivery_method);
-    buffer.write_u16::<BigEndian>(seq);
-    buffer.write_u16::<BigEndian>(ack_seq);
-    buffer.write_u32::<BigEndian>(ack_field);
-    buffer
+    while server.recv().is_some() {}
 }
 
 fn receive_unreliable_benchmark(c: &mut Criterion) {
-    let mut connection =
-        VirtualConnection::new(SERVER_ADDR.parse().unwrap(), &Arc::new(Config::default()));
-
-    // setup fake received bytes.
-    let mut buffer = standard_header_bytes(DeliveryMethod::UnreliableUnordered);
-    buffer.append(&mut vec![1; 500]);
+    let mut server = Socket::bind(server_address()).unwrap();
+    let mut client = Socket::bind(client_address()).unwrap();
 
     c.bench_function("process unreliable packet on receive", move |b| {
-        b.iter(|| process_packet_when_received(&mut connection, &buffer))
+        b.iter(|| {
+            process_packet_round(
+                &mut client,
+                &mut server,
+                Packet::unreliable(server_address(), vec![1; 500]),
+            )
+        })
     });
 }
 
 fn receive_reliable_benchmark(c: &mut Criterion) {
-    let mut connection =
-        VirtualConnection::new(SERVER_ADDR.parse().unwrap(), &Arc::new(Config::default()));
-
-    // setup fake received bytes.
-    let mut buffer = acked_header_bytes(DeliveryMethod::ReliableUnordered, 0, 1, 2);
-    buffer.append(&mut vec![1; 500]);
+    let mut server = Socket::bind(server_address()).unwrap();
+    let mut client = Socket::bind(client_address()).unwrap();
 
     c.bench_function("process reliable packet on receive", move |b| {
-        b.iter(|| process_packet_when_received(&mut connection, &buffer))
+        b.iter(|| {
+            process_packet_round(
+                &mut client,
+                &mut server,
+                Packet::reliable_unordered(server_address(), vec![1; 500]),
+            )
+        })
     });
 }
 
+/// Sends a burst of packets from the client to the server, which are sent and received in batches when
+/// `batch_size` is larger than one.
+fn process_burst_round(client: &mut Socket, server: &mut Socket, burst_size: usize) {
+    for _ in 0..burst_size {
+        client
+            .send(Packet::unreliable(server_address(), vec![1; 500]))
+            .unwrap();
+    }
+    client.manual_poll(Instant::now());
+    server.manual_poll(Instant::now());
+
+    while server.recv().is_some() {}
+}
+
+fn burst_benchmark(c: &mut Criterion, name: &str, batch_size: usize) {
+    let config = Config {
+        batch_size,
+        ..Default::default()
+    };
+    let mut server = Socket::bind_with_config(server_address(), config.clone()).unwrap();
+    let mut client = Socket::bind_with_config(client_address(), config).unwrap();
+
+    c.bench_function(name, move |b| {
+        b.iter(|| process_burst_round(&mut client, &mut server, 32))
+    });
+}
+
+fn receive_burst_benchmark(c: &mut Criterion) {
+    burst_benchmark(c, "process burst of 32 unreliable packets", 1);
+}
+
+fn receive_batched_burst_benchmark(c: &mut Criterion) {
+    burst_benchmark(
+        c,
+        "process burst of 32 unreliable packets in batches",
+        Config::default().batch_size,
+    );
+}
+
 criterion_group!(
     benches,
     receive_unreliable_benchmark,
-    receive_reliable_benchmark
+    receive_reliable_benchmark,
+    receive_burst_benchmark,
+    receive_batched_burst_benchmark
 );
 criterion_main!(benches);
@@ -11,7 +11,7 @@ const SERVER: &str = "127.0.0.1:12351";
 
 fn server() -> Result<(), ErrorKind> {
     let mut socket = Socket::bind(SERVER)?;
-    let (mut sender, mut receiver) = (socket.get_packet_sender(), socket.get_event_receiver());
+    let (sender, receiver) = (socket.get_packet_sender(), socket.get_event_receiver());
     let _thread = thread::spawn(move || socket.start_polling());
 
     loop {
@@ -29,10 +29,12 @@ fn server() -> Result<(), ErrorKind> {
 
                     println!("Received {:?} from {:?}", msg, ip);
 
-                    sender.send(Packet::reliable_unordered(
-                        packet.addr(),
-                        "Copy that!".as_bytes().to_vec(),
-                    ));
+                    sender
+                        .send(Packet::reliable_unordered(
+                            packet.addr(),
+                            "Copy that!".as_bytes().to_vec(),
+                        ))
+                        .unwrap();
                 }
                 SocketEvent::Timeout(address) => {
                     println!("Client timed out: {}", address);
@@ -60,12 +62,14 @@ fn client() -> Result<(), ErrorKind> {
     loop {
         s_buffer.clear();
         stdin.read_line(&mut s_buffer)?;
-        let line = s_buffer.replace(|x| x == '\n' || x == '\r', "");
-
-        socket.send(Packet::reliable_unordered(
-            server,
-            line.clone().into_bytes(),
-        ));
+        let line = s_buffer.replace(['\n', '\r'], "");
+
+        socket
+            .send(Packet::reliable_unordered(
+                server,
+                line.clone().into_bytes(),
+            ))
+            .unwrap();
 
         socket.manual_poll(Instant::now());
 
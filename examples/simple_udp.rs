@@ -3,16 +3,15 @@
 //! 2. setting up client to send data.
 //! 3. serialize data to send and deserialize when received.
 use bincode::{deserialize, serialize};
-use crossbeam_channel::{Receiver, Sender};
-use laminar::{ErrorKind, Packet, Socket, SocketEvent};
+use laminar::{Packet, Socket, SocketEvent};
 use serde_derive::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use std::{thread, time::Instant};
+use std::time::Instant;
 
 /// The socket address of where the server is located.
-const SERVER_ADDR: &'static str = "127.0.0.1:12345";
+const SERVER_ADDR: &str = "127.0.0.1:12345";
 // The client address from where the data is sent.
-const CLIENT_ADDR: &'static str = "127.0.0.1:12346";
+const CLIENT_ADDR: &str = "127.0.0.1:12346";
 
 fn client_address() -> SocketAddr {
     CLIENT_ADDR.parse().unwrap()
@@ -69,11 +68,8 @@ pub fn main() {
     // Coords { longitude: 5.4545, latitude: 3.344, altitude: 1.33 }
     // Text { string: "Some information" }
     while let Some(pkt) = server.recv() {
-        match pkt {
-            SocketEvent::Packet(pkt) => {
-                println!["{:?}", deserialize::<DataType>(pkt.payload()).unwrap()]
-            }
-            _ => {}
+        if let SocketEvent::Packet(pkt) = pkt {
+            println!["{:?}", deserialize::<DataType>(pkt.payload()).unwrap()]
         }
     }
 }
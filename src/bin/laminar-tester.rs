@@ -99,18 +99,9 @@ impl From<clap::ArgMatches<'_>> for ServerConfiguration {
 fn process_server_subcommand(m: clap::ArgMatches<'_>) {
     let config = ServerConfiguration::from(m);
 
-    let run_duration = config.run_duration;
-
-    thread::spawn(move || {
-        info!("Server started");
-        info!("Server listening on: {:?}", config.listen_host);
-        run_server(config).expect("Server should run.");
-    });
-
-    info!("Main thread sleeping");
-    thread::sleep(run_duration);
-    info!("Shutting down...");
-    exit(0);
+    info!("Server started");
+    info!("Server listening on: {:?}", config.listen_host);
+    run_server(config).expect("Server should run.");
 }
 
 fn process_client_subcommand(m: clap::ArgMatches<'_>) {
@@ -118,31 +109,42 @@ fn process_client_subcommand(m: clap::ArgMatches<'_>) {
     debug!("Endpoint is: {:?}", client_config.listen_host);
     debug!("Client destination is: {:?}", client_config.destination);
     run_client(client_config).expect("Client should run.");
-    exit(0);
 }
 
 fn run_server(server_config: ServerConfiguration) -> Result<()> {
     let mut socket = Socket::bind(server_config.listen_host)?;
+    let (sender, receiver) = (socket.get_packet_sender(), socket.get_event_receiver());
+    let shutdown = socket.get_shutdown_handle();
+    let polling = thread::spawn(move || socket.start_polling());
 
     let mut throughput = ThroughputMonitoring::new(Duration::from_secs(1));
+    let end = Instant::now() + server_config.run_duration;
 
-    loop {
-        socket.manual_poll(Instant::now());
-        if let Some(event) = socket.recv() {
+    while Instant::now() < end {
+        if let Ok(event) = receiver.recv_timeout(Duration::from_millis(100)) {
             match event {
                 SocketEvent::Packet(_) => {
                     println!["Got a packet"];
                     throughput.tick();
                 }
                 SocketEvent::Connect(address) => {
-                    socket.send(Packet::unreliable(address, vec![0])).unwrap();
+                    sender.send(Packet::unreliable(address, vec![0]))?;
                 }
+                SocketEvent::Disconnect(address) => info!("Client disconnected: {}", address),
                 _ => error!("Event not handled yet."),
             }
         }
 
         info!("{}", throughput);
     }
+
+    info!("Shutting down...");
+    shutdown.shutdown_gracefully(Duration::from_secs(1))?;
+    let connections = polling
+        .join()
+        .expect("The polling thread should not panic.");
+    info!("Served {} connections", connections.len());
+    Ok(())
 }
 
 fn run_client(config: ClientConfiguration) -> Result<()> {
@@ -152,7 +154,7 @@ fn run_client(config: ClientConfiguration) -> Result<()> {
     match config.test_name.as_str() {
         "steady-stream" => {
             test_steady_stream(config, socket);
-            exit(0);
+            Ok(())
         }
         _ => {
             error!("Invalid test name");
@@ -167,20 +169,21 @@ fn test_steady_stream(config: ClientConfiguration, mut socket: Socket) {
 
     let test_packet = Packet::reliable_unordered(config.destination, config.test_name.into_bytes());
 
-    let time_quantum = 1000 / config.packet_ps as u64;
+    let time_quantum = 1000 / config.packet_ps;
     let start_time = Instant::now();
     let mut packets_sent = 0;
 
     loop {
         socket.send(test_packet.clone()).unwrap();
         socket.manual_poll(Instant::now());
-        while let Some(_) = socket.recv() {}
+        while socket.recv().is_some() {}
 
         packets_sent += 1;
 
         if start_time.elapsed() >= config.maximal_duration {
             info!("Ending test!");
             info!("Sent: {} packets", packets_sent);
+            socket.disconnect(config.destination);
             return;
         }
 
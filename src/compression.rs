@@ -0,0 +1,239 @@
+//! This module provides the compression of payloads, see `Config::compressor`.
+
+mod lz4;
+
+pub use self::lz4::Lz4Compressor;
+
+use crc::crc16;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    sync::Arc,
+};
+
+/// The size of the segments which are counted when training a dictionary.
+const SEGMENT_SIZE: usize = 8;
+
+/// Compresses and decompresses the payloads of packets, see `Config::compressor`.
+///
+/// A dictionary is data which is expected to resemble the payloads, both endpoints have to use the same one.
+/// It is empty when no dictionary is set.
+pub trait Compressor: Debug + Send + Sync {
+    /// Returns the compressor of payloads with `dictionary`.
+    ///
+    /// It is created once whenever the dictionary of a connection is set, so work which only depends on the dictionary
+    /// is not repeated for every payload.
+    fn with_dictionary(&self, dictionary: &[u8]) -> Box<dyn DictionaryCompressor>;
+}
+
+/// Compresses and decompresses payloads with the dictionary it was created with, see `Compressor::with_dictionary`.
+pub trait DictionaryCompressor: Debug + Send + Sync {
+    /// Appends the compression of `input` to `output`.
+    fn compress(&self, input: &[u8], output: &mut Vec<u8>);
+
+    /// Appends the decompression of `input` to `output`.
+    ///
+    /// Returns false if `input` is corrupt or decompresses to more than `max_size` bytes, `output` may then contain a part of it.
+    fn decompress(&self, input: &[u8], output: &mut Vec<u8>, max_size: usize) -> bool;
+}
+
+/// The compressor and dictionary of a connection.
+///
+/// A compressed payload starts with the identifier of the dictionary it was compressed with,
+/// so a payload of a remote endpoint with another dictionary is rejected instead of being decompressed into garbage.
+#[derive(Clone, Debug)]
+pub struct Compression {
+    compressor: Arc<dyn Compressor>,
+    dictionary: Arc<dyn DictionaryCompressor>,
+    dictionary_id: u16,
+    max_size: usize,
+}
+
+impl Compression {
+    /// Returns the compression of a connection, payloads are not decompressed to more than `max_size` bytes.
+    pub fn new(
+        compressor: Arc<dyn Compressor>,
+        dictionary: Option<Arc<[u8]>>,
+        max_size: usize,
+    ) -> Compression {
+        let dictionary = dictionary.as_deref().unwrap_or_default();
+        Compression {
+            dictionary: Arc::from(compressor.with_dictionary(dictionary)),
+            dictionary_id: dictionary_id(dictionary),
+            compressor,
+            max_size,
+        }
+    }
+
+    /// Replaces the dictionary.
+    pub fn set_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) {
+        let dictionary = dictionary.as_deref().unwrap_or_default();
+        self.dictionary = Arc::from(self.compressor.with_dictionary(dictionary));
+        self.dictionary_id = dictionary_id(dictionary);
+    }
+
+    /// Returns the compression of `input`, `None` if it does not shrink.
+    pub fn compress(&self, input: &[u8]) -> Option<Vec<u8>> {
+        let mut output = Vec::with_capacity(input.len());
+        output.extend_from_slice(&self.dictionary_id.to_be_bytes());
+        self.dictionary.compress(input, &mut output);
+        if output.len() < input.len() {
+            Some(output)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the decompression of `input`, `None` if it is corrupt, too large or compressed with another dictionary.
+    pub fn decompress(&self, input: &[u8]) -> Option<Vec<u8>> {
+        let input = match input {
+            [high, low, input @ ..] if u16::from_be_bytes([*high, *low]) == self.dictionary_id => {
+                input
+            }
+            _ => return None,
+        };
+
+        let mut output = Vec::new();
+        if self
+            .dictionary
+            .decompress(input, &mut output, self.max_size)
+        {
+            Some(output)
+        } else {
+            None
+        }
+    }
+}
+
+// Returns the identifier of a dictionary which is sent with the payloads compressed with it, it is the CRC16 of its bytes.
+fn dictionary_id(dictionary: &[u8]) -> u16 {
+    crc16::checksum_x25(dictionary)
+}
+
+/// Trains a dictionary of at most `max_size` bytes from sample payloads, see `Config::compression_dictionary`.
+///
+/// The dictionary consists of the runs of bytes which occur in more than one sample, the runs which save the most bytes
+/// are placed last, where they are the cheapest to refer to.
+pub fn train_dictionary(samples: &[&[u8]], max_size: usize) -> Vec<u8> {
+    // Count in how many samples each segment occurs
+    let mut counts: HashMap<&[u8], usize> = HashMap::new();
+    for sample in samples {
+        let segments: HashSet<&[u8]> = sample.windows(SEGMENT_SIZE).collect();
+        for segment in segments {
+            *counts.entry(segment).or_insert(0) += 1;
+        }
+    }
+
+    // Find the runs of consecutive segments which are shared with other samples
+    let mut runs: HashMap<&[u8], usize> = HashMap::new();
+    for sample in samples {
+        let mut start = None;
+        let segments = sample.windows(SEGMENT_SIZE).map(Some).chain(Some(None));
+        for (index, segment) in segments.enumerate() {
+            let count = segment.map_or(0, |segment| counts[segment]);
+            match start {
+                None if count > 1 => start = Some((index, count)),
+                Some((first, first_count)) if count <= 1 => {
+                    let run = &sample[first..index - 1 + SEGMENT_SIZE];
+                    let score = runs.entry(run).or_insert(0);
+                    *score = (*score).max(first_count * run.len());
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut runs: Vec<(&[u8], usize)> = runs.into_iter().collect();
+    runs.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut chosen: Vec<&[u8]> = Vec::new();
+    let mut size = 0;
+    for (run, _) in runs {
+        let contained = chosen
+            .iter()
+            .any(|chosen| chosen.windows(run.len()).any(|window| window == run));
+        if size + run.len() <= max_size && !contained {
+            size += run.len();
+            chosen.push(run);
+        }
+    }
+
+    chosen.into_iter().rev().flatten().copied().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{train_dictionary, Compression, Lz4Compressor};
+    use std::sync::Arc;
+
+    #[test]
+    fn skip_payloads_which_do_not_shrink() {
+        let compression = Compression::new(Arc::new(Lz4Compressor), None, 1024);
+
+        assert_eq!(compression.compress(&[1, 2, 3, 4, 5, 6, 7, 8]), None);
+
+        let compressed = compression.compress(&[7; 100]).unwrap();
+        assert!(compressed.len() < 100);
+        assert_eq!(compression.decompress(&compressed).unwrap(), vec![7; 100]);
+    }
+
+    #[test]
+    fn refuse_to_decompress_beyond_the_maximal_size() {
+        let compression = Compression::new(Arc::new(Lz4Compressor), None, 1024);
+        let compressed = compression.compress(&[7; 2000]).unwrap();
+
+        assert_eq!(compression.decompress(&compressed), None);
+    }
+
+    #[test]
+    fn reject_payloads_compressed_with_another_dictionary() {
+        let dictionary: Arc<[u8]> = Arc::from(&b"position rotation velocity"[..]);
+        let mut compression = Compression::new(Arc::new(Lz4Compressor), None, 1024);
+        let mut other = compression.clone();
+        other.set_dictionary(Some(dictionary.clone()));
+
+        let payload = b"position velocity position velocity";
+        let compressed = other.compress(payload).unwrap();
+        assert_eq!(compression.decompress(&compressed), None);
+
+        compression.set_dictionary(Some(dictionary));
+        assert_eq!(compression.decompress(&compressed).unwrap(), payload);
+    }
+
+    #[test]
+    fn train_a_dictionary_of_shared_runs() {
+        let samples: Vec<Vec<u8>> = (0..10u8)
+            .map(|i| {
+                [
+                    b"player position update: x=".as_ref(),
+                    &[i; 4],
+                    b" y=".as_ref(),
+                ]
+                .concat()
+            })
+            .collect();
+        let samples: Vec<&[u8]> = samples.iter().map(Vec::as_slice).collect();
+
+        let dictionary = train_dictionary(&samples, 64);
+        assert!(dictionary.len() <= 64);
+        assert!(dictionary
+            .windows(26)
+            .any(|window| window == b"player position update: x="));
+
+        // A payload like the samples compresses better with the dictionary
+        let payload = [
+            b"player position update: x=".as_ref(),
+            &[42; 4],
+            b" y=".as_ref(),
+        ]
+        .concat();
+        let without = Compression::new(Arc::new(Lz4Compressor), None, 1024);
+        let with = Compression::new(Arc::new(Lz4Compressor), Some(dictionary.into()), 1024);
+        let compressed = with.compress(&payload).unwrap();
+        assert!(without
+            .compress(&payload)
+            .is_none_or(|other| compressed.len() < other.len()));
+        assert_eq!(with.decompress(&compressed).unwrap(), payload);
+    }
+}
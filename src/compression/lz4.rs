@@ -0,0 +1,319 @@
+use super::{Compressor, DictionaryCompressor};
+use byteorder::{ByteOrder, LittleEndian};
+use std::{
+    fmt,
+    sync::{Mutex, PoisonError},
+};
+
+/// The shortest match which is encoded.
+const MIN_MATCH: usize = 4;
+/// The farthest a match can refer back, which is the last 64 KiB of the dictionary at most.
+const MAX_OFFSET: usize = u16::MAX as usize;
+/// The last match starts at least this many bytes before the end of the input.
+const MATCH_FIND_LIMIT: usize = 12;
+/// The last bytes of the input are always literals.
+const LAST_LITERALS: usize = 5;
+/// The number of bits of the hash of four bytes, which indexes the table of positions.
+const HASH_LOG: u32 = 12;
+
+/// A fast compressor which writes the LZ4 block format, a dictionary is used as the history preceding the input.
+///
+/// It favours speed over ratio, which suits the small payloads of games that are sent many times per second.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Lz4Compressor;
+
+impl Compressor for Lz4Compressor {
+    fn with_dictionary(&self, dictionary: &[u8]) -> Box<dyn DictionaryCompressor> {
+        let dictionary: Box<[u8]> =
+            dictionary[dictionary.len().saturating_sub(MAX_OFFSET)..].into();
+
+        // Positions are stored plus one, so zero means empty
+        let mut table = Box::new([0u32; 1 << HASH_LOG]);
+        for position in 0..dictionary.len().saturating_sub(MIN_MATCH - 1) {
+            table[hash(&dictionary[position..])] = position as u32 + 1;
+        }
+
+        let scratch = Mutex::new(Scratch {
+            table: table.clone(),
+            touched: Vec::new(),
+        });
+        Box::new(Lz4Dictionary {
+            dictionary,
+            table,
+            scratch,
+        })
+    }
+}
+
+// The last 64 KiB of a dictionary, with the table of the positions of its hashes which each compression starts from.
+struct Lz4Dictionary {
+    dictionary: Box<[u8]>,
+    table: Box<[u32; 1 << HASH_LOG]>,
+    scratch: Mutex<Scratch>,
+}
+
+// The table a compression works on, it is reused so the table of the dictionary is not copied for every payload.
+struct Scratch {
+    table: Box<[u32; 1 << HASH_LOG]>,
+    // The slots written by the last compression, which differ from the table of the dictionary.
+    touched: Vec<usize>,
+}
+
+impl fmt::Debug for Lz4Dictionary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Lz4Dictionary({} bytes)", self.dictionary.len())
+    }
+}
+
+impl DictionaryCompressor for Lz4Dictionary {
+    fn compress(&self, input: &[u8], output: &mut Vec<u8>) {
+        // Positions count from the start of the dictionary, which precedes the input
+        let start = self.dictionary.len();
+        let end = start + input.len();
+        let byte = |position: usize| {
+            if position < start {
+                self.dictionary[position]
+            } else {
+                input[position - start]
+            }
+        };
+
+        // Only the slots written by the last compression are reset, instead of the whole table
+        let mut scratch = self.scratch.lock().unwrap_or_else(PoisonError::into_inner);
+        let Scratch { table, touched } = &mut *scratch;
+        for index in touched.drain(..) {
+            table[index] = self.table[index];
+        }
+
+        let mut anchor = start;
+        let mut position = start;
+        while position + MATCH_FIND_LIMIT <= end {
+            let index = hash(&input[position - start..]);
+            touched.push(index);
+            let slot = &mut table[index];
+            let candidate = (*slot as usize).checked_sub(1);
+            *slot = position as u32 + 1;
+
+            let matched = candidate.filter(|&candidate| {
+                position - candidate <= MAX_OFFSET
+                    && (0..MIN_MATCH).all(|index| byte(candidate + index) == byte(position + index))
+            });
+            match matched {
+                Some(candidate) => {
+                    let mut length = MIN_MATCH;
+                    while position + length < end - LAST_LITERALS
+                        && byte(candidate + length) == byte(position + length)
+                    {
+                        length += 1;
+                    }
+
+                    write_sequence(
+                        output,
+                        &input[anchor - start..position - start],
+                        Some((position - candidate, length)),
+                    );
+                    position += length;
+                    anchor = position;
+                }
+                None => position += 1,
+            }
+        }
+
+        write_sequence(output, &input[anchor - start..], None);
+    }
+
+    fn decompress(&self, input: &[u8], output: &mut Vec<u8>, max_size: usize) -> bool {
+        let base = output.len();
+        decompress_sequences(input, &self.dictionary, output, base, base + max_size).is_some()
+    }
+}
+
+// Decodes the sequences of `input` to the end of `output`, matches may refer back to `base` and into the `dictionary` before it.
+// Returns `None` when the input is corrupt or `output` would grow beyond `limit` bytes.
+fn decompress_sequences(
+    input: &[u8],
+    dictionary: &[u8],
+    output: &mut Vec<u8>,
+    base: usize,
+    limit: usize,
+) -> Option<()> {
+    let mut index = 0;
+    loop {
+        let token = *input.get(index)?;
+        index += 1;
+
+        let mut literals = usize::from(token >> 4);
+        if literals == 15 {
+            literals += read_length(input, &mut index)?;
+        }
+        let literals = input.get(index..index.checked_add(literals)?)?;
+        if output.len() + literals.len() > limit {
+            return None;
+        }
+        output.extend_from_slice(literals);
+        index += literals.len();
+
+        // The last sequence only has literals
+        if index == input.len() {
+            return Some(());
+        }
+
+        let offset = usize::from(LittleEndian::read_u16(input.get(index..index + 2)?));
+        index += 2;
+        if offset == 0 || offset > output.len() - base + dictionary.len() {
+            return None;
+        }
+
+        let mut length = usize::from(token & 15);
+        if length == 15 {
+            length += read_length(input, &mut index)?;
+        }
+        length += MIN_MATCH;
+        if output.len() + length > limit {
+            return None;
+        }
+
+        // Copied byte by byte, since a match may overlap the bytes it produces
+        for _ in 0..length {
+            let produced = output.len() - base;
+            let byte = if offset > produced {
+                dictionary[dictionary.len() + produced - offset]
+            } else {
+                output[output.len() - offset]
+            };
+            output.push(byte);
+        }
+    }
+}
+
+// Writes a sequence of literals followed by a match of the given offset and length, the last sequence has no match.
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_length = matched.map_or(0, |(_, length)| length - MIN_MATCH);
+    output.push((literals.len().min(15) << 4 | match_length.min(15)) as u8);
+    if literals.len() >= 15 {
+        write_length(output, literals.len() - 15);
+    }
+    output.extend_from_slice(literals);
+
+    if let Some((offset, _)) = matched {
+        let mut buffer = [0; 2];
+        LittleEndian::write_u16(&mut buffer, offset as u16);
+        output.extend_from_slice(&buffer);
+        if match_length >= 15 {
+            write_length(output, match_length - 15);
+        }
+    }
+}
+
+// Writes the rest of a length which did not fit in the token, in bytes of 255 followed by the remainder.
+fn write_length(output: &mut Vec<u8>, mut length: usize) {
+    while length >= 255 {
+        output.push(255);
+        length -= 255;
+    }
+    output.push(length as u8);
+}
+
+// Reads the rest of a length which did not fit in the token.
+fn read_length(input: &[u8], index: &mut usize) -> Option<usize> {
+    let mut length = 0usize;
+    loop {
+        let byte = *input.get(*index)?;
+        *index += 1;
+        length = length.checked_add(usize::from(byte))?;
+        if byte != 255 {
+            return Some(length);
+        }
+    }
+}
+
+fn hash(data: &[u8]) -> usize {
+    let sequence = LittleEndian::read_u32(data);
+    (sequence.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lz4Compressor;
+    use crate::compression::Compressor;
+
+    fn round_trip(input: &[u8], dictionary: &[u8]) -> Vec<u8> {
+        let compressor = Lz4Compressor.with_dictionary(dictionary);
+        let mut compressed = Vec::new();
+        compressor.compress(input, &mut compressed);
+
+        let mut decompressed = Vec::new();
+        assert!(compressor.decompress(&compressed, &mut decompressed, input.len()));
+        assert_eq!(decompressed, input);
+        compressed
+    }
+
+    #[test]
+    fn round_trip_payloads() {
+        round_trip(&[], &[]);
+        round_trip(&[1, 2, 3], &[]);
+        round_trip(b"abcabcabcabcabcabcabcabcabcabc", &[]);
+
+        let text: Vec<u8> = (0..5000u32)
+            .map(|i| (i % 251) as u8 ^ (i / 97) as u8)
+            .collect();
+        round_trip(&text, &[]);
+        assert!(round_trip(&[0; 4000], &[]).len() < 40);
+    }
+
+    #[test]
+    fn compress_alike_each_time() {
+        let dictionary = b"the quick brown fox jumps over the lazy dog";
+        let compressor = Lz4Compressor.with_dictionary(dictionary);
+        let compress = |input: &[u8]| {
+            let mut compressed = Vec::new();
+            compressor.compress(input, &mut compressed);
+            compressed
+        };
+
+        // Positions of an earlier payload are not left behind for the next one
+        let first = compress(b"the lazy cat jumps over the quick dog");
+        compress(b"a brown cat sleeps next to a lazy fox and the dog");
+        assert_eq!(compress(b"the lazy cat jumps over the quick dog"), first);
+    }
+
+    #[test]
+    fn refer_to_the_dictionary() {
+        let dictionary = b"the quick brown fox jumps over the lazy dog";
+        let compressed = round_trip(b"the quick brown fox jumps over the lazy cat", dictionary);
+
+        assert!(compressed.len() < 20);
+    }
+
+    #[test]
+    fn write_the_lz4_block_format() {
+        let mut compressed = Vec::new();
+        Lz4Compressor
+            .with_dictionary(&[])
+            .compress(&[b'a'; 20], &mut compressed);
+
+        // One literal, a match of 14 at offset 1 and five literals
+        assert_eq!(
+            compressed,
+            [0x1a, b'a', 1, 0, 0x50, b'a', b'a', b'a', b'a', b'a']
+        );
+    }
+
+    #[test]
+    fn reject_corrupt_input() {
+        let compressor = Lz4Compressor.with_dictionary(&[]);
+        let mut output = Vec::new();
+        // A match before the start of the output
+        assert!(!compressor.decompress(&[0x10, b'a', 2, 0, 0x00], &mut output, 100));
+        // A truncated literal
+        assert!(!compressor.decompress(&[0x30, b'a'], &mut output, 100));
+        // Beyond the maximal size
+        assert!(!compressor.decompress(&[0x1f, b'a', 1, 0, 200, 0x00], &mut output, 100));
+        // A match before the start of the dictionary
+        assert!(!Lz4Compressor.with_dictionary(b"ab").decompress(
+            &[0x10, b'a', 4, 0, 0x00],
+            &mut output,
+            100
+        ));
+    }
+}
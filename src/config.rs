@@ -1,11 +1,107 @@
-use crate::net::constants::{DEFAULT_MTU, FRAGMENT_SIZE_DEFAULT, MAX_FRAGMENTS_DEFAULT};
-use std::{default::Default, time::Duration};
+use crate::compression::Compressor;
+use crate::net::{
+    constants::{
+        DEFAULT_MTU, FRAGMENT_SIZE_DEFAULT, IPV4_MTU, IPV6_MTU, MAX_FRAGMENTS_DEFAULT,
+        MAX_FRAGMENT_OVERHEAD, WIRE_VERSION,
+    },
+    Features, OverflowPolicy,
+};
+use std::{default::Default, net::SocketAddr, sync::Arc, time::Duration};
 
 #[derive(Clone, Debug)]
 /// Contains the configuration options to configure laminar for special use-cases.
 pub struct Config {
     /// Value which can specify the amount of time that can pass without hearing from a client before considering them disconnected
     pub idle_connection_timeout: Duration,
+    /// Value which can specify the maximal number of connections, `None` means there is no limit.
+    ///
+    /// Only the connections we sent to or validated count towards the limit, peers we only received from have no stored
+    /// connection and are not counted. Datagrams from new peers over the limit are answered with a small rejection and raise
+    /// `SocketEvent::ConnectionRejected`, once per peer until `idle_connection_timeout` passed. Sending to a peer without a
+    /// stored connection fails with `ErrorKind::ConnectionLimitReached`, a peer we only received from is sent the rejection
+    /// then, and `SocketEvent::ConnectionRejected` is raised for it.
+    ///
+    /// At most `constants::MAX_ANONYMOUS_PEERS` peers we only received from are remembered, regardless of the limit. A new one
+    /// evicts the peer which was heard from least recently, for which `SocketEvent::Evicted` is raised. Enable
+    /// `validate_addresses` so peers with spoofed addresses cannot evict others.
+    pub max_connections: Option<usize>,
+    /// Value which can specify whether new peers have to prove that they receive datagrams at their address, before a
+    /// connection is made for them.
+    ///
+    /// A datagram from an unvalidated address is dropped and answered with a challenge, which is never larger than the
+    /// datagram. The peer returns the cookie of the challenge, after which `SocketEvent::Connect` is raised and its reliable
+    /// packets are resent. This keeps spoofed datagrams from making us send larger replies to the spoofed address.
+    /// Unreliable datagrams smaller than a challenge are dropped without reply until the address is validated.
+    ///
+    /// Every peer answers challenges, this only has to be enabled on the side which accepts connections.
+    pub validate_addresses: bool,
+    /// Value which can specify the id of the application protocol, e.g. a hash of the game and its network version.
+    ///
+    /// It is mixed into the protocol version of every packet, so only endpoints with the same id accept each other's packets.
+    /// Packets of another id are answered with a version mismatch reply, on which the sender raises `SocketEvent::VersionMismatch`.
+    /// A peer is answered once until `idle_connection_timeout` passed, further packets are dropped silently.
+    pub protocol_id: u64,
+    /// Value which can specify whether a CRC32 of the whole datagram is appended to it, with the protocol version and `protocol_id`
+    /// mixed in.
+    ///
+    /// Datagrams whose checksum does not match, because they were corrupted or use another protocol version, are dropped before
+    /// they are processed and reported like other decoding errors with `ErrorKind::ChecksumMismatch`. A peer we have no connection
+    /// with is answered with a version mismatch reply which has the protocol of its datagram mixed in, so only a peer of another
+    /// protocol accepts it, a corrupt datagram of a peer of ours gets a reply it drops as corrupt.
+    /// The checksum replaces the 2 byte protocol version of the standard header, so version validation and corruption detection share
+    /// the same bytes. Both endpoints have to enable it, it adds 2 bytes to every datagram.
+    pub checksum: bool,
+    /// Value which can specify whether a hello is sent when a connection is set up, which starts the negotiation of the wire version.
+    ///
+    /// Both endpoints advertise the wire versions and `features` they support in a hello, and settle on the highest version and the
+    /// features both support, see `ConnectionStats::negotiated`. This lets new wire features roll out gradually to endpoints of mixed
+    /// versions. When their versions do not overlap the connection is closed with a version mismatch.
+    ///
+    /// Hellos are always answered, so it only has to be enabled on one endpoint, e.g. the clients. The hello is resent until it is
+    /// answered, which adds at least one datagram in each direction to every connection.
+    pub negotiate: bool,
+    /// Value which can specify the oldest wire version this endpoint supports, up to `constants::WIRE_VERSION`.
+    ///
+    /// Newer values are lowered to `constants::WIRE_VERSION`.
+    pub min_wire_version: u8,
+    /// Value which can specify the optional features this endpoint offers in its hello, they are only used on connections where both
+    /// endpoints offer them.
+    ///
+    /// `Features::COMPRESSION` is only offered when there is a `compressor` as well, leaving it out keeps negotiated connections
+    /// uncompressed. All features are offered by default.
+    pub features: Features,
+    /// Value which can specify the compressor of payloads, `None` means payloads are not compressed.
+    ///
+    /// The payload of each packet or fragment is compressed on its own, and flagged as compressed in its header. Payloads which do not
+    /// shrink are sent as they are. Both endpoints need the same compressor to decompress each other's payloads, when the wire version
+    /// is negotiated it is only used on connections where both endpoints have one and offer `Features::COMPRESSION`.
+    /// See `Lz4Compressor` for the built-in compressor.
+    pub compressor: Option<Arc<dyn Compressor>>,
+    /// Value which can specify the dictionary of the compressor, data which resembles the payloads, e.g. one made with `train_dictionary`.
+    ///
+    /// Both endpoints have to use the same dictionary, it can be replaced on a single connection with `Socket::set_compression_dictionary`.
+    /// A compressed payload starts with a 2 byte identifier of its dictionary, payloads compressed with another dictionary are dropped.
+    pub compression_dictionary: Option<Arc<[u8]>>,
+    /// Value which can specify the maximal number of datagrams per second accepted from a single IP address, `None` means
+    /// there is no limit.
+    ///
+    /// Datagrams are counted before they are parsed, an address with a connection which exceeds the limit is banned for
+    /// `ban_duration`. Datagrams of addresses without a connection over the limit are only dropped, so spoofed datagrams
+    /// cannot get arbitrary addresses banned. The limit is counted in fixed windows of a second, so a burst which spans
+    /// two windows can reach twice the limit.
+    pub max_datagrams_per_ip: Option<u32>,
+    /// Value which can specify the maximal number of datagrams per second accepted from a subnet, a /24 for IPv4 and a /64
+    /// for IPv6 addresses. `None` means there is no limit.
+    ///
+    /// Datagrams over the limit are dropped, but the addresses of the subnet are not banned.
+    pub max_datagrams_per_subnet: Option<u32>,
+    /// Value which can specify the maximal number of datagrams per second from a single IP address which fail to decode,
+    /// an address with a connection which exceeds it is banned for `ban_duration`. `None` means there is no limit.
+    pub max_decode_errors_per_ip: Option<u32>,
+    /// Value which can specify for how long an address is banned when it exceeds one of the limits above.
+    ///
+    /// At most 1024 addresses are banned automatically at once, further addresses over their limit are only dropped.
+    pub ban_duration: Duration,
     /// Value which can specify the maximum size a packet can be in bytes. This value is inclusive of fragmenting; if a packet is fragmented, the total size of the fragments cannot exceed this value.
     ///
     /// Recommended value: 16384
@@ -24,7 +120,27 @@ pub struct Config {
     pub fragment_size: u16,
     /// Value which can specify the size of the buffer that queues up fragments ready to be reassembled once all fragments have arrived.```
     pub fragment_reassembly_buffer_size: u16,
-    /// Value that specifies the size of the buffer the UDP data will be read into. Defaults to `1450` bytes.
+    /// Value which can specify how long the fragments of an unreliable packet are kept, while waiting for its other fragments.
+    ///
+    /// When a fragment is still missing after this time, the whole packet is dropped. Reliable packets are not affected,
+    /// since a lost fragment of them gets resent. Defaults to 1 second.
+    pub fragment_reassembly_timeout: Duration,
+    /// Value which can specify the number of unreliable packets with redundancy which are protected by the same parities,
+    /// see `Packet::with_redundancy`. At most 128 are, values of 0 and 1 protect each packet on its own.
+    ///
+    /// Larger groups cost fewer parities, but a packet can only be rebuilt once the parities of its group are received.
+    pub parity_group_size: u8,
+    /// Value which can specify how long a group of unreliable packets with redundancy waits for more packets, see
+    /// `parity_group_size`.
+    ///
+    /// After this time the parities of the packets in the group so far are sent, so the last packets before a pause are
+    /// protected too. Defaults to 50 milliseconds.
+    pub parity_group_timeout: Duration,
+    /// Value that specifies the size of the buffer the UDP data will be read into.
+    ///
+    /// Defaults to `DEFAULT_MTU`, which fits the datagrams of both address families. Datagrams larger than this are truncated,
+    /// so it should not be smaller than the largest datagram of the remote endpoint, see `fragment_size`.
+    /// `Config::for_address` picks the size for the address family of a socket instead.
     pub receive_buffer_max_size: usize,
     /// Value which can specify the factor which will smooth out network jitter.
     ///
@@ -35,29 +151,164 @@ pub struct Config {
     ///
     /// Value which specifies the maximum round trip time before we consider it a problem. This is expressed in milliseconds.
     pub rtt_max_value: u16,
+    /// Value which can specify the maximal fraction of packets that may get lost.
+    ///
+    /// When more inbound or outbound packets get lost, the network quality is considered bad. This is expressed as a ratio, with 0 equal to 0% and 1 equal to 100%. Defaults to 10%.
+    pub max_packet_loss: f32,
+    /// Value which can specify the maximal jitter, the variation in round trip time.
+    ///
+    /// When the jitter is higher, the network quality is considered bad. Defaults to 50 milliseconds.
+    pub max_jitter: Duration,
     /// Value which can specify the event buffer we read socket events into.
     ///
-    /// Value that specifies the size of the event buffer into which we receive socket events, in bytes. Defaults to 1024.
+    /// Value that specifies the number of readiness events `Socket::start_polling` can receive from `mio` at once. Defaults to 1024.
     pub socket_event_buffer_size: usize,
     /// Value which can specify how long we should block polling for socket events.
     ///
-    /// Value that specifies how long we should block polling for socket events, in milliseconds. Defaults to `1ms`.
+    /// `Socket::start_polling` blocks until a datagram arrives, a packet is queued or a connection times out, but at most this long.
+    /// Defaults to `None`, which blocks until there is something to do.
     pub socket_polling_timeout: Option<Duration>,
+    /// Value which can specify the maximal number of items an ordering stream buffers while waiting for a missing item.
+    ///
+    /// Items arriving once this limit is reached are dropped and a `SocketEvent::StreamStalled` is raised. Defaults to 1024.
+    pub max_ordering_buffered_items: usize,
+    /// Value which can specify the maximal number of bytes an ordering stream buffers while waiting for a missing item.
+    ///
+    /// Items arriving once this limit is reached are dropped and a `SocketEvent::StreamStalled` is raised. Defaults to 1 MiB.
+    pub max_ordering_buffered_bytes: usize,
+    /// Value which can specify how long an ordering stream may wait for a missing item before it is considered stalled.
+    ///
+    /// When this time has passed a `SocketEvent::StreamStalled` is raised, `None` disables this check. Defaults to 5 seconds.
+    pub ordering_stall_timeout: Option<Duration>,
+    /// Value which can specify how many reliable items may be in flight, sent but not acknowledged yet, on a single ordering or sequencing stream.
+    ///
+    /// Sending more items on a stream fails until the remote endpoint acknowledged older ones.
+    /// This way a stream that can't keep up won't make the sender buffer unlimited data.
+    ///
+    /// Every acknowledgment advertises the window of one of our ordering streams to the remote endpoint, the streams take turns.
+    /// It is lowered to the items that stream can still buffer, see `max_ordering_buffered_items`, so a stalled stream only throttles
+    /// itself. With a window set, the remote endpoint never has more items in flight on an ordering stream than we advertised for it.
+    ///
+    /// Acknowledgments are only carried by reliable packets, so only enable this when the remote endpoint sends reliable packets back
+    /// regularly, otherwise a stream with one-way traffic stops once the window is full. Defaults to `None`, which disables this check.
+    pub stream_receive_window: Option<usize>,
+    /// Value which can specify whether errors while receiving or decoding datagrams are raised as `SocketEvent::Error`.
+    ///
+    /// These errors are always logged, enable this to detect misbehaving peers or peers with another protocol version. Defaults to `false`.
+    pub emit_error_events: bool,
+    /// Value which can specify how many datagrams are received or sent with a single system call.
+    ///
+    /// Batching uses `recvmmsg` and `sendmmsg`, and is only available on Linux. It is capped at `MAX_BATCH_SIZE`,
+    /// a value of 1 disables it.
+    pub batch_size: usize,
+    /// Value which can specify whether datagrams of the same size to the same address are sent with UDP generic segmentation offload.
+    ///
+    /// This needs batching and Linux 4.18 or newer, datagrams are sent separately when the kernel does not support it.
+    pub udp_gso: bool,
+    /// Value which can specify how many unused buffers are kept for reuse.
+    ///
+    /// Received and sent datagrams are stored in pooled buffers of `receive_buffer_max_size` bytes, which are returned to
+    /// the pool once no packet refers to them anymore, so steady-state operation does not allocate per packet.
+    pub buffer_pool_size: usize,
+    /// Value which can specify how many events can be queued until they are received, `None` means there is no limit.
+    ///
+    /// Without a limit, a thread which stops receiving events lets memory grow without bound under a flood of packets.
+    pub event_channel_capacity: Option<usize>,
+    /// Value which can specify how many packets can be queued on the socket until it sends them, `None` means there is no limit.
+    pub packet_channel_capacity: Option<usize>,
+    /// Value which can specify what happens when a packet or event is queued on a full channel, see `OverflowPolicy`.
+    pub channel_overflow_policy: OverflowPolicy,
+    /// Value which can specify the size of the receive buffer of the UDP socket, `SO_RCVBUF`, `None` keeps the default of the OS.
+    ///
+    /// Raise it for servers which receive bursts of datagrams. Binding fails with `ErrorKind::SocketOptionError`
+    /// when the OS refuses the size or limits it, e.g. to `net.core.rmem_max` on Linux.
+    pub socket_recv_buffer_size: Option<usize>,
+    /// Value which can specify the size of the send buffer of the UDP socket, `SO_SNDBUF`, `None` keeps the default of the OS.
+    ///
+    /// Binding fails with `ErrorKind::SocketOptionError` when the OS refuses the size or limits it.
+    pub socket_send_buffer_size: Option<usize>,
+    /// Value which can specify the DSCP marking of sent datagrams, e.g. 46 (expedited forwarding) for gameplay traffic.
+    ///
+    /// It is set in the IPv4 TOS field and, on Linux, the IPv6 traffic class. Routers along the path may ignore or rewrite it.
+    /// `None` keeps the default of the OS.
+    pub dscp: Option<u8>,
+    /// Value which can specify whether several sockets may bind to the same port with `SO_REUSEPORT`, e.g. several server processes.
+    ///
+    /// The OS distributes the datagrams by the address they come from, so a remote endpoint keeps talking to the same socket.
+    /// This is only supported on Unix. Defaults to `false`.
+    pub reuse_port: bool,
+}
+
+impl Config {
+    /// Returns the default configuration, with the datagram sizes of the address family of `address`.
+    ///
+    /// Fragments are sized such that every datagram fits `IPV4_MTU` for IPv4 and `IPV6_MTU` for IPv6, and the receive
+    /// buffer fits the datagrams of the family. An unspecified IPv6 address may be a dual-stack socket, which receives
+    /// from IPv4 peers as well, so its receive buffer fits the datagrams of both families.
+    pub fn for_address(address: &SocketAddr) -> Config {
+        let (mtu, receive_buffer_max_size) = match address {
+            SocketAddr::V4(_) => (IPV4_MTU, IPV4_MTU),
+            SocketAddr::V6(address) if address.ip().is_unspecified() => (IPV6_MTU, DEFAULT_MTU),
+            SocketAddr::V6(_) => (IPV6_MTU, IPV6_MTU),
+        };
+        let fragment_size = mtu - MAX_FRAGMENT_OVERHEAD;
+        let config = Config::default();
+
+        Config {
+            max_packet_size: usize::from(config.max_fragments) * usize::from(fragment_size),
+            fragment_size,
+            receive_buffer_max_size: usize::from(receive_buffer_max_size),
+            ..config
+        }
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             idle_connection_timeout: Duration::from_secs(5),
+            max_connections: None,
+            validate_addresses: false,
+            protocol_id: 0,
+            checksum: false,
+            negotiate: false,
+            min_wire_version: WIRE_VERSION,
+            features: Features::COMPRESSION,
+            compressor: None,
+            compression_dictionary: None,
+            max_datagrams_per_ip: None,
+            max_datagrams_per_subnet: None,
+            max_decode_errors_per_ip: None,
+            ban_duration: Duration::from_secs(60),
             max_packet_size: (MAX_FRAGMENTS_DEFAULT * FRAGMENT_SIZE_DEFAULT) as usize,
             max_fragments: MAX_FRAGMENTS_DEFAULT as u8,
             fragment_size: FRAGMENT_SIZE_DEFAULT,
             fragment_reassembly_buffer_size: 64,
+            fragment_reassembly_timeout: Duration::from_secs(1),
+            parity_group_size: 4,
+            parity_group_timeout: Duration::from_millis(50),
             receive_buffer_max_size: DEFAULT_MTU as usize,
             rtt_smoothing_factor: 0.10,
             rtt_max_value: 250,
+            max_packet_loss: 0.1,
+            max_jitter: Duration::from_millis(50),
             socket_event_buffer_size: 1024,
-            socket_polling_timeout: Some(Duration::from_millis(1)),
+            socket_polling_timeout: None,
+            max_ordering_buffered_items: 1024,
+            max_ordering_buffered_bytes: 1024 * 1024,
+            ordering_stall_timeout: Some(Duration::from_secs(5)),
+            stream_receive_window: None,
+            emit_error_events: false,
+            batch_size: 32,
+            udp_gso: false,
+            buffer_pool_size: 1024,
+            event_channel_capacity: None,
+            packet_channel_capacity: None,
+            channel_overflow_policy: OverflowPolicy::Error,
+            socket_recv_buffer_size: None,
+            socket_send_buffer_size: None,
+            dscp: None,
+            reuse_port: false,
         }
     }
 }
@@ -1,4 +0,0 @@
-pub(crate) enum Either<L, R> {
-    Left(L),
-    Right(R),
-}
@@ -1,7 +1,7 @@
 //! This module contains the laminar error handling logic.
 
 use crate::SocketEvent;
-use crossbeam_channel::SendError;
+use crossbeam_channel::{SendError, TrySendError};
 use std::{
     fmt::{self, Display, Formatter},
     io, result,
@@ -19,16 +19,26 @@ pub enum ErrorKind {
     FragmentError(FragmentErrorKind),
     /// Error relating to receiving or parsing a packet
     PacketError(PacketErrorKind),
+    /// Error relating to controlling or sending on a stream
+    StreamError(StreamErrorKind),
     /// Wrapper around a std io::Error
     IOError(io::Error),
     /// Did not receive enough data
     ReceivedDataToShort,
     /// Protocol versions did not match
     ProtocolVersionMismatch,
+    /// The checksum of a datagram did not match its contents, see `Config::checksum`
+    ChecksumMismatch,
     /// Could not send on `SendChannel`.
     SendError(SendError<SocketEvent>),
+    /// Could not queue a packet or event because the channel is full, the rejected item is returned.
+    QueueFull(SocketEvent),
     /// Expected header but could not be read from buffer.
     CouldNotReadHeader(String),
+    /// Error relating to applying the socket options of the config
+    SocketOptionError(SocketOptionErrorKind),
+    /// A new connection could not be opened because `Config::max_connections` was reached
+    ConnectionLimitReached,
 }
 
 impl Display for ErrorKind {
@@ -49,6 +59,11 @@ impl Display for ErrorKind {
                 "Something went wrong with receiving/parsing packets. Reason: {:?}.",
                 e
             ),
+            ErrorKind::StreamError(e) => write!(
+                fmt,
+                "Something went wrong with controlling/sending on a stream. Reason: {:?}.",
+                e
+            ),
             ErrorKind::IOError(e) => write!(fmt, "An IO Error occurred. Reason: {:?}.", e),
             ErrorKind::ReceivedDataToShort => {
                 write!(fmt, "The received data did not have any length.")
@@ -56,16 +71,104 @@ impl Display for ErrorKind {
             ErrorKind::ProtocolVersionMismatch => {
                 write!(fmt, "The protocol versions do not match.")
             }
+            ErrorKind::ChecksumMismatch => {
+                write!(fmt, "The checksum does not match the received data.")
+            }
             ErrorKind::SendError(e) => write!(
                 fmt,
                 "Could not sent on channel because it was closed. Reason: {:?}",
                 e
             ),
+            ErrorKind::QueueFull(e) => write!(
+                fmt,
+                "Could not queue because the channel is full. Rejected: {:?}",
+                e
+            ),
             ErrorKind::CouldNotReadHeader(header) => write!(
                 fmt,
                 "Expected {} header but could not be read from buffer.",
                 header
             ),
+            ErrorKind::SocketOptionError(e) => write!(
+                fmt,
+                "Something went wrong with applying the socket options. Reason: {:?}.",
+                e
+            ),
+            ErrorKind::ConnectionLimitReached => {
+                write!(fmt, "The maximal number of connections was reached.")
+            }
+        }
+    }
+}
+
+/// Errors that could occur while receiving or decoding a datagram, raised as `SocketEvent::Error`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ReceiveErrorKind {
+    /// Error in decoding the packet
+    DecodingError(DecodingErrorKind),
+    /// Error relating to receiving or parsing a fragment
+    FragmentError(FragmentErrorKind),
+    /// Error relating to receiving or parsing a packet
+    PacketError(PacketErrorKind),
+    /// Error relating to controlling a stream
+    StreamError(StreamErrorKind),
+    /// An io::Error occurred while reading from the socket
+    IOError(io::ErrorKind),
+    /// Did not receive enough data
+    ReceivedDataToShort,
+    /// Protocol versions did not match
+    ProtocolVersionMismatch,
+    /// The checksum of the datagram did not match its contents
+    ChecksumMismatch,
+    /// Expected header but could not be read from buffer.
+    CouldNotReadHeader(String),
+}
+
+impl ReceiveErrorKind {
+    /// Returns the kind of the given error, or `None` if it did not occur while receiving or decoding a datagram.
+    pub(crate) fn from_error(error: &ErrorKind) -> Option<ReceiveErrorKind> {
+        match error {
+            ErrorKind::DecodingError(e) => Some(ReceiveErrorKind::DecodingError(e.clone())),
+            ErrorKind::FragmentError(e) => Some(ReceiveErrorKind::FragmentError(e.clone())),
+            ErrorKind::PacketError(e) => Some(ReceiveErrorKind::PacketError(e.clone())),
+            ErrorKind::StreamError(e) => Some(ReceiveErrorKind::StreamError(e.clone())),
+            ErrorKind::IOError(e) => Some(ReceiveErrorKind::IOError(e.kind())),
+            ErrorKind::ReceivedDataToShort => Some(ReceiveErrorKind::ReceivedDataToShort),
+            ErrorKind::ProtocolVersionMismatch => Some(ReceiveErrorKind::ProtocolVersionMismatch),
+            ErrorKind::ChecksumMismatch => Some(ReceiveErrorKind::ChecksumMismatch),
+            ErrorKind::CouldNotReadHeader(header) => {
+                Some(ReceiveErrorKind::CouldNotReadHeader(header.clone()))
+            }
+            ErrorKind::SendError(_)
+            | ErrorKind::QueueFull(_)
+            | ErrorKind::SocketOptionError(_)
+            | ErrorKind::ConnectionLimitReached => None,
+        }
+    }
+}
+
+impl Display for ReceiveErrorKind {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiveErrorKind::DecodingError(e) => write!(fmt, "{}", e),
+            ReceiveErrorKind::FragmentError(e) => write!(fmt, "{}", e),
+            ReceiveErrorKind::PacketError(e) => write!(fmt, "{}", e),
+            ReceiveErrorKind::StreamError(e) => write!(fmt, "{}", e),
+            ReceiveErrorKind::IOError(e) => write!(fmt, "An IO Error occurred. Reason: {:?}.", e),
+            ReceiveErrorKind::ReceivedDataToShort => {
+                write!(fmt, "The received data did not have any length.")
+            }
+            ReceiveErrorKind::ProtocolVersionMismatch => {
+                write!(fmt, "The protocol versions do not match.")
+            }
+            ReceiveErrorKind::ChecksumMismatch => {
+                write!(fmt, "The checksum does not match the received data.")
+            }
+            ReceiveErrorKind::CouldNotReadHeader(header) => write!(
+                fmt,
+                "Expected {} header but could not be read from buffer.",
+                header
+            ),
         }
     }
 }
@@ -79,6 +182,10 @@ pub enum DecodingErrorKind {
     OrderingGuarantee,
     /// The [DeliveryGuarantee] could not be read
     DeliveryGuarantee,
+    /// The [StreamCommand] could not be read
+    StreamCommand,
+    /// The compressed payload could not be decompressed, see `Config::compressor`
+    Compression,
 }
 
 impl Display for DecodingErrorKind {
@@ -91,6 +198,12 @@ impl Display for DecodingErrorKind {
             DecodingErrorKind::DeliveryGuarantee => {
                 write!(fmt, "The delivery guarantee could not be read.")
             }
+            DecodingErrorKind::StreamCommand => {
+                write!(fmt, "The stream command could not be read.")
+            }
+            DecodingErrorKind::Compression => {
+                write!(fmt, "The compressed payload could not be decompressed.")
+            }
         }
     }
 }
@@ -112,6 +225,83 @@ impl Display for PacketErrorKind {
     }
 }
 
+/// Errors that could occur while controlling or sending on a stream
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum StreamErrorKind {
+    /// The ordering guarantee does not refer to an ordering or sequencing stream
+    NotAStream,
+    /// There is no connection with the given address
+    UnknownConnection,
+    /// The stream was closed, it has to be reset before items can be sent on it again
+    StreamClosed,
+    /// The stream has the maximal number of unacknowledged items in flight
+    ExceededReceiveWindow,
+}
+
+impl Display for StreamErrorKind {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match *self {
+            StreamErrorKind::NotAStream => {
+                write!(fmt, "The ordering guarantee does not refer to a stream.")
+            }
+            StreamErrorKind::UnknownConnection => {
+                write!(fmt, "There is no connection with the given address.")
+            }
+            StreamErrorKind::StreamClosed => {
+                write!(fmt, "The stream was closed and has not been reset since.")
+            }
+            StreamErrorKind::ExceededReceiveWindow => write!(
+                fmt,
+                "The stream has too many unacknowledged items in flight."
+            ),
+        }
+    }
+}
+
+/// Errors that could occur while applying the socket options of the config
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SocketOptionErrorKind {
+    /// The OS refused to set the option, e.g. because it is not supported on this platform
+    Refused {
+        /// The name of the option, e.g. `SO_RCVBUF`
+        option: &'static str,
+        /// The error the OS reported
+        reason: io::ErrorKind,
+    },
+    /// The OS set a smaller value than requested, e.g. a buffer size limited by `net.core.rmem_max` on Linux
+    Limited {
+        /// The name of the option, e.g. `SO_RCVBUF`
+        option: &'static str,
+        /// The value in the config
+        requested: usize,
+        /// The value the OS set
+        actual: usize,
+    },
+}
+
+impl Display for SocketOptionErrorKind {
+    fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            SocketOptionErrorKind::Refused { option, reason } => {
+                write!(
+                    fmt,
+                    "The OS refused to set {}. Reason: {:?}.",
+                    option, reason
+                )
+            }
+            SocketOptionErrorKind::Limited {
+                option,
+                requested,
+                actual,
+            } => write!(
+                fmt,
+                "The OS limited {} to {} instead of {}.",
+                option, actual, requested
+            ),
+        }
+    }
+}
+
 /// Errors that could occur with constructing/parsing fragment contents
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum FragmentErrorKind {
@@ -164,6 +354,18 @@ impl From<PacketErrorKind> for ErrorKind {
     }
 }
 
+impl From<StreamErrorKind> for ErrorKind {
+    fn from(inner: StreamErrorKind) -> Self {
+        ErrorKind::StreamError(inner)
+    }
+}
+
+impl From<SocketOptionErrorKind> for ErrorKind {
+    fn from(inner: SocketOptionErrorKind) -> Self {
+        ErrorKind::SocketOptionError(inner)
+    }
+}
+
 impl From<FragmentErrorKind> for ErrorKind {
     fn from(inner: FragmentErrorKind) -> Self {
         ErrorKind::FragmentError(inner)
@@ -175,3 +377,12 @@ impl From<crossbeam_channel::SendError<SocketEvent>> for ErrorKind {
         ErrorKind::SendError(inner)
     }
 }
+
+impl From<TrySendError<SocketEvent>> for ErrorKind {
+    fn from(inner: TrySendError<SocketEvent>) -> Self {
+        match inner {
+            TrySendError::Full(event) => ErrorKind::QueueFull(event),
+            TrySendError::Disconnected(event) => ErrorKind::SendError(SendError(event)),
+        }
+    }
+}
@@ -1,11 +1,12 @@
 //! This module provides the logic around the processing of the packet.
-//! Like ordering, sequencing, controlling congestion, fragmentation, and packet acknowledgment.
+//! Like ordering, sequencing, controlling congestion, fragmentation, forward error correction, and packet acknowledgment.
 
 mod acknowledgment;
 mod congestion;
 mod fragmenter;
 
 pub mod arranging;
+pub mod fec;
 
 pub use self::acknowledgment::AcknowledgmentHandler;
 pub use self::acknowledgment::SentPacket;
@@ -1,7 +1,9 @@
 use crate::packet::OrderingGuarantee;
+use crate::packet::PacketType;
+use crate::packet::Payload;
 use crate::packet::SequenceNumber;
 use crate::sequence_buffer::{sequence_less_than, SequenceBuffer};
-use std::collections::HashMap;
+use std::{cmp::Reverse, collections::HashMap};
 
 const REDUNDANT_PACKET_ACKS_SIZE: u16 = 32;
 const DEFAULT_SEND_PACKETS_SIZE: usize = 256;
@@ -15,6 +17,8 @@ pub struct AcknowledgmentHandler {
     // Using a Hashmap to track every packet we send out so we can ensure that we can resend when
     // dropped.
     sent_packets: HashMap<u16, SentPacket>,
+    // The number of packets in `sent_packets` per ordering guarantee, not counting stream commands.
+    packets_in_flight: HashMap<OrderingGuarantee, usize>,
     // However, we can only reasonably ack up to REDUNDANT_PACKET_ACKS_SIZE + 1 packets on each
     // message we send so this should be that large
     received_packets: SequenceBuffer<ReceivedPacket>,
@@ -25,8 +29,9 @@ impl AcknowledgmentHandler {
     pub fn new() -> Self {
         AcknowledgmentHandler {
             sequence_number: 0,
-            remote_ack_sequence_num: u16::max_value(),
+            remote_ack_sequence_num: u16::MAX,
             sent_packets: HashMap::with_capacity(DEFAULT_SEND_PACKETS_SIZE),
+            packets_in_flight: HashMap::new(),
             received_packets: SequenceBuffer::with_capacity(REDUNDANT_PACKET_ACKS_SIZE + 1),
         }
     }
@@ -76,14 +81,14 @@ impl AcknowledgmentHandler {
             .insert(remote_seq_num, ReceivedPacket {});
 
         // The current remote_ack_seq was (clearly) received so we should remove it.
-        self.sent_packets.remove(&remote_ack_seq);
+        self.remove_sent_packet(remote_ack_seq);
 
         // The remote_ack_field is going to include whether or not the past 32 packets have been
         // received successfully. If so, we have no need to resend old packets.
         for i in 1..=REDUNDANT_PACKET_ACKS_SIZE {
             let ack_sequence = remote_ack_seq.wrapping_sub(i);
             if remote_ack_field & 1 == 1 {
-                self.sent_packets.remove(&ack_sequence);
+                self.remove_sent_packet(ack_sequence);
             }
             remote_ack_field >>= 1;
         }
@@ -92,14 +97,39 @@ impl AcknowledgmentHandler {
     /// Enqueue the outgoing packet for acknowledgment.
     pub fn process_outgoing(
         &mut self,
-        payload: &[u8],
+        payload: impl Into<Payload>,
         ordering_guarantee: OrderingGuarantee,
         item_identifier: Option<SequenceNumber>,
     ) {
+        self.process_outgoing_with_type(
+            PacketType::Packet,
+            payload,
+            ordering_guarantee,
+            item_identifier,
+        );
+    }
+
+    /// Enqueue the outgoing packet, of the given `PacketType`, for acknowledgment.
+    pub fn process_outgoing_with_type(
+        &mut self,
+        packet_type: PacketType,
+        payload: impl Into<Payload>,
+        ordering_guarantee: OrderingGuarantee,
+        item_identifier: Option<SequenceNumber>,
+    ) {
+        // A packet which was not acknowledged after the sequence numbers wrapped around is replaced
+        self.remove_sent_packet(self.sequence_number);
+        if packet_type == PacketType::Packet {
+            *self
+                .packets_in_flight
+                .entry(ordering_guarantee)
+                .or_insert(0) += 1;
+        }
         self.sent_packets.insert(
             self.sequence_number,
             SentPacket {
-                payload: Box::from(payload),
+                packet_type,
+                payload: payload.into(),
                 ordering_guarantee,
                 item_identifier,
             },
@@ -109,14 +139,41 @@ impl AcknowledgmentHandler {
         self.sequence_number = self.sequence_number.wrapping_add(1);
     }
 
+    /// Returns the number of packets which are not acknowledged yet.
+    pub fn packets_awaiting_ack(&self) -> usize {
+        self.sent_packets.len()
+    }
+
+    /// Returns the number of packets, sent with the given `OrderingGuarantee`, which are not acknowledged yet.
+    pub fn packets_in_flight(&self, ordering_guarantee: OrderingGuarantee) -> usize {
+        self.packets_in_flight
+            .get(&ordering_guarantee)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Forgets a packet awaiting acknowledgment, and no longer counts it as in flight.
+    fn remove_sent_packet(&mut self, sequence: SequenceNumber) -> Option<SentPacket> {
+        let packet = self.sent_packets.remove(&sequence)?;
+        if packet.packet_type == PacketType::Packet {
+            if let Some(count) = self.packets_in_flight.get_mut(&packet.ordering_guarantee) {
+                *count -= 1;
+                if *count == 0 {
+                    self.packets_in_flight.remove(&packet.ordering_guarantee);
+                }
+            }
+        }
+        Some(packet)
+    }
+
     /// Returns a `Vec` of packets we believe have been dropped.
     pub fn dropped_packets(&mut self) -> Vec<SentPacket> {
-        let mut sent_sequences: Vec<SequenceNumber> = self.sent_packets.keys().cloned().collect();
-        sent_sequences.sort();
-
         let remote_ack_sequence = self.remote_ack_sequence_num;
-        sent_sequences
-            .into_iter()
+        // Only the dropped sequences are collected, so this does not allocate when nothing was dropped
+        let mut dropped_sequences: Vec<SequenceNumber> = self
+            .sent_packets
+            .keys()
+            .cloned()
             .filter(|s| {
                 if sequence_less_than(*s, remote_ack_sequence) {
                     remote_ack_sequence.wrapping_sub(*s) > REDUNDANT_PACKET_ACKS_SIZE
@@ -124,14 +181,33 @@ impl AcknowledgmentHandler {
                     false
                 }
             })
-            .flat_map(|s| self.sent_packets.remove(&s))
+            .collect();
+        dropped_sequences.sort();
+
+        dropped_sequences
+            .into_iter()
+            .flat_map(|s| self.remove_sent_packet(s))
+            .collect()
+    }
+
+    /// Returns a `Vec` of all packets awaiting acknowledgment, in the order they were sent, and forgets them.
+    pub fn take_unacknowledged_packets(&mut self) -> Vec<SentPacket> {
+        let mut sequences: Vec<SequenceNumber> = self.sent_packets.keys().cloned().collect();
+        // Sequence numbers wrap around, so they are ordered by how long ago they were sent
+        let newest = self.sequence_number.wrapping_sub(1);
+        sequences.sort_by_key(|s| Reverse(newest.wrapping_sub(*s)));
+
+        sequences
+            .into_iter()
+            .flat_map(|s| self.remove_sent_packet(s))
             .collect()
     }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct SentPacket {
-    pub payload: Box<[u8]>,
+    pub packet_type: PacketType,
+    pub payload: Payload,
     pub ordering_guarantee: OrderingGuarantee,
     pub item_identifier: Option<SequenceNumber>,
 }
@@ -145,7 +221,7 @@ pub struct ReceivedPacket;
 mod test {
     use crate::infrastructure::acknowledgment::ReceivedPacket;
     use crate::infrastructure::{AcknowledgmentHandler, SentPacket};
-    use crate::packet::OrderingGuarantee;
+    use crate::packet::{OrderingGuarantee, PacketType};
     use log::debug;
 
     #[test]
@@ -161,7 +237,7 @@ mod test {
     #[test]
     fn local_seq_num_wraps_on_overflow() {
         let mut handler = AcknowledgmentHandler::new();
-        handler.sequence_number = u16::max_value();
+        handler.sequence_number = u16::MAX;
         handler.process_outgoing(vec![].as_slice(), OrderingGuarantee::None, None);
         assert_eq!(handler.local_sequence_num(), 0);
     }
@@ -175,15 +251,9 @@ mod test {
     #[test]
     fn ack_bitfield_with_some_values() {
         let mut handler = AcknowledgmentHandler::new();
-        handler
-            .received_packets
-            .insert(0, ReceivedPacket::default());
-        handler
-            .received_packets
-            .insert(1, ReceivedPacket::default());
-        handler
-            .received_packets
-            .insert(3, ReceivedPacket::default());
+        handler.received_packets.insert(0, ReceivedPacket);
+        handler.received_packets.insert(1, ReceivedPacket);
+        handler.received_packets.insert(3, ReceivedPacket);
         assert_eq!(handler.remote_sequence_num(), 3);
         assert_eq!(handler.ack_bitfield(), 0b110)
     }
@@ -203,7 +273,8 @@ mod test {
         assert_eq!(
             handler.dropped_packets(),
             vec![SentPacket {
-                payload: vec![1, 2, 3].into_boxed_slice(),
+                packet_type: PacketType::Packet,
+                payload: vec![1, 2, 3].into(),
                 ordering_guarantee: OrderingGuarantee::None,
                 item_identifier: None,
             }]
@@ -285,4 +356,51 @@ mod test {
         assert_eq!(handler.sent_packets.len(), 1);
         assert_eq!(handler.local_sequence_num(), 1);
     }
+
+    #[test]
+    fn count_packets_in_flight_per_ordering_guarantee() {
+        let mut handler = AcknowledgmentHandler::new();
+        let stream = OrderingGuarantee::Ordered(Some(1));
+
+        handler.process_outgoing(vec![1].as_slice(), stream, Some(1));
+        handler.process_outgoing(vec![2].as_slice(), stream, Some(2));
+        handler.process_outgoing(
+            vec![3].as_slice(),
+            OrderingGuarantee::Ordered(Some(2)),
+            Some(1),
+        );
+        handler.process_outgoing_with_type(PacketType::StreamControl, &[0][..], stream, Some(3));
+        assert_eq!(handler.packets_in_flight(stream), 2);
+
+        handler.process_incoming(0, 1, 0b1);
+        assert_eq!(handler.packets_in_flight(stream), 0);
+        assert_eq!(
+            handler.packets_in_flight(OrderingGuarantee::Ordered(Some(2))),
+            1
+        );
+        handler.take_unacknowledged_packets();
+        assert_eq!(
+            handler.packets_in_flight(OrderingGuarantee::Ordered(Some(2))),
+            0
+        );
+    }
+
+    #[test]
+    fn take_unacknowledged_packets_in_sending_order() {
+        let mut handler = AcknowledgmentHandler::new();
+        handler.sequence_number = u16::MAX - 1;
+        for payload in 1..=4 {
+            handler.process_outgoing(vec![payload].as_slice(), OrderingGuarantee::None, None);
+        }
+        // Acknowledges the second packet
+        handler.process_incoming(0, u16::MAX, 0);
+
+        let payloads: Vec<u8> = handler
+            .take_unacknowledged_packets()
+            .iter()
+            .map(|packet| packet.payload[0])
+            .collect();
+        assert_eq!(payloads, vec![1, 3, 4]);
+        assert!(handler.take_unacknowledged_packets().is_empty());
+    }
 }
@@ -42,8 +42,8 @@
 mod ordering;
 mod sequencing;
 
-pub use self::ordering::{IterMut, OrderingStream, OrderingSystem};
-pub use self::sequencing::{SequencingStream, SequencingSystem};
+pub use self::ordering::{OrderingStream, OrderingSystem};
+pub use self::sequencing::SequencingSystem;
 
 /// A trait which can be implemented for arranging operations.
 pub trait Arranging {
@@ -65,7 +65,12 @@ pub trait ArrangingSystem {
     type Stream;
 
     /// Returns the number of streams currently created.
+    #[allow(dead_code)]
     fn stream_count(&self) -> usize;
+    /// Returns the ids of the streams currently created.
+    fn stream_ids(&self) -> Vec<u8>;
+    /// Try to get a `Stream` by `stream_id`.
+    fn get_stream(&self, stream_id: u8) -> Option<&Self::Stream>;
     /// Try to get a `Stream` by `stream_id`. When the stream does not exist, it will be inserted by the given `stream_id` and returned.
     fn get_or_create_stream(&mut self, stream_id: u8) -> &mut Self::Stream;
 }
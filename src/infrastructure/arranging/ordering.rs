@@ -73,7 +73,10 @@
 
 use super::{Arranging, ArrangingSystem};
 use crate::packet::SequenceNumber;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 /// An ordering system that can arrange items in order on different streams.
 ///
@@ -84,18 +87,66 @@ use std::collections::HashMap;
 pub struct OrderingSystem<T> {
     // '[HashMap]' with streams on which items can be ordered.
     streams: HashMap<u8, OrderingStream<T>>,
+    // the maximal number of items each stream may buffer.
+    max_buffered_items: usize,
+    // the maximal number of bytes each stream may buffer.
+    max_buffered_bytes: usize,
 }
 
 impl<T> OrderingSystem<T> {
-    /// Constructs a new [`OrderingSystem`](./struct.OrderingSystem.html).
+    /// Constructs a new [`OrderingSystem`](./struct.OrderingSystem.html) whose streams can buffer an unlimited amount of items.
+    #[cfg(test)]
     pub fn new() -> OrderingSystem<T> {
+        OrderingSystem::with_limits(usize::MAX, usize::MAX)
+    }
+
+    /// Constructs a new [`OrderingSystem`](./struct.OrderingSystem.html) whose streams will buffer at most `max_buffered_items` items
+    /// and `max_buffered_bytes` bytes while waiting for a missing item.
+    pub fn with_limits(max_buffered_items: usize, max_buffered_bytes: usize) -> OrderingSystem<T> {
         OrderingSystem {
             streams: HashMap::with_capacity(32),
+            max_buffered_items,
+            max_buffered_bytes,
         }
     }
+
+    /// Returns the ids of the streams that are stalled.
+    ///
+    /// See [`OrderingStream::check_stalled`](./struct.OrderingStream.html#method.check_stalled) for when a stream is considered stalled.
+    pub fn stalled_streams(&mut self, time: Instant, timeout: Option<Duration>) -> Vec<u8> {
+        self.streams
+            .iter_mut()
+            .filter_map(|(stream_id, stream)| {
+                if stream.check_stalled(time, timeout) {
+                    Some(*stream_id)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the id of the stream which follows `stream_id`, wrapping around to the lowest id,
+    /// or `None` if there are no streams.
+    pub fn next_stream_id(&self, stream_id: u8) -> Option<u8> {
+        let ids = || self.streams.keys().copied();
+        ids()
+            .filter(|id| *id > stream_id)
+            .min()
+            .or_else(|| ids().min())
+    }
+
+    /// Returns the earliest time at which one of the streams will be considered stalled,
+    /// or `None` if no stream is waiting for a missing item.
+    pub fn stall_deadline(&self, timeout: Option<Duration>) -> Option<Instant> {
+        self.streams
+            .values()
+            .filter_map(|stream| stream.stall_deadline(timeout))
+            .min()
+    }
 }
 
-impl<'a, T> ArrangingSystem for OrderingSystem<T> {
+impl<T> ArrangingSystem for OrderingSystem<T> {
     type Stream = OrderingStream<T>;
 
     /// Returns the number of ordering streams currently active.
@@ -103,12 +154,25 @@ impl<'a, T> ArrangingSystem for OrderingSystem<T> {
         self.streams.len()
     }
 
+    /// Returns the ids of the ordering streams currently active.
+    fn stream_ids(&self) -> Vec<u8> {
+        self.streams.keys().cloned().collect()
+    }
+
+    /// Try to get an [`OrderingStream`](./struct.OrderingStream.html) by `stream_id`.
+    fn get_stream(&self, stream_id: u8) -> Option<&Self::Stream> {
+        self.streams.get(&stream_id)
+    }
+
     /// Try to get an [`OrderingStream`](./struct.OrderingStream.html) by `stream_id`.
     /// When the stream does not exist, it will be inserted by the given `stream_id` and returned.
     fn get_or_create_stream(&mut self, stream_id: u8) -> &mut Self::Stream {
-        self.streams
-            .entry(stream_id)
-            .or_insert_with(|| OrderingStream::new(stream_id))
+        let max_buffered_items = self.max_buffered_items;
+        let max_buffered_bytes = self.max_buffered_bytes;
+
+        self.streams.entry(stream_id).or_insert_with(|| {
+            OrderingStream::with_limits(stream_id, max_buffered_items, max_buffered_bytes)
+        })
     }
 }
 
@@ -120,11 +184,18 @@ impl<'a, T> ArrangingSystem for OrderingSystem<T> {
 ///
 /// There are three scenarios that are important to us.
 /// 1. `incoming_index` == `expected_index`.
-/// This package meets the expected order, so we can return it immediately.
+///    This package meets the expected order, so we can return it immediately.
 /// 2. `incoming_index` > `expected_index`.
-/// This package is newer than we expect, so we have to hold it temporarily until we have received all previous packages.
+///    This package is newer than we expect, so we have to hold it temporarily until we have received all previous packages.
 /// 3. `incoming_index`< `expected_index`
-/// This can only happen in cases where we have a duplicated package. Again we don't give anything back.
+///    This can only happen in cases where we have a duplicated package. Again we don't give anything back.
+///
+/// # Limits
+///
+/// A single missing item blocks all items behind it, this is also called head-of-line blocking.
+/// To prevent that the stream grows without bound, the items and bytes that are waiting are capped.
+/// Items that arrive once the stream is full are dropped, and the stream reports itself as stalled.
+///
 /// # Remarks
 /// - See [super-module](../index.html) for more information about streams.
 pub struct OrderingStream<T> {
@@ -137,16 +208,25 @@ pub struct OrderingStream<T> {
     expected_index: usize,
     // unique identifier which should be used for ordering on a different stream e.g. the remote endpoint.
     unique_item_identifier: u16,
+    // the total size, in bytes, of the items in `storage`.
+    buffered_bytes: usize,
+    // the maximal number of items `storage` may hold.
+    max_buffered_items: usize,
+    // the maximal number of bytes `storage` may hold.
+    max_buffered_bytes: usize,
+    // the index we are waiting for and since when, used to detect long lasting gaps.
+    waiting_since: Option<(usize, Instant)>,
+    // whether the current gap has already been reported as a stall.
+    stall_reported: bool,
+    // whether items were dropped because `storage` was full.
+    overflowed: bool,
+    // whether we closed this stream for sending.
+    closed: bool,
+    // whether the remote endpoint closed this stream.
+    closed_by_remote: bool,
 }
 
 impl<T> OrderingStream<T> {
-    /// Constructs a new, empty [`OrderingStream<T>`](./struct.OrderingStream.html).
-    ///
-    /// The default stream will have a capacity of 32 items.
-    pub fn new(stream_id: u8) -> OrderingStream<T> {
-        OrderingStream::with_capacity(1024, stream_id)
-    }
-
     /// Constructs a new, empty [`OrderingStream`] with the specified capacity.
     ///
     /// The stream will be able to hold exactly capacity elements without
@@ -162,9 +242,32 @@ impl<T> OrderingStream<T> {
             expected_index: 1,
             _stream_id: stream_id,
             unique_item_identifier: 0,
+            buffered_bytes: 0,
+            max_buffered_items: usize::MAX,
+            max_buffered_bytes: usize::MAX,
+            waiting_since: None,
+            stall_reported: false,
+            overflowed: false,
+            closed: false,
+            closed_by_remote: false,
         }
     }
 
+    /// Constructs a new, empty [`OrderingStream`] which will buffer at most `max_buffered_items` items
+    /// and `max_buffered_bytes` bytes while waiting for a missing item.
+    ///
+    /// [`OrderingStream`]: ./struct.OrderingStream.html
+    pub fn with_limits(
+        stream_id: u8,
+        max_buffered_items: usize,
+        max_buffered_bytes: usize,
+    ) -> OrderingStream<T> {
+        let mut stream = OrderingStream::with_capacity(max_buffered_items.min(1024), stream_id);
+        stream.max_buffered_items = max_buffered_items;
+        stream.max_buffered_bytes = max_buffered_bytes;
+        stream
+    }
+
     /// Returns the identifier of this stream.
     #[cfg(test)]
     pub fn stream_id(&self) -> u8 {
@@ -177,12 +280,91 @@ impl<T> OrderingStream<T> {
         self.expected_index
     }
 
+    /// Returns the number of items waiting for an older item to arrive.
+    pub fn buffered_items(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Returns the number of bytes waiting for an older item to arrive.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
     /// Returns the unique identifier which should be used for ordering on the other stream e.g. the remote endpoint.
     pub fn new_item_identifier(&mut self) -> SequenceNumber {
         self.unique_item_identifier = self.unique_item_identifier.wrapping_add(1);
         self.unique_item_identifier
     }
 
+    /// Returns the unique identifier that the next call to `new_item_identifier` will return.
+    pub fn next_item_identifier(&self) -> SequenceNumber {
+        self.unique_item_identifier.wrapping_add(1)
+    }
+
+    /// Returns whether we closed this stream for sending.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Marks this stream as closed, or open, for sending.
+    pub fn set_closed(&mut self, closed: bool) {
+        self.closed = closed;
+    }
+
+    /// Returns whether the remote endpoint closed this stream.
+    pub fn is_closed_by_remote(&self) -> bool {
+        self.closed_by_remote
+    }
+
+    /// Checks whether this stream is stalled.
+    ///
+    /// A stream is stalled when it has been waiting for the same missing item for at least `timeout`,
+    /// or when it had to drop items because its limits were reached.
+    /// A `timeout` of `None` only reports the latter.
+    ///
+    /// # Remarks
+    /// - A stall is reported once, the next stall is reported after the missing item has arrived.
+    /// - The waiting time is measured from the first call that notices the gap, so this should be called regularly.
+    pub fn check_stalled(&mut self, time: Instant, timeout: Option<Duration>) -> bool {
+        if self.storage.is_empty() {
+            self.waiting_since = None;
+        } else {
+            match self.waiting_since {
+                Some((index, _)) if index == self.expected_index => {}
+                _ => {
+                    self.waiting_since = Some((self.expected_index, time));
+                    self.stall_reported = false;
+                }
+            }
+        }
+
+        if self.overflowed {
+            self.overflowed = false;
+            self.stall_reported = true;
+            return true;
+        }
+
+        if let (Some((_, since)), Some(timeout)) = (self.waiting_since, timeout) {
+            if !self.stall_reported && time.duration_since(since) >= timeout {
+                self.stall_reported = true;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Returns the time at which this stream will be considered stalled if the missing item does not arrive,
+    /// or `None` if it is not waiting for a missing item, or the stall was already reported.
+    ///
+    /// This is only known after [`check_stalled`](#method.check_stalled) noticed the gap.
+    pub fn stall_deadline(&self, timeout: Option<Duration>) -> Option<Instant> {
+        match (self.waiting_since, timeout) {
+            (Some((_, since)), Some(timeout)) if !self.stall_reported => Some(since + timeout),
+            _ => None,
+        }
+    }
+
     /// Returns an iterator of stored items.
     ///
     /// # Algorithm for returning items from an Iterator.
@@ -208,15 +390,92 @@ impl<T> OrderingStream<T> {
     /// # Remarks
     /// - Iterator mutates the `expected_index`.
     /// - You can't use this iterator for iterating trough all cached values.
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut {
             items: &mut self.storage,
             expected_index: &mut self.expected_index,
+            buffered_bytes: &mut self.buffered_bytes,
         }
     }
 }
 
-impl<T> Arranging for OrderingStream<T> {
+impl<T: AsRef<[u8]>> OrderingStream<T> {
+    /// Resets this stream such that it continues at `next_index`.
+    ///
+    /// Buffered items older than `next_index` are discarded, and the stream no longer waits for the items before it.
+    /// Buffered items starting at `next_index` can be read with `iter_mut` afterwards.
+    ///
+    /// # Remarks
+    /// - The stream will never go back, a `next_index` lower than the `expected_index` only removes the closed state.
+    pub fn reset(&mut self, next_index: usize) {
+        let buffered_bytes = &mut self.buffered_bytes;
+        self.storage.retain(|index, item| {
+            if *index < next_index {
+                *buffered_bytes -= item.as_ref().len();
+                false
+            } else {
+                true
+            }
+        });
+
+        self.expected_index = self.expected_index.max(next_index);
+        self.waiting_since = None;
+        self.stall_reported = false;
+        self.overflowed = false;
+        self.closed_by_remote = false;
+    }
+
+    /// Puts back the item which was last returned by `arrange` or `iter_mut`, e.g. because it could not be delivered.
+    ///
+    /// The item is returned by `iter_mut` again, or replaced when it arrives again.
+    ///
+    /// # Remarks
+    /// - The item is buffered regardless of the limits of this stream.
+    pub fn put_back(&mut self, item: T) {
+        self.expected_index -= 1;
+        self.buffered_bytes += item.as_ref().len();
+        self.storage.insert(self.expected_index, item);
+    }
+
+    /// Closes this stream on behalf of the remote endpoint, which will continue at `next_index` when it resets the stream.
+    ///
+    /// All buffered items are discarded and the memory they used is released.
+    pub fn close_by_remote(&mut self, next_index: usize) {
+        self.reset(next_index);
+        self.storage = HashMap::new();
+        self.buffered_bytes = 0;
+        self.closed_by_remote = true;
+    }
+}
+
+impl<T: AsRef<[u8]>> OrderingStream<T> {
+    /// Returns whether an item with `incoming_offset` and `item_size` bytes does not fit in the limits of this stream,
+    /// `arrange` drops such an item and the stream reports itself as stalled.
+    ///
+    /// # Remarks
+    /// - A reliable item which does not fit should not be acknowledged, so it is resent once the stream has room again.
+    pub fn exceeds_limits(&mut self, incoming_offset: usize, item_size: usize) -> bool {
+        if incoming_offset <= self.expected_index {
+            return false;
+        }
+
+        // an item which is buffered already is replaced
+        let (buffered_items, buffered_bytes) = match self.storage.get(&incoming_offset) {
+            Some(old) => (
+                self.storage.len() - 1,
+                self.buffered_bytes - old.as_ref().len(),
+            ),
+            None => (self.storage.len(), self.buffered_bytes),
+        };
+
+        let exceeds = buffered_items >= self.max_buffered_items
+            || buffered_bytes.saturating_add(item_size) > self.max_buffered_bytes;
+        self.overflowed |= exceeds;
+        exceeds
+    }
+}
+
+impl<T: AsRef<[u8]>> Arranging for OrderingStream<T> {
     type ArrangingItem = T;
 
     /// Will order the given item based on the ordering algorithm.
@@ -227,25 +486,45 @@ impl<T> Arranging for OrderingStream<T> {
     ///
     /// There are three scenarios that are important to us.
     /// 1. `incoming_index` == `expected_index`.
-    /// This package meets the expected order, so we can return it immediately.
+    ///    This package meets the expected order, so we can return it immediately.
     /// 2. `incoming_index` > `expected_index`.
-    /// This package is newer than we expect, so we have to hold it temporarily until we have received all previous packages.
+    ///    This package is newer than we expect, so we have to hold it temporarily until we have received all previous packages.
     /// 3. `incoming_index` < `expected_index`
-    /// This can only happen in cases where we have a duplicated package. Again we don't give anything back.
+    ///    This can only happen in cases where we have a duplicated package. Again we don't give anything back.
     ///
     /// # Remark
     /// - When we receive an item there is a possibility that a gab is filled and one or more items will could be returned.
     ///   You should use the `iter_mut` instead for reading the items in order.
     ///   However the item given to `arrange` will be returned directly when it matches the `expected_index`.
+    /// - Newer items that do not fit in the limits of this stream are dropped, see [`check_stalled`](#method.check_stalled).
     fn arrange(
         &mut self,
         incoming_offset: usize,
         item: Self::ArrangingItem,
     ) -> Option<Self::ArrangingItem> {
+        if incoming_offset >= self.expected_index {
+            self.closed_by_remote = false;
+        }
+
         if incoming_offset == self.expected_index {
+            // an item which was put back is replaced by the one given
+            if let Some(old) = self.storage.remove(&incoming_offset) {
+                self.buffered_bytes -= old.as_ref().len();
+            }
+
             self.expected_index += 1;
             Some(item)
         } else if incoming_offset > self.expected_index {
+            let item_size = item.as_ref().len();
+            if self.exceeds_limits(incoming_offset, item_size) {
+                return None;
+            }
+
+            if let Some(old) = self.storage.remove(&incoming_offset) {
+                self.buffered_bytes -= old.as_ref().len();
+            }
+
+            self.buffered_bytes += item_size;
             self.storage.insert(incoming_offset, item);
             None
         } else {
@@ -272,18 +551,20 @@ impl<T> Arranging for OrderingStream<T> {
 pub struct IterMut<'a, T> {
     items: &'a mut HashMap<usize, T>,
     expected_index: &'a mut usize,
+    buffered_bytes: &'a mut usize,
 }
 
-impl<'a, T> Iterator for IterMut<'a, T> {
+impl<'a, T: AsRef<[u8]>> Iterator for IterMut<'a, T> {
     type Item = T;
 
     /// Returns `Some` when there is an item in our cache matching the `expected_index`.
     /// Returns `None` if there are no times matching our `expected` index.
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
-        match self.items.remove(&self.expected_index) {
+        match self.items.remove(self.expected_index) {
             None => None,
             Some(e) => {
                 *self.expected_index += 1;
+                *self.buffered_bytes -= e.as_ref().len();
                 Some(e)
             }
         }
@@ -292,7 +573,8 @@ impl<'a, T> Iterator for IterMut<'a, T> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Arranging, ArrangingSystem, OrderingSystem};
+    use super::{Arranging, ArrangingSystem, OrderingStream, OrderingSystem};
+    use std::time::{Duration, Instant};
 
     #[derive(Debug, PartialEq, Clone)]
     struct Packet {
@@ -309,6 +591,13 @@ mod tests {
         }
     }
 
+    // test packets do not carry a payload, so they do not count towards the buffered bytes.
+    impl AsRef<[u8]> for Packet {
+        fn as_ref(&self) -> &[u8] {
+            &[]
+        }
+    }
+
     #[test]
     fn create_stream() {
         let mut system: OrderingSystem<Packet> = OrderingSystem::new();
@@ -328,6 +617,20 @@ mod tests {
         assert_eq!(stream.stream_id(), 1);
     }
 
+    #[test]
+    fn take_turns_between_the_streams() {
+        let mut system: OrderingSystem<Packet> = OrderingSystem::new();
+        assert_eq!(system.next_stream_id(0), None);
+
+        for stream_id in [7, 2, 5] {
+            system.get_or_create_stream(stream_id);
+        }
+        assert_eq!(system.next_stream_id(0), Some(2));
+        assert_eq!(system.next_stream_id(2), Some(5));
+        assert_eq!(system.next_stream_id(5), Some(7));
+        assert_eq!(system.next_stream_id(7), Some(2));
+    }
+
     #[test]
     fn can_iterate() {
         let mut system: OrderingSystem<Packet> = OrderingSystem::new();
@@ -347,9 +650,9 @@ mod tests {
                 stub_packet1
             );
 
-            stream.arrange(4, stub_packet4.clone()).is_none();
-            stream.arrange(5, stub_packet5.clone()).is_none();
-            stream.arrange(3, stub_packet3.clone()).is_none();
+            assert!(stream.arrange(4, stub_packet4.clone()).is_none());
+            assert!(stream.arrange(5, stub_packet5.clone()).is_none());
+            assert!(stream.arrange(3, stub_packet3.clone()).is_none());
         }
         {
             let mut iterator = stream.iter_mut();
@@ -378,16 +681,10 @@ mod tests {
         ( [$( $x:expr ),*] , [$( $y:expr),*] , $stream_id:expr) => {
         {
             // initialize vector of given range on the left.
-            let mut before: Vec<usize> = Vec::new();
-            $(
-                before.push($x);
-            )*
+            let before: Vec<usize> = vec![$($x),*];
 
             // initialize vector of given range on the right.
-            let mut after: Vec<usize> = Vec::new();
-            $(
-                after.push($y);
-            )*
+            let after: Vec<usize> = vec![$($y),*];
 
             // generate test packets
             let mut packets = Vec::new();
@@ -450,4 +747,147 @@ mod tests {
         assert_order!([3, 2, 4, 1, 5], [1, 2, 3, 4, 5], 7);
         assert_order!([2, 1, 4, 3, 5], [1, 2, 3, 4, 5], 8);
     }
+
+    #[test]
+    fn drop_items_exceeding_item_limit() {
+        let mut system: OrderingSystem<Packet> = OrderingSystem::with_limits(2, usize::MAX);
+        let stream = system.get_or_create_stream(1);
+
+        assert!(stream.arrange(3, Packet::new(3, 1)).is_none());
+        assert!(stream.arrange(4, Packet::new(4, 1)).is_none());
+        assert!(stream.arrange(5, Packet::new(5, 1)).is_none());
+        assert_eq!(stream.buffered_items(), 2);
+
+        // the missing item is never buffered, so it is still returned.
+        assert_eq!(
+            stream.arrange(1, Packet::new(1, 1)),
+            Some(Packet::new(1, 1))
+        );
+        assert!(stream.check_stalled(Instant::now(), None));
+    }
+
+    #[test]
+    fn drop_items_exceeding_byte_limit() {
+        let mut stream: OrderingStream<Box<[u8]>> = OrderingStream::with_limits(1, 100, 10);
+
+        assert!(stream.arrange(2, vec![0; 6].into_boxed_slice()).is_none());
+        assert!(stream.arrange(3, vec![0; 6].into_boxed_slice()).is_none());
+        assert_eq!(stream.buffered_items(), 1);
+
+        // once the gap is filled the buffered bytes are released again.
+        assert!(stream.arrange(1, vec![0; 6].into_boxed_slice()).is_some());
+        assert_eq!(stream.iter_mut().count(), 1);
+        assert!(stream.arrange(5, vec![0; 10].into_boxed_slice()).is_none());
+        assert!(stream.arrange(6, vec![0; 1].into_boxed_slice()).is_none());
+        assert_eq!(stream.buffered_items(), 1);
+    }
+
+    #[test]
+    fn check_limits_before_arranging() {
+        let mut stream: OrderingStream<Box<[u8]>> = OrderingStream::with_limits(1, 1, 10);
+
+        assert!(!stream.exceeds_limits(2, 6));
+        assert!(stream.arrange(2, vec![0; 6].into_boxed_slice()).is_none());
+        // replacing the buffered item or arranging the expected one is always possible.
+        assert!(!stream.exceeds_limits(2, 10));
+        assert!(!stream.exceeds_limits(0, 100));
+        assert!(!stream.overflowed);
+
+        assert!(stream.exceeds_limits(3, 1));
+        assert!(stream.overflowed);
+        assert_eq!(stream.buffered_items(), 1);
+    }
+
+    #[test]
+    fn put_back_item_which_could_not_be_delivered() {
+        let mut stream: OrderingStream<Box<[u8]>> = OrderingStream::with_limits(1, 100, 100);
+
+        assert!(stream.arrange(2, vec![2].into_boxed_slice()).is_none());
+        let first = stream.arrange(1, vec![1].into_boxed_slice()).unwrap();
+        let second = stream.iter_mut().next().unwrap();
+
+        stream.put_back(second);
+        assert_eq!(stream.expected_index(), 2);
+        assert_eq!(stream.buffered_bytes(), 1);
+        assert_eq!(&*stream.iter_mut().next().unwrap(), &[2]);
+
+        // an item which is put back is replaced when it arrives again.
+        stream.put_back(vec![2].into_boxed_slice());
+        stream.put_back(first);
+        assert_eq!(
+            &*stream.arrange(1, vec![1].into_boxed_slice()).unwrap(),
+            &[1]
+        );
+        assert_eq!(stream.iter_mut().count(), 1);
+        assert_eq!(stream.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn report_gap_exceeding_timeout_once() {
+        let mut system: OrderingSystem<Packet> = OrderingSystem::new();
+        let timeout = Some(Duration::from_millis(100));
+        let now = Instant::now();
+
+        system.get_or_create_stream(1).arrange(2, Packet::new(2, 1));
+
+        assert!(system.stalled_streams(now, timeout).is_empty());
+        assert_eq!(
+            system.stall_deadline(timeout),
+            Some(now + Duration::from_millis(100))
+        );
+        assert!(system
+            .stalled_streams(now + Duration::from_millis(99), timeout)
+            .is_empty());
+        assert_eq!(
+            system.stalled_streams(now + Duration::from_millis(100), timeout),
+            vec![1]
+        );
+        assert_eq!(system.stall_deadline(timeout), None);
+        assert!(system
+            .stalled_streams(now + Duration::from_millis(200), timeout)
+            .is_empty());
+
+        // filling the gap resets the stall.
+        let stream = system.get_or_create_stream(1);
+        stream.arrange(1, Packet::new(1, 1));
+        assert_eq!(stream.iter_mut().count(), 1);
+        assert!(system
+            .stalled_streams(now + Duration::from_millis(300), timeout)
+            .is_empty());
+    }
+
+    #[test]
+    fn reset_skips_missing_items() {
+        let mut stream: OrderingStream<Box<[u8]>> = OrderingStream::with_limits(1, 100, 100);
+
+        assert!(stream.arrange(2, vec![0; 2].into_boxed_slice()).is_none());
+        assert!(stream.arrange(4, vec![0; 4].into_boxed_slice()).is_none());
+        assert!(stream.arrange(5, vec![0; 5].into_boxed_slice()).is_none());
+
+        stream.reset(4);
+        assert_eq!(stream.expected_index(), 4);
+        assert_eq!(stream.buffered_bytes(), 9);
+        assert_eq!(stream.iter_mut().count(), 2);
+        assert_eq!(stream.buffered_bytes(), 0);
+
+        // a reset will never move the stream back.
+        stream.reset(1);
+        assert_eq!(stream.expected_index(), 6);
+    }
+
+    #[test]
+    fn close_by_remote_discards_buffered_items() {
+        let mut stream: OrderingStream<Box<[u8]>> = OrderingStream::with_limits(1, 100, 100);
+
+        assert!(stream.arrange(3, vec![0; 3].into_boxed_slice()).is_none());
+        stream.close_by_remote(7);
+
+        assert!(stream.is_closed_by_remote());
+        assert_eq!(stream.buffered_items(), 0);
+        assert_eq!(stream.buffered_bytes(), 0);
+
+        // a new item after the close reopens the stream.
+        assert!(stream.arrange(7, vec![0; 7].into_boxed_slice()).is_some());
+        assert!(!stream.is_closed_by_remote());
+    }
 }
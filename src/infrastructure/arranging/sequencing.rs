@@ -41,6 +41,16 @@ impl<T> ArrangingSystem for SequencingSystem<T> {
         self.streams.len()
     }
 
+    /// Returns the ids of the sequencing streams currently created.
+    fn stream_ids(&self) -> Vec<u8> {
+        self.streams.keys().cloned().collect()
+    }
+
+    /// Try to get an [`SequencingStream`](./struct.SequencingStream.html) by `stream_id`.
+    fn get_stream(&self, stream_id: u8) -> Option<&Self::Stream> {
+        self.streams.get(&stream_id)
+    }
+
     /// Try to get an [`SequencingStream`](./struct.SequencingStream.html) by `stream_id`.
     /// When the stream does not exist, it will be inserted by the given `stream_id` and returned.
     fn get_or_create_stream(&mut self, stream_id: u8) -> &mut Self::Stream {
@@ -58,11 +68,11 @@ impl<T> ArrangingSystem for SequencingSystem<T> {
 ///
 /// There are two scenarios that are important to us.
 /// 1. `incoming_index` >= `top_index`.
-/// This item is the newest or newer than the last one we have seen.
-/// Because of that we should return it back to the user.
+///    This item is the newest or newer than the last one we have seen.
+///    Because of that we should return it back to the user.
 /// 2. `incoming_index` < `top_index`.
-/// This item is older than the newest item we have seen so far.
-/// Since we don't care about old items we can toss it a way.
+///    This item is older than the newest item we have seen so far.
+///    Since we don't care about old items we can toss it a way.
 ///
 /// # Remarks
 /// - See [super-module](../index.html) for more information about streams.
@@ -75,6 +85,10 @@ pub struct SequencingStream<T> {
     phantom: PhantomData<T>,
     // unique identifier which should be used for ordering on an other stream e.g. the remote endpoint.
     unique_item_identifier: u16,
+    // whether we closed this stream for sending.
+    closed: bool,
+    // whether the remote endpoint closed this stream.
+    closed_by_remote: bool,
 }
 
 impl<T> SequencingStream<T> {
@@ -87,6 +101,8 @@ impl<T> SequencingStream<T> {
             top_index: 0,
             phantom: PhantomData,
             unique_item_identifier: 0,
+            closed: false,
+            closed_by_remote: false,
         }
     }
 
@@ -101,6 +117,41 @@ impl<T> SequencingStream<T> {
         self.unique_item_identifier = self.unique_item_identifier.wrapping_add(1);
         self.unique_item_identifier
     }
+
+    /// Returns the unique identifier that the next call to `new_item_identifier` will return.
+    pub fn next_item_identifier(&self) -> SequenceNumber {
+        self.unique_item_identifier.wrapping_add(1)
+    }
+
+    /// Returns whether we closed this stream for sending.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// Marks this stream as closed, or open, for sending.
+    pub fn set_closed(&mut self, closed: bool) {
+        self.closed = closed;
+    }
+
+    /// Returns whether the remote endpoint closed this stream.
+    pub fn is_closed_by_remote(&self) -> bool {
+        self.closed_by_remote
+    }
+
+    /// Resets this stream such that it continues at `next_index`, older items will be tossed away.
+    ///
+    /// # Remarks
+    /// - The stream will never go back, a `next_index` lower than the `top_index` only removes the closed state.
+    pub fn reset(&mut self, next_index: usize) {
+        self.top_index = self.top_index.max(next_index.saturating_sub(1));
+        self.closed_by_remote = false;
+    }
+
+    /// Closes this stream on behalf of the remote endpoint, which will continue at `next_index` when it resets the stream.
+    pub fn close_by_remote(&mut self, next_index: usize) {
+        self.reset(next_index);
+        self.closed_by_remote = true;
+    }
 }
 
 impl<T> Arranging for SequencingStream<T> {
@@ -114,11 +165,11 @@ impl<T> Arranging for SequencingStream<T> {
     ///
     /// There are two scenarios that are important to us.
     /// 1. `incoming_index` >= `top_index`.
-    /// This item is the newest or newer than the last one we have seen.
-    /// Because of that we should return it back to the user.
+    ///    This item is the newest or newer than the last one we have seen.
+    ///    Because of that we should return it back to the user.
     /// 2. `incoming_index` < `top_index`.
-    /// This item is older than we the newest packet we have seen so far.
-    /// Since we don't care about old items we can toss it a way.
+    ///    This item is older than we the newest packet we have seen so far.
+    ///    Since we don't care about old items we can toss it a way.
     ///
     /// # Remark
     /// - All old packets will be tossed away.
@@ -130,6 +181,7 @@ impl<T> Arranging for SequencingStream<T> {
     ) -> Option<Self::ArrangingItem> {
         if incoming_index > self.top_index {
             self.top_index = incoming_index;
+            self.closed_by_remote = false;
             return Some(item);
         }
         None
@@ -173,21 +225,33 @@ mod tests {
         assert_eq!(stream.stream_id(), 1);
     }
 
+    #[test]
+    fn reset_skips_older_items() {
+        let mut system: SequencingSystem<Packet> = SequencingSystem::new();
+        let stream = system.get_or_create_stream(1);
+
+        assert!(stream.arrange(1, Packet::new(1, 1)).is_some());
+        stream.close_by_remote(5);
+        assert!(stream.is_closed_by_remote());
+
+        assert!(stream.arrange(4, Packet::new(4, 1)).is_none());
+        assert!(stream.arrange(5, Packet::new(5, 1)).is_some());
+        assert!(!stream.is_closed_by_remote());
+
+        // a reset will never move the stream back.
+        stream.reset(2);
+        assert!(stream.arrange(5, Packet::new(5, 1)).is_none());
+    }
+
     /// asserts that the given collection, on the left, should result - after it is sequenced - into the given collection, on the right.
     macro_rules! assert_sequence {
         ( [$( $x:expr ),*], [$( $y:expr),*], $stream_id:expr) => {
             {
                 // initialize vector of given range on the left.
-                let mut before: Vec<usize> = Vec::new();
-                $(
-                    before.push($x);
-                )*
+                let before: Vec<usize> = vec![$($x),*];
 
                 // initialize vector of given range on the right.
-                let mut after: Vec<usize> = Vec::new();
-                $(
-                    after.push($y);
-                )*
+                let after: Vec<usize> = vec![$($y),*];
 
                 // generate test packets
                 let mut packets = Vec::new();
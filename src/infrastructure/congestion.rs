@@ -1,16 +1,23 @@
 use crate::{
-    net::{NetworkQuality, RttMeasurer},
+    net::{InboundLossMeasurer, JitterMeasurer, LossMeasurer, NetworkQuality, RttMeasurer},
+    packet::SequenceNumber,
     sequence_buffer::{CongestionData, SequenceBuffer},
     Config,
 };
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+// The number of most recent packets over which packet loss is estimated.
+const LOSS_WINDOW_SIZE: usize = 256;
 
 /// Type that is responsible for keeping track of congestion information.
 pub struct CongestionHandler {
     rtt_measurer: RttMeasurer,
+    jitter_measurer: JitterMeasurer,
+    outbound_loss: LossMeasurer,
+    inbound_loss: InboundLossMeasurer,
     congestion_data: SequenceBuffer<CongestionData>,
-    _quality: NetworkQuality,
+    config: Config,
 }
 
 impl CongestionHandler {
@@ -18,17 +25,74 @@ impl CongestionHandler {
     pub fn new(config: &Config) -> CongestionHandler {
         CongestionHandler {
             rtt_measurer: RttMeasurer::new(config),
-            congestion_data: SequenceBuffer::with_capacity(<u16>::max_value()),
-            _quality: NetworkQuality::Good,
+            jitter_measurer: JitterMeasurer::new(),
+            outbound_loss: LossMeasurer::new(LOSS_WINDOW_SIZE),
+            inbound_loss: InboundLossMeasurer::new(LOSS_WINDOW_SIZE),
+            congestion_data: SequenceBuffer::with_capacity(u16::MAX),
+            config: config.clone(),
         }
     }
 
     /// Process incoming sequence number.
     ///
     /// This will calculate the RTT-time and smooth down the RTT-value to prevent uge RTT-spikes.
-    pub fn process_incoming(&mut self, incoming_seq: u16) {
+    pub fn process_incoming(&mut self, incoming_seq: u16, time: Instant) {
         let congestion_data = self.congestion_data.get_mut(incoming_seq);
-        self.rtt_measurer.calculate_rrt(congestion_data);
+        let rtt_sample = congestion_data
+            .as_ref()
+            .map(|data| time.saturating_duration_since(data.sending_time));
+        self.rtt_measurer.calculate_rrt(congestion_data, time);
+
+        // acknowledgments are repeated, only the first one says something about the round trip time.
+        if let Some(rtt_sample) = rtt_sample {
+            self.jitter_measurer.record(rtt_sample);
+            self.congestion_data.remove(incoming_seq);
+        }
+    }
+
+    /// Process the outcome of packets we sent, `acknowledged` packets arrived and `dropped` packets are considered lost.
+    pub fn process_acknowledgments(&mut self, acknowledged: usize, dropped: usize) {
+        for _ in 0..acknowledged {
+            self.outbound_loss.record(false);
+        }
+        for _ in 0..dropped {
+            self.outbound_loss.record(true);
+        }
+    }
+
+    /// Process the sequence number of a packet we received, gaps in the sequence numbers are considered lost.
+    pub fn process_received(&mut self, remote_seq: SequenceNumber) {
+        self.inbound_loss.record(remote_seq);
+    }
+
+    /// Returns the smoothed round trip time.
+    pub fn rtt(&self) -> Duration {
+        self.rtt_measurer.smoothed_rtt()
+    }
+
+    /// Returns the estimated jitter of the round trip time.
+    pub fn jitter(&self) -> Duration {
+        self.jitter_measurer.jitter()
+    }
+
+    /// Returns the fraction of the most recent packets we sent that got lost.
+    pub fn outbound_loss(&self) -> f32 {
+        self.outbound_loss.loss()
+    }
+
+    /// Returns the fraction of the most recent packets the remote endpoint sent that got lost.
+    pub fn inbound_loss(&self) -> f32 {
+        self.inbound_loss.loss()
+    }
+
+    /// Returns the quality of the network, based on the round trip time, packet loss and jitter.
+    pub fn quality(&self) -> NetworkQuality {
+        NetworkQuality::estimate(
+            self.rtt(),
+            self.outbound_loss().max(self.inbound_loss()),
+            self.jitter(),
+            &self.config,
+        )
     }
 
     /// Process outgoing sequence number.
@@ -45,7 +109,7 @@ impl CongestionHandler {
 mod test {
     use crate::infrastructure::CongestionHandler;
     use crate::Config;
-    use std::time::Instant;
+    use std::time::{Duration, Instant};
 
     #[test]
     fn congestion_entry_created() {
@@ -53,16 +117,19 @@ mod test {
 
         congestion_handler.process_outgoing(1, Instant::now());
 
-        assert_eq!(congestion_handler.congestion_data.exists(1), true);
+        assert!(congestion_handler.congestion_data.exists(1));
     }
 
     #[test]
     fn rtt_value_is_updated() {
         let mut congestion_handler = CongestionHandler::new(&Config::default());
 
+        let time = Instant::now();
+
         assert_eq!(congestion_handler.rtt_measurer.get_rtt(), 0.);
-        congestion_handler.process_outgoing(1, Instant::now());
-        congestion_handler.process_incoming(1);
-        assert_eq!(congestion_handler.rtt_measurer.get_rtt() != 0., true);
+        congestion_handler.process_outgoing(1, time);
+        congestion_handler.process_incoming(1, time + Duration::from_millis(50));
+        assert!(congestion_handler.rtt_measurer.get_rtt() != 0.);
+        assert_eq!(congestion_handler.rtt(), Duration::from_millis(50));
     }
 }
@@ -0,0 +1,558 @@
+//! Forward error correction: parities computed over a group of datagrams, which rebuild lost datagrams of the group.
+//!
+//! Each datagram of a group is a shard of the length of the datagram followed by the datagram, padded with zeros to the
+//! longest shard of the group. A parity is a sum of the shards, each multiplied by a coefficient, in GF(256).
+//! XOR parities use 1 for all coefficients, Reed-Solomon parities use a Cauchy matrix, of which every square submatrix is
+//! invertible. So as many lost datagrams as there are parities received can be solved for.
+
+use crate::{
+    net::constants::PARITY_TRAILER_SIZE,
+    packet::{header::ParityHeader, Redundancy},
+};
+use byteorder::{BigEndian, ByteOrder};
+use lazy_static::lazy_static;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+/// The maximal number of datagrams in a group, which keeps the coefficients of the Cauchy matrix distinct.
+pub const MAX_GROUP_SIZE: usize = 128;
+/// The number of groups a receiver keeps, datagrams of older groups can no longer be rebuilt.
+const MAX_GROUPS: usize = 64;
+/// The size of the length which precedes a datagram in its shard.
+const LENGTH_SIZE: usize = 2;
+
+lazy_static! {
+    // The powers of 2 in GF(256), with the polynomial x^8 + x^4 + x^3 + x^2 + 1, and their logarithms.
+    // The powers are repeated, so the sum of two logarithms can be looked up without a modulo.
+    static ref TABLES: ([u8; 510], [u8; 256]) = {
+        let mut exp = [0; 510];
+        let mut log = [0; 256];
+        let mut value: u16 = 1;
+        for power in 0..255 {
+            exp[power] = value as u8;
+            exp[power + 255] = value as u8;
+            log[value as usize] = power as u8;
+            value <<= 1;
+            if value & 0x100 != 0 {
+                value ^= 0x11d;
+            }
+        }
+        (exp, log)
+    };
+}
+
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let (exp, log) = &*TABLES;
+    exp[usize::from(log[usize::from(a)]) + usize::from(log[usize::from(b)])]
+}
+
+fn inv(a: u8) -> u8 {
+    let (exp, log) = &*TABLES;
+    exp[255 - usize::from(log[usize::from(a)])]
+}
+
+// Returns the coefficient of a datagram in a parity, the datagrams and parities have distinct points below and above 128.
+fn coefficient(reed_solomon: bool, parity_index: u8, data_index: u8) -> u8 {
+    if reed_solomon {
+        inv((0x80 | parity_index) ^ data_index)
+    } else {
+        1
+    }
+}
+
+// Adds `source` multiplied by `factor` to `target`.
+fn add_scaled(target: &mut [u8], source: &[u8], factor: u8) {
+    for (target, source) in target.iter_mut().zip(source) {
+        *target ^= mul(factor, *source);
+    }
+}
+
+// Returns the shard of a datagram, which is `size` bytes long.
+fn shard(datagram: &[u8], size: usize) -> Vec<u8> {
+    let mut shard = vec![0; size];
+    BigEndian::write_u16(&mut shard, datagram.len() as u16);
+    shard[LENGTH_SIZE..LENGTH_SIZE + datagram.len()].copy_from_slice(datagram);
+    shard
+}
+
+/// Appends the trailer of a datagram in a parity group, which carries the group and the index of the datagram in it.
+pub fn write_trailer(buffer: &mut Vec<u8>, group: u16, index: u8) {
+    buffer.extend_from_slice(&group.to_be_bytes());
+    buffer.push(index);
+}
+
+/// Returns a datagram of a parity group without its trailer, with the group and the index of the datagram in it.
+/// `None` if it is too short to have a trailer.
+pub fn split_trailer(datagram: &[u8]) -> Option<(&[u8], u16, u8)> {
+    let length = datagram.len().checked_sub(PARITY_TRAILER_SIZE)?;
+    let (datagram, trailer) = datagram.split_at(length);
+    Some((datagram, BigEndian::read_u16(trailer), trailer[2]))
+}
+
+/// Returns the parities of a group of datagrams, with their headers.
+pub fn encode(
+    group: u16,
+    datagrams: &[Vec<u8>],
+    redundancy: Redundancy,
+) -> Vec<(ParityHeader, Vec<u8>)> {
+    if datagrams.is_empty() || datagrams.len() > MAX_GROUP_SIZE {
+        return Vec::new();
+    }
+
+    let size = datagrams.iter().map(Vec::len).max().unwrap_or(0) + LENGTH_SIZE;
+    let shards: Vec<Vec<u8>> = datagrams
+        .iter()
+        .map(|datagram| shard(datagram, size))
+        .collect();
+
+    (0..redundancy.parity_count())
+        .map(|parity_index| {
+            let mut parity = vec![0; size];
+            for (data_index, shard) in shards.iter().enumerate() {
+                let factor =
+                    coefficient(redundancy.is_reed_solomon(), parity_index, data_index as u8);
+                add_scaled(&mut parity, shard, factor);
+            }
+
+            let header = ParityHeader::new(
+                group,
+                datagrams.len() as u8,
+                parity_index,
+                redundancy.is_reed_solomon(),
+            );
+            (header, parity)
+        })
+        .collect()
+}
+
+// Solves `matrix * x = rows` for `x` by Gauss-Jordan elimination, `None` if the matrix is singular.
+fn solve(mut matrix: Vec<Vec<u8>>, mut rows: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    let count = matrix.len();
+    for column in 0..count {
+        let pivot = (column..count).find(|&row| matrix[row][column] != 0)?;
+        matrix.swap(column, pivot);
+        rows.swap(column, pivot);
+
+        let scale = inv(matrix[column][column]);
+        matrix[column]
+            .iter_mut()
+            .for_each(|value| *value = mul(*value, scale));
+        rows[column]
+            .iter_mut()
+            .for_each(|value| *value = mul(*value, scale));
+
+        let (pivot_coefficients, pivot_row) = (matrix[column].clone(), rows[column].clone());
+        for row in (0..count).filter(|&row| row != column) {
+            let factor = matrix[row][column];
+            if factor != 0 {
+                add_scaled(&mut matrix[row], &pivot_coefficients, factor);
+                add_scaled(&mut rows[row], &pivot_row, factor);
+            }
+        }
+    }
+
+    Some(rows)
+}
+
+/// Assigns datagrams to parity groups and computes the parities of the groups, on the sending side of a connection.
+///
+/// Unreliable datagrams are collected in an open group, its parities are returned with its last datagram, or by `flush`
+/// when no more datagrams follow in time. A fragmented packet is a group of its own, see `new_group`.
+pub struct ParityEncoder {
+    group_size: usize,
+    next_group: u16,
+    open: Option<OpenGroup>,
+}
+
+struct OpenGroup {
+    group: u16,
+    datagrams: Vec<Vec<u8>>,
+    redundancy: Redundancy,
+    opened: Instant,
+}
+
+impl ParityEncoder {
+    /// Creates an encoder which closes a group of unreliable datagrams after `group_size` of them.
+    pub fn new(group_size: u8) -> ParityEncoder {
+        ParityEncoder {
+            group_size: usize::from(group_size).clamp(1, MAX_GROUP_SIZE),
+            next_group: 0,
+            open: None,
+        }
+    }
+
+    /// Returns the identifier of a new group.
+    pub fn new_group(&mut self) -> u16 {
+        let group = self.next_group;
+        self.next_group = self.next_group.wrapping_add(1);
+        group
+    }
+
+    /// Returns the group and the index in it of the next unreliable datagram, a group is opened at `time` if none is open.
+    pub fn next_member(&mut self, time: Instant) -> (u16, u8) {
+        if self.open.is_none() {
+            self.open = Some(OpenGroup {
+                group: self.new_group(),
+                datagrams: Vec::new(),
+                redundancy: Redundancy::None,
+                opened: time,
+            });
+        }
+
+        let open = self.open.as_ref().expect("A group was opened above");
+        (open.group, open.datagrams.len() as u8)
+    }
+
+    /// Adds an unreliable datagram, without its trailer, to the open group and returns the parities of the group when it is full.
+    ///
+    /// The group is protected by the most parities any of its datagrams asked for.
+    pub fn add_member(
+        &mut self,
+        datagram: Vec<u8>,
+        redundancy: Redundancy,
+        time: Instant,
+    ) -> Vec<(ParityHeader, Vec<u8>)> {
+        self.next_member(time);
+        let open = self.open.as_mut().expect("A group was opened above");
+        open.datagrams.push(datagram);
+        open.redundancy = open.redundancy.max(redundancy);
+
+        if open.datagrams.len() < self.group_size {
+            return Vec::new();
+        }
+
+        self.flush()
+    }
+
+    /// Returns the time at which the open group is closed by `flush`, once it waited `timeout` for more datagrams.
+    pub fn deadline(&self, timeout: Duration) -> Option<Instant> {
+        self.open
+            .as_ref()
+            .filter(|open| !open.datagrams.is_empty())
+            .map(|open| open.opened + timeout)
+    }
+
+    /// Closes the open group and returns the parities of the datagrams it has so far.
+    pub fn flush(&mut self) -> Vec<(ParityHeader, Vec<u8>)> {
+        match self.open.take() {
+            Some(open) => encode(open.group, &open.datagrams, open.redundancy),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Collects the datagrams and parities of groups and rebuilds lost datagrams, on the receiving side of a connection.
+///
+/// Only the last groups are kept, a group is finished once it is complete or its datagrams are rebuilt.
+#[derive(Default)]
+pub struct ParityDecoder {
+    groups: HashMap<u16, ReceivedGroup>,
+    order: VecDeque<u16>,
+}
+
+#[derive(Default)]
+struct ReceivedGroup {
+    datagrams: HashMap<u8, Vec<u8>>,
+    parities: Vec<(ParityHeader, Vec<u8>)>,
+    rebuilt: Vec<u8>,
+    finished: bool,
+}
+
+impl ParityDecoder {
+    /// Returns whether the datagram with the given index in a group was rebuilt already, so it has to be dropped when it arrives late.
+    pub fn is_rebuilt(&self, group: u16, index: u8) -> bool {
+        self.groups
+            .get(&group)
+            .is_some_and(|received| received.rebuilt.contains(&index))
+    }
+
+    /// Records a datagram of a group, without its trailer, returns the datagrams of the group which can be rebuilt now.
+    pub fn receive_datagram(&mut self, group: u16, index: u8, datagram: &[u8]) -> Vec<Vec<u8>> {
+        let received = self.group_mut(group);
+        if received.finished {
+            return Vec::new();
+        }
+
+        received.datagrams.insert(index, datagram.to_vec());
+        received.rebuild()
+    }
+
+    /// Records a parity of a group, returns the datagrams of the group which can be rebuilt now.
+    ///
+    /// A parity which is too short to hold a shard, or whose group is empty or too large, is ignored.
+    pub fn receive_parity(&mut self, header: ParityHeader, parity: &[u8]) -> Vec<Vec<u8>> {
+        let data_count = usize::from(header.data_count());
+        if parity.len() < LENGTH_SIZE || data_count == 0 || data_count > MAX_GROUP_SIZE {
+            return Vec::new();
+        }
+
+        let received = self.group_mut(header.group());
+        let duplicate = received
+            .parities
+            .iter()
+            .any(|(other, _)| other.index() == header.index());
+        if received.finished || duplicate {
+            return Vec::new();
+        }
+
+        received.parities.push((header, parity.to_vec()));
+        received.rebuild()
+    }
+
+    // Returns the given group, which is added when it is new and the oldest group is forgotten when there are too many.
+    fn group_mut(&mut self, group: u16) -> &mut ReceivedGroup {
+        if !self.groups.contains_key(&group) {
+            if self.order.len() == MAX_GROUPS {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.groups.remove(&oldest);
+                }
+            }
+            self.order.push_back(group);
+        }
+
+        self.groups.entry(group).or_default()
+    }
+}
+
+impl ReceivedGroup {
+    // Rebuilds the lost datagrams once there are as many parities as lost datagrams, which finishes the group.
+    fn rebuild(&mut self) -> Vec<Vec<u8>> {
+        let (header, size) = match self.parities.first() {
+            Some((header, parity)) => (*header, parity.len()),
+            None => return Vec::new(),
+        };
+        // Parities which disagree with the first one about the group can't be used
+        self.parities.retain(|(other, parity)| {
+            other.data_count() == header.data_count()
+                && other.is_reed_solomon() == header.is_reed_solomon()
+                && parity.len() == size
+        });
+
+        let missing: Vec<u8> = (0..header.data_count())
+            .filter(|index| !self.datagrams.contains_key(index))
+            .collect();
+        if missing.len() > self.parities.len() {
+            return Vec::new();
+        }
+        let rebuilt = if missing.is_empty() {
+            Vec::new()
+        } else {
+            self.solve(&missing, size, header).unwrap_or_default()
+        };
+
+        self.finished = true;
+        self.datagrams.clear();
+        self.parities.clear();
+        rebuilt
+    }
+
+    // Solves the parities for the missing datagrams, `None` if a datagram does not fit the parities.
+    fn solve(&mut self, missing: &[u8], size: usize, header: ParityHeader) -> Option<Vec<Vec<u8>>> {
+        let reed_solomon = header.is_reed_solomon();
+        let parities = &self.parities[..missing.len()];
+
+        // Subtracting the received datagrams leaves a sum of the missing ones in each parity
+        let mut rows: Vec<Vec<u8>> = parities.iter().map(|(_, parity)| parity.clone()).collect();
+        for (index, datagram) in self
+            .datagrams
+            .iter()
+            .filter(|(index, _)| **index < header.data_count())
+        {
+            if datagram.len() + LENGTH_SIZE > size {
+                return None;
+            }
+            let shard = shard(datagram, size);
+            for (row, (parity, _)) in rows.iter_mut().zip(parities) {
+                add_scaled(
+                    row,
+                    &shard,
+                    coefficient(reed_solomon, parity.index(), *index),
+                );
+            }
+        }
+
+        let matrix = parities
+            .iter()
+            .map(|(parity, _)| {
+                missing
+                    .iter()
+                    .map(|index| coefficient(reed_solomon, parity.index(), *index))
+                    .collect()
+            })
+            .collect();
+
+        let shards = solve(matrix, rows)?;
+        let mut rebuilt = Vec::with_capacity(missing.len());
+        for (shard, index) in shards.into_iter().zip(missing) {
+            let length = usize::from(BigEndian::read_u16(&shard));
+            if LENGTH_SIZE + length > size {
+                return None;
+            }
+            rebuilt.push(shard[LENGTH_SIZE..LENGTH_SIZE + length].to_vec());
+            self.rebuilt.push(*index);
+        }
+
+        Some(rebuilt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, inv, mul, split_trailer, write_trailer, ParityDecoder, ParityEncoder};
+    use crate::packet::{header::ParityHeader, Redundancy};
+    use std::time::{Duration, Instant};
+
+    fn datagrams() -> Vec<Vec<u8>> {
+        vec![
+            vec![1, 2, 3, 4, 5],
+            vec![6, 7],
+            vec![8, 9, 10, 11, 12, 13, 14],
+            vec![15],
+        ]
+    }
+
+    #[test]
+    fn multiply_and_invert_in_gf256() {
+        assert_eq!(mul(2, 0x80), 0x1d);
+        assert_eq!(mul(7, 0), 0);
+        for value in 1..=255u8 {
+            assert_eq!(mul(value, inv(value)), 1);
+        }
+    }
+
+    #[test]
+    fn rebuild_a_lost_datagram_from_a_xor_parity() {
+        let datagrams = datagrams();
+        let parities = encode(3, &datagrams, Redundancy::Xor);
+        assert_eq!(parities.len(), 1);
+
+        let mut decoder = ParityDecoder::default();
+        for index in [0, 1, 3] {
+            let datagram = &datagrams[index];
+            assert!(decoder
+                .receive_datagram(3, index as u8, datagram)
+                .is_empty());
+        }
+        let (header, parity) = &parities[0];
+        assert_eq!(
+            decoder.receive_parity(*header, parity),
+            vec![datagrams[2].clone()]
+        );
+        assert!(decoder.is_rebuilt(3, 2));
+        assert!(!decoder.is_rebuilt(3, 1));
+    }
+
+    #[test]
+    fn rebuild_lost_datagrams_from_reed_solomon_parities() {
+        let datagrams = datagrams();
+        let parities = encode(0, &datagrams, Redundancy::ReedSolomon(3));
+        assert_eq!(parities.len(), 3);
+
+        // Three of the four datagrams and one parity are lost
+        let mut decoder = ParityDecoder::default();
+        assert!(decoder
+            .receive_parity(parities[2].0, &parities[2].1)
+            .is_empty());
+        assert!(decoder.receive_datagram(0, 1, &datagrams[1]).is_empty());
+        assert!(decoder
+            .receive_parity(parities[0].0, &parities[0].1)
+            .is_empty());
+        let rebuilt = decoder.receive_parity(parities[1].0, &parities[1].1);
+
+        assert_eq!(
+            rebuilt,
+            vec![
+                datagrams[0].clone(),
+                datagrams[2].clone(),
+                datagrams[3].clone()
+            ]
+        );
+    }
+
+    #[test]
+    fn ignore_malformed_parities() {
+        let mut decoder = ParityDecoder::default();
+
+        assert!(decoder
+            .receive_parity(ParityHeader::new(0, 1, 0, false), &[1])
+            .is_empty());
+        assert!(decoder
+            .receive_parity(ParityHeader::new(1, 0, 0, false), &[0; 8])
+            .is_empty());
+        assert!(decoder
+            .receive_parity(ParityHeader::new(2, 200, 0, true), &[0; 8])
+            .is_empty());
+        assert!(decoder.groups.is_empty());
+    }
+
+    #[test]
+    fn ignore_a_group_which_is_complete() {
+        let datagrams = datagrams();
+        let parities = encode(0, &datagrams, Redundancy::Xor);
+
+        let mut decoder = ParityDecoder::default();
+        for (index, datagram) in datagrams.iter().enumerate() {
+            decoder.receive_datagram(0, index as u8, datagram);
+        }
+        assert!(decoder
+            .receive_parity(parities[0].0, &parities[0].1)
+            .is_empty());
+        assert!(!decoder.is_rebuilt(0, 0));
+    }
+
+    #[test]
+    fn return_the_parities_of_a_full_group() {
+        let mut encoder = ParityEncoder::new(2);
+        let now = Instant::now();
+
+        assert_eq!(encoder.next_member(now), (0, 0));
+        assert!(encoder
+            .add_member(vec![1], Redundancy::None, now)
+            .is_empty());
+        assert_eq!(encoder.next_member(now), (0, 1));
+        let parities = encoder.add_member(vec![2, 3], Redundancy::ReedSolomon(2), now);
+        assert_eq!(parities.len(), 2);
+        assert_eq!(parities[0].0.group(), 0);
+        assert_eq!(parities[0].0.data_count(), 2);
+
+        assert_eq!(encoder.new_group(), 1);
+        assert_eq!(encoder.next_member(now), (2, 0));
+    }
+
+    #[test]
+    fn flush_an_open_group_after_its_timeout() {
+        let mut encoder = ParityEncoder::new(4);
+        let now = Instant::now();
+        let timeout = Duration::from_millis(50);
+
+        // A group without datagrams has nothing to flush
+        assert_eq!(encoder.next_member(now), (0, 0));
+        assert_eq!(encoder.deadline(timeout), None);
+
+        assert!(encoder
+            .add_member(vec![1, 2], Redundancy::Xor, now + timeout)
+            .is_empty());
+        assert_eq!(encoder.deadline(timeout), Some(now + timeout));
+
+        let parities = encoder.flush();
+        assert_eq!(parities.len(), 1);
+        assert_eq!(parities[0].0.group(), 0);
+        assert_eq!(parities[0].0.data_count(), 1);
+        assert_eq!(encoder.deadline(timeout), None);
+        assert!(encoder.flush().is_empty());
+    }
+
+    #[test]
+    fn split_what_is_written() {
+        let mut datagram = vec![1, 2, 3];
+        write_trailer(&mut datagram, 258, 7);
+
+        assert_eq!(split_trailer(&datagram), Some((&[1, 2, 3][..], 258, 7)));
+        assert_eq!(split_trailer(&[1, 2]), None);
+    }
+}
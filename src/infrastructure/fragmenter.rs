@@ -5,8 +5,7 @@ use crate::{
     packet::header::FragmentHeader,
     sequence_buffer::{ReassemblyData, SequenceBuffer},
 };
-
-use std::io::Write;
+use std::time::Instant;
 
 /// Type that will manage fragmentation of packets.
 pub struct Fragmentation {
@@ -28,6 +27,7 @@ impl Fragmentation {
     /// For example we have two numbers:
     /// - number 1 = 4000;
     /// - number 2 = 1024;
+    ///
     /// If you do it the easy way the answer will be 4000/1024 = 3.90625.
     /// But since we care about how how many whole times the number fits in we need the result 4.
     ///
@@ -58,7 +58,7 @@ impl Fragmentation {
         } else {
             0
         };
-        ((payload_length / fragment_size) + remainder)
+        (payload_length / fragment_size) + remainder
     }
 
     /// Split the given payload into fragments and write those fragments to the passed packet data.
@@ -93,15 +93,48 @@ impl Fragmentation {
         Ok(fragments)
     }
 
+    /// Returns the number of packets of which we received some, but not all fragments.
+    pub fn packets_in_reassembly(&self) -> usize {
+        self.fragments.entry_count()
+    }
+
+    /// Drops the packets which are still missing fragments after `Config::fragment_reassembly_timeout`, returns how many were dropped.
+    pub fn drop_expired(&mut self, time: Instant) -> usize {
+        let timeout = self.config.fragment_reassembly_timeout;
+        let before = self.fragments.entry_count();
+        self.fragments.retain(|reassembly_data| {
+            reassembly_data
+                .started
+                .is_none_or(|started| time.saturating_duration_since(started) < timeout)
+        });
+
+        before - self.fragments.entry_count()
+    }
+
+    /// Returns the earliest time at which a packet which is still missing fragments is dropped, see `drop_expired`.
+    pub fn expiry_deadline(&self) -> Option<Instant> {
+        self.fragments
+            .iter()
+            .filter_map(|reassembly_data| reassembly_data.started)
+            .min()
+            .map(|started| started + self.config.fragment_reassembly_timeout)
+    }
+
     /// This will read fragment data and return the complete packet when all fragments are received.
     pub fn handle_fragment(
         &mut self,
         fragment_header: FragmentHeader,
         fragment_payload: &[u8],
+        time: Instant,
     ) -> Result<Option<Vec<u8>>> {
         // read fragment packet
+        if fragment_header.fragment_count() > self.config.max_fragments
+            || fragment_header.id() >= fragment_header.fragment_count()
+        {
+            Err(FragmentErrorKind::ExceededMaxFragments)?;
+        }
 
-        self.create_fragment_if_not_exists(fragment_header);
+        self.create_fragment_if_not_exists(fragment_header, time);
 
         let num_fragments_received;
         let num_fragments_total;
@@ -128,18 +161,22 @@ impl Fragmentation {
             reassembly_data.num_fragments_received += 1;
             reassembly_data.fragments_received[usize::from(fragment_header.id())] = true;
 
-            // add the payload from the fragment to the buffer whe have in cache
-            reassembly_data.buffer.write_all(&*fragment_payload)?;
+            // insert the payload after the fragments before it, fragments may arrive in any order or be rebuilt from parities
+            let id = usize::from(fragment_header.id());
+            let offset: usize = reassembly_data.fragment_lengths[..id].iter().sum();
+            reassembly_data.fragment_lengths[id] = fragment_payload.len();
+            reassembly_data
+                .buffer
+                .splice(offset..offset, fragment_payload.iter().copied());
 
             num_fragments_received = reassembly_data.num_fragments_received;
             num_fragments_total = reassembly_data.num_fragments_total;
-            sequence = reassembly_data.sequence as u16;
+            sequence = reassembly_data.sequence;
             total_buffer = reassembly_data.buffer.clone();
         }
 
         // if whe received all fragments then remove entry and return the total received bytes.
         if num_fragments_received == num_fragments_total {
-            let sequence = sequence as u16;
             self.fragments.remove(sequence);
 
             return Ok(Some(total_buffer));
@@ -149,12 +186,13 @@ impl Fragmentation {
     }
 
     /// If fragment does not exist we need to insert a new entry.
-    fn create_fragment_if_not_exists(&mut self, fragment_header: FragmentHeader) {
+    fn create_fragment_if_not_exists(&mut self, fragment_header: FragmentHeader, time: Instant) {
         if !self.fragments.exists(fragment_header.sequence()) {
             let reassembly_data = ReassemblyData::new(
                 fragment_header.sequence(),
                 fragment_header.fragment_count(),
                 (u16::from(FRAGMENT_HEADER_SIZE) + self.config.fragment_size) as usize,
+                time,
             );
 
             self.fragments
@@ -166,6 +204,9 @@ impl Fragmentation {
 #[cfg(test)]
 mod test {
     use super::Fragmentation;
+    use crate::config::Config;
+    use crate::packet::header::FragmentHeader;
+    use std::time::{Duration, Instant};
 
     #[test]
     pub fn expect_right_number_of_fragments() {
@@ -175,4 +216,83 @@ mod test {
         assert_eq!(fragment_number, 4);
         assert_eq!(fragment_number1, 1);
     }
+
+    #[test]
+    pub fn reassemble_fragments_which_arrive_out_of_order() {
+        let config = Config {
+            fragment_size: 4,
+            ..Config::default()
+        };
+        let payload: Vec<u8> = (0..10).collect();
+        let fragments = Fragmentation::spit_into_fragments(&payload, &config).unwrap();
+
+        let time = Instant::now();
+        let mut fragmentation = Fragmentation::new(&config);
+        for id in [2, 0] {
+            let header = FragmentHeader::new(1, id, 3);
+            let reassembled =
+                fragmentation.handle_fragment(header, fragments[usize::from(id)], time);
+            assert_eq!(reassembled.unwrap(), None);
+        }
+        let reassembled =
+            fragmentation.handle_fragment(FragmentHeader::new(1, 1, 3), fragments[1], time);
+
+        assert_eq!(reassembled.unwrap(), Some(payload));
+    }
+
+    #[test]
+    pub fn reject_fragments_beyond_the_fragment_count() {
+        let config = Config {
+            max_fragments: 20,
+            ..Config::default()
+        };
+        let time = Instant::now();
+        let mut fragmentation = Fragmentation::new(&config);
+
+        assert!(fragmentation
+            .handle_fragment(FragmentHeader::new(1, 3, 3), &[1], time)
+            .is_err());
+        assert!(fragmentation
+            .handle_fragment(FragmentHeader::new(2, 0, 21), &[1], time)
+            .is_err());
+        assert_eq!(fragmentation.packets_in_reassembly(), 0);
+        assert_eq!(
+            fragmentation
+                .handle_fragment(FragmentHeader::new(3, 19, 20), &[1], time)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    pub fn drop_packets_which_miss_fragments_after_timeout() {
+        let config = Config {
+            fragment_reassembly_timeout: Duration::from_millis(100),
+            ..Config::default()
+        };
+        let time = Instant::now();
+        let mut fragmentation = Fragmentation::new(&config);
+        let first = fragmentation.handle_fragment(FragmentHeader::new(1, 0, 2), &[1], time);
+        let second = fragmentation.handle_fragment(
+            FragmentHeader::new(2, 0, 2),
+            &[2],
+            time + Duration::from_millis(60),
+        );
+        assert_eq!(first.unwrap(), None);
+        assert_eq!(second.unwrap(), None);
+
+        assert_eq!(
+            fragmentation.drop_expired(time + Duration::from_millis(100)),
+            1
+        );
+        assert_eq!(fragmentation.packets_in_reassembly(), 1);
+
+        // the missing fragment of the dropped packet starts a new reassembly which can not complete
+        let late = fragmentation.handle_fragment(
+            FragmentHeader::new(1, 1, 2),
+            &[1],
+            time + Duration::from_millis(110),
+        );
+        assert_eq!(late.unwrap(), None);
+    }
 }
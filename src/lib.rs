@@ -22,10 +22,11 @@
 #![warn(missing_docs)]
 #![allow(clippy::trivially_copy_pass_by_ref)]
 
+mod compression;
 mod config;
-mod either;
 mod error;
 mod infrastructure;
+mod metrics;
 mod net;
 mod packet;
 mod protocol_version;
@@ -37,7 +38,17 @@ mod throughput;
 #[cfg(feature = "tester")]
 pub use self::throughput::ThroughputMonitoring;
 
+#[cfg(feature = "async")]
+pub use self::net::AsyncSocket;
+
+pub use self::compression::{train_dictionary, Compressor, DictionaryCompressor, Lz4Compressor};
 pub use self::config::Config;
-pub use self::error::{ErrorKind, Result};
-pub use self::net::{LinkConditioner, Socket, SocketEvent};
-pub use self::packet::{DeliveryGuarantee, OrderingGuarantee, Packet};
+pub use self::error::{ErrorKind, ReceiveErrorKind, Result};
+pub use self::metrics::{error_label, InMemoryMetrics, Metrics, PrometheusExporter};
+pub use self::net::{
+    Ban, ConnectionManager, ConnectionStats, Features, LinkConditioner, Negotiated, NetworkQuality,
+    OverflowPolicy, PacketSender, Receiver, ShutdownHandle, Socket, SocketEvent, StreamInfo,
+};
+pub use self::packet::{
+    BufferMut, BufferPool, DeliveryGuarantee, OrderingGuarantee, Packet, Payload, Redundancy,
+};
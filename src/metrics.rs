@@ -0,0 +1,327 @@
+//! This module provides hooks to gather metrics about the socket and its connections.
+//!
+//! Implement [`Metrics`](./trait.Metrics.html) and pass it to `Socket::set_metrics` to be notified of key events.
+//! [`InMemoryMetrics`](./struct.InMemoryMetrics.html) counts all events, and can be rendered in the Prometheus text format
+//! with the [`PrometheusExporter`](./struct.PrometheusExporter.html).
+
+use crate::error::ErrorKind;
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Write},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+};
+
+/// Hooks which are called by the `Socket` on key events.
+///
+/// All hooks do nothing by default, so only the interesting ones have to be implemented.
+/// The socket could be polled on another thread, that is why the hooks take `&self` and the trait requires `Send + Sync`.
+pub trait Metrics: Debug + Send + Sync {
+    /// A datagram of `bytes` bytes was sent to `addr`.
+    fn datagram_sent(&self, _addr: SocketAddr, _bytes: usize) {}
+
+    /// A datagram of `bytes` bytes was received from `addr`.
+    fn datagram_received(&self, _addr: SocketAddr, _bytes: usize) {}
+
+    /// A datagram received from `addr` could not be processed.
+    fn decode_error(&self, _addr: SocketAddr, _error: &ErrorKind) {}
+
+    /// `count` reliable packets to `addr` were resent because they were not acknowledged.
+    fn resent_packets(&self, _addr: SocketAddr, _count: usize) {}
+
+    /// A packet from `addr` was reassembled from its fragments.
+    fn packet_reassembled(&self, _addr: SocketAddr) {}
+
+    /// A datagram was received from `addr`, which we do not have a connection with yet.
+    fn connection_opened(&self, _addr: SocketAddr) {}
+
+    /// The connection with `addr` was dropped because we did not hear from it for too long.
+    fn connection_timed_out(&self, _addr: SocketAddr) {}
+}
+
+/// Returns a short name for the kind of error, which can be used as a label.
+pub fn error_label(error: &ErrorKind) -> &'static str {
+    match error {
+        ErrorKind::DecodingError(_) => "decoding",
+        ErrorKind::FragmentError(_) => "fragment",
+        ErrorKind::PacketError(_) => "packet",
+        ErrorKind::StreamError(_) => "stream",
+        ErrorKind::IOError(_) => "io",
+        ErrorKind::ReceivedDataToShort => "received_data_too_short",
+        ErrorKind::ProtocolVersionMismatch => "protocol_version_mismatch",
+        ErrorKind::ChecksumMismatch => "checksum_mismatch",
+        ErrorKind::SendError(_) => "send",
+        ErrorKind::QueueFull(_) => "queue_full",
+        ErrorKind::CouldNotReadHeader(_) => "could_not_read_header",
+        ErrorKind::SocketOptionError(_) => "socket_option",
+        ErrorKind::ConnectionLimitReached => "connection_limit_reached",
+    }
+}
+
+/// [`Metrics`](./trait.Metrics.html) implementation which keeps a count of every event in memory.
+///
+/// Share it with the socket through an `Arc` and read the counters from any thread.
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    datagrams_sent: AtomicU64,
+    datagrams_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    packets_resent: AtomicU64,
+    fragments_reassembled: AtomicU64,
+    connections_opened: AtomicU64,
+    connections_timed_out: AtomicU64,
+    decode_errors: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl InMemoryMetrics {
+    /// Constructs a new `InMemoryMetrics` with all counters at zero.
+    pub fn new() -> InMemoryMetrics {
+        InMemoryMetrics::default()
+    }
+
+    /// Returns the number of datagrams sent.
+    pub fn datagrams_sent(&self) -> u64 {
+        self.datagrams_sent.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of datagrams received.
+    pub fn datagrams_received(&self) -> u64 {
+        self.datagrams_received.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes sent.
+    pub fn bytes_sent(&self) -> u64 {
+        self.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes received.
+    pub fn bytes_received(&self) -> u64 {
+        self.bytes_received.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of reliable packets that were resent.
+    pub fn packets_resent(&self) -> u64 {
+        self.packets_resent.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of packets that were reassembled from fragments.
+    pub fn fragments_reassembled(&self) -> u64 {
+        self.fragments_reassembled.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of connections that were opened.
+    pub fn connections_opened(&self) -> u64 {
+        self.connections_opened.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of connections that timed out.
+    pub fn connections_timed_out(&self) -> u64 {
+        self.connections_timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of datagrams that could not be processed, by the label of the error, see [`error_label`](./fn.error_label.html).
+    pub fn decode_errors(&self) -> BTreeMap<&'static str, u64> {
+        self.decode_errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl Metrics for InMemoryMetrics {
+    fn datagram_sent(&self, _addr: SocketAddr, bytes: usize) {
+        self.datagrams_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn datagram_received(&self, _addr: SocketAddr, bytes: usize) {
+        self.datagrams_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn decode_error(&self, _addr: SocketAddr, error: &ErrorKind) {
+        *self
+            .decode_errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(error_label(error))
+            .or_insert(0) += 1;
+    }
+
+    fn resent_packets(&self, _addr: SocketAddr, count: usize) {
+        self.packets_resent
+            .fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    fn packet_reassembled(&self, _addr: SocketAddr) {
+        self.fragments_reassembled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn connection_opened(&self, _addr: SocketAddr) {
+        self.connections_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn connection_timed_out(&self, _addr: SocketAddr) {
+        self.connections_timed_out.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Renders [`InMemoryMetrics`](./struct.InMemoryMetrics.html) in the [Prometheus text format](https://prometheus.io/docs/instrumenting/exposition_formats/).
+#[derive(Debug, Clone)]
+pub struct PrometheusExporter {
+    namespace: String,
+}
+
+impl PrometheusExporter {
+    /// Constructs a new `PrometheusExporter` which prefixes every metric with `laminar_`.
+    pub fn new() -> PrometheusExporter {
+        PrometheusExporter::with_namespace("laminar")
+    }
+
+    /// Constructs a new `PrometheusExporter` which prefixes every metric with the given namespace and an underscore.
+    pub fn with_namespace(namespace: &str) -> PrometheusExporter {
+        PrometheusExporter {
+            namespace: namespace.to_owned(),
+        }
+    }
+
+    /// Renders all counters of the given metrics, e.g. to serve them on a `/metrics` endpoint.
+    pub fn render(&self, metrics: &InMemoryMetrics) -> String {
+        let mut output = String::new();
+
+        let counters = [
+            (
+                "datagrams_sent_total",
+                "Number of datagrams sent.",
+                metrics.datagrams_sent(),
+            ),
+            (
+                "datagrams_received_total",
+                "Number of datagrams received.",
+                metrics.datagrams_received(),
+            ),
+            (
+                "bytes_sent_total",
+                "Number of bytes sent.",
+                metrics.bytes_sent(),
+            ),
+            (
+                "bytes_received_total",
+                "Number of bytes received.",
+                metrics.bytes_received(),
+            ),
+            (
+                "packets_resent_total",
+                "Number of reliable packets resent because they were not acknowledged.",
+                metrics.packets_resent(),
+            ),
+            (
+                "fragments_reassembled_total",
+                "Number of packets reassembled from fragments.",
+                metrics.fragments_reassembled(),
+            ),
+            (
+                "connections_opened_total",
+                "Number of connections opened.",
+                metrics.connections_opened(),
+            ),
+            (
+                "connections_timed_out_total",
+                "Number of connections that timed out.",
+                metrics.connections_timed_out(),
+            ),
+        ];
+
+        for (name, help, value) in counters.iter() {
+            self.write_header(&mut output, name, help);
+            writeln!(output, "{}_{} {}", self.namespace, name, value)
+                .expect("writing to a string can't fail");
+        }
+
+        self.write_header(
+            &mut output,
+            "decode_errors_total",
+            "Number of received datagrams that could not be processed.",
+        );
+        for (kind, value) in metrics.decode_errors() {
+            writeln!(
+                output,
+                "{}_decode_errors_total{{kind=\"{}\"}} {}",
+                self.namespace, kind, value
+            )
+            .expect("writing to a string can't fail");
+        }
+
+        output
+    }
+
+    fn write_header(&self, output: &mut String, name: &str, help: &str) {
+        writeln!(output, "# HELP {}_{} {}", self.namespace, name, help)
+            .expect("writing to a string can't fail");
+        writeln!(output, "# TYPE {}_{} counter", self.namespace, name)
+            .expect("writing to a string can't fail");
+    }
+}
+
+impl Default for PrometheusExporter {
+    fn default() -> Self {
+        PrometheusExporter::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InMemoryMetrics, Metrics, PrometheusExporter};
+    use crate::error::{ErrorKind, PacketErrorKind};
+    use std::net::SocketAddr;
+
+    fn address() -> SocketAddr {
+        "127.0.0.1:12345".parse().unwrap()
+    }
+
+    #[test]
+    fn count_events() {
+        let metrics = InMemoryMetrics::new();
+
+        metrics.datagram_sent(address(), 10);
+        metrics.datagram_sent(address(), 20);
+        metrics.datagram_received(address(), 5);
+        metrics.resent_packets(address(), 3);
+        metrics.decode_error(address(), &ErrorKind::ProtocolVersionMismatch);
+        metrics.decode_error(address(), &ErrorKind::ProtocolVersionMismatch);
+
+        assert_eq!(metrics.datagrams_sent(), 2);
+        assert_eq!(metrics.bytes_sent(), 30);
+        assert_eq!(metrics.datagrams_received(), 1);
+        assert_eq!(metrics.bytes_received(), 5);
+        assert_eq!(metrics.packets_resent(), 3);
+        assert_eq!(
+            metrics.decode_errors().get("protocol_version_mismatch"),
+            Some(&2)
+        );
+    }
+
+    #[test]
+    fn render_prometheus_text_format() {
+        let metrics = InMemoryMetrics::new();
+        metrics.datagram_sent(address(), 10);
+        metrics.decode_error(
+            address(),
+            &ErrorKind::PacketError(PacketErrorKind::ExceededMaxPacketSize),
+        );
+
+        let output = PrometheusExporter::with_namespace("game").render(&metrics);
+
+        assert!(output.contains("# HELP game_datagrams_sent_total Number of datagrams sent.\n"));
+        assert!(output.contains("# TYPE game_datagrams_sent_total counter\n"));
+        assert!(output.contains("\ngame_datagrams_sent_total 1\n"));
+        assert!(output.contains("\ngame_bytes_sent_total 10\n"));
+        assert!(output.contains("\ngame_connections_timed_out_total 0\n"));
+        assert!(output.contains("\ngame_decode_errors_total{kind=\"packet\"} 1\n"));
+    }
+}
@@ -1,17 +1,43 @@
 //! This module provides the logic between the low-level abstract types and the types that the user will be interacting with.
 //! You can think of the socket, connection management, congestion control.
 
+mod address;
+mod address_validation;
+#[cfg(feature = "async")]
+mod async_socket;
+mod batch;
+mod channel;
 mod connection;
+mod connection_manager;
+mod connection_stats;
 mod events;
 mod link_conditioner;
+mod negotiation;
+mod packet_sender;
 mod quality;
+mod rate_limiter;
+mod shutdown;
 mod socket;
+mod socket_options;
+mod stream_info;
 mod virtual_connection;
 
 pub mod constants;
 
+#[cfg(feature = "async")]
+pub use self::async_socket::AsyncSocket;
+pub use self::channel::{OverflowPolicy, Receiver};
+pub use self::connection_manager::ConnectionManager;
+pub use self::connection_stats::ConnectionStats;
 pub use self::events::SocketEvent;
 pub use self::link_conditioner::LinkConditioner;
-pub use self::quality::{NetworkQuality, RttMeasurer};
+pub use self::negotiation::{Features, Negotiated};
+pub use self::packet_sender::PacketSender;
+pub use self::quality::{
+    InboundLossMeasurer, JitterMeasurer, LossMeasurer, NetworkQuality, RttMeasurer,
+};
+pub use self::rate_limiter::Ban;
+pub use self::shutdown::ShutdownHandle;
 pub use self::socket::Socket;
+pub use self::stream_info::StreamInfo;
 pub use self::virtual_connection::VirtualConnection;
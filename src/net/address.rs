@@ -0,0 +1,61 @@
+use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6};
+
+/// Returns the canonical form of `address`, which identifies a remote endpoint.
+///
+/// Dual-stack sockets report IPv4 peers with IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`), these are turned into
+/// IPv4 addresses so a peer is a single connection, whichever form it is addressed with.
+pub fn canonical_address(address: SocketAddr) -> SocketAddr {
+    match address {
+        SocketAddr::V6(v6) => match v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::V4(SocketAddrV4::new(ip, v6.port())),
+            None => address,
+        },
+        SocketAddr::V4(_) => address,
+    }
+}
+
+/// Returns the canonical form of `ip`, IPv4-mapped IPv6 addresses are turned into IPv4 addresses like in `canonical_address`.
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    }
+}
+
+/// Returns `address` in the form a socket of the given address family can send to,
+/// IPv4 addresses are mapped into IPv6 for IPv6 sockets.
+pub fn address_for_socket(address: SocketAddr, ipv6_socket: bool) -> SocketAddr {
+    match canonical_address(address) {
+        SocketAddr::V4(v4) if ipv6_socket => {
+            SocketAddr::V6(SocketAddrV6::new(v4.ip().to_ipv6_mapped(), v4.port(), 0, 0))
+        }
+        address => address,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{address_for_socket, canonical_address};
+    use std::net::SocketAddr;
+
+    #[test]
+    fn ipv4_mapped_addresses_are_canonical_ipv4_addresses() {
+        let ipv4 = "127.0.0.1:10001".parse::<SocketAddr>().unwrap();
+        let mapped = "[::ffff:127.0.0.1]:10001".parse::<SocketAddr>().unwrap();
+        let ipv6 = "[::1]:10001".parse::<SocketAddr>().unwrap();
+
+        assert_eq!(canonical_address(mapped), ipv4);
+        assert_eq!(canonical_address(ipv4), ipv4);
+        assert_eq!(canonical_address(ipv6), ipv6);
+    }
+
+    #[test]
+    fn map_ipv4_addresses_for_ipv6_sockets() {
+        let ipv4 = "127.0.0.1:10001".parse::<SocketAddr>().unwrap();
+        let mapped = "[::ffff:127.0.0.1]:10001".parse::<SocketAddr>().unwrap();
+
+        assert_eq!(address_for_socket(ipv4, true), mapped);
+        assert_eq!(address_for_socket(mapped, true), mapped);
+        assert_eq!(address_for_socket(mapped, false), ipv4);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::net::constants::COOKIE_SIZE;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash, Hasher},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+/// A cookie is accepted in the period it was issued in and the following one.
+const COOKIE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Issues and checks the cookies of challenges, see `Config::validate_addresses`.
+///
+/// A cookie is a keyed hash of the address and the current period, so no state is kept for unvalidated peers.
+/// The key is random for every validator, only this validator can issue cookies it accepts.
+#[derive(Debug)]
+pub struct AddressValidator {
+    key: RandomState,
+    epoch: Instant,
+}
+
+impl AddressValidator {
+    pub fn new(epoch: Instant) -> AddressValidator {
+        AddressValidator {
+            key: RandomState::new(),
+            epoch,
+        }
+    }
+
+    /// Returns the cookie for `address` at `time`.
+    pub fn cookie(&self, address: SocketAddr, time: Instant) -> [u8; COOKIE_SIZE] {
+        self.cookie_for_period(address, self.period(time))
+    }
+
+    /// Returns whether `cookie` was issued for `address`, and has not expired at `time`.
+    pub fn is_valid(&self, address: SocketAddr, cookie: &[u8], time: Instant) -> bool {
+        let period = self.period(time);
+        cookie == self.cookie_for_period(address, period)
+            || (period > 0 && cookie == self.cookie_for_period(address, period - 1))
+    }
+
+    fn period(&self, time: Instant) -> u64 {
+        time.saturating_duration_since(self.epoch).as_secs() / COOKIE_PERIOD.as_secs()
+    }
+
+    fn cookie_for_period(&self, address: SocketAddr, period: u64) -> [u8; COOKIE_SIZE] {
+        let mut hasher = self.key.build_hasher();
+        address.hash(&mut hasher);
+        period.hash(&mut hasher);
+        hasher.finish().to_be_bytes()
+    }
+}
+
+/// Mixes the identifier of our connection into a cookie, or takes it out again.
+///
+/// A peer whose address changed returns the cookie mixed with the identifier we gave the connection,
+/// which only the peer of the connection knows, so knowing the identifier of the peer is not enough to take it over.
+pub fn bind_cookie(cookie: &[u8], connection_id: u32) -> Vec<u8> {
+    cookie
+        .iter()
+        .zip(
+            connection_id
+                .to_be_bytes()
+                .iter()
+                .chain(std::iter::repeat(&0)),
+        )
+        .map(|(byte, id_byte)| byte ^ id_byte)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bind_cookie, AddressValidator, COOKIE_PERIOD};
+    use std::{net::SocketAddr, time::Instant};
+
+    #[test]
+    fn accept_cookies_of_the_same_address_until_they_expire() {
+        let address = "127.0.0.1:10001".parse::<SocketAddr>().unwrap();
+        let other_address = "127.0.0.1:10002".parse::<SocketAddr>().unwrap();
+        let now = Instant::now();
+        let validator = AddressValidator::new(now);
+
+        let cookie = validator.cookie(address, now);
+        assert!(validator.is_valid(address, &cookie, now));
+        assert!(validator.is_valid(address, &cookie, now + COOKIE_PERIOD));
+        assert!(!validator.is_valid(address, &cookie, now + 2 * COOKIE_PERIOD));
+        assert!(!validator.is_valid(other_address, &cookie, now));
+        assert!(!validator.is_valid(address, &cookie[1..], now));
+        assert!(!AddressValidator::new(now).is_valid(address, &cookie, now));
+    }
+
+    #[test]
+    fn accept_bound_cookies_only_with_the_same_connection_id() {
+        let address = "127.0.0.1:10003".parse::<SocketAddr>().unwrap();
+        let now = Instant::now();
+        let validator = AddressValidator::new(now);
+
+        let bound = bind_cookie(&validator.cookie(address, now), 7);
+        assert!(!validator.is_valid(address, &bound, now));
+        assert!(!validator.is_valid(address, &bind_cookie(&bound, 8), now));
+        assert!(validator.is_valid(address, &bind_cookie(&bound, 7), now));
+        assert_eq!(bind_cookie(&bound, 0), bound);
+    }
+}
@@ -0,0 +1,276 @@
+use crate::{
+    config::Config,
+    error::{ErrorKind, Result},
+    net::{OverflowPolicy, PacketSender, Receiver, Socket, SocketEvent},
+    packet::Packet,
+};
+use futures::{
+    channel::mpsc::{self, Sender},
+    executor::block_on,
+    future, SinkExt, Stream,
+};
+use log::error;
+use std::{
+    mem,
+    net::{SocketAddr, ToSocketAddrs},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll, Waker},
+    thread,
+    time::Instant,
+};
+
+/// An asynchronous front-end for [Socket], available with the `async` feature.
+///
+/// The socket is driven by a background thread which blocks until a datagram arrives, a packet is sent,
+/// or the next timeout is due, instead of polling on an interval. That is why it works with any async runtime.
+/// Events are received through its `Stream` implementation, the background thread stops when it is dropped.
+///
+/// Events are handed over one at a time, so `Config::event_channel_capacity` and `Config::channel_overflow_policy`
+/// apply to the events which are not received in time, like they do for [Socket].
+#[derive(Debug)]
+pub struct AsyncSocket {
+    local_addr: SocketAddr,
+    packet_sender: PacketSender,
+    policy: OverflowPolicy,
+    // Sends which wait for room in the packet channel, woken each time the socket took packets out of it.
+    waiting_sends: Arc<Mutex<Vec<Waker>>>,
+    event_receiver: mpsc::Receiver<SocketEvent>,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl AsyncSocket {
+    /// Binds to the socket and starts driving it on a background thread.
+    pub fn bind<A: ToSocketAddrs>(addresses: A) -> Result<Self> {
+        AsyncSocket::new(Socket::bind(addresses)?)
+    }
+
+    /// Binds to the socket with the given configuration and starts driving it on a background thread.
+    pub fn bind_with_config<A: ToSocketAddrs>(addresses: A, config: Config) -> Result<Self> {
+        AsyncSocket::new(Socket::bind_with_config(addresses, config)?)
+    }
+
+    /// Binds to any local port on the system, if available, and starts driving it on a background thread.
+    pub fn bind_any() -> Result<Self> {
+        AsyncSocket::new(Socket::bind_any()?)
+    }
+
+    /// Binds to any local port on the system, if available, with a given config and starts driving it on a background thread.
+    pub fn bind_any_with_config(config: Config) -> Result<Self> {
+        AsyncSocket::new(Socket::bind_any_with_config(config)?)
+    }
+
+    /// Starts driving the given socket on a background thread, e.g. after setting a link conditioner or metrics on it.
+    pub fn new(mut socket: Socket) -> Result<Self> {
+        let local_addr = socket.local_addr()?;
+        let packet_sender = socket.get_packet_sender();
+        let policy = socket.config().channel_overflow_policy;
+        // The events wait in the channel of the socket, which applies the capacity and overflow policy of the config
+        let (event_sender, event_receiver) = mpsc::channel(0);
+        {
+            let socket_events = socket.get_event_receiver();
+            thread::spawn(move || forward(socket_events, event_sender));
+        }
+
+        let waiting_sends = Arc::new(Mutex::new(Vec::new()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        {
+            let waiting_sends = waiting_sends.clone();
+            let shutdown = shutdown.clone();
+            thread::spawn(move || drive(socket, &waiting_sends, &shutdown));
+        }
+
+        Ok(AsyncSocket {
+            local_addr,
+            packet_sender,
+            policy,
+            waiting_sends,
+            event_receiver,
+            shutdown,
+        })
+    }
+
+    /// Returns the local address this socket is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Queues the packet and wakes the background thread to send it.
+    ///
+    /// Under `OverflowPolicy::Block` this waits until there is room for the packet, without blocking the thread,
+    /// the other policies are applied right away, see `Socket::send`.
+    pub async fn send(&self, packet: Packet) -> Result<()> {
+        if self.policy != OverflowPolicy::Block {
+            return self.packet_sender.send(packet);
+        }
+
+        let mut packet = Some(packet);
+        future::poll_fn(|cx| {
+            let waiting = packet
+                .take()
+                .expect("The send was polled after it completed");
+            match self.packet_sender.try_send(waiting) {
+                Err(ErrorKind::QueueFull(SocketEvent::Packet(waiting))) => {
+                    self.waiting_sends
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .push(cx.waker().clone());
+                    // The socket may have taken packets out before we were registered
+                    match self.packet_sender.try_send(waiting) {
+                        Err(ErrorKind::QueueFull(SocketEvent::Packet(waiting))) => {
+                            packet = Some(waiting);
+                            Poll::Pending
+                        }
+                        result => Poll::Ready(result),
+                    }
+                }
+                result => Poll::Ready(result),
+            }
+        })
+        .await
+    }
+}
+
+impl Stream for AsyncSocket {
+    type Item = SocketEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<SocketEvent>> {
+        Pin::new(&mut self.event_receiver).poll_next(cx)
+    }
+}
+
+impl Drop for AsyncSocket {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Release);
+        if let Err(e) = self.packet_sender.wake() {
+            error!("Could not wake the socket to shut it down: {:?}", e);
+        }
+    }
+}
+
+// Processes the socket, and blocks until there is new work, until the `AsyncSocket` is dropped.
+fn drive(mut socket: Socket, waiting_sends: &Mutex<Vec<Waker>>, shutdown: &AtomicBool) {
+    loop {
+        socket.manual_poll(Instant::now());
+        wake_waiting_sends(waiting_sends);
+
+        if shutdown.load(Ordering::Acquire) {
+            break;
+        }
+
+        socket.wait_for_activity(None);
+    }
+
+    // Sends which still wait fail once the socket is dropped
+    drop(socket);
+    wake_waiting_sends(waiting_sends);
+}
+
+fn wake_waiting_sends(waiting_sends: &Mutex<Vec<Waker>>) {
+    let wakers = mem::take(&mut *waiting_sends.lock().unwrap_or_else(PoisonError::into_inner));
+    for waker in wakers {
+        waker.wake();
+    }
+}
+
+// Hands the events of the socket over to the `AsyncSocket`, waiting until each one is taken.
+fn forward(socket_events: Receiver<SocketEvent>, mut event_sender: Sender<SocketEvent>) {
+    for event in socket_events.iter() {
+        if block_on(event_sender.send(event)).is_err() {
+            break;
+        }
+    }
+
+    // Events are discarded until the socket is dropped, so it never blocks on a full channel
+    for _ in socket_events.iter() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AsyncSocket;
+    use crate::{Config, OverflowPolicy, Packet, SocketEvent};
+    use futures::{executor::block_on, StreamExt};
+    use std::{net::SocketAddr, time::Duration};
+
+    #[test]
+    fn send_and_receive() {
+        let server_addr = "127.0.0.1:12392".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12393".parse::<SocketAddr>().unwrap();
+
+        let mut server = AsyncSocket::bind(server_addr).unwrap();
+        let client = AsyncSocket::bind(client_addr).unwrap();
+
+        block_on(async {
+            client
+                .send(Packet::reliable_unordered(server_addr, vec![1, 2, 3]))
+                .await
+                .unwrap();
+
+            assert_eq!(server.next().await, Some(SocketEvent::Connect(client_addr)));
+            assert_eq!(
+                server.next().await,
+                Some(SocketEvent::Packet(Packet::reliable_unordered(
+                    client_addr,
+                    vec![1, 2, 3]
+                )))
+            );
+        });
+    }
+
+    #[test]
+    fn wake_up_for_timeouts() {
+        let server_addr = "127.0.0.1:12394".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12395".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            idle_connection_timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut server = AsyncSocket::bind_with_config(server_addr, config).unwrap();
+        let _client = AsyncSocket::bind(client_addr).unwrap();
+
+        block_on(async {
+            server
+                .send(Packet::unreliable(client_addr, vec![]))
+                .await
+                .unwrap();
+
+            assert_eq!(server.next().await, Some(SocketEvent::Timeout(client_addr)));
+        });
+    }
+
+    #[test]
+    fn wait_for_room_in_bounded_channels() {
+        let server_addr = "127.0.0.1:12396".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12397".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            event_channel_capacity: Some(1),
+            packet_channel_capacity: Some(1),
+            channel_overflow_policy: OverflowPolicy::Block,
+            ..Default::default()
+        };
+        let mut server = AsyncSocket::bind_with_config(server_addr, config.clone()).unwrap();
+        let client = AsyncSocket::bind_with_config(client_addr, config).unwrap();
+
+        block_on(async {
+            for payload in 0..20 {
+                client
+                    .send(Packet::reliable_unordered(server_addr, vec![payload]))
+                    .await
+                    .unwrap();
+            }
+
+            assert_eq!(server.next().await, Some(SocketEvent::Connect(client_addr)));
+            for payload in 0..20 {
+                match server.next().await {
+                    Some(SocketEvent::Packet(packet)) => assert_eq!(packet.payload(), [payload]),
+                    event => panic!("Expected a packet, got {:?}", event),
+                }
+            }
+        });
+    }
+}
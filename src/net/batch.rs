@@ -0,0 +1,424 @@
+//! Sending and receiving batches of datagrams, with a single system call per batch on Linux.
+
+use mio::net::UdpSocket;
+use std::{io, net::SocketAddr};
+
+/// Receives up to `buffers.len()` datagrams, the length and the sender of each received datagram is pushed to `received`.
+///
+/// On Linux this is a single `recvmmsg` call, elsewhere a single datagram is received into the first buffer.
+/// Returns an error of kind `WouldBlock` when there was nothing to receive.
+#[cfg(target_os = "linux")]
+pub fn receive_batch(
+    socket: &UdpSocket,
+    buffers: &mut [impl AsMut<[u8]>],
+    received: &mut Vec<(usize, SocketAddr)>,
+) -> io::Result<()> {
+    linux::receive_batch(socket, buffers, received)
+}
+
+/// Receives up to `buffers.len()` datagrams, the length and the sender of each received datagram is pushed to `received`.
+///
+/// On Linux this is a single `recvmmsg` call, elsewhere a single datagram is received into the first buffer.
+/// Returns an error of kind `WouldBlock` when there was nothing to receive.
+#[cfg(not(target_os = "linux"))]
+pub fn receive_batch(
+    socket: &UdpSocket,
+    buffers: &mut [impl AsMut<[u8]>],
+    received: &mut Vec<(usize, SocketAddr)>,
+) -> io::Result<()> {
+    received.push(socket.recv_from(buffers[0].as_mut())?);
+    Ok(())
+}
+
+/// Sends the datagrams, at most `batch_size` of them per system call, and returns the number of bytes sent.
+///
+/// On Linux this uses `sendmmsg`, and with `gso` consecutive datagrams of the same size to the same address are sent
+/// as one message which the kernel or network card splits up (UDP generic segmentation offload).
+/// Elsewhere every datagram is sent with its own call. Datagrams that could not be sent are reported to `on_error`.
+pub fn send_batch(
+    socket: &UdpSocket,
+    datagrams: &[(SocketAddr, impl AsRef<[u8]>)],
+    batch_size: usize,
+    gso: bool,
+    on_error: impl FnMut(SocketAddr, io::Error),
+) -> usize {
+    #[cfg(target_os = "linux")]
+    {
+        if batch_size > 1 {
+            return linux::send_batch(socket, datagrams, batch_size, gso, on_error);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (batch_size, gso);
+
+    send_each(socket, datagrams, on_error)
+}
+
+// Sends every datagram with its own system call.
+fn send_each(
+    socket: &UdpSocket,
+    datagrams: &[(SocketAddr, impl AsRef<[u8]>)],
+    mut on_error: impl FnMut(SocketAddr, io::Error),
+) -> usize {
+    let mut bytes_sent = 0;
+    for (addr, payload) in datagrams {
+        match socket.send_to(payload.as_ref(), addr) {
+            Ok(bytes) => bytes_sent += bytes,
+            Err(e) => on_error(*addr, e),
+        }
+    }
+    bytes_sent
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::send_each;
+    use crate::net::constants::{MAX_BATCH_SIZE, MAX_GSO_SEGMENTS};
+    use mio::net::UdpSocket;
+    use std::{
+        io, mem,
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+        os::unix::io::AsRawFd,
+        ptr,
+    };
+
+    // Not every target of the libc crate defines this option yet.
+    const UDP_SEGMENT: libc::c_int = 103;
+    // Large enough for a control message with the u16 segment size.
+    const CONTROL_WORDS: usize = 4;
+    // The maximal size of a UDP payload.
+    const MAX_GSO_BYTES: usize = 65_507;
+
+    pub fn receive_batch(
+        socket: &UdpSocket,
+        buffers: &mut [impl AsMut<[u8]>],
+        received: &mut Vec<(usize, SocketAddr)>,
+    ) -> io::Result<()> {
+        let count = buffers.len().min(MAX_BATCH_SIZE);
+
+        // This is all zeroed C data, for which zero is a valid value.
+        let mut addresses: [libc::sockaddr_storage; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+        let mut iovecs: [libc::iovec; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+        let mut messages: [libc::mmsghdr; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+
+        for (((buffer, iovec), address), message) in buffers
+            .iter_mut()
+            .zip(iovecs.iter_mut())
+            .zip(addresses.iter_mut())
+            .zip(messages.iter_mut())
+        {
+            let buffer = buffer.as_mut();
+            iovec.iov_base = buffer.as_mut_ptr() as *mut libc::c_void;
+            iovec.iov_len = buffer.len();
+            message.msg_hdr.msg_name = address as *mut _ as *mut libc::c_void;
+            message.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+            message.msg_hdr.msg_iov = iovec;
+            message.msg_hdr.msg_iovlen = 1;
+        }
+
+        // The messages point to buffers which outlive this call.
+        let result = unsafe {
+            libc::recvmmsg(
+                socket.as_raw_fd(),
+                messages.as_mut_ptr(),
+                count as _,
+                libc::MSG_DONTWAIT as _,
+                ptr::null_mut(),
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        for (message, address) in messages.iter().zip(addresses.iter()).take(result as usize) {
+            received.push((message.msg_len as usize, from_raw_address(address)?));
+        }
+
+        Ok(())
+    }
+
+    pub fn send_batch(
+        socket: &UdpSocket,
+        datagrams: &[(SocketAddr, impl AsRef<[u8]>)],
+        batch_size: usize,
+        gso: bool,
+        mut on_error: impl FnMut(SocketAddr, io::Error),
+    ) -> usize {
+        let batch_size = batch_size.min(MAX_BATCH_SIZE);
+        let mut bytes_sent = 0;
+        let mut next = 0;
+
+        while next < datagrams.len() {
+            let batch = &datagrams[next..datagrams.len().min(next + batch_size)];
+            let groups = group_datagrams(batch, gso);
+
+            match send_groups(socket, batch, &groups) {
+                Ok((sent_groups, bytes)) => {
+                    bytes_sent += bytes;
+                    next += groups[..sent_groups]
+                        .iter()
+                        .map(|(_, len)| len)
+                        .sum::<usize>();
+                }
+                Err(e) => {
+                    // The first group could not be sent, try its datagrams separately in case segmentation offload is not supported.
+                    let (start, len) = groups[0];
+                    if len > 1 && e.kind() != io::ErrorKind::WouldBlock {
+                        bytes_sent += send_each(socket, &batch[start..start + len], &mut on_error);
+                    } else if len == 1 {
+                        on_error(batch[start].0, e);
+                    } else {
+                        for (addr, _) in &batch[start..start + len] {
+                            on_error(*addr, io::Error::from(e.kind()));
+                        }
+                    }
+                    next += len;
+                }
+            }
+        }
+
+        bytes_sent
+    }
+
+    // Splits the datagrams in groups which are sent as one message, every group is a `(start, length)` pair.
+    fn group_datagrams(batch: &[(SocketAddr, impl AsRef<[u8]>)], gso: bool) -> Vec<(usize, usize)> {
+        let mut groups: Vec<(usize, usize)> = Vec::with_capacity(batch.len());
+
+        for (index, (addr, payload)) in batch.iter().enumerate() {
+            if let Some((start, len)) = groups.last_mut() {
+                let (first_addr, first_payload) = &batch[*start];
+                let first_payload = first_payload.as_ref();
+                let last_payload = batch[*start + *len - 1].1.as_ref();
+                // All segments except the last have to be of the same size
+                let fits = gso
+                    && addr == first_addr
+                    && last_payload.len() == first_payload.len()
+                    && payload.as_ref().len() <= first_payload.len()
+                    && *len < MAX_GSO_SEGMENTS
+                    && (*len + 1) * first_payload.len() <= MAX_GSO_BYTES;
+                if fits {
+                    *len += 1;
+                    continue;
+                }
+            }
+            groups.push((index, 1));
+        }
+
+        groups
+    }
+
+    // Sends the groups with `sendmmsg`, returns how many groups and bytes were sent.
+    fn send_groups(
+        socket: &UdpSocket,
+        batch: &[(SocketAddr, impl AsRef<[u8]>)],
+        groups: &[(usize, usize)],
+    ) -> io::Result<(usize, usize)> {
+        // This is all zeroed C data, for which zero is a valid value.
+        let mut addresses: [libc::sockaddr_storage; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+        let mut iovecs: [libc::iovec; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+        let mut controls = [[0u64; CONTROL_WORDS]; MAX_BATCH_SIZE];
+        let mut messages: [libc::mmsghdr; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+
+        for (iovec, (_, payload)) in iovecs.iter_mut().zip(batch.iter()) {
+            let payload = payload.as_ref();
+            iovec.iov_base = payload.as_ptr() as *mut libc::c_void;
+            iovec.iov_len = payload.len();
+        }
+
+        for (index, &(start, len)) in groups.iter().enumerate() {
+            let (addr, payload) = &batch[start];
+            let message = &mut messages[index].msg_hdr;
+
+            message.msg_namelen = to_raw_address(addr, &mut addresses[index]);
+            message.msg_name = &mut addresses[index] as *mut _ as *mut libc::c_void;
+            message.msg_iov = &mut iovecs[start];
+            message.msg_iovlen = len as _;
+
+            if len > 1 {
+                let control = &mut controls[index];
+                message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+                message.msg_controllen = mem::size_of_val(control) as _;
+                // The control buffer is large and aligned enough for one u16 control message.
+                unsafe {
+                    let header = libc::CMSG_FIRSTHDR(message);
+                    (*header).cmsg_level = libc::SOL_UDP;
+                    (*header).cmsg_type = UDP_SEGMENT;
+                    (*header).cmsg_len = libc::CMSG_LEN(mem::size_of::<u16>() as _) as _;
+                    ptr::write_unaligned(
+                        libc::CMSG_DATA(header) as *mut u16,
+                        payload.as_ref().len() as u16,
+                    );
+                    message.msg_controllen = libc::CMSG_SPACE(mem::size_of::<u16>() as _) as _;
+                }
+            }
+        }
+
+        // The messages point to buffers which outlive this call.
+        let result = unsafe {
+            libc::sendmmsg(
+                socket.as_raw_fd(),
+                messages.as_mut_ptr(),
+                groups.len() as _,
+                0,
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let sent_groups = result as usize;
+        let bytes = messages[..sent_groups]
+            .iter()
+            .map(|message| message.msg_len as usize)
+            .sum();
+        Ok((sent_groups, bytes))
+    }
+
+    fn from_raw_address(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                // The kernel wrote a `sockaddr_in` for this family.
+                let address = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+                Ok(SocketAddr::V4(SocketAddrV4::new(
+                    Ipv4Addr::from(u32::from_be(address.sin_addr.s_addr)),
+                    u16::from_be(address.sin_port),
+                )))
+            }
+            libc::AF_INET6 => {
+                // The kernel wrote a `sockaddr_in6` for this family.
+                let address = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+                Ok(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(address.sin6_addr.s6_addr),
+                    u16::from_be(address.sin6_port),
+                    address.sin6_flowinfo,
+                    address.sin6_scope_id,
+                )))
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Received a datagram from an unsupported address family.",
+            )),
+        }
+    }
+
+    fn to_raw_address(addr: &SocketAddr, storage: &mut libc::sockaddr_storage) -> libc::socklen_t {
+        match addr {
+            SocketAddr::V4(addr) => {
+                // `sockaddr_storage` is large and aligned enough for any address.
+                let raw = unsafe { &mut *(storage as *mut _ as *mut libc::sockaddr_in) };
+                raw.sin_family = libc::AF_INET as libc::sa_family_t;
+                raw.sin_port = addr.port().to_be();
+                raw.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+                mem::size_of::<libc::sockaddr_in>() as libc::socklen_t
+            }
+            SocketAddr::V6(addr) => {
+                // `sockaddr_storage` is large and aligned enough for any address.
+                let raw = unsafe { &mut *(storage as *mut _ as *mut libc::sockaddr_in6) };
+                raw.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                raw.sin6_port = addr.port().to_be();
+                raw.sin6_addr.s6_addr = addr.ip().octets();
+                raw.sin6_flowinfo = addr.flowinfo();
+                raw.sin6_scope_id = addr.scope_id();
+                mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{receive_batch, send_batch};
+    use mio::net::UdpSocket;
+    use std::{io, net::SocketAddr, thread::sleep, time::Duration};
+
+    fn bind(addr: &str) -> UdpSocket {
+        UdpSocket::bind(&addr.parse::<SocketAddr>().unwrap()).unwrap()
+    }
+
+    fn receive_all(socket: &UdpSocket) -> Vec<(SocketAddr, Vec<u8>)> {
+        sleep(Duration::from_millis(50));
+
+        let mut buffers = vec![vec![0; 1500]; 8];
+        let mut received = Vec::new();
+        let mut datagrams = Vec::new();
+        loop {
+            received.clear();
+            match receive_batch(socket, &mut buffers, &mut received) {
+                Ok(()) => {
+                    for (index, &(len, addr)) in received.iter().enumerate() {
+                        datagrams.push((addr, buffers[index][..len].to_vec()));
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return datagrams,
+                Err(e) => panic!("Could not receive: {:?}", e),
+            }
+        }
+    }
+
+    fn datagrams(to: SocketAddr, payloads: &[&[u8]]) -> Vec<(SocketAddr, Box<[u8]>)> {
+        payloads
+            .iter()
+            .map(|payload| (to, payload.to_vec().into_boxed_slice()))
+            .collect()
+    }
+
+    #[test]
+    fn send_and_receive_batch() {
+        let sender = bind("127.0.0.1:12400");
+        let receiver = bind("127.0.0.1:12401");
+        let sender_addr = sender.local_addr().unwrap();
+        let payloads: &[&[u8]] = &[
+            &[1, 2],
+            &[3],
+            &[4, 5, 6],
+            &[7],
+            &[8],
+            &[9],
+            &[10],
+            &[11],
+            &[12],
+        ];
+
+        let bytes = send_batch(
+            &sender,
+            &datagrams(receiver.local_addr().unwrap(), payloads),
+            4,
+            false,
+            |_, e| panic!("Could not send: {:?}", e),
+        );
+
+        assert_eq!(bytes, 12);
+        assert_eq!(
+            receive_all(&receiver),
+            payloads
+                .iter()
+                .map(|payload| (sender_addr, payload.to_vec()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn send_segments_of_equal_size() {
+        let sender = bind("127.0.0.1:12402");
+        let receiver = bind("127.0.0.1:12403");
+        let payloads: &[&[u8]] = &[&[1, 1], &[2, 2], &[3]];
+
+        let bytes = send_batch(
+            &sender,
+            &datagrams(receiver.local_addr().unwrap(), payloads),
+            32,
+            true,
+            |_, e| panic!("Could not send: {:?}", e),
+        );
+
+        // The receiver gets the segments as separate datagrams, whether or not offloading is supported
+        assert_eq!(bytes, 5);
+        assert_eq!(
+            receive_all(&receiver)
+                .into_iter()
+                .map(|(_, payload)| payload)
+                .collect::<Vec<_>>(),
+            vec![vec![1, 1], vec![2, 2], vec![3]]
+        );
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{
+    net::SocketEvent,
+    packet::{DeliveryGuarantee, Packet},
+};
+use crossbeam_channel::{RecvError, RecvTimeoutError, TryRecvError, TrySendError};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Specifies what happens when a packet or event is queued on a full channel, see `Config::channel_overflow_policy`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Blocks until there is room in the channel.
+    ///
+    /// Only use this when the channel is drained by another thread than the one queueing on it, otherwise it blocks forever.
+    Block,
+    /// Drops the oldest queued unreliable packet to make room, or the new one if it is unreliable and no other is queued.
+    ///
+    /// Fails with `ErrorKind::QueueFull` when only reliable packets or other events are queued.
+    DropOldestUnreliable,
+    /// Fails with `ErrorKind::QueueFull`, which returns the packet or event that could not be queued.
+    #[default]
+    Error,
+}
+
+/// Items which are unreliable may be dropped when a channel overflows.
+pub trait Unreliable {
+    /// Returns whether this is an unreliable packet.
+    fn is_unreliable(&self) -> bool;
+}
+
+impl Unreliable for Packet {
+    fn is_unreliable(&self) -> bool {
+        self.delivery_guarantee() == DeliveryGuarantee::Unreliable
+    }
+}
+
+impl Unreliable for SocketEvent {
+    fn is_unreliable(&self) -> bool {
+        match self {
+            SocketEvent::Packet(packet) => packet.is_unreliable(),
+            _ => false,
+        }
+    }
+}
+
+// The state of a channel, which is shared by its senders and receivers.
+struct Shared<T> {
+    state: Mutex<State<T>>,
+    // Notified when an item is queued or the last sender is dropped.
+    queued: Condvar,
+    // Notified when an item is taken out or the last receiver is dropped.
+    taken: Condvar,
+    capacity: Option<usize>,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    senders: usize,
+    receivers: usize,
+}
+
+impl<T> Shared<T> {
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn is_full(&self, state: &State<T>) -> bool {
+        self.capacity
+            .is_some_and(|capacity| state.items.len() >= capacity)
+    }
+}
+
+/// The sending half of a channel, which applies an `OverflowPolicy` when the channel is full.
+pub struct PolicySender<T> {
+    shared: Arc<Shared<T>>,
+    policy: OverflowPolicy,
+}
+
+/// The receiving half of a channel, e.g. the events of a `Socket`, see `Socket::get_event_receiver`.
+///
+/// It can be cloned, each item is received by one of the receivers.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Creates a channel which holds at most `capacity` items, or any number of items if it is `None`.
+pub fn channel<T: Unreliable>(
+    capacity: Option<usize>,
+    policy: OverflowPolicy,
+) -> (PolicySender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            items: VecDeque::new(),
+            senders: 1,
+            receivers: 1,
+        }),
+        queued: Condvar::new(),
+        taken: Condvar::new(),
+        capacity,
+    });
+
+    (
+        PolicySender {
+            shared: shared.clone(),
+            policy,
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T: Unreliable> PolicySender<T> {
+    /// Queues the item, applying the overflow policy when the channel is full.
+    ///
+    /// Fails with `TrySendError::Disconnected` when all receivers were dropped.
+    pub fn send(&self, item: T) -> Result<(), TrySendError<T>> {
+        self.queue(item, self.policy)
+    }
+
+    /// Queues the item like `send`, but fails with `TrySendError::Full` instead of blocking under `OverflowPolicy::Block`.
+    #[cfg(feature = "async")]
+    pub fn try_send(&self, item: T) -> Result<(), TrySendError<T>> {
+        match self.policy {
+            OverflowPolicy::Block => self.queue(item, OverflowPolicy::Error),
+            policy => self.queue(item, policy),
+        }
+    }
+
+    // Queues the item, the oldest unreliable item is dropped in place, so the other items are not touched.
+    fn queue(&self, item: T, policy: OverflowPolicy) -> Result<(), TrySendError<T>> {
+        let mut state = self.shared.lock();
+        loop {
+            if state.receivers == 0 {
+                return Err(TrySendError::Disconnected(item));
+            }
+            if !self.shared.is_full(&state) {
+                break;
+            }
+
+            match policy {
+                OverflowPolicy::Block => {
+                    state = self
+                        .shared
+                        .taken
+                        .wait(state)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+                OverflowPolicy::DropOldestUnreliable => {
+                    match state.items.iter().position(Unreliable::is_unreliable) {
+                        Some(oldest_unreliable) => {
+                            state.items.remove(oldest_unreliable);
+                            break;
+                        }
+                        None if item.is_unreliable() => return Ok(()),
+                        None => return Err(TrySendError::Full(item)),
+                    }
+                }
+                OverflowPolicy::Error => return Err(TrySendError::Full(item)),
+            }
+        }
+
+        state.items.push_back(item);
+        self.shared.queued.notify_one();
+        Ok(())
+    }
+}
+
+impl<T> Clone for PolicySender<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        PolicySender {
+            shared: self.shared.clone(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl<T> Drop for PolicySender<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.shared.queued.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for PolicySender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PolicySender")
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Takes the oldest item out of the channel, without waiting for one.
+    ///
+    /// Fails with `TryRecvError::Disconnected` when the channel is empty and all senders were dropped.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match self.take(&mut state) {
+            Some(item) => Ok(item),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    /// Takes the oldest item out of the channel, waiting until there is one.
+    ///
+    /// Fails with `RecvError` when the channel is empty and all senders were dropped.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(item) = self.take(&mut state) {
+                return Ok(item);
+            }
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+
+            state = self
+                .shared
+                .queued
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Takes the oldest item out of the channel, waiting at most `timeout` until there is one.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(item) = self.take(&mut state) {
+                return Ok(item);
+            }
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+            state = self
+                .shared
+                .queued
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Returns an iterator which takes the items out of the channel until it is empty.
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.try_recv().ok())
+    }
+
+    /// Returns an iterator which waits for each next item, until the channel is empty and all senders were dropped.
+    pub fn iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(move || self.recv().ok())
+    }
+
+    /// Returns the number of items in the channel.
+    pub fn len(&self) -> usize {
+        self.shared.lock().items.len()
+    }
+
+    /// Returns whether the channel is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn take(&self, state: &mut State<T>) -> Option<T> {
+        let item = state.items.pop_front()?;
+        self.shared.taken.notify_one();
+        Some(item)
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.shared.lock().receivers += 1;
+        Receiver {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.receivers -= 1;
+        if state.receivers == 0 {
+            self.shared.taken.notify_all();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("Receiver { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{channel, OverflowPolicy};
+    use crate::packet::Packet;
+    use crossbeam_channel::{TryRecvError, TrySendError};
+    use std::{net::SocketAddr, thread};
+
+    fn addr() -> SocketAddr {
+        "127.0.0.1:10001".parse().unwrap()
+    }
+
+    #[test]
+    fn fail_when_full() {
+        let (sender, receiver) = channel(Some(1), OverflowPolicy::Error);
+
+        sender.send(Packet::unreliable(addr(), vec![1])).unwrap();
+        assert_eq!(
+            sender.send(Packet::unreliable(addr(), vec![2])),
+            Err(TrySendError::Full(Packet::unreliable(addr(), vec![2])))
+        );
+        assert_eq!(receiver.len(), 1);
+    }
+
+    #[test]
+    fn drop_oldest_unreliable_packet_when_full() {
+        let (sender, receiver) = channel(Some(3), OverflowPolicy::DropOldestUnreliable);
+
+        sender
+            .send(Packet::reliable_unordered(addr(), vec![1]))
+            .unwrap();
+        sender.send(Packet::unreliable(addr(), vec![2])).unwrap();
+        sender.send(Packet::unreliable(addr(), vec![3])).unwrap();
+        sender
+            .send(Packet::reliable_unordered(addr(), vec![4]))
+            .unwrap();
+
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![
+                Packet::reliable_unordered(addr(), vec![1]),
+                Packet::unreliable(addr(), vec![3]),
+                Packet::reliable_unordered(addr(), vec![4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn keep_reliable_packets_when_full() {
+        let (sender, receiver) = channel(Some(1), OverflowPolicy::DropOldestUnreliable);
+
+        sender
+            .send(Packet::reliable_unordered(addr(), vec![1]))
+            .unwrap();
+        // The new packet is the oldest unreliable one
+        sender.send(Packet::unreliable(addr(), vec![2])).unwrap();
+        assert_eq!(
+            sender.send(Packet::reliable_unordered(addr(), vec![3])),
+            Err(TrySendError::Full(Packet::reliable_unordered(
+                addr(),
+                vec![3]
+            )))
+        );
+
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![Packet::reliable_unordered(addr(), vec![1])]
+        );
+    }
+
+    #[test]
+    fn block_until_there_is_room() {
+        let (sender, receiver) = channel(Some(1), OverflowPolicy::Block);
+
+        let sending = thread::spawn(move || {
+            for payload in 0..10 {
+                sender
+                    .send(Packet::unreliable(addr(), vec![payload]))
+                    .unwrap();
+            }
+        });
+
+        let payloads: Vec<u8> = receiver.iter().map(|packet| packet.payload()[0]).collect();
+        assert_eq!(payloads, (0..10).collect::<Vec<_>>());
+        sending.join().unwrap();
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+    }
+}
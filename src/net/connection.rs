@@ -1,7 +1,7 @@
-pub use crate::net::{NetworkQuality, RttMeasurer, VirtualConnection};
+pub use crate::net::VirtualConnection;
 
 use crate::config::Config;
-use crate::either::Either::{self, Left, Right};
+use crate::net::address::canonical_address;
 use std::{
     collections::HashMap,
     net::SocketAddr,
@@ -10,15 +10,21 @@ use std::{
 
 /// Maintains a registry of active "connections". Essentially, when we receive a packet on the
 /// socket from a particular `SocketAddr`, we will track information about it here.
+///
+/// Connections are looked up by the canonical form of an address, so an IPv4 peer is the same connection
+/// whether it is addressed with its IPv4 address or the IPv4-mapped IPv6 address of a dual-stack socket.
+/// They can also be looked up by the identifier the remote endpoint gave them, which stays the same when its address changes.
 #[derive(Debug)]
 pub struct ActiveConnections {
     connections: HashMap<SocketAddr, VirtualConnection>,
+    remote_connection_ids: HashMap<u32, SocketAddr>,
 }
 
 impl ActiveConnections {
     pub fn new() -> Self {
         Self {
             connections: HashMap::new(),
+            remote_connection_ids: HashMap::new(),
         }
     }
 
@@ -30,24 +36,20 @@ impl ActiveConnections {
         config: &Config,
         time: Instant,
     ) -> &mut VirtualConnection {
+        let address = canonical_address(address);
         self.connections
             .entry(address)
             .or_insert_with(|| VirtualConnection::new(address, config, time))
     }
 
-    /// Try to get or create a [VirtualConnection] by address. If the connection does not exist, it will be
-    /// created and returned, but not inserted into the table of active connections.
-    pub(crate) fn get_or_create_connection(
-        &mut self,
-        address: SocketAddr,
-        config: &Config,
-        time: Instant,
-    ) -> Either<&mut VirtualConnection, VirtualConnection> {
-        if let Some(connection) = self.connections.get_mut(&address) {
-            Left(connection)
-        } else {
-            Right(VirtualConnection::new(address, config, time))
-        }
+    /// Try to get a `VirtualConnection` by address.
+    pub fn get_connection(&self, address: &SocketAddr) -> Option<&VirtualConnection> {
+        self.connections.get(&canonical_address(*address))
+    }
+
+    /// Try to get a mutable `VirtualConnection` by address.
+    pub fn get_connection_mut(&mut self, address: &SocketAddr) -> Option<&mut VirtualConnection> {
+        self.connections.get_mut(&canonical_address(*address))
     }
 
     /// Removes the connection from `ActiveConnections` by socket address.
@@ -55,7 +57,58 @@ impl ActiveConnections {
         &mut self,
         address: &SocketAddr,
     ) -> Option<(SocketAddr, VirtualConnection)> {
-        self.connections.remove_entry(address)
+        let removed = self.connections.remove_entry(&canonical_address(*address));
+        if let Some((address, connection)) = &removed {
+            self.forget_remote_connection_id(connection, *address);
+        }
+        removed
+    }
+
+    /// Records the identifier the remote endpoint at `address` gave its connection.
+    ///
+    /// A new identifier replaces the previous one, e.g. when the remote endpoint created its connection again.
+    pub fn record_remote_connection_id(&mut self, address: &SocketAddr, id: u32) {
+        let address = canonical_address(*address);
+        if let Some(connection) = self.connections.get_mut(&address) {
+            if id != 0 && connection.remote_connection_id() != Some(id) {
+                let previous = connection.remote_connection_id();
+                connection.set_remote_connection_id(id);
+                if let Some(previous) = previous {
+                    if self.remote_connection_ids.get(&previous) == Some(&address) {
+                        self.remote_connection_ids.remove(&previous);
+                    }
+                }
+                self.remote_connection_ids.entry(id).or_insert(address);
+            }
+        }
+    }
+
+    /// Returns the address of the connection to which the remote endpoint gave the identifier `id`.
+    pub fn find_by_remote_connection_id(&self, id: u32) -> Option<SocketAddr> {
+        self.remote_connection_ids.get(&id).copied()
+    }
+
+    /// Moves the connection with `old` to `new`, keeping all its state.
+    ///
+    /// Returns false if there is no connection with `old`, or there already is one with `new`.
+    pub fn migrate_connection(&mut self, old: &SocketAddr, new: &SocketAddr) -> bool {
+        let new = canonical_address(*new);
+        if self.connections.contains_key(&new) {
+            return false;
+        }
+
+        match self.connections.remove_entry(&canonical_address(*old)) {
+            Some((old, mut connection)) => {
+                self.forget_remote_connection_id(&connection, old);
+                connection.remote_address = new;
+                if let Some(id) = connection.remote_connection_id() {
+                    self.remote_connection_ids.entry(id).or_insert(new);
+                }
+                self.connections.insert(new, connection);
+                true
+            }
+            None => false,
+        }
     }
 
     /// Check for and return `VirtualConnection`s which have been idling longer than `max_idle_time`.
@@ -67,16 +120,34 @@ impl ActiveConnections {
             .collect()
     }
 
+    /// Returns an iterator over all connections.
+    pub fn iter(&self) -> impl Iterator<Item = &VirtualConnection> {
+        self.connections.values()
+    }
+
+    /// Returns a mutable iterator over all connections.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut VirtualConnection> {
+        self.connections.values_mut()
+    }
+
     /// Returns true if the given connection exists.
     pub fn exists(&self, address: &SocketAddr) -> bool {
-        self.connections.contains_key(&address)
+        self.connections.contains_key(&canonical_address(*address))
     }
 
     /// Returns the number of connected clients.
-    #[cfg(test)]
-    pub(crate) fn count(&self) -> usize {
+    pub fn count(&self) -> usize {
         self.connections.len()
     }
+
+    // Removes the identifier of a connection from the index, unless it belongs to another connection.
+    fn forget_remote_connection_id(&mut self, connection: &VirtualConnection, address: SocketAddr) {
+        if let Some(id) = connection.remote_connection_id() {
+            if self.remote_connection_ids.get(&id) == Some(&address) {
+                self.remote_connection_ids.remove(&id);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -84,7 +155,6 @@ mod tests {
     use super::{ActiveConnections, Config};
     use std::{
         sync::Arc,
-        thread,
         time::{Duration, Instant},
     };
 
@@ -156,6 +226,34 @@ mod tests {
         assert!(!connections.connections.contains_key(&address));
     }
 
+    #[test]
+    fn migrate_connection_to_a_new_address() {
+        let mut connections = ActiveConnections::new();
+        let config = Config::default();
+
+        let old = ADDRESS.parse().unwrap();
+        let new = "127.0.0.1:12346".parse().unwrap();
+        connections.get_or_insert_connection(old, &config, Instant::now());
+        connections.record_remote_connection_id(&old, 7);
+        assert_eq!(connections.find_by_remote_connection_id(7), Some(old));
+
+        assert!(connections.migrate_connection(&old, &new));
+        assert!(!connections.exists(&old));
+        assert_eq!(
+            connections.get_connection(&new).unwrap().remote_address,
+            new
+        );
+        assert_eq!(connections.find_by_remote_connection_id(7), Some(new));
+
+        // the remote endpoint created its connection again
+        connections.record_remote_connection_id(&new, 8);
+        assert_eq!(connections.find_by_remote_connection_id(7), None);
+        assert_eq!(connections.find_by_remote_connection_id(8), Some(new));
+
+        connections.remove_connection(&new);
+        assert_eq!(connections.find_by_remote_connection_id(8), None);
+    }
+
     #[test]
     fn remove_non_existent_connection() {
         let mut connections = ActiveConnections::new();
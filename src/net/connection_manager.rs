@@ -0,0 +1,2667 @@
+use crate::{
+    compression::Compression,
+    config::Config,
+    error::{ErrorKind, ReceiveErrorKind, Result, StreamErrorKind},
+    infrastructure::SentPacket,
+    metrics::Metrics,
+    net::{
+        address::{canonical_address, canonical_ip},
+        address_validation::{bind_cookie, AddressValidator},
+        channel::{channel, PolicySender, Receiver},
+        connection::ActiveConnections,
+        constants::{
+            CHECKSUM_SIZE, MAX_ANONYMOUS_PEERS, MAX_ERROR_COUNTERS, MAX_REJECTED_PEERS,
+            PROTOCOL_VERSION_SIZE, STANDARD_HEADER_SIZE,
+        },
+        events::SocketEvent,
+        link_conditioner::LinkConditioner,
+        negotiation::Hello,
+        rate_limiter::RateLimiter,
+        Ban, ConnectionStats, StreamInfo, VirtualConnection,
+    },
+    packet::{
+        header::StandardHeader, BufferPool, DeliveryGuarantee, OrderingGuarantee, Outgoing,
+        OutgoingPacket, OutgoingPacketBuilder, Packet, PacketReader, PacketType, Payload,
+        StreamCommand,
+    },
+    protocol_version::ProtocolVersion,
+};
+use byteorder::{BigEndian, ByteOrder};
+use crossbeam_channel::TryRecvError;
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The protocol state of laminar without any IO, it keeps track of all connections with remote endpoints.
+///
+/// This allows to run laminar on top of any transport that can deliver datagrams, `Socket` wraps it around a UDP socket.
+/// - Datagrams received from the transport are fed with `handle_datagram`.
+/// - Packets are sent with `send`, the resulting datagrams are taken with `poll_transmit` and have to be sent on the transport.
+/// - `handle_timers` has to be called at the latest at `next_deadline`, to raise timeouts and stalled streams.
+/// - Events are taken with `poll_event`.
+#[derive(Debug)]
+pub struct ConnectionManager {
+    config: Config,
+    connections: ActiveConnections,
+    link_conditioner: Option<LinkConditioner>,
+    metrics: Option<Arc<dyn Metrics>>,
+    event_sender: PolicySender<SocketEvent>,
+    event_receiver: Receiver<SocketEvent>,
+    transmit_queue: VecDeque<(SocketAddr, Payload)>,
+    buffer_pool: BufferPool,
+    address_validator: AddressValidator,
+    rate_limiter: RateLimiter,
+    // Peers we received from but never sent to, with the time they were last heard and the identifier they gave their
+    // connection. They have no stored connection and do not count towards `Config::max_connections`, at most
+    // `MAX_ANONYMOUS_PEERS` are remembered until they time out.
+    anonymous_peers: HashMap<SocketAddr, (Instant, u32)>,
+    // Decompresses the datagrams of the peers we never sent to.
+    compression: Option<Compression>,
+    // Peers which were rejected, with the time of the rejection, see `reject`.
+    rejected_peers: HashMap<SocketAddr, Instant>,
+    // Peers of another protocol which were replied to, with the time of the reply, see `reply_version_mismatch`.
+    mismatched_peers: HashMap<SocketAddr, Instant>,
+    // The number of errors received from addresses without a stored connection, with the time of the last one.
+    errors_without_connection: HashMap<SocketAddr, (u64, Instant)>,
+    protocol_version: u16,
+}
+
+impl ConnectionManager {
+    /// Constructs a new `ConnectionManager` without any connections.
+    pub fn new(config: Config) -> ConnectionManager {
+        let (event_sender, event_receiver) = channel(
+            config.event_channel_capacity,
+            config.channel_overflow_policy,
+        );
+        let buffer_pool = BufferPool::new(config.buffer_pool_size, config.receive_buffer_max_size);
+        let rate_limiter = RateLimiter::new(
+            config.max_datagrams_per_ip,
+            config.max_datagrams_per_subnet,
+            config.max_decode_errors_per_ip,
+            config.ban_duration,
+        );
+        let protocol_version = ProtocolVersion::get_crc16(config.protocol_id);
+        let compression = config.compressor.clone().map(|compressor| {
+            Compression::new(
+                compressor,
+                config.compression_dictionary.clone(),
+                config.max_packet_size,
+            )
+        });
+        ConnectionManager {
+            config,
+            connections: ActiveConnections::new(),
+            link_conditioner: None,
+            metrics: None,
+            event_sender,
+            event_receiver,
+            transmit_queue: VecDeque::new(),
+            buffer_pool,
+            address_validator: AddressValidator::new(Instant::now()),
+            rate_limiter,
+            anonymous_peers: HashMap::new(),
+            compression,
+            rejected_peers: HashMap::new(),
+            mismatched_peers: HashMap::new(),
+            errors_without_connection: HashMap::new(),
+            protocol_version,
+        }
+    }
+
+    /// Returns the configuration of this manager.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Returns the pool of buffers used for datagrams and payloads.
+    ///
+    /// Receiving datagrams into buffers of this pool and passing them to `handle_payload` avoids copying them.
+    pub fn buffer_pool(&self) -> &BufferPool {
+        &self.buffer_pool
+    }
+
+    /// Processes a datagram received from `addr`, the resulting events can be taken with `poll_event`.
+    ///
+    /// Errors are counted on the connection, reported to the metrics and raised as `SocketEvent::Error` if this is enabled in the config.
+    pub fn handle_datagram(
+        &mut self,
+        addr: SocketAddr,
+        payload: &[u8],
+        time: Instant,
+    ) -> Result<()> {
+        let mut buffer = self.buffer_pool.acquire();
+        buffer.extend_from_slice(payload);
+        self.handle_payload(addr, buffer.freeze(), time)
+    }
+
+    /// Processes a datagram received from `addr` like `handle_datagram`, the payloads of the resulting packets share its buffer.
+    ///
+    /// Datagrams from banned addresses, or over the rate limits of the config, are dropped before they are parsed.
+    pub fn handle_payload(
+        &mut self,
+        addr: SocketAddr,
+        payload: Payload,
+        time: Instant,
+    ) -> Result<()> {
+        let addr = canonical_address(addr);
+        // Only addresses with a connection are banned, see `RateLimiter`
+        let validated = self.connections.exists(&addr);
+        if !self.rate_limiter.allow(addr.ip(), validated, time) {
+            return Ok(());
+        }
+
+        match self.process_datagram(addr, &payload, time) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                if ReceiveErrorKind::from_error(&e).is_some() {
+                    self.rate_limiter
+                        .record_decode_error(addr.ip(), validated, time);
+                }
+                self.report_receive_error(Some(addr), &e, time)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Processes a packet to be sent, the resulting datagrams can be taken with `poll_transmit`.
+    ///
+    /// Reliable packets to the same address which were not acknowledged in time are resent as well.
+    pub fn send(&mut self, packet: Packet, time: Instant) -> Result<()> {
+        let addr = canonical_address(packet.addr());
+        if !self.connections.exists(&addr) && self.is_full() {
+            // A peer we only received from was raised as connected, it is told that we are full instead
+            if let Some((_, remote_connection_id)) = self.anonymous_peers.remove(&addr) {
+                self.queue_control_packet(
+                    addr,
+                    PacketType::ConnectionRejected,
+                    &[],
+                    self.protocol_version,
+                    remote_connection_id,
+                );
+                self.event_sender
+                    .send(SocketEvent::ConnectionRejected(addr))?;
+            }
+            return Err(ErrorKind::ConnectionLimitReached);
+        }
+        let anonymous = self.anonymous_peers.remove(&addr);
+
+        self.store_connection(addr, time);
+        if let Some((_, remote_connection_id)) = anonymous {
+            self.connections
+                .record_remote_connection_id(&addr, remote_connection_id);
+        }
+
+        let connection = self
+            .connections
+            .get_or_insert_connection(addr, &self.config, time);
+
+        let dropped = connection.gather_dropped_packets();
+        self.resend_packets(addr, dropped, time);
+
+        let connection = self
+            .connections
+            .get_or_insert_connection(addr, &self.config, time);
+        let processed_packet = connection.process_outgoing_with_redundancy(
+            packet.shared_payload(),
+            packet.delivery_guarantee(),
+            packet.order_guarantee(),
+            packet.redundancy(),
+            None,
+            time,
+        )?;
+
+        self.queue_outgoing(addr, processed_packet);
+
+        // Sent after the first packet, which makes the remote endpoint set up its connection
+        if self.hello_due(addr, time) {
+            self.send_hello(addr, time);
+        }
+        Ok(())
+    }
+
+    /// Resets the given ordering or sequencing stream of the connection with `addr`, see `Socket::reset_stream`.
+    pub fn reset_stream(
+        &mut self,
+        addr: SocketAddr,
+        stream: OrderingGuarantee,
+        time: Instant,
+    ) -> Result<()> {
+        let addr = canonical_address(addr);
+        self.send_stream_command(addr, StreamCommand::Reset, stream, time)
+    }
+
+    /// Closes the given ordering or sequencing stream of the connection with `addr`, see `Socket::close_stream`.
+    pub fn close_stream(
+        &mut self,
+        addr: SocketAddr,
+        stream: OrderingGuarantee,
+        time: Instant,
+    ) -> Result<()> {
+        let addr = canonical_address(addr);
+        self.send_stream_command(addr, StreamCommand::Close, stream, time)
+    }
+
+    /// Raises `SocketEvent::StreamStalled` for stalled ordering streams and `SocketEvent::Timeout` for idle connections,
+    /// which are removed. Hellos which were not answered are sent again.
+    pub fn handle_timers(&mut self, time: Instant) -> Result<()> {
+        // Check for ordering streams that are blocked on a missing packet
+        let stalled_streams = self.stalled_streams(time);
+        // Resend hellos until the remote endpoint answers
+        self.handle_unanswered_hellos(time);
+        // Release the memory of unreliable packets that will not be completed anymore
+        for connection in self.connections.iter_mut() {
+            connection.drop_expired_fragments(time);
+        }
+        // Send the parities of unreliable packets which no more packets followed
+        self.handle_open_parity_groups(time);
+
+        // Events are only queued once every timer ran, so a full event channel doesn't hold up any of them
+        let mut queued = Ok(());
+        for event in stalled_streams {
+            if let Err(e) = self.event_sender.send(event) {
+                queued = Err(e.into());
+            }
+        }
+        // Finally check for idle clients
+        let idle_clients = self.handle_idle_clients(time);
+        queued.and(idle_clients)
+    }
+
+    /// Sends the reliable packets of every connection again which are not acknowledged yet.
+    ///
+    /// Lost packets are otherwise only resent once newer packets are acknowledged, e.g. this flushes them when nothing
+    /// new is sent anymore.
+    pub fn resend_unacknowledged(&mut self, time: Instant) {
+        let addresses: Vec<SocketAddr> = self
+            .connections
+            .iter()
+            .map(|connection| connection.remote_address)
+            .collect();
+        for address in addresses {
+            if let Some(connection) = self.connections.get_connection_mut(&address) {
+                let unacknowledged = connection.take_unacknowledged_packets();
+                self.resend_packets(address, unacknowledged, time);
+            }
+        }
+    }
+
+    /// Queues a disconnect notice to `addr` and removes the connection, returns false if there is no such connection.
+    ///
+    /// The remote endpoint raises `SocketEvent::Disconnect` instead of waiting for the connection to time out.
+    /// The notice is not acknowledged, when its datagram is lost the connection times out as usual.
+    pub fn disconnect(&mut self, addr: SocketAddr) -> bool {
+        let addr = canonical_address(addr);
+        if !self.connections.exists(&addr) {
+            return false;
+        }
+
+        self.queue_notice(addr, PacketType::Disconnect);
+        self.connections.remove_connection(&addr);
+        true
+    }
+
+    /// Queues a disconnect notice to every connection and removes them, see `disconnect`.
+    pub fn disconnect_all(&mut self) {
+        let addresses: Vec<SocketAddr> = self
+            .connections
+            .iter()
+            .map(|connection| connection.remote_address)
+            .collect();
+        for address in addresses {
+            self.disconnect(address);
+        }
+    }
+
+    /// Replaces the dictionary of the compressor on the connection with `addr`, `None` compresses without a dictionary.
+    ///
+    /// Returns false if there is no such connection or no compressor in the config.
+    pub fn set_compression_dictionary(
+        &mut self,
+        addr: SocketAddr,
+        dictionary: Option<Arc<[u8]>>,
+    ) -> bool {
+        let addr = canonical_address(addr);
+        self.connections
+            .get_connection_mut(&addr)
+            .is_some_and(|connection| connection.set_compression_dictionary(dictionary))
+    }
+
+    /// Bans `ip` for `duration`, or permanently if it is `None`, its datagrams are dropped before they are parsed.
+    ///
+    /// Existing connections with the address are kept, they time out unless they are disconnected.
+    pub fn ban(&mut self, ip: IpAddr, duration: Option<Duration>, time: Instant) {
+        self.rate_limiter.ban(canonical_ip(ip), duration, time);
+    }
+
+    /// Lifts the ban of `ip`, returns false if it was not banned.
+    pub fn unban(&mut self, ip: IpAddr) -> bool {
+        self.rate_limiter.unban(canonical_ip(ip))
+    }
+
+    /// Returns the addresses which are banned at `time`, manually or because they exceeded the limits of the config.
+    pub fn bans(&self, time: Instant) -> Vec<Ban> {
+        self.rate_limiter.bans(time)
+    }
+
+    /// Returns the latest time at which `handle_timers` should be called again to raise timeouts and stalled streams in time,
+    /// or `None` if there are no connections and no peers we received from.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let timeout = self.config.idle_connection_timeout;
+        self.connections
+            .iter()
+            .map(|connection| connection.next_deadline())
+            .chain(
+                self.anonymous_peers
+                    .values()
+                    .map(|(last_heard, _)| *last_heard + timeout),
+            )
+            .min()
+    }
+
+    /// Takes the next datagram that has to be sent on the transport, with the address it has to be sent to.
+    ///
+    /// Datagrams are counted in the statistics of their connection and the metrics once they are taken here.
+    pub fn poll_transmit(&mut self) -> Option<(SocketAddr, Payload)> {
+        let (addr, payload) = self.transmit_queue.pop_front()?;
+
+        if let Some(metrics) = &self.metrics {
+            metrics.datagram_sent(addr, payload.len());
+        }
+        if let Some(connection) = self.connections.get_connection_mut(&addr) {
+            connection.record_sent(payload.len());
+        }
+
+        Some((addr, payload))
+    }
+
+    /// Takes the next event that occurred.
+    pub fn poll_event(&mut self) -> Option<SocketEvent> {
+        match self.event_receiver.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => panic!["This can never happen"],
+        }
+    }
+
+    /// Returns a snapshot of the statistics of the connection with `addr`, `None` if there is no such connection.
+    pub fn connection_stats(&self, addr: SocketAddr, time: Instant) -> Option<ConnectionStats> {
+        let addr = canonical_address(addr);
+        self.connections
+            .get_connection(&addr)
+            .map(|connection| connection.stats(time))
+    }
+
+    /// Returns a snapshot of the statistics of every active connection.
+    pub fn connections(&self, time: Instant) -> Vec<ConnectionStats> {
+        self.connections
+            .iter()
+            .map(|connection| connection.stats(time))
+            .collect()
+    }
+
+    /// Returns the state of the given ordering or sequencing stream of the connection with `addr`.
+    ///
+    /// Returns `None` if there is no such connection, or if the stream was never used.
+    pub fn stream_info(&self, addr: SocketAddr, stream: OrderingGuarantee) -> Option<StreamInfo> {
+        let addr = canonical_address(addr);
+        self.connections
+            .get_connection(&addr)
+            .and_then(|connection| connection.stream_info(stream))
+    }
+
+    /// Returns the state of all ordering and sequencing streams used on the connection with `addr`.
+    pub fn streams(&self, addr: SocketAddr) -> Vec<StreamInfo> {
+        let addr = canonical_address(addr);
+        self.connections
+            .get_connection(&addr)
+            .map(|connection| connection.streams())
+            .unwrap_or_default()
+    }
+
+    /// Set the link conditioner which decides which outgoing datagrams are dropped. See [LinkConditioner] for further details.
+    pub fn set_link_conditioner(&mut self, link_conditioner: Option<LinkConditioner>) {
+        self.link_conditioner = link_conditioner;
+    }
+
+    /// Set the metrics which are notified of key events. See [Metrics] for further details.
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn Metrics>>) {
+        self.metrics = metrics;
+    }
+
+    /// Returns a receiver of the events, which is shared with `poll_event`.
+    pub(crate) fn event_receiver(&self) -> Receiver<SocketEvent> {
+        self.event_receiver.clone()
+    }
+
+    // Counts an error that occurred while receiving a datagram from `address` and raises a
+    // `SocketEvent::Error` if this is enabled in the config.
+    pub(crate) fn report_receive_error(
+        &mut self,
+        address: Option<SocketAddr>,
+        error: &ErrorKind,
+        time: Instant,
+    ) -> Result<()> {
+        let kind = match ReceiveErrorKind::from_error(error) {
+            Some(kind) => kind,
+            None => return Ok(()),
+        };
+
+        if let Some(address) = address {
+            if let Some(metrics) = &self.metrics {
+                metrics.decode_error(address, error);
+            }
+            if let Some(connection) = self.connections.get_connection_mut(&address) {
+                connection.record_receive_error(1);
+            } else if self.errors_without_connection.contains_key(&address)
+                || self.errors_without_connection.len() < MAX_ERROR_COUNTERS
+            {
+                let (errors, last_error) = self
+                    .errors_without_connection
+                    .entry(address)
+                    .or_insert((0, time));
+                *errors += 1;
+                *last_error = time;
+            }
+        }
+
+        if self.config.emit_error_events {
+            self.event_sender.send(SocketEvent::Error {
+                addr: address,
+                kind,
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the number of datagrams received from `addr` which could not be processed, see `ConnectionStats::errors_received`.
+    ///
+    /// Errors of addresses without a connection are counted as well, e.g. of peers on an outdated build, until no error was
+    /// received from them for `Config::idle_connection_timeout`. They are counted for at most 1024 such addresses at once.
+    pub fn errors_received(&self, addr: SocketAddr) -> u64 {
+        let addr = canonical_address(addr);
+        match self.connections.get_connection(&addr) {
+            Some(connection) => connection.errors_received(),
+            None => self
+                .errors_without_connection
+                .get(&addr)
+                .map_or(0, |(errors, _)| *errors),
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn connection_count(&self) -> usize {
+        self.connections.count()
+    }
+
+    // Processes a datagram received from `address`.
+    fn process_datagram(
+        &mut self,
+        address: SocketAddr,
+        payload: &Payload,
+        time: Instant,
+    ) -> Result<()> {
+        if payload.is_empty() {
+            return Err(ErrorKind::ReceivedDataToShort)?;
+        }
+
+        if let Some(metrics) = &self.metrics {
+            metrics.datagram_received(address, payload.len());
+        }
+
+        // Corrupt datagrams are dropped before a connection processes them
+        let received = payload.len();
+        let verified;
+        let payload = if self.config.checksum {
+            match self.verify_checksum(payload) {
+                Ok(payload) => verified = payload,
+                Err(e) => {
+                    self.reply_to_checksum_mismatch(address, payload, received, time);
+                    return Err(e);
+                }
+            }
+            &verified
+        } else {
+            payload
+        };
+
+        let header = PacketReader::new(payload).read_standard_header().ok();
+        if let Some(header) =
+            header.filter(|header| !header.is_current_protocol(self.config.protocol_id))
+        {
+            // Tell the peer its packets are not accepted, a notice is never replied to so two peers cannot keep replying
+            if !Self::is_control_packet(&header) {
+                self.reply_version_mismatch(
+                    address,
+                    header.protocol_version(),
+                    header.connection_id(),
+                    received,
+                    time,
+                );
+            }
+            return Err(ErrorKind::ProtocolVersionMismatch);
+        }
+        let remote_connection_id = header.map_or(0, |header| header.connection_id());
+
+        // Control packets are handled here instead of being processed by a connection
+        if let Some(header) = header.filter(Self::is_control_packet) {
+            let body = &payload[STANDARD_HEADER_SIZE as usize..];
+            return self.process_control_packet(address, &header, body, received, time);
+        }
+
+        if !self.connections.exists(&address) {
+            // A known peer whose address changed has to prove it receives datagrams at the new one, before the connection is moved
+            let moved = remote_connection_id != 0
+                && self
+                    .connections
+                    .find_by_remote_connection_id(remote_connection_id)
+                    .is_some();
+            if moved {
+                let cookie = self.address_validator.cookie(address, time);
+                self.queue_reply(address, PacketType::Challenge, &cookie, received);
+                return Ok(());
+            }
+
+            // Unvalidated peers are only answered with a challenge, no connection is allocated for them
+            if self.config.validate_addresses {
+                let cookie = self.address_validator.cookie(address, time);
+                self.queue_reply(address, PacketType::Challenge, &cookie, received);
+                return Ok(());
+            }
+
+            // New peers over the limit are rejected, a peer we never sent to has no stored connection and is not counted
+            if !self.anonymous_peers.contains_key(&address) {
+                if self.is_full() {
+                    return self.reject(address, remote_connection_id, received, time);
+                }
+
+                if self.anonymous_peers.len() >= MAX_ANONYMOUS_PEERS {
+                    self.forget_least_recent_anonymous_peer()?;
+                }
+                if let Some(metrics) = &self.metrics {
+                    metrics.connection_opened(address);
+                }
+                self.event_sender.send(SocketEvent::Connect(address))?;
+            }
+            let remote_connection_id = self
+                .anonymous_peers
+                .get(&address)
+                .map_or(0, |(_, remote_connection_id)| *remote_connection_id);
+            self.anonymous_peers
+                .insert(address, (time, remote_connection_id));
+        }
+
+        match self.connections.get_connection_mut(&address) {
+            Some(existing) => {
+                let reassembled = existing.packets_reassembled();
+                let result = existing.process_incoming(payload, &self.event_sender, time);
+                if let Some(metrics) = &self.metrics {
+                    for _ in reassembled..existing.packets_reassembled() {
+                        metrics.packet_reassembled(address);
+                    }
+                }
+                if result.is_ok() {
+                    self.connections
+                        .record_remote_connection_id(&address, remote_connection_id);
+                }
+                result
+            }
+            None => {
+                let result = VirtualConnection::process_anonymous(
+                    payload,
+                    canonical_address(address),
+                    self.compression.as_ref(),
+                    &self.event_sender,
+                );
+                if let (Ok(()), Some(peer)) = (&result, self.anonymous_peers.get_mut(&address)) {
+                    peer.1 = remote_connection_id;
+                }
+                result
+            }
+        }
+    }
+
+    // Returns the datagram without its checksum, fails with `ErrorKind::ChecksumMismatch` if it does not match.
+    //
+    // The checksum replaces the protocol version, which is mixed into it, so the version is put back in front of the datagram.
+    fn verify_checksum(&self, payload: &Payload) -> Result<Payload> {
+        match Self::checksum_tag(payload) {
+            Some((data, tag)) if tag == ProtocolVersion::checksum_tag(self.config.protocol_id) => {
+                let mut buffer = self.buffer_pool.acquire();
+                buffer.extend_from_slice(
+                    &ProtocolVersion::get_crc16(self.config.protocol_id).to_be_bytes(),
+                );
+                buffer.extend_from_slice(data);
+                Ok(buffer.freeze())
+            }
+            _ => Err(ErrorKind::ChecksumMismatch),
+        }
+    }
+
+    // Returns the datagram without its checksum and the tag its sender mixed into the checksum, see `ProtocolVersion::checksum`.
+    fn checksum_tag(payload: &[u8]) -> Option<(&[u8], u32)> {
+        let length = payload.len().checked_sub(CHECKSUM_SIZE)?;
+        let (data, checksum) = payload.split_at(length);
+        Some((
+            data,
+            ProtocolVersion::checksum(data, BigEndian::read_u32(checksum)),
+        ))
+    }
+
+    // Replaces the protocol version of the datagram in `buffer` by a checksum at its end, if it is enabled in the config.
+    fn append_checksum(&self, buffer: &mut Vec<u8>) {
+        if self.config.checksum {
+            Self::replace_version_by_checksum(
+                buffer,
+                ProtocolVersion::checksum_tag(self.config.protocol_id),
+            );
+        }
+    }
+
+    // Replaces the protocol version of the datagram in `buffer` by a checksum at its end, which has `tag` mixed in.
+    fn replace_version_by_checksum(buffer: &mut Vec<u8>, tag: u32) {
+        buffer.drain(..PROTOCOL_VERSION_SIZE);
+        let checksum = ProtocolVersion::checksum(buffer, tag);
+        buffer.extend_from_slice(&checksum.to_be_bytes());
+    }
+
+    // Replies to a datagram whose checksum does not carry our protocol, which is corrupt or of a peer of another protocol.
+    //
+    // The reply has the tag which the peer mixed into its checksum, so only a peer of that protocol accepts it and raises
+    // `SocketEvent::VersionMismatch`, a peer of ours drops it like a corrupt datagram. Datagrams of our connections are
+    // taken for corrupt ones and not replied to.
+    fn reply_to_checksum_mismatch(
+        &mut self,
+        address: SocketAddr,
+        payload: &Payload,
+        received: usize,
+        time: Instant,
+    ) {
+        if self.connections.exists(&address) {
+            return;
+        }
+        let (data, tag) = match Self::checksum_tag(payload) {
+            Some(checksum) => checksum,
+            None => return,
+        };
+
+        // The protocol version is not sent, any version does to read the rest of the header
+        let mut datagram = self.buffer_pool.acquire();
+        datagram.extend_from_slice(&[0; PROTOCOL_VERSION_SIZE]);
+        datagram.extend_from_slice(data);
+        let header = match PacketReader::new(&datagram).read_standard_header() {
+            Ok(header) if !Self::is_control_packet(&header) => header,
+            _ => return,
+        };
+        if !self.remember_mismatched_peer(address, time) || !self.fits_reply(0, received) {
+            return;
+        }
+
+        let mut reply = self.buffer_pool.acquire();
+        OutgoingPacketBuilder::new(&[])
+            .with_default_header(
+                PacketType::VersionMismatch,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::None,
+                0,
+                header.connection_id(),
+            )
+            .build()
+            .write_contents(&mut reply);
+        Self::replace_version_by_checksum(&mut reply, tag);
+        self.queue_datagram(address, reply.freeze());
+    }
+
+    // Returns whether the packet is a notice which closes the connection, a hello or a challenge, they are not processed by a connection.
+    fn is_control_packet(header: &StandardHeader) -> bool {
+        matches!(
+            header.packet_type(),
+            PacketType::Disconnect
+                | PacketType::ConnectionRejected
+                | PacketType::VersionMismatch
+                | PacketType::Hello
+                | PacketType::Challenge
+                | PacketType::ChallengeResponse
+        )
+    }
+
+    // Processes a control packet received from `address`, of a datagram with `received` bytes.
+    fn process_control_packet(
+        &mut self,
+        address: SocketAddr,
+        header: &StandardHeader,
+        body: &[u8],
+        received: usize,
+        time: Instant,
+    ) -> Result<()> {
+        match header.packet_type() {
+            // A notice is never replied to, it only closes a connection when it proves to come from the peer
+            PacketType::Disconnect => {
+                // The notice carries the identifier the peer gave its connection with us
+                let from_peer =
+                    self.connections
+                        .get_connection(&address)
+                        .is_some_and(|connection| {
+                            connection.remote_connection_id() == Some(header.connection_id())
+                        });
+                // A peer we never sent to is only forgotten once its identifier is recorded, 0 means none is
+                let from_anonymous_peer =
+                    self.anonymous_peers
+                        .get(&address)
+                        .is_some_and(|(_, remote_connection_id)| {
+                            *remote_connection_id != 0
+                                && *remote_connection_id == header.connection_id()
+                        });
+                let removed = if from_peer {
+                    self.connections.remove_connection(&address).is_some()
+                } else if from_anonymous_peer {
+                    self.anonymous_peers.remove(&address).is_some()
+                } else {
+                    false
+                };
+                if removed {
+                    self.event_sender.send(SocketEvent::Disconnect(address))?;
+                }
+            }
+            PacketType::ConnectionRejected | PacketType::VersionMismatch => {
+                // The reply carries the identifier of our connection, which the peer read from our datagram
+                let replied = self
+                    .connections
+                    .get_connection(&address)
+                    .is_some_and(|connection| connection.connection_id == header.connection_id());
+                if replied && self.connections.remove_connection(&address).is_some() {
+                    let event = if header.packet_type() == PacketType::ConnectionRejected {
+                        SocketEvent::ConnectionRejected(address)
+                    } else {
+                        SocketEvent::VersionMismatch(address)
+                    };
+                    self.event_sender.send(event)?;
+                }
+            }
+            // Only hellos and challenges from peers we sent to are answered, so they cannot be reflected at others
+            PacketType::Hello => {
+                let remote = Hello::read(body)?;
+                // A hello carries the identifier the peer gave its connection with us, once we know it
+                let connection = match self.connections.get_connection_mut(&address) {
+                    Some(connection)
+                        if connection
+                            .remote_connection_id()
+                            .is_none_or(|id| id == header.connection_id()) =>
+                    {
+                        connection
+                    }
+                    _ => return Ok(()),
+                };
+
+                // The first hello settles the negotiation, later ones are only answered
+                if connection.negotiated().is_none() {
+                    match Hello::new(&self.config, true).negotiate(&remote) {
+                        Some(negotiated) => connection.set_negotiated(negotiated),
+                        None => {
+                            self.queue_control_packet(
+                                address,
+                                PacketType::VersionMismatch,
+                                &[],
+                                self.protocol_version,
+                                header.connection_id(),
+                            );
+                            self.connections.remove_connection(&address);
+                            self.event_sender
+                                .send(SocketEvent::VersionMismatch(address))?;
+                            return Ok(());
+                        }
+                    }
+                }
+
+                if !remote.acknowledged {
+                    self.send_hello(address, time);
+                }
+            }
+
+            PacketType::Challenge => {
+                if let Some(connection) = self.connections.get_connection_mut(&address) {
+                    // The peer dropped our reliable packets until it validated our address
+                    let unacknowledged = connection.take_unacknowledged_packets();
+                    // When our address changed, the identifier the peer gave the connection proves it is ours
+                    let response =
+                        bind_cookie(body, connection.remote_connection_id().unwrap_or(0));
+                    self.queue_reply(address, PacketType::ChallengeResponse, &response, received);
+                    self.resend_packets(address, unacknowledged, time);
+                }
+            }
+            PacketType::ChallengeResponse => {
+                if self.connections.exists(&address) {
+                    return Ok(());
+                }
+
+                // A moved peer has to return the cookie bound to the identifier we gave the connection
+                let moved_from = match header.connection_id() {
+                    0 => None,
+                    id => self.connections.find_by_remote_connection_id(id),
+                };
+                if let Some(old) = moved_from {
+                    let connection_id = self
+                        .connections
+                        .get_connection(&old)
+                        .map_or(0, |connection| connection.connection_id);
+                    if self.address_validator.is_valid(
+                        address,
+                        &bind_cookie(body, connection_id),
+                        time,
+                    ) {
+                        self.connections.migrate_connection(&old, &address);
+                        self.anonymous_peers.remove(&address);
+                        self.event_sender
+                            .send(SocketEvent::AddressChanged { old, new: address })?;
+                    }
+                } else if self.config.validate_addresses
+                    && self.address_validator.is_valid(address, body, time)
+                {
+                    if self.is_full() {
+                        return self.reject(address, header.connection_id(), received, time);
+                    }
+
+                    self.store_connection(address, time);
+                    self.connections
+                        .record_remote_connection_id(&address, header.connection_id());
+                    if self.config.negotiate {
+                        self.send_hello(address, time);
+                    }
+                    if let Some(metrics) = &self.metrics {
+                        metrics.connection_opened(address);
+                    }
+                    self.event_sender.send(SocketEvent::Connect(address))?;
+                }
+            }
+            _ => unreachable!("Only control packets are processed here"),
+        }
+
+        Ok(())
+    }
+
+    // Rejects a new peer over the connection limit, in reply to a datagram with `received` bytes which carried the
+    // identifier `connection_id` of the connection of the peer. The rejection carries it back, see `process_control_packet`.
+    //
+    // A peer is rejected once until `Config::idle_connection_timeout` passed, further datagrams are dropped silently.
+    // At most `MAX_REJECTED_PEERS` are remembered, rejections of more peers are dropped as well.
+    fn reject(
+        &mut self,
+        address: SocketAddr,
+        connection_id: u32,
+        received: usize,
+        time: Instant,
+    ) -> Result<()> {
+        if self.rejected_peers.contains_key(&address)
+            || self.rejected_peers.len() >= MAX_REJECTED_PEERS
+        {
+            return Ok(());
+        }
+        self.rejected_peers.insert(address, time);
+
+        if self.fits_reply(0, received) {
+            self.queue_control_packet(
+                address,
+                PacketType::ConnectionRejected,
+                &[],
+                self.protocol_version,
+                connection_id,
+            );
+        }
+        self.event_sender
+            .send(SocketEvent::ConnectionRejected(address))?;
+        Ok(())
+    }
+
+    // Returns whether `Config::max_connections` is reached by the stored connections.
+    fn is_full(&self) -> bool {
+        self.config
+            .max_connections
+            .is_some_and(|max_connections| self.connections.count() >= max_connections)
+    }
+
+    // Makes room for a new peer we never sent to, by evicting the one which was heard from least recently.
+    fn forget_least_recent_anonymous_peer(&mut self) -> Result<()> {
+        let least_recent = self
+            .anonymous_peers
+            .iter()
+            .min_by_key(|(_, (last_heard, _))| *last_heard)
+            .map(|(address, _)| *address);
+
+        if let Some(address) = least_recent {
+            self.event_sender.send(SocketEvent::Evicted(address))?;
+            self.anonymous_peers.remove(&address);
+        }
+        Ok(())
+    }
+
+    // Queues a notice of the given type, which consists of only the standard header.
+    fn queue_notice(&mut self, addr: SocketAddr, packet_type: PacketType) {
+        self.queue_control_packet(
+            addr,
+            packet_type,
+            &[],
+            self.protocol_version,
+            self.own_connection_id(addr),
+        );
+    }
+
+    // Returns whether our hello has to be sent to `addr`, see `VirtualConnection::hello_due`.
+    fn hello_due(&self, addr: SocketAddr, time: Instant) -> bool {
+        self.connections
+            .get_connection(&addr)
+            .is_some_and(|connection| connection.hello_due(time))
+    }
+
+    // Queues our hello to `addr`, which tells whether we received the hello of the remote endpoint already.
+    fn send_hello(&mut self, addr: SocketAddr, time: Instant) {
+        let connection = match self.connections.get_connection_mut(&addr) {
+            Some(connection) => connection,
+            None => return,
+        };
+        connection.record_hello_sent(time);
+        let hello = Hello::new(&self.config, connection.negotiated().is_some());
+        let connection_id = connection.connection_id;
+        self.queue_control_packet(
+            addr,
+            PacketType::Hello,
+            &hello.to_bytes(),
+            self.protocol_version,
+            connection_id,
+        );
+    }
+
+    // Queues a control packet in reply to a datagram with `received` bytes from a peer whose address may not be validated.
+    // The reply is dropped when it is larger than the datagram, so a spoofed datagram cannot be amplified.
+    fn queue_reply(
+        &mut self,
+        addr: SocketAddr,
+        packet_type: PacketType,
+        body: &[u8],
+        received: usize,
+    ) {
+        if self.fits_reply(body.len(), received) {
+            self.queue_control_packet(
+                addr,
+                packet_type,
+                body,
+                self.protocol_version,
+                self.own_connection_id(addr),
+            );
+        }
+    }
+
+    // Replies to a datagram of another protocol version or application protocol id, like `queue_reply`.
+    // The reply carries the protocol version of the peer and the identifier of its connection, so the peer accepts it.
+    //
+    // A peer is replied to once until `Config::idle_connection_timeout` passed, like in `reject`.
+    fn reply_version_mismatch(
+        &mut self,
+        addr: SocketAddr,
+        protocol_version: u16,
+        connection_id: u32,
+        received: usize,
+        time: Instant,
+    ) {
+        if !self.remember_mismatched_peer(addr, time) {
+            return;
+        }
+
+        if self.fits_reply(0, received) {
+            self.queue_control_packet(
+                addr,
+                PacketType::VersionMismatch,
+                &[],
+                protocol_version,
+                connection_id,
+            );
+        }
+    }
+
+    // Remembers that a peer of another protocol was replied to, returns false if it was already or too many peers are remembered.
+    fn remember_mismatched_peer(&mut self, addr: SocketAddr, time: Instant) -> bool {
+        if self.mismatched_peers.contains_key(&addr)
+            || self.mismatched_peers.len() >= MAX_REJECTED_PEERS
+        {
+            return false;
+        }
+        self.mismatched_peers.insert(addr, time);
+        true
+    }
+
+    // Returns whether a control packet with a body of `body_length` bytes is not larger than a datagram with `received` bytes.
+    fn fits_reply(&self, body_length: usize, received: usize) -> bool {
+        let checksum_size = if self.config.checksum {
+            CHECKSUM_SIZE - PROTOCOL_VERSION_SIZE
+        } else {
+            0
+        };
+        STANDARD_HEADER_SIZE as usize + body_length + checksum_size <= received
+    }
+
+    // Stores the connection with `addr`, which takes over the errors counted for the address so far.
+    fn store_connection(&mut self, addr: SocketAddr, time: Instant) {
+        let errors = self.errors_without_connection.remove(&addr);
+        let connection = self
+            .connections
+            .get_or_insert_connection(addr, &self.config, time);
+        if let Some((errors, _)) = errors {
+            connection.record_receive_error(errors);
+        }
+    }
+
+    // Returns the identifier of our connection with `addr`, 0 if there is none.
+    fn own_connection_id(&self, addr: SocketAddr) -> u32 {
+        self.connections
+            .get_connection(&addr)
+            .map_or(0, |connection| connection.connection_id)
+    }
+
+    // Queues an unreliable packet of the given type, which consists of the standard header and `body`.
+    // It carries `connection_id`, which is ours except for rejections and version mismatches, see `reject`.
+    fn queue_control_packet(
+        &mut self,
+        addr: SocketAddr,
+        packet_type: PacketType,
+        body: &[u8],
+        protocol_version: u16,
+        connection_id: u32,
+    ) {
+        let packet = OutgoingPacketBuilder::new(body)
+            .with_default_header(
+                packet_type,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::None,
+                protocol_version,
+                connection_id,
+            )
+            .build();
+        self.queue_outgoing(addr, Outgoing::Packet(packet));
+    }
+
+    /// Iterate through all of the idle connections based on `idle_connection_timeout` config and
+    /// remove them from the active connections, together with the idle peers we never sent to. For each
+    /// connection removed, we will send a `SocketEvent::TimeOut` event to the `event_sender` channel.
+    fn handle_idle_clients(&mut self, time: Instant) -> Result<()> {
+        let timeout = self.config.idle_connection_timeout;
+        let mut idle_addresses = self.connections.idle_connections(timeout, time);
+        idle_addresses.extend(
+            self.anonymous_peers
+                .iter()
+                .filter(|(_, (last_heard, _))| {
+                    time.saturating_duration_since(*last_heard) >= timeout
+                })
+                .map(|(address, _)| *address),
+        );
+        self.rejected_peers
+            .retain(|_, rejected| time.saturating_duration_since(*rejected) < timeout);
+        self.mismatched_peers
+            .retain(|_, replied| time.saturating_duration_since(*replied) < timeout);
+        self.errors_without_connection
+            .retain(|_, (_, last_error)| time.saturating_duration_since(*last_error) < timeout);
+
+        // A peer is only forgotten once its timeout is queued, otherwise it times out again on the next call
+        for address in idle_addresses {
+            self.event_sender.send(SocketEvent::Timeout(address))?;
+            self.connections.remove_connection(&address);
+            self.anonymous_peers.remove(&address);
+            if let Some(metrics) = &self.metrics {
+                metrics.connection_timed_out(address);
+            }
+        }
+
+        Ok(())
+    }
+
+    // Sends our hello again to the connections which did not answer it in time.
+    fn handle_unanswered_hellos(&mut self, time: Instant) {
+        let addresses: Vec<SocketAddr> = self
+            .connections
+            .iter()
+            .filter(|connection| connection.hello_due(time))
+            .map(|connection| connection.remote_address)
+            .collect();
+        for address in addresses {
+            self.send_hello(address, time);
+        }
+    }
+
+    // Queues the parities of the open parity groups which waited `Config::parity_group_timeout` for more packets.
+    fn handle_open_parity_groups(&mut self, time: Instant) {
+        let mut parities = Vec::new();
+        for connection in self.connections.iter_mut() {
+            let address = connection.remote_address;
+            parities.extend(
+                connection
+                    .flush_parities(time)
+                    .into_iter()
+                    .map(|packet| (address, packet)),
+            );
+        }
+        for (address, packet) in parities {
+            self.queue_outgoing(address, Outgoing::Packet(packet));
+        }
+    }
+
+    /// Iterate through all of the connections and look for ordering streams which are stalled,
+    /// see `Config::ordering_stall_timeout`. For each stalled stream, we will return a
+    /// `SocketEvent::StreamStalled` event to be queued on the `event_sender` channel.
+    fn stalled_streams(&mut self, time: Instant) -> Vec<SocketEvent> {
+        let mut events = Vec::new();
+        for connection in self.connections.iter_mut() {
+            let address = connection.remote_address;
+            events.extend(
+                connection
+                    .stalled_ordering_streams(time)
+                    .into_iter()
+                    .map(|stream_id| SocketEvent::StreamStalled(address, stream_id)),
+            );
+        }
+
+        events
+    }
+
+    // Constructs a command for the given stream and queues it to be sent.
+    fn send_stream_command(
+        &mut self,
+        addr: SocketAddr,
+        command: StreamCommand,
+        stream: OrderingGuarantee,
+        time: Instant,
+    ) -> Result<()> {
+        let connection = self
+            .connections
+            .get_connection_mut(&addr)
+            .ok_or(StreamErrorKind::UnknownConnection)?;
+
+        let processed_packet = connection.process_stream_command(command, stream, None, time)?;
+
+        self.queue_outgoing(addr, processed_packet);
+        Ok(())
+    }
+
+    // Sends reliable packets to `addr` again which were not acknowledged, they keep their ordering guarantee and item identifier.
+    fn resend_packets(&mut self, addr: SocketAddr, packets: Vec<SentPacket>, time: Instant) {
+        if packets.is_empty() {
+            return;
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.resent_packets(addr, packets.len());
+        }
+
+        let connection = match self.connections.get_connection_mut(&addr) {
+            Some(connection) => connection,
+            None => return,
+        };
+        let processed_packets: Vec<Outgoing> = packets
+            .iter()
+            .flat_map(|waiting_packet| match waiting_packet.packet_type {
+                PacketType::StreamControl => StreamCommand::try_from(waiting_packet.payload[0])
+                    .and_then(|command| {
+                        connection.process_stream_command(
+                            command,
+                            waiting_packet.ordering_guarantee,
+                            waiting_packet.item_identifier,
+                            time,
+                        )
+                    }),
+                _ => connection.process_outgoing(
+                    &waiting_packet.payload,
+                    // Because a delivery guarantee is only sent with reliable packets
+                    DeliveryGuarantee::Reliable,
+                    // This is stored with the dropped packet because they could be mixed
+                    waiting_packet.ordering_guarantee,
+                    waiting_packet.item_identifier,
+                    time,
+                ),
+            })
+            .collect();
+
+        for processed_packet in processed_packets {
+            self.queue_outgoing(addr, processed_packet);
+        }
+    }
+
+    // Queues the datagrams of an `Outgoing` to be sent, unless the link conditioner drops them.
+    fn queue_outgoing(&mut self, addr: SocketAddr, outgoing: Outgoing<'_>) {
+        if self.should_send_packet() {
+            match outgoing {
+                Outgoing::Packet(outgoing) => self.queue_outgoing_packet(addr, &outgoing),
+                Outgoing::Fragments(packets) => {
+                    for outgoing in packets {
+                        self.queue_outgoing_packet(addr, &outgoing);
+                    }
+                }
+                Outgoing::PacketWithParities(outgoing, parities) => {
+                    self.queue_outgoing_packet(addr, &outgoing);
+                    for parity in parities {
+                        self.queue_outgoing_packet(addr, &parity);
+                    }
+                }
+            }
+        }
+    }
+
+    // Queues a single packet to be sent, in a pooled buffer with its checksum.
+    fn queue_outgoing_packet(&mut self, addr: SocketAddr, outgoing: &OutgoingPacket<'_>) {
+        let mut buffer = self.buffer_pool.acquire();
+        outgoing.write_contents(&mut buffer);
+        self.append_checksum(&mut buffer);
+        self.queue_datagram(addr, buffer.freeze());
+    }
+
+    // Queues a single datagram to be sent.
+    fn queue_datagram(&mut self, addr: SocketAddr, payload: Payload) {
+        self.transmit_queue.push_back((addr, payload));
+    }
+
+    // In the presence of a link conditioner, we would like it to determine whether or not we should
+    // send a packet.
+    fn should_send_packet(&mut self) -> bool {
+        if let Some(link_conditioner) = &mut self.link_conditioner {
+            link_conditioner.should_send()
+        } else {
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConnectionManager;
+    use crate::{
+        compression::Lz4Compressor,
+        net::{
+            address_validation::bind_cookie,
+            constants::{
+                CHECKSUM_SIZE, DEFAULT_ORDERING_STREAM, DEFAULT_SEQUENCING_STREAM,
+                MAX_ANONYMOUS_PEERS, PROTOCOL_VERSION_SIZE, STANDARD_HEADER_SIZE, WIRE_VERSION,
+            },
+            link_conditioner::LinkConditioner,
+            negotiation::{Hello, HELLO_INTERVAL},
+            Features, Negotiated, VirtualConnection,
+        },
+        packet::{
+            header::{HeaderWriter, ParityHeader},
+            DeliveryGuarantee, OrderingGuarantee, OutgoingPacketBuilder, PacketReader, PacketType,
+            Payload, Redundancy,
+        },
+        protocol_version::ProtocolVersion,
+        Config, ErrorKind, InMemoryMetrics, Packet, SocketEvent,
+    };
+    use std::{
+        net::SocketAddr,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn exchange_packets_without_socket() {
+        let server_addr = "127.0.0.1:10001".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10002".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        client
+            .send(Packet::reliable_unordered(server_addr, vec![1, 2, 3]), now)
+            .unwrap();
+        let (addr, datagram) = client.poll_transmit().unwrap();
+        assert_eq!(addr, server_addr);
+        assert_eq!(client.poll_transmit(), None);
+
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+        assert_eq!(server.poll_event(), Some(SocketEvent::Connect(client_addr)));
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::reliable_unordered(
+                client_addr,
+                vec![1, 2, 3]
+            )))
+        );
+        assert_eq!(server.poll_event(), None);
+    }
+
+    #[test]
+    fn accept_ipv4_mapped_addresses_for_ipv4_connections() {
+        let client_addr = "127.0.0.1:10053".parse::<SocketAddr>().unwrap();
+        let mapped_addr = "[::ffff:127.0.0.1]:10053".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            compressor: Some(Arc::new(Lz4Compressor)),
+            ..Config::default()
+        };
+        let mut server = ConnectionManager::new(config);
+        let now = Instant::now();
+        server
+            .send(Packet::reliable_ordered(client_addr, vec![1], None), now)
+            .unwrap();
+        let stream = OrderingGuarantee::Ordered(None);
+
+        assert_eq!(
+            server
+                .connection_stats(mapped_addr, now)
+                .unwrap()
+                .remote_address,
+            client_addr
+        );
+        assert!(server.stream_info(mapped_addr, stream).is_some());
+        assert_eq!(server.streams(mapped_addr).len(), 1);
+        assert!(server.set_compression_dictionary(mapped_addr, Some(Arc::from(&[1, 2, 3][..]))));
+        assert!(server.reset_stream(mapped_addr, stream, now).is_ok());
+        assert!(server.close_stream(mapped_addr, stream, now).is_ok());
+        assert!(server.disconnect(mapped_addr));
+        assert!(server.connection_stats(client_addr, now).is_none());
+    }
+
+    #[test]
+    fn count_datagrams_when_they_are_transmitted() {
+        let server_addr = "127.0.0.1:10042".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10043".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let metrics = Arc::new(InMemoryMetrics::new());
+        client.set_metrics(Some(metrics.clone()));
+        let now = Instant::now();
+
+        // datagrams dropped by the link conditioner are not sent
+        let mut link_conditioner = LinkConditioner::new();
+        link_conditioner.set_packet_loss(1.0);
+        client.set_link_conditioner(Some(link_conditioner));
+        client
+            .send(Packet::unreliable(server_addr, vec![1, 2, 3]), now)
+            .unwrap();
+        assert_eq!(client.poll_transmit(), None);
+        let stats = client.connection_stats(server_addr, now).unwrap();
+        assert_eq!(stats.packets_sent, 0);
+        assert_eq!(metrics.datagrams_sent(), 0);
+
+        client.set_link_conditioner(None);
+        for _ in 0..2 {
+            client
+                .send(Packet::reliable_unordered(server_addr, vec![1, 2, 3]), now)
+                .unwrap();
+        }
+        let stats = client.connection_stats(server_addr, now).unwrap();
+        assert_eq!(stats.packets_sent, 0);
+        let (_, first) = client.poll_transmit().unwrap();
+        let (_, second) = client.poll_transmit().unwrap();
+
+        let stats = client.connection_stats(server_addr, now).unwrap();
+        assert_eq!(stats.packets_sent, 2);
+        assert_eq!(stats.bytes_sent, (first.len() + second.len()) as u64);
+        assert_eq!(metrics.datagrams_sent(), 2);
+        assert_eq!(metrics.bytes_sent(), (first.len() + second.len()) as u64);
+
+        // the round trip time follows the injected time instead of the wall clock
+        server
+            .send(Packet::unreliable(client_addr, vec![]), now)
+            .unwrap();
+        server.handle_datagram(client_addr, &first, now).unwrap();
+        server.handle_datagram(client_addr, &second, now).unwrap();
+        server
+            .send(Packet::reliable_unordered(client_addr, vec![]), now)
+            .unwrap();
+        let (_, reply) = server.poll_transmit().and(server.poll_transmit()).unwrap();
+        let later = now + Duration::from_millis(40);
+        client.handle_datagram(server_addr, &reply, later).unwrap();
+        assert_eq!(
+            client.connection_stats(server_addr, later).unwrap().rtt,
+            Duration::from_millis(40)
+        );
+    }
+
+    #[test]
+    fn send_one_way_reliable_ordered_packets_without_acknowledgments() {
+        let server_addr = "127.0.0.1:10044".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10045".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        // the server only replies unreliably, so none of the packets is acknowledged
+        server
+            .send(Packet::unreliable(client_addr, vec![]), now)
+            .unwrap();
+        let (_, reply) = server.poll_transmit().unwrap();
+        client.handle_datagram(server_addr, &reply, now).unwrap();
+
+        let mut received = 0;
+        for id in 0..1100u32 {
+            client
+                .send(
+                    Packet::reliable_ordered(server_addr, id.to_be_bytes().to_vec(), None),
+                    now,
+                )
+                .unwrap();
+            while let Some((_, datagram)) = client.poll_transmit() {
+                server.handle_datagram(client_addr, &datagram, now).unwrap();
+            }
+            while let Some(event) = server.poll_event() {
+                if let SocketEvent::Packet(packet) = event {
+                    assert_eq!(packet.payload(), &id.to_be_bytes()[..]);
+                    received += 1;
+                }
+            }
+        }
+        assert_eq!(received, 1100);
+        assert_eq!(server.poll_transmit(), None);
+    }
+
+    #[test]
+    fn time_out_at_the_next_deadline() {
+        let remote_addr = "127.0.0.1:10003".parse::<SocketAddr>().unwrap();
+        let mut manager = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        assert_eq!(manager.next_deadline(), None);
+
+        manager
+            .send(Packet::unreliable(remote_addr, vec![]), now)
+            .unwrap();
+        let deadline = manager.next_deadline().unwrap();
+
+        manager.handle_timers(now).unwrap();
+        assert_eq!(manager.poll_event(), None);
+
+        manager.handle_timers(deadline).unwrap();
+        assert_eq!(
+            manager.poll_event(),
+            Some(SocketEvent::Timeout(remote_addr))
+        );
+        assert_eq!(manager.next_deadline(), None);
+    }
+
+    #[test]
+    fn disconnect_notice_closes_the_remote_connection() {
+        let server_addr = "127.0.0.1:10004".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10005".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        client
+            .send(Packet::unreliable(server_addr, vec![1]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+        server
+            .send(Packet::unreliable(client_addr, vec![2]), now)
+            .unwrap();
+        while server.poll_transmit().is_some() {}
+        while server.poll_event().is_some() {}
+        assert_eq!(server.connection_count(), 1);
+
+        assert!(client.disconnect(server_addr));
+        assert!(!client.disconnect(server_addr));
+        assert_eq!(client.next_deadline(), None);
+
+        let (addr, notice) = client.poll_transmit().unwrap();
+        assert_eq!(addr, server_addr);
+        server.handle_datagram(client_addr, &notice, now).unwrap();
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Disconnect(client_addr))
+        );
+        assert_eq!(server.connection_count(), 0);
+    }
+
+    #[test]
+    fn ignore_notices_which_do_not_carry_the_connection_id() {
+        let server_addr = "127.0.0.1:10038".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10039".parse::<SocketAddr>().unwrap();
+        let stranger_addr = "127.0.0.1:10040".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+        client
+            .send(Packet::unreliable(server_addr, vec![1]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+        server
+            .send(Packet::unreliable(client_addr, vec![2]), now)
+            .unwrap();
+        while server.poll_transmit().is_some() {}
+        while server.poll_event().is_some() {}
+
+        // Notices with other identifiers are spoofed, as are notices for peers we never had
+        for packet_type in [
+            PacketType::Disconnect,
+            PacketType::ConnectionRejected,
+            PacketType::VersionMismatch,
+        ] {
+            let notice = packet_of_connection(packet_type, &[], 42);
+            server.handle_datagram(client_addr, &notice, now).unwrap();
+            server.handle_datagram(stranger_addr, &notice, now).unwrap();
+        }
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.connection_count(), 1);
+
+        // The disconnect notice of the client carries the identifier of its connection
+        assert!(client.disconnect(server_addr));
+        let (_, notice) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &notice, now).unwrap();
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Disconnect(client_addr))
+        );
+        server.handle_datagram(client_addr, &notice, now).unwrap();
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.connection_count(), 0);
+
+        // A peer we never sent to is only disconnected by a notice with the identifier it sent us before
+        let mut client = ConnectionManager::new(Config::default());
+        client
+            .send(Packet::unreliable(server_addr, vec![3]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+        while server.poll_event().is_some() {}
+
+        let spoofed = packet_of_connection(PacketType::Disconnect, &[], 42);
+        server.handle_datagram(client_addr, &spoofed, now).unwrap();
+        assert_eq!(server.poll_event(), None);
+
+        // Without a recorded identifier, no notice is taken for the peer's
+        let packet = packet_of_connection(PacketType::Packet, &[4], 0);
+        server.handle_datagram(stranger_addr, &packet, now).unwrap();
+        while server.poll_event().is_some() {}
+        let spoofed = packet_of_connection(PacketType::Disconnect, &[], 0);
+        server
+            .handle_datagram(stranger_addr, &spoofed, now)
+            .unwrap();
+        assert_eq!(server.poll_event(), None);
+
+        assert!(client.disconnect(server_addr));
+        let (_, notice) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &notice, now).unwrap();
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Disconnect(client_addr))
+        );
+    }
+
+    #[test]
+    fn count_errors_of_addresses_without_a_connection() {
+        let peer_addr = "127.0.0.1:10041".parse::<SocketAddr>().unwrap();
+
+        let config = Config::default();
+        let mut server = ConnectionManager::new(config.clone());
+        let now = Instant::now();
+
+        // A peer on an outdated build, whose datagrams have another protocol version
+        let outdated = packet_of_connection(PacketType::Packet, &[1], 7)
+            .into_iter()
+            .enumerate()
+            .map(|(index, byte)| if index == 0 { !byte } else { byte })
+            .collect::<Vec<u8>>();
+        for _ in 0..2 {
+            assert!(server.handle_datagram(peer_addr, &outdated, now).is_err());
+        }
+        assert_eq!(server.errors_received(peer_addr), 2);
+        assert_eq!(server.connection_count(), 0);
+
+        // The connection takes over the count
+        server
+            .send(Packet::unreliable(peer_addr, vec![2]), now)
+            .unwrap();
+        assert_eq!(
+            server
+                .connection_stats(peer_addr, now)
+                .unwrap()
+                .errors_received,
+            2
+        );
+        server.disconnect(peer_addr);
+
+        // The count is dropped once the address was quiet for the idle timeout
+        assert!(server.handle_datagram(peer_addr, &outdated, now).is_err());
+        server
+            .handle_timers(now + config.idle_connection_timeout)
+            .unwrap();
+        assert_eq!(server.errors_received(peer_addr), 0);
+    }
+
+    #[test]
+    fn reject_new_peers_over_the_connection_limit() {
+        let server_addr = "127.0.0.1:10006".parse::<SocketAddr>().unwrap();
+        let first_addr = "127.0.0.1:10007".parse::<SocketAddr>().unwrap();
+        let second_addr = "127.0.0.1:10008".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config {
+            max_connections: Some(1),
+            ..Config::default()
+        });
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        server
+            .send(Packet::unreliable(first_addr, vec![1]), now)
+            .unwrap();
+        while server.poll_transmit().is_some() {}
+        match server.send(Packet::unreliable(second_addr, vec![1]), now) {
+            Err(ErrorKind::ConnectionLimitReached) => {}
+            result => panic!(
+                "Expected the connection limit to be reached, got {:?}",
+                result
+            ),
+        }
+
+        client
+            .send(Packet::unreliable(server_addr, vec![2]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        server.handle_datagram(second_addr, &datagram, now).unwrap();
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::ConnectionRejected(second_addr))
+        );
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.connection_count(), 1);
+
+        // The client closes its connection when it receives the rejection
+        let (addr, rejection) = server.poll_transmit().unwrap();
+        assert_eq!(addr, second_addr);
+        client
+            .handle_datagram(server_addr, &rejection, now)
+            .unwrap();
+        assert_eq!(
+            client.poll_event(),
+            Some(SocketEvent::ConnectionRejected(server_addr))
+        );
+        assert_eq!(client.connection_count(), 0);
+        assert_eq!(client.poll_transmit(), None);
+    }
+
+    #[test]
+    fn count_only_stored_connections_towards_the_connection_limit() {
+        let server_addr = "127.0.0.1:10035".parse::<SocketAddr>().unwrap();
+        let first_addr = "127.0.0.1:10036".parse::<SocketAddr>().unwrap();
+        let second_addr = "127.0.0.1:10037".parse::<SocketAddr>().unwrap();
+        let third_addr = "127.0.0.1:10046".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config {
+            max_connections: Some(1),
+            ..Config::default()
+        });
+        let now = Instant::now();
+        // Every peer has its own connection identifier, or it would be taken for a known peer whose address changed
+        let datagrams_of_new_peer = || {
+            let mut client = ConnectionManager::new(Config::default());
+            for payload in [vec![1], vec![2]] {
+                client
+                    .send(Packet::unreliable(server_addr, payload), now)
+                    .unwrap();
+            }
+            std::iter::from_fn(move || client.poll_transmit()).collect::<Vec<_>>()
+        };
+
+        // Peers we never sent to connect once each, without taking up a place
+        for addr in [first_addr, second_addr] {
+            for (_, datagram) in &datagrams_of_new_peer() {
+                server.handle_datagram(addr, datagram, now).unwrap();
+            }
+            assert_eq!(server.poll_event(), Some(SocketEvent::Connect(addr)));
+            assert_eq!(
+                server.poll_event(),
+                Some(SocketEvent::Packet(Packet::unreliable(addr, vec![1])))
+            );
+            assert_eq!(
+                server.poll_event(),
+                Some(SocketEvent::Packet(Packet::unreliable(addr, vec![2])))
+            );
+        }
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.connection_count(), 0);
+
+        // The first peer gets the place once we send to it
+        server
+            .send(Packet::unreliable(first_addr, vec![3]), now)
+            .unwrap();
+        while server.poll_transmit().is_some() {}
+        assert_eq!(server.connection_count(), 1);
+
+        // The second peer is rejected once we send to it, instead of staying connected without a place
+        match server.send(Packet::unreliable(second_addr, vec![3]), now) {
+            Err(ErrorKind::ConnectionLimitReached) => {}
+            result => panic!(
+                "Expected the connection limit to be reached, got {:?}",
+                result
+            ),
+        }
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::ConnectionRejected(second_addr))
+        );
+        let (addr, rejection) = server.poll_transmit().unwrap();
+        assert_eq!(addr, second_addr);
+        assert_eq!(
+            PacketReader::new(&rejection)
+                .read_standard_header()
+                .unwrap()
+                .packet_type(),
+            PacketType::ConnectionRejected
+        );
+        assert_eq!(server.poll_transmit(), None);
+        match server.send(Packet::unreliable(second_addr, vec![4]), now) {
+            Err(ErrorKind::ConnectionLimitReached) => {}
+            result => panic!(
+                "Expected the connection limit to be reached, got {:?}",
+                result
+            ),
+        }
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.poll_transmit(), None);
+
+        // A new peer is over the limit now, it is rejected once
+        for (_, datagram) in &datagrams_of_new_peer() {
+            server.handle_datagram(third_addr, datagram, now).unwrap();
+        }
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::ConnectionRejected(third_addr))
+        );
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(
+            server.poll_transmit().map(|(addr, _)| addr),
+            Some(third_addr)
+        );
+        assert_eq!(server.poll_transmit(), None);
+    }
+
+    #[test]
+    fn keep_idle_peers_until_their_timeout_is_queued() {
+        let server_addr = "127.0.0.1:10051".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10052".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            event_channel_capacity: Some(2),
+            ..Config::default()
+        };
+        let mut server = ConnectionManager::new(config.clone());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+        client
+            .send(Packet::unreliable(server_addr, vec![1]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+
+        // The connect and packet events fill the channel, so the timeout can't be queued yet
+        let timeout = now + config.idle_connection_timeout;
+        match server.handle_timers(timeout) {
+            Err(ErrorKind::QueueFull(SocketEvent::Timeout(addr))) => assert_eq!(addr, client_addr),
+            result => panic!("Expected the event channel to be full, got {:?}", result),
+        }
+        assert_eq!(server.poll_event(), Some(SocketEvent::Connect(client_addr)));
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                vec![1]
+            )))
+        );
+
+        server.handle_timers(timeout).unwrap();
+        assert_eq!(server.poll_event(), Some(SocketEvent::Timeout(client_addr)));
+        server.handle_timers(timeout).unwrap();
+        assert_eq!(server.poll_event(), None);
+    }
+
+    #[test]
+    fn forget_the_least_recently_heard_peer_we_never_sent_to() {
+        let server_addr = "127.0.0.1:10047".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+        client
+            .send(Packet::unreliable(server_addr, vec![1]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+
+        let peers: Vec<SocketAddr> = (0..=MAX_ANONYMOUS_PEERS)
+            .map(|index| SocketAddr::from(([10, 0, (index / 256) as u8, index as u8], 10048)))
+            .collect();
+        for (index, peer) in peers.iter().enumerate() {
+            let time = now + Duration::from_millis(index as u64);
+            server.handle_datagram(*peer, &datagram, time).unwrap();
+        }
+
+        let events: Vec<_> = std::iter::from_fn(|| server.poll_event()).collect();
+        assert_eq!(events.len(), 2 * peers.len() + 1);
+        assert_eq!(
+            events[events.len() - 3..],
+            [
+                SocketEvent::Evicted(peers[0]),
+                SocketEvent::Connect(peers[MAX_ANONYMOUS_PEERS]),
+                SocketEvent::Packet(Packet::unreliable(peers[MAX_ANONYMOUS_PEERS], vec![1])),
+            ]
+        );
+    }
+
+    #[test]
+    fn deliver_only_what_a_datagram_holds_on_its_own_from_peers_we_never_sent_to() {
+        let server_addr = "127.0.0.1:10049".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10050".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+        for payload in [vec![1], vec![2]] {
+            client
+                .send(Packet::reliable_ordered(server_addr, payload, None), now)
+                .unwrap();
+        }
+        client
+            .send(Packet::reliable_sequenced(server_addr, vec![3], None), now)
+            .unwrap();
+        client
+            .send(
+                Packet::reliable_unordered(server_addr, vec![4; 2 * 1024 * 8]),
+                now,
+            )
+            .unwrap();
+        while let Some((_, datagram)) = client.poll_transmit() {
+            server.handle_datagram(client_addr, &datagram, now).unwrap();
+        }
+
+        // The second ordered item can't be delivered without the first one, and fragments are not reassembled
+        assert_eq!(server.poll_event(), Some(SocketEvent::Connect(client_addr)));
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::reliable_ordered(
+                client_addr,
+                vec![1],
+                Some(DEFAULT_ORDERING_STREAM)
+            )))
+        );
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::reliable_sequenced(
+                client_addr,
+                vec![3],
+                Some(DEFAULT_SEQUENCING_STREAM)
+            )))
+        );
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.connection_count(), 0);
+    }
+
+    #[test]
+    fn validate_the_address_of_new_peers() {
+        let server_addr = "127.0.0.1:10009".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10010".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config {
+            validate_addresses: true,
+            ..Config::default()
+        });
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        client
+            .send(Packet::reliable_unordered(server_addr, vec![1, 2, 3]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.connection_count(), 0);
+
+        // The challenge is not larger than the datagram it answers
+        let (addr, challenge) = server.poll_transmit().unwrap();
+        assert_eq!(addr, client_addr);
+        assert!(challenge.len() <= datagram.len());
+        client
+            .handle_datagram(server_addr, &challenge, now)
+            .unwrap();
+        assert_eq!(client.poll_event(), None);
+
+        // The client returns the cookie and resends the packet the server dropped
+        while let Some((addr, datagram)) = client.poll_transmit() {
+            assert_eq!(addr, server_addr);
+            server.handle_datagram(client_addr, &datagram, now).unwrap();
+        }
+        assert_eq!(server.poll_event(), Some(SocketEvent::Connect(client_addr)));
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::reliable_unordered(
+                client_addr,
+                vec![1, 2, 3]
+            )))
+        );
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.connection_count(), 1);
+        // Without negotiation the server sends no hello
+        assert_eq!(server.poll_transmit(), None);
+    }
+
+    #[test]
+    fn never_send_more_than_received_to_unvalidated_peers() {
+        let server_addr = "127.0.0.1:10011".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10012".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config {
+            validate_addresses: true,
+            ..Config::default()
+        });
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        // Too small to be answered with a challenge
+        client
+            .send(Packet::unreliable(server_addr, vec![1]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+        assert_eq!(server.poll_transmit(), None);
+
+        // A forged cookie does not validate the address
+        let forged = control_packet(PacketType::ChallengeResponse, &[0; 8]);
+        server.handle_datagram(client_addr, &forged, now).unwrap();
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.connection_count(), 0);
+
+        // Challenges are only answered for peers that were sent to
+        let challenge = control_packet(PacketType::Challenge, &[0; 8]);
+        let mut stranger = ConnectionManager::new(Config::default());
+        stranger
+            .handle_datagram(server_addr, &challenge, now)
+            .unwrap();
+        assert_eq!(stranger.poll_transmit(), None);
+        assert_eq!(stranger.poll_event(), None);
+    }
+
+    #[test]
+    fn move_the_connection_when_the_address_changes() {
+        let server_addr = "127.0.0.1:10013".parse::<SocketAddr>().unwrap();
+        let old_addr = "127.0.0.1:10014".parse::<SocketAddr>().unwrap();
+        let new_addr = "127.0.0.1:10015".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        client
+            .send(Packet::reliable_unordered(server_addr, vec![1]), now)
+            .unwrap();
+        server
+            .send(Packet::reliable_unordered(old_addr, vec![2]), now)
+            .unwrap();
+        client
+            .send(Packet::reliable_unordered(server_addr, vec![3]), now)
+            .unwrap();
+        while let Some((_, datagram)) = client.poll_transmit() {
+            server.handle_datagram(old_addr, &datagram, now).unwrap();
+        }
+        while let Some((_, datagram)) = server.poll_transmit() {
+            client.handle_datagram(server_addr, &datagram, now).unwrap();
+        }
+        while server.poll_event().is_some() {}
+        while client.poll_event().is_some() {}
+
+        // The NAT mapping of the client changes, the server challenges the new address
+        client
+            .send(Packet::reliable_unordered(server_addr, vec![4]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        server.handle_datagram(new_addr, &datagram, now).unwrap();
+        assert_eq!(server.poll_event(), None);
+        let (addr, challenge) = server.poll_transmit().unwrap();
+        assert_eq!(addr, new_addr);
+
+        client
+            .handle_datagram(server_addr, &challenge, now)
+            .unwrap();
+        while let Some((_, datagram)) = client.poll_transmit() {
+            server.handle_datagram(new_addr, &datagram, now).unwrap();
+        }
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::AddressChanged {
+                old: old_addr,
+                new: new_addr
+            })
+        );
+        let events: Vec<SocketEvent> = std::iter::from_fn(|| server.poll_event()).collect();
+        assert!(
+            events.contains(&SocketEvent::Packet(Packet::reliable_unordered(
+                new_addr,
+                vec![4]
+            )))
+        );
+        assert!(!events.contains(&SocketEvent::Connect(new_addr)));
+
+        assert_eq!(server.connection_count(), 1);
+        assert!(server.connection_stats(old_addr, now).is_none());
+        assert!(server.connection_stats(new_addr, now).is_some());
+    }
+
+    #[test]
+    fn keep_the_connection_when_a_stranger_uses_the_identifier_of_the_peer() {
+        let server_addr = "127.0.0.1:10027".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10028".parse::<SocketAddr>().unwrap();
+        let stranger_addr = "127.0.0.1:10029".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        server
+            .send(Packet::reliable_unordered(client_addr, vec![1]), now)
+            .unwrap();
+        client
+            .send(Packet::reliable_unordered(server_addr, vec![2]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+        while server.poll_transmit().is_some() {}
+        while server.poll_event().is_some() {}
+        let client_id = client
+            .connections
+            .get_connection(&server_addr)
+            .unwrap()
+            .connection_id;
+
+        // The stranger receives the challenge at its own address, but does not know the identifier the server gave the connection
+        let datagram = packet_of_connection(PacketType::Packet, &[0; 16], client_id);
+        server
+            .handle_datagram(stranger_addr, &datagram, now)
+            .unwrap();
+        let (addr, challenge) = server.poll_transmit().unwrap();
+        assert_eq!(addr, stranger_addr);
+
+        let cookie = &challenge[STANDARD_HEADER_SIZE as usize..];
+        for response in [cookie.to_vec(), bind_cookie(cookie, 1)].iter() {
+            let datagram = packet_of_connection(PacketType::ChallengeResponse, response, client_id);
+            server
+                .handle_datagram(stranger_addr, &datagram, now)
+                .unwrap();
+        }
+        assert_eq!(server.poll_event(), None);
+        assert!(server.connection_stats(client_addr, now).is_some());
+        assert!(server.connection_stats(stranger_addr, now).is_none());
+    }
+
+    #[test]
+    fn ignore_malformed_parities_of_unknown_peers() {
+        let server_addr = "127.0.0.1:10030".parse::<SocketAddr>().unwrap();
+        let mut server = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        // A parity of a group of one datagram with a body of a single byte, 15 bytes in total
+        let mut body = Vec::new();
+        ParityHeader::new(0, 1, 0, false).parse(&mut body).unwrap();
+        body.push(1);
+        let parity = control_packet(PacketType::Parity, &body);
+        assert_eq!(parity.len(), 15);
+
+        server.handle_datagram(server_addr, &parity, now).unwrap();
+        assert_eq!(server.poll_transmit(), None);
+        assert_eq!(server.connection_count(), 0);
+    }
+
+    #[test]
+    fn drop_datagrams_with_a_wrong_checksum() {
+        let server_addr = "127.0.0.1:10016".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10017".parse::<SocketAddr>().unwrap();
+        let config = Config {
+            checksum: true,
+            ..Config::default()
+        };
+
+        let mut server = ConnectionManager::new(config.clone());
+        let mut client = ConnectionManager::new(config);
+        let metrics = Arc::new(InMemoryMetrics::new());
+        server.set_metrics(Some(metrics.clone()));
+        let now = Instant::now();
+
+        client
+            .send(Packet::unreliable(server_addr, vec![1, 2, 3]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        // the checksum takes the place of the protocol version
+        assert_eq!(
+            datagram.len(),
+            STANDARD_HEADER_SIZE as usize - PROTOCOL_VERSION_SIZE + 3 + CHECKSUM_SIZE
+        );
+
+        let mut corrupt = datagram.to_vec();
+        corrupt[STANDARD_HEADER_SIZE as usize] ^= 1;
+        match server.handle_datagram(client_addr, &corrupt, now) {
+            Err(ErrorKind::ChecksumMismatch) => {}
+            result => panic!("Expected a checksum mismatch, got {:?}", result),
+        }
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(metrics.decode_errors().get("checksum_mismatch"), Some(&1));
+
+        // The corrupt datagram may be of another protocol, the reply to it is corrupt for the client
+        let (_, reply) = server.poll_transmit().unwrap();
+        match client.handle_datagram(server_addr, &reply, now) {
+            Err(ErrorKind::ChecksumMismatch) => {}
+            result => panic!("Expected a checksum mismatch, got {:?}", result),
+        }
+        assert_eq!(client.poll_event(), None);
+        assert_eq!(client.connection_count(), 1);
+
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+        assert_eq!(server.poll_event(), Some(SocketEvent::Connect(client_addr)));
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                vec![1, 2, 3]
+            )))
+        );
+    }
+
+    #[test]
+    fn fail_the_checksum_of_corrupt_datagrams_and_other_protocols() {
+        let client_addr = "127.0.0.1:10056".parse::<SocketAddr>().unwrap();
+        let config = Config {
+            checksum: true,
+            protocol_id: 1,
+            ..Config::default()
+        };
+
+        let mut client = ConnectionManager::new(config.clone());
+        let now = Instant::now();
+        client
+            .send(Packet::unreliable(client_addr, vec![1, 2, 3]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+
+        let server = ConnectionManager::new(config.clone());
+        assert!(server.verify_checksum(&datagram).is_ok());
+
+        let mut corrupt = datagram.to_vec();
+        let last_payload_byte = corrupt.len() - CHECKSUM_SIZE - 1;
+        corrupt[last_payload_byte] ^= 1;
+        match server.verify_checksum(&Payload::from(corrupt)) {
+            Err(ErrorKind::ChecksumMismatch) => {}
+            result => panic!("Expected a checksum mismatch, got {:?}", result),
+        }
+
+        let other_protocol = ConnectionManager::new(Config {
+            protocol_id: 2,
+            ..config
+        });
+        match other_protocol.verify_checksum(&datagram) {
+            Err(ErrorKind::ChecksumMismatch) => {}
+            result => panic!("Expected a checksum mismatch, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reply_to_peers_of_another_protocol_with_a_checksum() {
+        let server_addr = "127.0.0.1:10054".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10055".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config {
+            protocol_id: 2,
+            checksum: true,
+            ..Config::default()
+        });
+        let mut client = ConnectionManager::new(Config {
+            protocol_id: 1,
+            checksum: true,
+            ..Config::default()
+        });
+        let now = Instant::now();
+
+        client
+            .send(Packet::unreliable(server_addr, vec![1, 2, 3]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        match server.handle_datagram(client_addr, &datagram, now) {
+            Err(ErrorKind::ChecksumMismatch) => {}
+            result => panic!("Expected a checksum mismatch, got {:?}", result),
+        }
+
+        let (_, reply) = server.poll_transmit().unwrap();
+        client.handle_datagram(server_addr, &reply, now).unwrap();
+        assert_eq!(
+            client.poll_event(),
+            Some(SocketEvent::VersionMismatch(server_addr))
+        );
+        assert_eq!(client.connection_count(), 0);
+    }
+
+    #[test]
+    fn reply_to_peers_of_another_protocol_with_a_version_mismatch() {
+        let server_addr = "127.0.0.1:10018".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10019".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config {
+            protocol_id: 2,
+            ..Config::default()
+        });
+        let mut client = ConnectionManager::new(Config {
+            protocol_id: 1,
+            ..Config::default()
+        });
+        let now = Instant::now();
+
+        client
+            .send(Packet::unreliable(server_addr, vec![1, 2, 3]), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        match server.handle_datagram(client_addr, &datagram, now) {
+            Err(ErrorKind::ProtocolVersionMismatch) => {}
+            result => panic!("Expected a version mismatch, got {:?}", result),
+        }
+        assert_eq!(server.poll_event(), None);
+        assert_eq!(server.connection_count(), 0);
+
+        let (addr, reply) = server.poll_transmit().unwrap();
+        assert_eq!(addr, client_addr);
+        assert!(reply.len() <= datagram.len());
+
+        // further datagrams of the peer are not answered until it timed out
+        server.handle_datagram(client_addr, &datagram, now).ok();
+        assert_eq!(server.poll_transmit(), None);
+        let timeout = now + Config::default().idle_connection_timeout;
+        server.handle_timers(timeout).unwrap();
+        server.handle_datagram(client_addr, &datagram, timeout).ok();
+        assert!(server.poll_transmit().is_some());
+
+        client.handle_datagram(server_addr, &reply, now).unwrap();
+        assert_eq!(
+            client.poll_event(),
+            Some(SocketEvent::VersionMismatch(server_addr))
+        );
+        assert_eq!(client.connection_count(), 0);
+        assert!(client.poll_transmit().is_none());
+    }
+
+    #[test]
+    fn negotiate_the_wire_version_and_features() {
+        let server_addr = "127.0.0.1:10020".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10021".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config {
+            compressor: Some(Arc::new(Lz4Compressor)),
+            ..Config::default()
+        });
+        let mut client = ConnectionManager::new(Config {
+            negotiate: true,
+            compressor: Some(Arc::new(Lz4Compressor)),
+            ..Config::default()
+        });
+        let now = Instant::now();
+
+        // The first hello is lost, since the server has no connection with the client yet
+        client
+            .send(Packet::unreliable(server_addr, vec![1]), now)
+            .unwrap();
+        while let Some((_, datagram)) = client.poll_transmit() {
+            server.handle_datagram(client_addr, &datagram, now).unwrap();
+        }
+        server
+            .send(Packet::unreliable(client_addr, vec![2]), now)
+            .unwrap();
+        let (_, datagram) = server.poll_transmit().unwrap();
+        client.handle_datagram(server_addr, &datagram, now).unwrap();
+        assert_eq!(server.poll_transmit(), None);
+
+        let resend = client.next_deadline().unwrap();
+        assert_eq!(resend, now + HELLO_INTERVAL);
+        client.handle_timers(resend).unwrap();
+        let (_, hello) = client.poll_transmit().unwrap();
+        server.handle_datagram(client_addr, &hello, resend).unwrap();
+        let (_, answer) = server.poll_transmit().unwrap();
+        client
+            .handle_datagram(server_addr, &answer, resend)
+            .unwrap();
+
+        let negotiated = Some(Negotiated {
+            version: WIRE_VERSION,
+            features: Features::COMPRESSION,
+        });
+        assert_eq!(
+            client
+                .connection_stats(server_addr, resend)
+                .unwrap()
+                .negotiated,
+            negotiated
+        );
+        assert_eq!(
+            server
+                .connection_stats(client_addr, resend)
+                .unwrap()
+                .negotiated,
+            negotiated
+        );
+        // The answer acknowledges the hello of the client, so it is not answered again
+        assert_eq!(client.poll_transmit(), None);
+        client.handle_timers(resend + HELLO_INTERVAL).unwrap();
+        assert_eq!(client.poll_transmit(), None);
+
+        // A later hello is only answered, it does not change what was settled
+        let downgrade = Hello {
+            min_version: WIRE_VERSION + 1,
+            max_version: WIRE_VERSION + 1,
+            features: Features::NONE,
+            acknowledged: false,
+        };
+        let client_id = server
+            .connections
+            .get_connection(&client_addr)
+            .and_then(VirtualConnection::remote_connection_id)
+            .unwrap();
+        let hello = packet_of_connection(PacketType::Hello, &downgrade.to_bytes(), client_id);
+        server.handle_datagram(client_addr, &hello, resend).unwrap();
+        assert!(server.poll_transmit().is_some());
+        assert_eq!(server.poll_transmit(), None);
+
+        // A hello with another identifier is not even answered
+        let hello = packet_of_connection(PacketType::Hello, &downgrade.to_bytes(), 42);
+        server.handle_datagram(client_addr, &hello, resend).unwrap();
+        assert_eq!(server.poll_transmit(), None);
+        assert_eq!(
+            server
+                .connection_stats(client_addr, resend)
+                .unwrap()
+                .negotiated,
+            negotiated
+        );
+    }
+
+    #[test]
+    fn close_the_connection_without_a_common_wire_version() {
+        let client_addr = "127.0.0.1:10022".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+
+        server
+            .send(Packet::unreliable(client_addr, vec![1]), now)
+            .unwrap();
+        server.poll_transmit().unwrap();
+
+        let hello = Hello {
+            min_version: WIRE_VERSION + 1,
+            max_version: WIRE_VERSION + 1,
+            features: Features::NONE,
+            acknowledged: false,
+        };
+        server
+            .handle_datagram(
+                client_addr,
+                &control_packet(PacketType::Hello, &hello.to_bytes()),
+                now,
+            )
+            .unwrap();
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::VersionMismatch(client_addr))
+        );
+        assert_eq!(server.connection_count(), 0);
+
+        let (addr, notice) = server.poll_transmit().unwrap();
+        assert_eq!(addr, client_addr);
+        assert_eq!(
+            PacketReader::new(&notice)
+                .read_standard_header()
+                .unwrap()
+                .packet_type(),
+            PacketType::VersionMismatch
+        );
+    }
+
+    #[test]
+    fn exchange_compressed_payloads() {
+        let server_addr = "127.0.0.1:10023".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10024".parse::<SocketAddr>().unwrap();
+        let config = Config {
+            compressor: Some(Arc::new(Lz4Compressor)),
+            ..Config::default()
+        };
+
+        let mut server = ConnectionManager::new(config.clone());
+        let mut client = ConnectionManager::new(config);
+        let now = Instant::now();
+        server
+            .send(Packet::unreliable(client_addr, vec![]), now)
+            .unwrap();
+        server.poll_transmit().unwrap();
+
+        // A fragmented packet is compressed per fragment
+        let snapshot = vec![3; 3000];
+        client
+            .send(
+                Packet::reliable_unordered(server_addr, snapshot.clone()),
+                now,
+            )
+            .unwrap();
+        let mut sent = 0;
+        while let Some((_, datagram)) = client.poll_transmit() {
+            sent += datagram.len();
+            server.handle_datagram(client_addr, &datagram, now).unwrap();
+        }
+        assert!(sent < snapshot.len() / 10);
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::reliable_unordered(
+                client_addr,
+                snapshot
+            )))
+        );
+
+        // Both endpoints switch to the same dictionary
+        let dictionary: Arc<[u8]> = Arc::from(&b"position rotation velocity"[..]);
+        assert!(server.set_compression_dictionary(client_addr, Some(dictionary.clone())));
+        assert!(client.set_compression_dictionary(server_addr, Some(dictionary)));
+
+        let update = b"position velocity".to_vec();
+        client
+            .send(Packet::unreliable(server_addr, update.clone()), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        assert!(datagram.len() < STANDARD_HEADER_SIZE as usize + update.len());
+        server.handle_datagram(client_addr, &datagram, now).unwrap();
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                update.clone()
+            )))
+        );
+
+        // A payload compressed with another dictionary is dropped
+        assert!(client.set_compression_dictionary(server_addr, None));
+        client
+            .send(Packet::unreliable(server_addr, update.repeat(4)), now)
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+        assert!(server.handle_datagram(client_addr, &datagram, now).is_err());
+        assert_eq!(server.poll_event(), None);
+    }
+
+    #[test]
+    fn rebuild_lost_datagrams_from_parities() {
+        let server_addr = "127.0.0.1:10025".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10026".parse::<SocketAddr>().unwrap();
+
+        let mut server = ConnectionManager::new(Config::default());
+        let mut client = ConnectionManager::new(Config::default());
+        let now = Instant::now();
+        server
+            .send(Packet::unreliable(client_addr, vec![]), now)
+            .unwrap();
+        server.poll_transmit().unwrap();
+
+        // The three fragments are followed by a parity, the second fragment gets lost
+        let snapshot: Vec<u8> = (0..2100u32).map(|i| i as u8).collect();
+        client
+            .send(
+                Packet::reliable_unordered(server_addr, snapshot.clone())
+                    .with_redundancy(Redundancy::Xor),
+                now,
+            )
+            .unwrap();
+        let datagrams: Vec<_> = std::iter::from_fn(|| client.poll_transmit()).collect();
+        assert_eq!(datagrams.len(), 4);
+        for (index, (_, datagram)) in datagrams.iter().enumerate() {
+            if index != 1 {
+                server.handle_datagram(client_addr, datagram, now).unwrap();
+            }
+        }
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::reliable_unordered(
+                client_addr,
+                snapshot
+            )))
+        );
+
+        // Unreliable packets are protected in groups of four, two of them get lost
+        for id in 0..4u8 {
+            client
+                .send(
+                    Packet::unreliable(server_addr, vec![id; id as usize + 1])
+                        .with_redundancy(Redundancy::ReedSolomon(2)),
+                    now,
+                )
+                .unwrap();
+        }
+        let datagrams: Vec<_> = std::iter::from_fn(|| client.poll_transmit()).collect();
+        assert_eq!(datagrams.len(), 6);
+        for (index, (_, datagram)) in datagrams.iter().enumerate() {
+            if index != 0 && index != 2 {
+                server.handle_datagram(client_addr, datagram, now).unwrap();
+            }
+        }
+        for id in [1, 3, 0, 2] {
+            assert_eq!(
+                server.poll_event(),
+                Some(SocketEvent::Packet(Packet::unreliable(
+                    client_addr,
+                    vec![id; id as usize + 1]
+                )))
+            );
+        }
+
+        // A lost datagram which arrives late was delivered already
+        server
+            .handle_datagram(client_addr, &datagrams[0].1, now)
+            .unwrap();
+        assert_eq!(server.poll_event(), None);
+        let stats = server.connection_stats(client_addr, now).unwrap();
+        assert_eq!(stats.packets_rebuilt, 3);
+    }
+
+    #[test]
+    fn flush_the_parities_of_an_open_group_after_its_timeout() {
+        let server_addr = "127.0.0.1:10031".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10032".parse::<SocketAddr>().unwrap();
+
+        let config = Config::default();
+        let mut server = ConnectionManager::new(config.clone());
+        let mut client = ConnectionManager::new(config.clone());
+        let now = Instant::now();
+        server
+            .send(Packet::unreliable(client_addr, vec![]), now)
+            .unwrap();
+        server.poll_transmit().unwrap();
+
+        // A single packet leaves its group open, its parity follows once no more packets came in time
+        client
+            .send(
+                Packet::unreliable(server_addr, vec![1, 2, 3]).with_redundancy(Redundancy::Xor),
+                now,
+            )
+            .unwrap();
+        client.poll_transmit().unwrap();
+        assert!(client.poll_transmit().is_none());
+        let deadline = now + config.parity_group_timeout;
+        assert_eq!(client.next_deadline(), Some(deadline));
+
+        client.handle_timers(deadline).unwrap();
+        let (_, parity) = client.poll_transmit().unwrap();
+        assert!(client.poll_transmit().is_none());
+        assert!(client.next_deadline().unwrap() > deadline);
+
+        // The lost packet is rebuilt from the parity alone
+        server
+            .handle_datagram(client_addr, &parity, deadline)
+            .unwrap();
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                vec![1, 2, 3]
+            )))
+        );
+    }
+
+    #[test]
+    fn protect_more_fragments_than_a_group_holds_over_several_groups() {
+        let server_addr = "127.0.0.1:10033".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:10034".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            fragment_size: 8,
+            max_fragments: 200,
+            ..Config::default()
+        };
+        let mut server = ConnectionManager::new(config.clone());
+        let mut client = ConnectionManager::new(config);
+        let now = Instant::now();
+        server
+            .send(Packet::unreliable(client_addr, vec![]), now)
+            .unwrap();
+        server.poll_transmit().unwrap();
+
+        // 130 fragments make a group of 128 and one of 2, a fragment of each group gets lost
+        let payload: Vec<u8> = (0..1040u32).map(|i| i as u8).collect();
+        client
+            .send(
+                Packet::unreliable(server_addr, payload.clone()).with_redundancy(Redundancy::Xor),
+                now,
+            )
+            .unwrap();
+        let datagrams: Vec<_> = std::iter::from_fn(|| client.poll_transmit()).collect();
+        assert_eq!(datagrams.len(), 132);
+        for (index, (_, datagram)) in datagrams.iter().enumerate() {
+            if index != 5 && index != 129 {
+                server.handle_datagram(client_addr, datagram, now).unwrap();
+            }
+        }
+        assert_eq!(
+            server.poll_event(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                payload
+            )))
+        );
+    }
+
+    fn control_packet(packet_type: PacketType, body: &[u8]) -> Vec<u8> {
+        packet_of_connection(packet_type, body, 0)
+    }
+
+    fn packet_of_connection(packet_type: PacketType, body: &[u8], connection_id: u32) -> Vec<u8> {
+        let mut datagram = Vec::new();
+        OutgoingPacketBuilder::new(body)
+            .with_default_header(
+                packet_type,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::None,
+                ProtocolVersion::get_crc16(0),
+                connection_id,
+            )
+            .build()
+            .write_contents(&mut datagram);
+        datagram
+    }
+}
@@ -0,0 +1,48 @@
+use crate::net::{Negotiated, NetworkQuality};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// A snapshot of the statistics of a connection.
+///
+/// Returned by `Socket::connection_stats` and `Socket::connections`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ConnectionStats {
+    /// The address of the remote endpoint.
+    pub remote_address: SocketAddr,
+    /// The smoothed round trip time, zero until the first packet is acknowledged.
+    pub rtt: Duration,
+    /// The jitter of the round trip time.
+    pub jitter: Duration,
+    /// The fraction, between 0 and 1, of the most recent reliable packets we sent that got lost.
+    pub outbound_packet_loss: f32,
+    /// The fraction, between 0 and 1, of the most recent reliable packets the remote endpoint sent that got lost.
+    pub inbound_packet_loss: f32,
+    /// The quality of the network, decided from the round trip time, packet loss and jitter.
+    pub quality: NetworkQuality,
+    /// The number of datagrams sent, including resent packets, every single fragment and datagrams of the connection setup.
+    ///
+    /// Datagrams are counted when they are handed over to the transport, so the ones dropped by the link conditioner are not.
+    pub packets_sent: u64,
+    /// The number of packets received, including every single fragment.
+    pub packets_received: u64,
+    /// The number of bytes sent, including headers and checksums.
+    pub bytes_sent: u64,
+    /// The number of bytes received, including headers.
+    pub bytes_received: u64,
+    /// The number of reliable packets that were resent because they were not acknowledged.
+    pub packets_resent: u64,
+    /// The number of reliable packets that are sent but not acknowledged yet.
+    pub packets_in_flight: usize,
+    /// The number of packets of which some, but not all, fragments are received.
+    pub packets_in_reassembly: usize,
+    /// The number of packets that were reassembled from fragments.
+    pub packets_reassembled: u64,
+    /// The number of datagrams that were rebuilt from parities, instead of being resent, see `Packet::with_redundancy`.
+    pub packets_rebuilt: u64,
+    /// The number of datagrams received that could not be processed, e.g. because they were malformed.
+    pub errors_received: u64,
+    /// The time since we last heard from the remote endpoint.
+    pub last_heard: Duration,
+    /// The wire version and optional features both endpoints settled on, `None` until the hello of the remote endpoint is received.
+    pub negotiated: Option<Negotiated>,
+}
@@ -1,11 +1,11 @@
 /// The size of the fragment header.
 pub const FRAGMENT_HEADER_SIZE: u8 = 4;
 /// The size of the acknowledgment header.
-pub const ACKED_PACKET_HEADER: u8 = 8;
+pub const ACKED_PACKET_HEADER: u8 = 11;
 /// The size of the arranging header.
 pub const ARRANGING_PACKET_HEADER: u8 = 3;
 /// The size of the standard header.
-pub const STANDARD_HEADER_SIZE: u8 = 5;
+pub const STANDARD_HEADER_SIZE: u8 = 9;
 /// The ordering stream that will be used to order on if there is not ordering stream specified.
 pub const DEFAULT_ORDERING_STREAM: u8 = 255;
 /// The sequencing stream that will be used to sequence packets on if there is not sequencing stream specified.
@@ -14,17 +14,73 @@ pub const DEFAULT_SEQUENCING_STREAM: u8 = 255;
 pub const MAX_FRAGMENTS_DEFAULT: u16 = 16;
 /// Default maximal size of each fragment.
 pub const FRAGMENT_SIZE_DEFAULT: u16 = 1024;
-/// Maximum transmission unit of the payload.
+/// Maximum size of a UDP payload over IPv4 that fits in an ethernet frame without IP fragmentation.
 ///
-/// Derived from ethernet_mtu - ipv6_header_size - udp_header_size - packet header size
-///       1452 = 1500         - 40               - 8               - 8
+/// Derived from ethernet_mtu - ipv4_header_size - udp_header_size
+///       1472 = 1500         - 20               - 8
+///
+/// This is not strictly guaranteed -- there may be less room in an ethernet frame than this due to
+/// IPv4 options or tunnels along the path.
+pub const IPV4_MTU: u16 = 1472;
+/// Maximum size of a UDP payload over IPv6 that fits in an ethernet frame without IP fragmentation.
+///
+/// Derived from ethernet_mtu - ipv6_header_size - udp_header_size
+///       1452 = 1500         - 40               - 8
 ///
 /// This is not strictly guaranteed -- there may be less room in an ethernet frame than this due to
 /// variability in ipv6 header size.
-pub const DEFAULT_MTU: u16 = 1452;
+pub const IPV6_MTU: u16 = 1452;
+/// Maximum transmission unit of the payload when the address family is not known.
+///
+/// This is the larger of `IPV4_MTU` and `IPV6_MTU`, so a datagram which a peer of either family sends without
+/// IP fragmentation is never truncated, whichever way the endpoints were set up.
+pub const DEFAULT_MTU: u16 = if IPV4_MTU > IPV6_MTU {
+    IPV4_MTU
+} else {
+    IPV6_MTU
+};
+/// The largest number of bytes a datagram carries on top of a fragment of `Config::fragment_size` bytes.
+///
+/// A fragment is sent with the standard, fragment, acknowledgment and arranging headers and, when compressed, the 2 byte
+/// identifier of its dictionary. A parity of a group of fragments adds another standard header, the parity header and
+/// 2 bytes of length to these, and the checksum adds 2 bytes to every datagram.
+pub const MAX_FRAGMENT_OVERHEAD: u16 = (STANDARD_HEADER_SIZE
+    + FRAGMENT_HEADER_SIZE
+    + ACKED_PACKET_HEADER
+    + ARRANGING_PACKET_HEADER
+    + STANDARD_HEADER_SIZE
+    + PARITY_HEADER_SIZE) as u16
+    + 2
+    + 2
+    + (CHECKSUM_SIZE - PROTOCOL_VERSION_SIZE) as u16;
+/// The maximal number of datagrams sent or received with a single system call.
+pub const MAX_BATCH_SIZE: usize = 64;
+/// The maximal number of datagrams sent as one message with UDP generic segmentation offload.
+pub const MAX_GSO_SEGMENTS: usize = 64;
 /// This is the current protocol version.
 ///
 /// It is used for:
 /// - Generating crc16 for the packet header.
 /// - Validating if arriving packets have the same protocol version.
-pub const PROTOCOL_VERSION: &str = "laminar-0.1.0";
+pub const PROTOCOL_VERSION: &str = "laminar-0.2.0";
+/// The newest wire version this build supports, connections settle on the highest version both endpoints support.
+///
+/// Unlike `PROTOCOL_VERSION`, endpoints of different wire versions accept each other's packets, see `Config::min_wire_version`.
+pub const WIRE_VERSION: u8 = 1;
+/// The size of the checksum appended to each datagram, when enabled in the config.
+pub const CHECKSUM_SIZE: usize = 4;
+/// The size of the protocol version which starts the standard header, it is not sent when the checksum is enabled in the config.
+pub const PROTOCOL_VERSION_SIZE: usize = 2;
+/// The maximal number of rejected peers and peers of another protocol which are remembered each, further ones are not answered.
+pub const MAX_REJECTED_PEERS: usize = 1024;
+/// The maximal number of peers we received from but never sent to which are remembered, the least recently heard one is
+/// evicted for a new one, see `SocketEvent::Evicted`.
+pub const MAX_ANONYMOUS_PEERS: usize = 1024;
+/// The maximal number of addresses without a connection whose receive errors are counted at once.
+pub const MAX_ERROR_COUNTERS: usize = 1024;
+/// The size of the cookie carried by a challenge and its response.
+pub const COOKIE_SIZE: usize = 8;
+/// The size of the parity header.
+pub const PARITY_HEADER_SIZE: u8 = 5;
+/// The size of the trailer of a datagram in a parity group, which carries the group and the index in it.
+pub const PARITY_TRAILER_SIZE: usize = 3;
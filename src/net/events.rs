@@ -1,3 +1,4 @@
+use crate::error::ReceiveErrorKind;
 use crate::packet::Packet;
 use std::net::SocketAddr;
 
@@ -12,4 +13,36 @@ pub enum SocketEvent {
     /// The client has been idling for a configurable amount of time.
     /// You can control the timeout in the config.
     Timeout(SocketAddr),
+    /// The client closed the connection, e.g. because its socket was shut down gracefully.
+    Disconnect(SocketAddr),
+    /// A client we only received from, but never sent to, was forgotten before it timed out, to make room for a new one.
+    /// See `Config::max_connections`.
+    Evicted(SocketAddr),
+    /// A new client was rejected because `Config::max_connections` was reached, it was replied that the server is full.
+    /// A client raises this as well when the server rejects it, with the address of the server.
+    ConnectionRejected(SocketAddr),
+    /// The remote endpoint does not accept our packets, because it uses another protocol version or `Config::protocol_id`.
+    /// The connection is closed.
+    VersionMismatch(SocketAddr),
+    /// The address of a client changed, e.g. because its NAT mapping was renewed, and the client proved it receives datagrams at the new address.
+    /// The connection keeps all its state, but packets to the client have to be sent to the new address from now on.
+    AddressChanged {
+        /// The address the client had before.
+        old: SocketAddr,
+        /// The address the connection was moved to.
+        new: SocketAddr,
+    },
+    /// An ordering stream of the client has been waiting too long for a missing packet, or had to drop packets because its buffer was full.
+    /// Packets behind the missing one are held back until it arrives.
+    /// You can control the buffer limits and the timeout in the config.
+    StreamStalled(SocketAddr, u8),
+    /// A datagram could not be received or decoded, e.g. because the client uses another protocol version.
+    /// `addr` is `None` when the error is not related to a single datagram, e.g. an IO error of the socket.
+    /// This event is only raised when enabled in the config.
+    Error {
+        /// The address the datagram was received from.
+        addr: Option<SocketAddr>,
+        /// The kind of error that occurred.
+        kind: ReceiveErrorKind,
+    },
 }
@@ -0,0 +1,259 @@
+use crate::{
+    config::Config,
+    error::{ErrorKind, Result},
+    net::constants::WIRE_VERSION,
+};
+use std::{ops::BitOr, time::Duration};
+
+/// A hello is sent again after this long, until the one of the remote endpoint is received.
+pub const HELLO_INTERVAL: Duration = Duration::from_millis(250);
+
+/// A set of optional wire features, which are only used on a connection when both endpoints support them.
+///
+/// See `Config::features`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Features {
+    bits: u8,
+}
+
+impl Features {
+    /// No optional features.
+    pub const NONE: Features = Features { bits: 0 };
+    /// Compression of the payloads.
+    pub const COMPRESSION: Features = Features { bits: 1 << 0 };
+
+    /// Returns the features of a bit set, bits of unknown features are ignored.
+    pub fn from_bits(bits: u8) -> Features {
+        Features {
+            bits: bits & Features::COMPRESSION.bits,
+        }
+    }
+
+    /// Returns the bit set of the features.
+    pub fn bits(self) -> u8 {
+        self.bits
+    }
+
+    /// Returns whether all of `other` are in this set.
+    pub fn contains(self, other: Features) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// Returns the features of this set which are not in `other`.
+    pub fn difference(self, other: Features) -> Features {
+        Features {
+            bits: self.bits & !other.bits,
+        }
+    }
+
+    /// Returns the features which are in both sets.
+    pub fn intersection(self, other: Features) -> Features {
+        Features {
+            bits: self.bits & other.bits,
+        }
+    }
+}
+
+impl BitOr for Features {
+    type Output = Features;
+
+    fn bitor(self, other: Features) -> Features {
+        Features {
+            bits: self.bits | other.bits,
+        }
+    }
+}
+
+/// What the endpoints of a connection settled on, see `ConnectionStats::negotiated`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Negotiated {
+    /// The highest wire version both endpoints support.
+    pub version: u8,
+    /// The optional features both endpoints support.
+    pub features: Features,
+}
+
+/// The body of a hello, which advertises what an endpoint supports.
+///
+/// Both endpoints send one when their connection is set up. A hello whose sender did not receive ours yet is answered
+/// with ours, a hello which acknowledges ours is not, so two endpoints never keep replying to each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hello {
+    pub min_version: u8,
+    pub max_version: u8,
+    pub features: Features,
+    /// Whether the sender received our hello already.
+    pub acknowledged: bool,
+}
+
+impl Hello {
+    /// The size of a hello body.
+    pub const SIZE: usize = 4;
+
+    /// Returns the hello of an endpoint with the given config, which offers the features of `Config::features`.
+    ///
+    /// Compression is only offered when there is a compressor as well.
+    /// A `Config::min_wire_version` newer than `WIRE_VERSION` is lowered to it, so the range is never empty.
+    pub fn new(config: &Config, acknowledged: bool) -> Hello {
+        let features = if config.compressor.is_some() {
+            config.features
+        } else {
+            config.features.difference(Features::COMPRESSION)
+        };
+
+        Hello {
+            min_version: config.min_wire_version.min(WIRE_VERSION),
+            max_version: WIRE_VERSION,
+            features,
+            acknowledged,
+        }
+    }
+
+    /// Reads a hello body, fails if it is too short.
+    pub fn read(body: &[u8]) -> Result<Hello> {
+        match body {
+            [min_version, max_version, features, acknowledged, ..] => Ok(Hello {
+                min_version: *min_version,
+                max_version: *max_version,
+                features: Features::from_bits(*features),
+                acknowledged: *acknowledged != 0,
+            }),
+            _ => Err(ErrorKind::CouldNotReadHeader(String::from("hello"))),
+        }
+    }
+
+    /// Returns the hello body.
+    pub fn to_bytes(self) -> [u8; Hello::SIZE] {
+        [
+            self.min_version,
+            self.max_version,
+            self.features.bits(),
+            self.acknowledged as u8,
+        ]
+    }
+
+    /// Settles on the highest wire version and the optional features both endpoints support,
+    /// `None` if their versions do not overlap.
+    pub fn negotiate(&self, remote: &Hello) -> Option<Negotiated> {
+        let version = self.max_version.min(remote.max_version);
+        if version < self.min_version.max(remote.min_version) {
+            return None;
+        }
+
+        Some(Negotiated {
+            version,
+            features: self.features.intersection(remote.features),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Features, Hello, Negotiated};
+    use crate::{compression::Lz4Compressor, net::constants::WIRE_VERSION, Config};
+    use std::sync::Arc;
+
+    fn hello(min_version: u8, max_version: u8, features: Features) -> Hello {
+        Hello {
+            min_version,
+            max_version,
+            features,
+            acknowledged: false,
+        }
+    }
+
+    #[test]
+    fn settle_on_the_highest_common_version_and_features() {
+        let local = hello(1, 3, Features::COMPRESSION);
+        let remote = hello(2, 5, Features::COMPRESSION);
+
+        assert_eq!(
+            local.negotiate(&remote),
+            Some(Negotiated {
+                version: 3,
+                features: Features::COMPRESSION,
+            })
+        );
+        assert_eq!(local.negotiate(&remote), remote.negotiate(&local));
+        assert_eq!(
+            local.negotiate(&hello(2, 5, Features::NONE)),
+            Some(Negotiated {
+                version: 3,
+                features: Features::NONE,
+            })
+        );
+    }
+
+    #[test]
+    fn fail_without_a_common_version() {
+        let local = hello(1, 2, Features::NONE);
+        let remote = hello(3, 4, Features::NONE);
+
+        assert_eq!(local.negotiate(&remote), None);
+    }
+
+    #[test]
+    fn read_what_is_written() {
+        let hello = Hello {
+            min_version: 1,
+            max_version: 2,
+            features: Features::COMPRESSION,
+            acknowledged: true,
+        };
+
+        assert_eq!(Hello::read(&hello.to_bytes()).unwrap(), hello);
+        assert!(Hello::read(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn only_offer_compression_with_a_compressor() {
+        let config = Config {
+            features: Features::COMPRESSION,
+            ..Config::default()
+        };
+
+        assert!(!Hello::new(&config, false)
+            .features
+            .contains(Features::COMPRESSION));
+    }
+
+    #[test]
+    fn only_offer_compression_when_it_is_in_the_features() {
+        let config = Config {
+            features: Features::NONE,
+            compressor: Some(Arc::new(Lz4Compressor)),
+            ..Config::default()
+        };
+        assert!(!Hello::new(&config, false)
+            .features
+            .contains(Features::COMPRESSION));
+
+        let config = Config {
+            compressor: Some(Arc::new(Lz4Compressor)),
+            ..Config::default()
+        };
+        assert!(Hello::new(&config, false)
+            .features
+            .contains(Features::COMPRESSION));
+    }
+
+    #[test]
+    fn lower_the_minimal_wire_version_to_the_supported_one() {
+        let config = Config {
+            min_wire_version: WIRE_VERSION + 1,
+            ..Config::default()
+        };
+
+        let hello = Hello::new(&config, false);
+        assert_eq!(hello.min_version, WIRE_VERSION);
+        assert!(hello.negotiate(&hello).is_some());
+    }
+
+    #[test]
+    fn ignore_unknown_features() {
+        let features = Features::from_bits(0xff);
+
+        assert!(features.contains(Features::COMPRESSION));
+        assert_eq!(features.bits(), Features::COMPRESSION.bits());
+    }
+}
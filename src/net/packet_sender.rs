@@ -0,0 +1,71 @@
+use crate::{
+    error::{ErrorKind, Result},
+    net::{channel::PolicySender, SocketEvent},
+    packet::Packet,
+};
+use crossbeam_channel::{SendError, TrySendError};
+use log::error;
+use mio::{Ready, SetReadiness};
+use std::io;
+
+/// A thread-safe handle to queue packets on a `Socket`.
+///
+/// Sending a packet wakes up the polling loop of the socket, so it is sent right away.
+#[derive(Clone, Debug)]
+pub struct PacketSender {
+    sender: PolicySender<Packet>,
+    waker: SetReadiness,
+}
+
+impl PacketSender {
+    pub(crate) fn new(sender: PolicySender<Packet>, waker: SetReadiness) -> PacketSender {
+        PacketSender { sender, waker }
+    }
+
+    /// Queues the packet to be sent by the socket.
+    ///
+    /// When the queue is full this applies `Config::channel_overflow_policy`, which fails with `ErrorKind::QueueFull` or blocks.
+    /// This fails with `ErrorKind::SendError` when the socket was dropped.
+    pub fn send(&self, packet: Packet) -> Result<()> {
+        self.send_without_wake(packet)?;
+        // Failing to wake the socket only delays the packet until it polls again
+        if let Err(e) = self.wake() {
+            error!("Could not wake the socket to send a packet: {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// Queues the packet like `send`, but fails with `ErrorKind::QueueFull` instead of blocking when the queue is full.
+    #[cfg(feature = "async")]
+    pub(crate) fn try_send(&self, packet: Packet) -> Result<()> {
+        self.sender.try_send(packet).map_err(Self::send_error)?;
+        if let Err(e) = self.wake() {
+            error!("Could not wake the socket to send a packet: {:?}", e);
+        }
+        Ok(())
+    }
+
+    /// Wakes up the polling loop of the socket.
+    pub(crate) fn wake(&self) -> io::Result<()> {
+        self.waker.set_readiness(Ready::readable())
+    }
+
+    /// Clears the wake up, after the polling loop woke up.
+    pub(crate) fn reset_wake(&self) -> io::Result<()> {
+        self.waker.set_readiness(Ready::empty())
+    }
+
+    /// Queues the packet without waking up the polling loop, to be used from the thread that polls the socket.
+    pub(crate) fn send_without_wake(&self, packet: Packet) -> Result<()> {
+        self.sender.send(packet).map_err(Self::send_error)
+    }
+
+    fn send_error(error: TrySendError<Packet>) -> ErrorKind {
+        match error {
+            TrySendError::Full(packet) => ErrorKind::QueueFull(SocketEvent::Packet(packet)),
+            TrySendError::Disconnected(packet) => {
+                ErrorKind::SendError(SendError(SocketEvent::Packet(packet)))
+            }
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use crate::config::Config;
-use crate::sequence_buffer::CongestionData;
+use crate::packet::SequenceNumber;
+use crate::sequence_buffer::{sequence_greater_than, CongestionData};
 
-use std::time::Duration;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
 
 /// Represents the quality of a network.
-#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum NetworkQuality {
     /// Connection is generally good, minimal packet loss or latency
     Good,
@@ -12,11 +14,169 @@ pub enum NetworkQuality {
     Bad,
 }
 
+impl NetworkQuality {
+    /// Decides the quality of a network from its round trip time, packet loss and jitter.
+    ///
+    /// The network is bad as soon as one of them exceeds the limit given by the config,
+    /// see `rtt_max_value`, `max_packet_loss` and `max_jitter`.
+    pub fn estimate(rtt: Duration, packet_loss: f32, jitter: Duration, config: &Config) -> Self {
+        if rtt > Duration::from_millis(u64::from(config.rtt_max_value))
+            || packet_loss > config.max_packet_loss
+            || jitter > config.max_jitter
+        {
+            NetworkQuality::Bad
+        } else {
+            NetworkQuality::Good
+        }
+    }
+}
+
+/// This type estimates the fraction of packets that got lost, over a sliding window of the most recent packets.
+pub struct LossMeasurer {
+    // whether the packet got lost, for the most recent packets with the newest at the back.
+    samples: VecDeque<bool>,
+    // the number of lost packets in `samples`.
+    lost: usize,
+    // the maximal number of packets in `samples`.
+    window_size: usize,
+}
+
+impl LossMeasurer {
+    /// Creates and returns a new LossMeasurer which considers the last `window_size` packets.
+    pub fn new(window_size: usize) -> LossMeasurer {
+        LossMeasurer {
+            samples: VecDeque::with_capacity(window_size),
+            lost: 0,
+            window_size,
+        }
+    }
+
+    /// Records whether the next packet got lost or not.
+    pub fn record(&mut self, lost: bool) {
+        if self.samples.len() == self.window_size {
+            if let Some(true) = self.samples.pop_front() {
+                self.lost -= 1;
+            }
+        }
+
+        if lost {
+            self.lost += 1;
+        }
+        self.samples.push_back(lost);
+    }
+
+    /// Marks a packet, which was recorded as lost, as received.
+    /// The packet is identified by how many packets were recorded after it.
+    pub fn mark_received(&mut self, packets_after: usize) {
+        if packets_after >= self.samples.len() {
+            return;
+        }
+
+        let index = self.samples.len() - 1 - packets_after;
+        if self.samples[index] {
+            self.samples[index] = false;
+            self.lost -= 1;
+        }
+    }
+
+    /// Returns the fraction, between 0 and 1, of the packets in the window that got lost.
+    pub fn loss(&self) -> f32 {
+        if self.samples.is_empty() {
+            0.
+        } else {
+            self.lost as f32 / self.samples.len() as f32
+        }
+    }
+}
+
+/// This type estimates the fraction of incoming packets that got lost, from the gaps in the sequence numbers we received.
+///
+/// Packets that arrive late fill their gap again, as long as they are still in the window.
+pub struct InboundLossMeasurer {
+    highest_sequence: Option<SequenceNumber>,
+    loss: LossMeasurer,
+}
+
+impl InboundLossMeasurer {
+    /// Creates and returns a new InboundLossMeasurer which considers the last `window_size` sequence numbers.
+    pub fn new(window_size: usize) -> InboundLossMeasurer {
+        InboundLossMeasurer {
+            highest_sequence: None,
+            loss: LossMeasurer::new(window_size),
+        }
+    }
+
+    /// Records that the packet with the given sequence number was received.
+    pub fn record(&mut self, sequence: SequenceNumber) {
+        match self.highest_sequence {
+            Some(highest) if sequence_greater_than(sequence, highest) => {
+                // every sequence number in between is missing, at most a window full matters.
+                let missing = usize::from(sequence.wrapping_sub(highest) - 1);
+                for _ in 0..missing.min(self.loss.window_size) {
+                    self.loss.record(true);
+                }
+
+                self.loss.record(false);
+                self.highest_sequence = Some(sequence);
+            }
+            Some(highest) => {
+                self.loss
+                    .mark_received(usize::from(highest.wrapping_sub(sequence)));
+            }
+            None => {
+                self.loss.record(false);
+                self.highest_sequence = Some(sequence);
+            }
+        }
+    }
+
+    /// Returns the fraction, between 0 and 1, of the incoming packets in the window that got lost.
+    pub fn loss(&self) -> f32 {
+        self.loss.loss()
+    }
+}
+
+/// This type estimates the jitter in the style of [RFC 3550](https://tools.ietf.org/html/rfc3550#section-6.4.1).
+///
+/// Packets don't carry the time they were sent, that is why the variation is measured in the round trip times of acknowledged packets
+/// instead of in the transit times.
+/// For every two consecutive samples, the difference `D` is taken, after which the jitter `J` is updated as `J += (|D| - J) / 16`.
+pub struct JitterMeasurer {
+    previous_sample: Option<Duration>,
+    jitter: f32,
+}
+
+impl JitterMeasurer {
+    /// Creates and returns a new JitterMeasurer
+    pub fn new() -> JitterMeasurer {
+        JitterMeasurer {
+            previous_sample: None,
+            jitter: 0.,
+        }
+    }
+
+    /// Records the round trip time of the next acknowledged packet.
+    pub fn record(&mut self, rtt: Duration) {
+        if let Some(previous) = self.previous_sample {
+            let difference = (rtt.as_secs_f32() - previous.as_secs_f32()).abs();
+            self.jitter += (difference - self.jitter) / 16.;
+        }
+
+        self.previous_sample = Some(rtt);
+    }
+
+    /// Returns the estimated jitter.
+    pub fn jitter(&self) -> Duration {
+        Duration::from_secs_f32(self.jitter.max(0.))
+    }
+}
+
 /// This type helps with calculating the round trip time from any packet.
 /// It is able to smooth out the network jitter if there is any.
 pub struct RttMeasurer {
     config: Config,
     rtt: f32,
+    smoothed_rtt: Option<Duration>,
 }
 
 impl RttMeasurer {
@@ -25,25 +185,55 @@ impl RttMeasurer {
         RttMeasurer {
             config: config.clone(),
             rtt: 0.,
+            smoothed_rtt: None,
         }
     }
 
     /// This will calculate the round trip time (rtt) from the given acknowledgment.
     /// Where after it updates the rtt from the given connection.
-    pub fn calculate_rrt(&mut self, congestion_data: Option<&mut CongestionData>) {
-        self.rtt = self.get_smoothed_rtt(congestion_data);
+    pub fn calculate_rrt(&mut self, congestion_data: Option<&mut CongestionData>, time: Instant) {
+        if let Some(data) = &congestion_data {
+            let sample = time.saturating_duration_since(data.sending_time);
+            self.smoothed_rtt = Some(self.smooth_rtt_sample(sample));
+        }
+
+        self.rtt = self.get_smoothed_rtt(congestion_data, time);
+    }
+
+    /// Returns the round trip time, averaged over the acknowledged packets by the smoothing factor.
+    ///
+    /// This is zero until the first packet is acknowledged.
+    pub fn smoothed_rtt(&self) -> Duration {
+        self.smoothed_rtt.unwrap_or_default()
+    }
+
+    /// Moves the smoothed round trip time towards the given sample by the configured smoothing factor.
+    fn smooth_rtt_sample(&self, sample: Duration) -> Duration {
+        match self.smoothed_rtt {
+            Some(rtt) => {
+                let rtt = rtt.as_secs_f32();
+                let smoothed =
+                    rtt + (sample.as_secs_f32() - rtt) * self.config.rtt_smoothing_factor;
+                Duration::from_secs_f32(smoothed.max(0.))
+            }
+            None => sample,
+        }
     }
 
     #[cfg(test)]
     pub fn get_rtt(&self) -> f32 {
-        return self.rtt;
+        self.rtt
     }
 
     /// This will get the smoothed round trip time (rtt) from the time we last heard from a packet.
-    fn get_smoothed_rtt(&self, congestion_avoidance_entry: Option<&mut CongestionData>) -> f32 {
+    fn get_smoothed_rtt(
+        &self,
+        congestion_avoidance_entry: Option<&mut CongestionData>,
+        time: Instant,
+    ) -> f32 {
         match congestion_avoidance_entry {
             Some(avoidance_data) => {
-                let elapsed_time = avoidance_data.sending_time.elapsed();
+                let elapsed_time = time.saturating_duration_since(avoidance_data.sending_time);
 
                 let rtt_time = self.as_milliseconds(elapsed_time);
 
@@ -78,14 +268,14 @@ impl RttMeasurer {
 
 #[cfg(test)]
 mod test {
-    use super::RttMeasurer;
+    use super::{InboundLossMeasurer, JitterMeasurer, LossMeasurer, NetworkQuality, RttMeasurer};
     use crate::config::Config;
     use crate::net::connection::VirtualConnection;
     use std::net::ToSocketAddrs;
     use std::time::{Duration, Instant};
 
-    static TEST_HOST_IP: &'static str = "127.0.0.1";
-    static TEST_PORT: &'static str = "20000";
+    static TEST_HOST_IP: &str = "127.0.0.1";
+    static TEST_PORT: &str = "20000";
 
     #[test]
     fn test_create_connection() {
@@ -110,10 +300,12 @@ mod test {
 
     #[test]
     fn smooth_out_rtt() {
-        let mut config = Config::default();
         // for test purpose make sure we set smoothing factor to 10%.
-        config.rtt_smoothing_factor = 0.10;
-        config.rtt_max_value = 250;
+        let config = Config {
+            rtt_smoothing_factor: 0.10,
+            rtt_max_value: 250,
+            ..Config::default()
+        };
 
         let network_quality = RttMeasurer::new(&config);
         let smoothed_rtt = network_quality.smooth_out_rtt(300);
@@ -121,4 +313,109 @@ mod test {
         // 300ms has exceeded 50ms over the max allowed rtt. So we check if or smoothing factor is now 10% from 50.
         assert_eq!(smoothed_rtt, 5.0);
     }
+
+    #[test]
+    fn smooth_rtt_samples() {
+        let mut network_quality = RttMeasurer::new(&Config {
+            rtt_smoothing_factor: 0.10,
+            ..Config::default()
+        });
+
+        assert_eq!(network_quality.smoothed_rtt(), Duration::from_millis(0));
+
+        network_quality.smoothed_rtt =
+            Some(network_quality.smooth_rtt_sample(Duration::from_millis(100)));
+        assert_eq!(network_quality.smoothed_rtt(), Duration::from_millis(100));
+
+        // the next sample only moves the round trip time by 10%.
+        let smoothed_rtt = network_quality.smooth_rtt_sample(Duration::from_millis(200));
+        assert!((smoothed_rtt.as_secs_f32() - 0.110).abs() < 0.0001);
+    }
+
+    #[test]
+    fn loss_is_measured_over_window() {
+        let mut loss_measurer = LossMeasurer::new(4);
+        assert_eq!(loss_measurer.loss(), 0.);
+
+        loss_measurer.record(true);
+        loss_measurer.record(false);
+        assert_eq!(loss_measurer.loss(), 0.5);
+
+        // the lost packet drops out of the window.
+        for _ in 0..3 {
+            loss_measurer.record(false);
+        }
+        assert_eq!(loss_measurer.loss(), 0.);
+    }
+
+    #[test]
+    fn inbound_loss_from_sequence_gaps() {
+        let mut loss_measurer = InboundLossMeasurer::new(256);
+
+        loss_measurer.record(65534);
+        loss_measurer.record(65535);
+        // 0 and 1 are missing.
+        loss_measurer.record(2);
+        loss_measurer.record(3);
+        assert_eq!(loss_measurer.loss(), 2. / 6.);
+
+        // a late packet fills its gap again, duplicates change nothing.
+        loss_measurer.record(1);
+        loss_measurer.record(1);
+        assert_eq!(loss_measurer.loss(), 1. / 6.);
+    }
+
+    #[test]
+    fn jitter_follows_rtt_variation() {
+        let mut jitter_measurer = JitterMeasurer::new();
+
+        jitter_measurer.record(Duration::from_millis(100));
+        assert_eq!(jitter_measurer.jitter(), Duration::from_millis(0));
+
+        // a difference of 16ms moves the jitter by 1ms.
+        jitter_measurer.record(Duration::from_millis(116));
+        assert!((jitter_measurer.jitter().as_secs_f32() - 0.001).abs() < 0.0001);
+    }
+
+    #[test]
+    fn network_quality_is_estimated() {
+        let config = Config::default();
+
+        assert_eq!(
+            NetworkQuality::estimate(
+                Duration::from_millis(50),
+                0.,
+                Duration::from_millis(5),
+                &config
+            ),
+            NetworkQuality::Good
+        );
+        assert_eq!(
+            NetworkQuality::estimate(
+                Duration::from_millis(300),
+                0.,
+                Duration::from_millis(5),
+                &config
+            ),
+            NetworkQuality::Bad
+        );
+        assert_eq!(
+            NetworkQuality::estimate(
+                Duration::from_millis(50),
+                0.5,
+                Duration::from_millis(5),
+                &config
+            ),
+            NetworkQuality::Bad
+        );
+        assert_eq!(
+            NetworkQuality::estimate(
+                Duration::from_millis(50),
+                0.,
+                Duration::from_millis(80),
+                &config
+            ),
+            NetworkQuality::Bad
+        );
+    }
 }
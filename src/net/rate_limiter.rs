@@ -0,0 +1,284 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::{Duration, Instant},
+};
+
+/// Datagrams and decoding errors are counted in windows of this length.
+const WINDOW: Duration = Duration::from_secs(1);
+/// The maximal number of bans, addresses are no longer banned automatically while it is reached.
+const MAX_BANS: usize = 1024;
+
+/// A banned IP address, see `Socket::bans`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ban {
+    /// The address whose datagrams are dropped.
+    pub ip: IpAddr,
+    /// When the ban is lifted, `None` if it is permanent.
+    pub expires: Option<Instant>,
+}
+
+/// Drops datagrams of banned addresses and of addresses or subnets which exceed the limits of the config,
+/// see `Config::max_datagrams_per_ip`.
+///
+/// The counters are reset every window instead of being kept per address, so spoofed source addresses
+/// do not grow them beyond the addresses seen in one window. Since the windows are fixed, an address can send up to
+/// twice its limit in a burst which spans the end of one window and the start of the next.
+///
+/// Only validated addresses are banned automatically, so datagrams with spoofed source addresses cannot get others
+/// banned, and at most `MAX_BANS` addresses are banned at once.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_datagrams_per_ip: Option<u32>,
+    max_datagrams_per_subnet: Option<u32>,
+    max_decode_errors_per_ip: Option<u32>,
+    ban_duration: Duration,
+    window_start: Option<Instant>,
+    datagrams_per_ip: HashMap<IpAddr, u32>,
+    datagrams_per_subnet: HashMap<IpAddr, u32>,
+    decode_errors_per_ip: HashMap<IpAddr, u32>,
+    bans: HashMap<IpAddr, Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(
+        max_datagrams_per_ip: Option<u32>,
+        max_datagrams_per_subnet: Option<u32>,
+        max_decode_errors_per_ip: Option<u32>,
+        ban_duration: Duration,
+    ) -> RateLimiter {
+        RateLimiter {
+            max_datagrams_per_ip,
+            max_datagrams_per_subnet,
+            max_decode_errors_per_ip,
+            ban_duration,
+            window_start: None,
+            datagrams_per_ip: HashMap::new(),
+            datagrams_per_subnet: HashMap::new(),
+            decode_errors_per_ip: HashMap::new(),
+            bans: HashMap::new(),
+        }
+    }
+
+    /// Counts a datagram from `ip` and returns whether it should be processed.
+    ///
+    /// A `validated` address which exceeds its limit is banned, while datagrams over the limit of other addresses or of
+    /// a subnet are only dropped.
+    pub fn allow(&mut self, ip: IpAddr, validated: bool, time: Instant) -> bool {
+        if self.is_banned(ip, time) {
+            return false;
+        }
+        self.start_window(time);
+
+        if exceeds(&mut self.datagrams_per_ip, ip, self.max_datagrams_per_ip) {
+            self.ban_automatically(ip, validated, time);
+            return false;
+        }
+        !exceeds(
+            &mut self.datagrams_per_subnet,
+            subnet(ip),
+            self.max_datagrams_per_subnet,
+        )
+    }
+
+    /// Counts a datagram from `ip` which failed to decode, a `validated` address is banned when it exceeds its limit.
+    pub fn record_decode_error(&mut self, ip: IpAddr, validated: bool, time: Instant) {
+        self.start_window(time);
+
+        if exceeds(
+            &mut self.decode_errors_per_ip,
+            ip,
+            self.max_decode_errors_per_ip,
+        ) {
+            self.ban_automatically(ip, validated, time);
+        }
+    }
+
+    /// Bans `ip` for `duration`, or permanently if it is `None`. An existing ban of the address is replaced.
+    pub fn ban(&mut self, ip: IpAddr, duration: Option<Duration>, time: Instant) {
+        self.bans
+            .insert(ip, duration.map(|duration| time + duration));
+    }
+
+    /// Lifts the ban of `ip`, returns false if it was not banned.
+    pub fn unban(&mut self, ip: IpAddr) -> bool {
+        self.bans.remove(&ip).is_some()
+    }
+
+    /// Returns the bans which have not expired at `time`.
+    pub fn bans(&self, time: Instant) -> Vec<Ban> {
+        self.bans
+            .iter()
+            .filter(|(_, expires)| !has_expired(**expires, time))
+            .map(|(ip, expires)| Ban {
+                ip: *ip,
+                expires: *expires,
+            })
+            .collect()
+    }
+
+    // Bans `ip` for the ban duration of the config, unless it is not validated or `MAX_BANS` is reached.
+    fn ban_automatically(&mut self, ip: IpAddr, validated: bool, time: Instant) {
+        if validated && self.bans.len() < MAX_BANS {
+            self.ban(ip, Some(self.ban_duration), time);
+        }
+    }
+
+    fn is_banned(&mut self, ip: IpAddr, time: Instant) -> bool {
+        match self.bans.get(&ip) {
+            Some(expires) if has_expired(*expires, time) => {
+                self.bans.remove(&ip);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    // Resets the counters when the current window has passed.
+    fn start_window(&mut self, time: Instant) {
+        let passed = self
+            .window_start
+            .is_none_or(|start| time.saturating_duration_since(start) >= WINDOW);
+        if passed {
+            self.window_start = Some(time);
+            self.datagrams_per_ip.clear();
+            self.datagrams_per_subnet.clear();
+            self.decode_errors_per_ip.clear();
+            self.bans.retain(|_, expires| !has_expired(*expires, time));
+        }
+    }
+}
+
+// Counts one more for `key` and returns whether it exceeds `limit`, nothing is counted without a limit.
+fn exceeds(counters: &mut HashMap<IpAddr, u32>, key: IpAddr, limit: Option<u32>) -> bool {
+    match limit {
+        Some(limit) => {
+            let count = counters.entry(key).or_insert(0);
+            *count = count.saturating_add(1);
+            *count > limit
+        }
+        None => false,
+    }
+}
+
+fn has_expired(expires: Option<Instant>, time: Instant) -> bool {
+    expires.is_some_and(|expires| expires <= time)
+}
+
+/// Returns the network address of the subnet of `ip`, a /24 for IPv4 and a /64 for IPv6.
+fn subnet(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => IpAddr::V4(Ipv4Addr::from(u32::from(ip) & 0xffff_ff00)),
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(
+            u128::from(ip) & 0xffff_ffff_ffff_ffff_0000_0000_0000_0000,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{subnet, Ban, RateLimiter, MAX_BANS, WINDOW};
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        time::{Duration, Instant},
+    };
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn ban_addresses_exceeding_their_limit() {
+        let ban_duration = Duration::from_secs(30);
+        let mut limiter = RateLimiter::new(Some(2), None, None, ban_duration);
+        let now = Instant::now();
+
+        assert!(limiter.allow(ip("10.0.0.1"), true, now));
+        assert!(limiter.allow(ip("10.0.0.1"), true, now));
+        assert!(!limiter.allow(ip("10.0.0.1"), true, now));
+        assert!(limiter.allow(ip("10.0.0.2"), true, now));
+
+        // The ban outlasts the window
+        assert!(!limiter.allow(ip("10.0.0.1"), true, now + WINDOW));
+        assert_eq!(
+            limiter.bans(now),
+            vec![Ban {
+                ip: ip("10.0.0.1"),
+                expires: Some(now + ban_duration)
+            }]
+        );
+        assert!(limiter.allow(ip("10.0.0.1"), true, now + ban_duration));
+        assert!(limiter.bans(now + ban_duration).is_empty());
+    }
+
+    #[test]
+    fn drop_datagrams_over_the_subnet_limit() {
+        let mut limiter = RateLimiter::new(None, Some(2), None, Duration::from_secs(30));
+        let now = Instant::now();
+
+        assert!(limiter.allow(ip("10.0.0.1"), true, now));
+        assert!(limiter.allow(ip("10.0.0.2"), true, now));
+        assert!(!limiter.allow(ip("10.0.0.3"), true, now));
+        assert!(limiter.allow(ip("10.0.1.1"), true, now));
+        assert!(limiter.bans(now).is_empty());
+
+        assert!(limiter.allow(ip("10.0.0.3"), true, now + WINDOW));
+    }
+
+    #[test]
+    fn ban_addresses_failing_to_decode() {
+        let mut limiter = RateLimiter::new(None, None, Some(1), Duration::from_secs(30));
+        let now = Instant::now();
+
+        limiter.record_decode_error(ip("10.0.0.1"), true, now);
+        assert!(limiter.allow(ip("10.0.0.1"), true, now));
+        limiter.record_decode_error(ip("10.0.0.1"), true, now);
+        assert!(!limiter.allow(ip("10.0.0.1"), true, now));
+    }
+
+    #[test]
+    fn only_ban_validated_addresses() {
+        let mut limiter = RateLimiter::new(Some(1), None, Some(1), Duration::from_secs(30));
+        let now = Instant::now();
+
+        assert!(limiter.allow(ip("10.0.0.1"), false, now));
+        assert!(!limiter.allow(ip("10.0.0.1"), false, now));
+        limiter.record_decode_error(ip("10.0.0.1"), false, now);
+        limiter.record_decode_error(ip("10.0.0.1"), false, now);
+        assert!(limiter.bans(now).is_empty());
+
+        // Without a ban the address is accepted again in the next window
+        assert!(limiter.allow(ip("10.0.0.1"), false, now + WINDOW));
+    }
+
+    #[test]
+    fn limit_the_number_of_bans() {
+        let mut limiter = RateLimiter::new(None, None, Some(0), Duration::from_secs(30));
+        let now = Instant::now();
+
+        for address in 0..=MAX_BANS as u32 {
+            limiter.record_decode_error(IpAddr::V4(Ipv4Addr::from(address)), true, now);
+        }
+        assert_eq!(limiter.bans(now).len(), MAX_BANS);
+        assert!(limiter.allow(IpAddr::V4(Ipv4Addr::from(MAX_BANS as u32)), true, now));
+    }
+
+    #[test]
+    fn add_and_remove_bans() {
+        let mut limiter = RateLimiter::new(None, None, None, Duration::from_secs(30));
+        let now = Instant::now();
+
+        limiter.ban(ip("::1"), None, now);
+        assert!(!limiter.allow(ip("::1"), true, now + Duration::from_secs(3600)));
+        assert!(limiter.unban(ip("::1")));
+        assert!(!limiter.unban(ip("::1")));
+        assert!(limiter.allow(ip("::1"), true, now));
+    }
+
+    #[test]
+    fn subnets_of_both_families() {
+        assert_eq!(subnet(ip("192.168.1.77")), ip("192.168.1.0"));
+        assert_eq!(subnet(ip("2001:db8:1:2:3:4:5:6")), ip("2001:db8:1:2::"));
+    }
+}
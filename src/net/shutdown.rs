@@ -0,0 +1,76 @@
+use crate::error::Result;
+use mio::{Ready, SetReadiness};
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+/// How the polling loop of a `Socket` is shut down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Shutdown {
+    /// Stop right away, connections are dropped without notice.
+    Immediate,
+    /// Wait at most the given duration for reliable packets to be acknowledged, then send disconnect notices.
+    Graceful(Duration),
+}
+
+/// A thread-safe handle to stop the polling loop of a `Socket`, see `Socket::get_shutdown_handle`.
+///
+/// Requesting a shutdown wakes up the polling loop, `Socket::start_polling` then returns the statistics of the connections.
+/// The socket can be used, and polled, again afterwards.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+    request: Arc<Mutex<Option<Shutdown>>>,
+    waker: SetReadiness,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(waker: SetReadiness) -> ShutdownHandle {
+        ShutdownHandle {
+            request: Arc::new(Mutex::new(None)),
+            waker,
+        }
+    }
+
+    /// Stops the polling loop right away, remote endpoints only notice when their connections time out.
+    ///
+    /// Fails when the polling loop could not be woken up, the shutdown is then only performed once the loop wakes up
+    /// for another reason, e.g. a received datagram or a timer.
+    pub fn shutdown(&self) -> Result<()> {
+        self.request(Shutdown::Immediate)
+    }
+
+    /// Stops the polling loop once the queued packets are sent and all reliable packets are acknowledged, or `linger` has passed.
+    /// Then every connection is sent a disconnect notice, on which the remote endpoint raises `SocketEvent::Disconnect`.
+    ///
+    /// Packets which are not acknowledged are sent again every two round trip times while lingering, so lost ones are flushed.
+    /// Acknowledgments travel with the reliable packets of the remote endpoint, so they only arrive while it keeps sending them,
+    /// otherwise the loop stops once `linger` has passed.
+    ///
+    /// Fails when the polling loop could not be woken up, like `shutdown`.
+    pub fn shutdown_gracefully(&self, linger: Duration) -> Result<()> {
+        self.request(Shutdown::Graceful(linger))
+    }
+
+    /// Returns whether a shutdown was requested which the polling loop has not performed yet.
+    pub fn is_shutdown_requested(&self) -> bool {
+        self.lock().is_some()
+    }
+
+    /// Takes the pending request, so the socket can be polled again after it is shut down.
+    pub(crate) fn take_request(&self) -> Option<Shutdown> {
+        self.lock().take()
+    }
+
+    fn request(&self, shutdown: Shutdown) -> Result<()> {
+        *self.lock() = Some(shutdown);
+        // The loop may block until its next deadline without a wake up, which is possibly never
+        self.waker.set_readiness(Ready::readable())?;
+        Ok(())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Shutdown>> {
+        // The request stays valid even if a thread panicked while holding the lock
+        self.request.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
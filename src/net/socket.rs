@@ -1,15 +1,28 @@
-use crate::either::Either::{Left, Right};
 use crate::{
     config::Config,
-    error::{ErrorKind, Result},
-    net::{connection::ActiveConnections, events::SocketEvent, link_conditioner::LinkConditioner},
-    packet::{DeliveryGuarantee, Outgoing, Packet},
+    error::Result,
+    metrics::Metrics,
+    net::{
+        address::address_for_socket,
+        batch,
+        channel::{channel, Receiver},
+        connection_manager::ConnectionManager,
+        constants::MAX_BATCH_SIZE,
+        events::SocketEvent,
+        link_conditioner::LinkConditioner,
+        packet_sender::PacketSender,
+        shutdown::{Shutdown, ShutdownHandle},
+        socket_options, Ban, ConnectionStats, StreamInfo,
+    },
+    packet::{BufferMut, OrderingGuarantee, Packet, Payload},
 };
-use crossbeam_channel::{self, unbounded, Receiver, SendError, Sender, TryRecvError};
+use crossbeam_channel::TryRecvError;
 use log::error;
+use mio::{Events, Poll, PollOpt, Ready, Registration, Token};
 use std::{
-    self, io,
-    net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket},
+    self, io, mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket},
+    sync::Arc,
     thread::{sleep, yield_now},
     time::{Duration, Instant},
 };
@@ -17,41 +30,80 @@ use std::{
 /// A reliable UDP socket implementation with configurable reliability and ordering guarantees.
 #[derive(Debug)]
 pub struct Socket {
-    socket: UdpSocket,
-    config: Config,
-    connections: ActiveConnections,
-    recv_buffer: Vec<u8>,
-    link_conditioner: Option<LinkConditioner>,
-    event_sender: Sender<SocketEvent>,
+    socket: mio::net::UdpSocket,
+    // IPv6 sockets send to IPv4 peers with IPv4-mapped addresses.
+    ipv6: bool,
+    manager: ConnectionManager,
+    recv_buffers: Vec<BufferMut>,
+    received: Vec<(usize, SocketAddr)>,
+    transmit_buffer: Vec<(SocketAddr, Payload)>,
     packet_receiver: Receiver<Packet>,
+    poll: Poll,
+    poll_events: Events,
+    // Keeps the waker of the packet sender registered with `poll`.
+    _waker_registration: Registration,
 
     receiver: Receiver<SocketEvent>,
-    sender: Sender<Packet>,
+    sender: PacketSender,
+    shutdown: ShutdownHandle,
 }
 
-enum UdpSocketState {
-    Empty,
-    MaybeMore,
-}
+const SOCKET: Token = Token(0);
+const WAKER: Token = Token(1);
+// The shortest time a graceful shutdown waits for acknowledgments, before it sends the unacknowledged packets again.
+const MIN_LINGER_RESEND_INTERVAL: Duration = Duration::from_millis(50);
 
 impl Socket {
     /// Binds to the socket and then sets up `ActiveConnections` to manage the "connections".
     /// Because UDP connections are not persistent, we can only infer the status of the remote
     /// endpoint by looking to see if they are still sending packets or not
+    ///
+    /// The configuration is the default one for the address family of the socket, see `Config::for_address`.
     pub fn bind<A: ToSocketAddrs>(addresses: A) -> Result<Self> {
-        Socket::bind_with_config(addresses, Config::default())
+        let socket = UdpSocket::bind(addresses)?;
+        let config = Config::for_address(&socket.local_addr()?);
+        Self::bind_internal(socket, config)
     }
 
     /// Bind to any local port on the system, if available
     pub fn bind_any() -> Result<Self> {
-        Self::bind_any_with_config(Config::default())
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+        Self::bind_any_with_config(Config::for_address(&address))
     }
 
     /// Bind to any local port on the system, if available, with a given config
     pub fn bind_any_with_config(config: Config) -> Result<Self> {
         let loopback = Ipv4Addr::new(127, 0, 0, 1);
         let address = SocketAddrV4::new(loopback, 0);
-        let socket = UdpSocket::bind(address)?;
+        let socket = socket_options::bind(address, &config)?;
+        Self::bind_internal(socket, config)
+    }
+
+    /// Bind to any local port of the IPv6 loopback address, if available
+    pub fn bind_any_ipv6() -> Result<Self> {
+        let address = SocketAddr::from((Ipv6Addr::LOCALHOST, 0));
+        Self::bind_any_ipv6_with_config(Config::for_address(&address))
+    }
+
+    /// Bind to any local port of the IPv6 loopback address, if available, with a given config
+    pub fn bind_any_ipv6_with_config(config: Config) -> Result<Self> {
+        let socket = socket_options::bind((Ipv6Addr::LOCALHOST, 0), &config)?;
+        Self::bind_internal(socket, config)
+    }
+
+    /// Bind to any local port on all IPv6 and IPv4 interfaces, if available, see `bind_dual_stack_with_config`.
+    pub fn bind_any_dual_stack() -> Result<Self> {
+        let address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0));
+        Self::bind_dual_stack_with_config(0, Config::for_address(&address))
+    }
+
+    /// Bind to `port` on all IPv6 and IPv4 interfaces with a single IPv6 socket, which has `IPV6_V6ONLY` turned off.
+    ///
+    /// IPv4 peers are reported with their IPv4 addresses and can be sent to with either their IPv4 address
+    /// or the IPv4-mapped IPv6 address, both refer to the same connection.
+    pub fn bind_dual_stack_with_config(port: u16, config: Config) -> Result<Self> {
+        let address = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+        let socket = socket_options::bind_to(address, true, &config)?;
         Self::bind_internal(socket, config)
     }
 
@@ -61,32 +113,72 @@ impl Socket {
     ///
     /// This function allows you to configure laminar with the passed configuration.
     pub fn bind_with_config<A: ToSocketAddrs>(addresses: A, config: Config) -> Result<Self> {
-        let socket = UdpSocket::bind(addresses)?;
+        let socket = socket_options::bind(addresses, &config)?;
         Self::bind_internal(socket, config)
     }
 
     fn bind_internal(socket: UdpSocket, config: Config) -> Result<Self> {
-        socket.set_nonblocking(true)?;
-        let (event_sender, event_receiver) = unbounded();
-        let (packet_sender, packet_receiver) = unbounded();
+        socket_options::apply(&socket, &config)?;
+        let ipv6 = socket.local_addr()?.is_ipv6();
+        // This also puts the socket in non-blocking mode
+        let socket = mio::net::UdpSocket::from_socket(socket)?;
+        let (packet_sender, packet_receiver) = channel(
+            config.packet_channel_capacity,
+            config.channel_overflow_policy,
+        );
+
+        let poll = Poll::new()?;
+        poll.register(&socket, SOCKET, Ready::readable(), PollOpt::edge())?;
+        let (waker_registration, waker) = Registration::new2();
+        poll.register(
+            &waker_registration,
+            WAKER,
+            Ready::readable(),
+            PollOpt::edge(),
+        )?;
+
+        let poll_events = Events::with_capacity(config.socket_event_buffer_size);
+        // Without `recvmmsg` only the first buffer is used
+        let batch_size = if cfg!(target_os = "linux") {
+            config.batch_size.clamp(1, MAX_BATCH_SIZE)
+        } else {
+            1
+        };
+        let manager = ConnectionManager::new(config);
+        let recv_buffers = (0..batch_size).map(|_| receive_buffer(&manager)).collect();
+
         Ok(Socket {
-            recv_buffer: vec![0; config.receive_buffer_max_size],
+            poll_events,
+            recv_buffers,
+            received: Vec::with_capacity(batch_size),
+            transmit_buffer: Vec::new(),
             socket,
-            config,
-            connections: ActiveConnections::new(),
-            link_conditioner: None,
-            event_sender,
+            ipv6,
+            receiver: manager.event_receiver(),
+            manager,
             packet_receiver,
+            poll,
+            _waker_registration: waker_registration,
 
-            sender: packet_sender,
-            receiver: event_receiver,
+            shutdown: ShutdownHandle::new(waker.clone()),
+            sender: PacketSender::new(packet_sender, waker),
         })
     }
 
+    /// Returns the local address this socket is bound to, e.g. to find the port chosen by `bind_any`.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    #[cfg(feature = "async")]
+    pub(crate) fn config(&self) -> &Config {
+        self.manager.config()
+    }
+
     /// Returns a handle to the packet sender which provides a thread-safe way to enqueue packets
     /// to be processed. This should be used when the socket is busy running its polling loop in a
-    /// separate thread.
-    pub fn get_packet_sender(&mut self) -> Sender<Packet> {
+    /// separate thread, sending on it wakes up the polling loop.
+    pub fn get_packet_sender(&mut self) -> PacketSender {
         self.sender.clone()
     }
 
@@ -97,14 +189,18 @@ impl Socket {
         self.receiver.clone()
     }
 
+    /// Returns a handle which stops the polling loop of this socket, e.g. from another thread,
+    /// see `ShutdownHandle` and `start_polling`.
+    pub fn get_shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
     /// Send a packet
+    ///
+    /// When `Config::packet_channel_capacity` packets are queued, this applies `Config::channel_overflow_policy`,
+    /// e.g. it fails with `ErrorKind::QueueFull` until the socket is polled again.
     pub fn send(&mut self, packet: Packet) -> Result<()> {
-        match self.sender.send(packet) {
-            Ok(_) => Ok(()),
-            Err(error) => Err(ErrorKind::SendError(SendError(SocketEvent::Packet(
-                error.0,
-            )))),
-        }
+        self.sender.send_without_wake(packet)
     }
 
     /// Receive a packet
@@ -116,18 +212,120 @@ impl Socket {
         }
     }
 
+    /// Returns a snapshot of the statistics of the connection with `addr`, `None` if there is no such connection.
+    pub fn connection_stats(&self, addr: SocketAddr) -> Option<ConnectionStats> {
+        self.manager.connection_stats(addr, Instant::now())
+    }
+
+    /// Returns the number of datagrams received from `addr` which could not be processed, also before a connection exists.
+    pub fn errors_received(&self, addr: SocketAddr) -> u64 {
+        self.manager.errors_received(addr)
+    }
+
+    /// Returns a snapshot of the statistics of every active connection.
+    pub fn connections(&self) -> Vec<ConnectionStats> {
+        self.manager.connections(Instant::now())
+    }
+
+    /// Resets the given ordering or sequencing stream of the connection with `addr`, e.g. `OrderingGuarantee::Ordered(Some(1))`.
+    ///
+    /// The remote endpoint will skip all items that were sent on this stream before, so it no longer waits for lost ones.
+    /// A closed stream is opened again.
+    pub fn reset_stream(&mut self, addr: SocketAddr, stream: OrderingGuarantee) -> Result<()> {
+        self.manager.reset_stream(addr, stream, Instant::now())?;
+        self.flush();
+        Ok(())
+    }
+
+    /// Closes the given ordering or sequencing stream of the connection with `addr`, e.g. `OrderingGuarantee::Ordered(Some(1))`.
+    ///
+    /// The remote endpoint will discard all items it buffered for this stream.
+    /// Sending on a closed stream fails until it is reset with `reset_stream`.
+    pub fn close_stream(&mut self, addr: SocketAddr, stream: OrderingGuarantee) -> Result<()> {
+        self.manager.close_stream(addr, stream, Instant::now())?;
+        self.flush();
+        Ok(())
+    }
+
+    /// Sends a disconnect notice to `addr` and removes the connection, returns false if there is no such connection.
+    ///
+    /// The remote endpoint raises `SocketEvent::Disconnect` instead of waiting for the connection to time out.
+    pub fn disconnect(&mut self, addr: SocketAddr) -> bool {
+        let disconnected = self.manager.disconnect(addr);
+        self.flush();
+        disconnected
+    }
+
+    /// Replaces the dictionary of the compressor on the connection with `addr`, `None` compresses without a dictionary.
+    ///
+    /// The remote endpoint has to use the same dictionary on its connection, e.g. one it agreed on in a packet.
+    /// Returns false if there is no such connection or no `Config::compressor`.
+    pub fn set_compression_dictionary(
+        &mut self,
+        addr: SocketAddr,
+        dictionary: Option<Arc<[u8]>>,
+    ) -> bool {
+        self.manager.set_compression_dictionary(addr, dictionary)
+    }
+
+    /// Bans `ip` for `duration`, or permanently if it is `None`, its datagrams are dropped before they are parsed.
+    ///
+    /// Addresses are also banned automatically when they exceed `Config::max_datagrams_per_ip` or `Config::max_decode_errors_per_ip`.
+    pub fn ban(&mut self, ip: IpAddr, duration: Option<Duration>) {
+        self.manager.ban(ip, duration, Instant::now());
+    }
+
+    /// Lifts the ban of `ip`, returns false if it was not banned.
+    pub fn unban(&mut self, ip: IpAddr) -> bool {
+        self.manager.unban(ip)
+    }
+
+    /// Returns the addresses which are currently banned.
+    pub fn bans(&self) -> Vec<Ban> {
+        self.manager.bans(Instant::now())
+    }
+
+    /// Returns the state of the given ordering or sequencing stream of the connection with `addr`.
+    ///
+    /// Returns `None` if there is no such connection, or if the stream was never used.
+    pub fn stream_info(&self, addr: SocketAddr, stream: OrderingGuarantee) -> Option<StreamInfo> {
+        self.manager.stream_info(addr, stream)
+    }
+
+    /// Returns the state of all ordering and sequencing streams used on the connection with `addr`.
+    pub fn streams(&self, addr: SocketAddr) -> Vec<StreamInfo> {
+        self.manager.streams(addr)
+    }
+
     /// Entry point to the run loop. This should run in a spawned thread since calls to `poll.poll`
-    /// are blocking. We will default this to sleeping for 1ms.
-    pub fn start_polling(&mut self) {
-        self.start_polling_with_duration(Some(Duration::from_millis(1)))
+    /// are blocking.
+    ///
+    /// The loop blocks until a datagram arrives, a packet is queued on the packet sender or the next deadline,
+    /// see `next_deadline`, has passed. It blocks at most `Config::socket_polling_timeout`.
+    ///
+    /// The loop runs until it is stopped with the handle of `get_shutdown_handle`,
+    /// then it returns the statistics the connections had at that point.
+    pub fn start_polling(&mut self) -> Vec<ConnectionStats> {
+        loop {
+            self.manual_poll(Instant::now());
+            if let Some(shutdown) = self.shutdown.take_request() {
+                return self.shut_down(shutdown);
+            }
+            self.wait_for_activity(self.manager.config().socket_polling_timeout);
+        }
     }
 
-    /// Run the polling loop with a specified sleep duration. This should run in a spawned thread
-    /// since calls to `poll.poll` are blocking.
-    pub fn start_polling_with_duration(&mut self, sleep_duration: Option<Duration>) {
-        // Nothing should break out of this loop!
+    /// Run the polling loop with a specified sleep duration, instead of waiting for activity on the socket.
+    /// This should run in a spawned thread since it only returns when it is stopped, like `start_polling`.
+    pub fn start_polling_with_duration(
+        &mut self,
+        sleep_duration: Option<Duration>,
+    ) -> Vec<ConnectionStats> {
         loop {
             self.manual_poll(Instant::now());
+            if let Some(shutdown) = self.shutdown.take_request() {
+                return self.shut_down(shutdown);
+            }
             match sleep_duration {
                 None => yield_now(),
                 Some(duration) => sleep(duration),
@@ -139,169 +337,182 @@ impl Socket {
     pub fn manual_poll(&mut self, time: Instant) {
         // First we pull all newly arrived packets and handle them
         loop {
-            match self.recv_from(time) {
-                Ok(UdpSocketState::MaybeMore) => continue,
-                Ok(UdpSocketState::Empty) => break,
-                Err(e) => error!("Encountered an error receiving data: {:?}", e),
+            self.received.clear();
+            match batch::receive_batch(&self.socket, &mut self.recv_buffers, &mut self.received) {
+                Ok(()) => {
+                    for (index, &(recv_len, address)) in self.received.iter().enumerate() {
+                        // The packets of the datagram share its buffer, so it is replaced by a new one
+                        let mut buffer = mem::replace(
+                            &mut self.recv_buffers[index],
+                            receive_buffer(&self.manager),
+                        );
+                        buffer.truncate(recv_len);
+                        if let Err(e) = self.manager.handle_payload(address, buffer.freeze(), time)
+                        {
+                            error!("Encountered an error receiving data: {:?}", e);
+                        }
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    let e = e.into();
+                    if let Err(e) = self.manager.report_receive_error(None, &e, time) {
+                        error!("Encountered an error when sending ErrorEvent: {:?}", e);
+                    }
+                    error!("Encountered an error receiving data: {:?}", e);
+                }
             }
         }
 
-        // Now grab all the packets waiting to be sent and send them
+        // Now grab all the packets waiting to be sent, they are sent in batches below
         while let Ok(p) = self.packet_receiver.try_recv() {
-            if let Err(e) = self.send_to(p, time) {
-                match e {
-                    ErrorKind::IOError(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
-                    _ => error!("There was an error sending packet: {:?}", e),
-                }
+            if let Err(e) = self.manager.send(p, time) {
+                error!("There was an error sending packet: {:?}", e);
             }
         }
 
-        // Finally check for idle clients
-        if let Err(e) = self.handle_idle_clients(time) {
-            error!("Encountered an error when sending TimeoutEvent: {:?}", e);
+        // Check for stalled ordering streams and idle clients
+        if let Err(e) = self.manager.handle_timers(time) {
+            error!("Encountered an error when sending a timer event: {:?}", e);
         }
+
+        self.flush();
     }
 
-    /// Set the link conditioner for this socket. See [LinkConditioner] for further details.
-    pub fn set_link_conditioner(&mut self, link_conditioner: Option<LinkConditioner>) {
-        self.link_conditioner = link_conditioner;
+    /// Returns the latest time at which `manual_poll` should be called again to raise timeouts and stalled streams in time,
+    /// or `None` if there are no connections.
+    ///
+    /// Incoming and queued packets are processed by `manual_poll` as well, this only covers the timers.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.manager.next_deadline()
     }
 
-    /// Iterate through all of the idle connections based on `idle_connection_timeout` config and
-    /// remove them from the active connections. For each connection removed, we will send a
-    /// `SocketEvent::TimeOut` event to the `event_sender` channel.
-    fn handle_idle_clients(&mut self, time: Instant) -> Result<()> {
-        let idle_addresses = self
-            .connections
-            .idle_connections(self.config.idle_connection_timeout, time);
-        for address in idle_addresses {
-            self.connections.remove_connection(&address);
-            self.event_sender.send(SocketEvent::Timeout(address))?;
+    /// Blocks until a datagram can be received, a packet is queued on the packet sender, the next deadline has passed,
+    /// or `max_timeout` has passed.
+    pub(crate) fn wait_for_activity(&mut self, max_timeout: Option<Duration>) {
+        let timeout = match (
+            self.next_deadline()
+                .map(|deadline| deadline.saturating_duration_since(Instant::now())),
+            max_timeout,
+        ) {
+            (Some(deadline), Some(max_timeout)) => Some(deadline.min(max_timeout)),
+            (deadline, None) => deadline,
+            (None, max_timeout) => max_timeout,
+        };
+
+        if let Err(e) = self.poll.poll(&mut self.poll_events, timeout) {
+            error!("Encountered an error polling the socket: {:?}", e);
         }
 
-        Ok(())
+        // Packets queued before this are sent by the next `manual_poll`, packets queued later wake us again.
+        if let Err(e) = self.sender.reset_wake() {
+            error!("Could not reset the waker of the socket: {:?}", e);
+        }
     }
 
-    // Serializes and sends a `Packet` on the socket. On success, returns the number of bytes written.
-    fn send_to(&mut self, packet: Packet, time: Instant) -> Result<usize> {
-        let connection =
-            self.connections
-                .get_or_insert_connection(packet.addr(), &self.config, time);
-
-        let dropped = connection.gather_dropped_packets();
-        let mut processed_packets: Vec<Outgoing> = dropped
-            .iter()
-            .flat_map(|waiting_packet| {
-                connection.process_outgoing(
-                    &waiting_packet.payload,
-                    // Because a delivery guarantee is only sent with reliable packets
-                    DeliveryGuarantee::Reliable,
-                    // This is stored with the dropped packet because they could be mixed
-                    waiting_packet.ordering_guarantee,
-                    waiting_packet.item_identifier,
-                    time,
-                )
-            })
-            .collect();
-
-        let processed_packet = connection.process_outgoing(
-            packet.payload(),
-            packet.delivery_guarantee(),
-            packet.order_guarantee(),
-            None,
-            time,
-        )?;
+    /// Set the link conditioner for this socket. See [LinkConditioner] for further details.
+    pub fn set_link_conditioner(&mut self, link_conditioner: Option<LinkConditioner>) {
+        self.manager.set_link_conditioner(link_conditioner);
+    }
 
-        processed_packets.push(processed_packet);
+    /// Set the metrics which are notified of key events on this socket. See [Metrics] for further details.
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn Metrics>>) {
+        self.manager.set_metrics(metrics);
+    }
 
-        let mut bytes_sent = 0;
+    // Performs a shutdown requested through the `ShutdownHandle`, returns the statistics of the connections.
+    fn shut_down(&mut self, shutdown: Shutdown) -> Vec<ConnectionStats> {
+        let linger = match shutdown {
+            Shutdown::Immediate => return self.connections(),
+            Shutdown::Graceful(linger) => linger,
+        };
 
-        for processed_packet in processed_packets {
-            if self.should_send_packet() {
-                match processed_packet {
-                    Outgoing::Packet(outgoing) => {
-                        bytes_sent += self.send_packet(&packet.addr(), &outgoing.contents())?;
-                    }
-                    Outgoing::Fragments(packets) => {
-                        for outgoing in packets {
-                            bytes_sent += self.send_packet(&packet.addr(), &outgoing.contents())?;
-                        }
-                    }
-                }
+        // The queued packets were sent by the last poll, keep polling until the reliable ones are acknowledged
+        let deadline = Instant::now() + linger;
+        let mut next_resend = Instant::now() + self.linger_resend_interval();
+        while self.packets_awaiting_ack() > 0 && Instant::now() < deadline {
+            let wake_up = deadline.min(next_resend);
+            self.wait_for_activity(Some(wake_up.saturating_duration_since(Instant::now())));
+            let now = Instant::now();
+            self.manual_poll(now);
+
+            // Lost packets are only noticed when newer packets are acknowledged, but no new packets are sent now
+            if now >= next_resend {
+                self.manager.resend_unacknowledged(now);
+                self.flush();
+                next_resend = now + self.linger_resend_interval();
             }
         }
-        Ok(bytes_sent)
-    }
 
-    // On success the packet will be sent on the `event_sender`
-    fn recv_from(&mut self, time: Instant) -> Result<UdpSocketState> {
-        match self.socket.recv_from(&mut self.recv_buffer) {
-            Ok((recv_len, address)) => {
-                if recv_len == 0 {
-                    return Err(ErrorKind::ReceivedDataToShort)?;
-                }
-                let received_payload = &self.recv_buffer[..recv_len];
-
-                if !self.connections.exists(&address) {
-                    self.event_sender.send(SocketEvent::Connect(address))?;
-                }
+        let stats = self.connections();
+        self.manager.disconnect_all();
+        self.flush();
+        stats
+    }
 
-                let connection =
-                    self.connections
-                        .get_or_create_connection(address, &self.config, time);
+    // Returns how long a graceful shutdown waits for acknowledgments, twice the largest round trip time of the connections.
+    fn linger_resend_interval(&self) -> Duration {
+        let rtt = self
+            .connections()
+            .iter()
+            .map(|stats| stats.rtt)
+            .max()
+            .unwrap_or_default();
+        (rtt * 2).max(MIN_LINGER_RESEND_INTERVAL)
+    }
 
-                match connection {
-                    Left(existing) => {
-                        existing.process_incoming(received_payload, &self.event_sender, time)?;
-                    }
-                    Right(mut anonymous) => {
-                        anonymous.process_incoming(received_payload, &self.event_sender, time)?;
-                    }
-                }
-            }
-            Err(e) => {
-                if e.kind() != io::ErrorKind::WouldBlock {
-                    error!("Encountered an error receiving data: {:?}", e);
-                    return Err(e.into());
-                } else {
-                    return Ok(UdpSocketState::Empty);
-                }
-            }
-        }
-        Ok(UdpSocketState::MaybeMore)
+    // Returns the number of reliable packets, on all connections, which are not acknowledged yet.
+    fn packets_awaiting_ack(&self) -> usize {
+        self.connections()
+            .iter()
+            .map(|stats| stats.packets_in_flight)
+            .sum()
     }
 
-    // Send a single packet over the UDP socket.
-    fn send_packet(&self, addr: &SocketAddr, payload: &[u8]) -> Result<usize> {
-        let bytes_sent = self.socket.send_to(payload, addr)?;
-        Ok(bytes_sent)
+    #[cfg(test)]
+    // Serializes and sends a `Packet` on the socket. On success, returns the number of bytes written.
+    fn send_to(&mut self, packet: Packet, time: Instant) -> Result<usize> {
+        self.manager.send(packet, time)?;
+        Ok(self.flush())
     }
 
-    // In the presence of a link conditioner, we would like it to determine whether or not we should
-    // send a packet.
-    fn should_send_packet(&mut self) -> bool {
-        if let Some(link_conditioner) = &mut self.link_conditioner {
-            link_conditioner.should_send()
-        } else {
-            true
+    // Sends all datagrams the connection manager has queued on the UDP socket, returns the number of bytes written.
+    fn flush(&mut self) -> usize {
+        self.transmit_buffer.clear();
+        while let Some((address, payload)) = self.manager.poll_transmit() {
+            self.transmit_buffer
+                .push((address_for_socket(address, self.ipv6), payload));
         }
+
+        let config = self.manager.config();
+        batch::send_batch(
+            &self.socket,
+            &self.transmit_buffer,
+            config.batch_size,
+            config.udp_gso,
+            |_, e| {
+                if e.kind() != io::ErrorKind::WouldBlock {
+                    error!("There was an error sending packet: {:?}", e);
+                }
+            },
+        )
     }
 
     #[cfg(test)]
     fn connection_count(&self) -> usize {
-        self.connections.count()
+        self.manager.connection_count()
     }
 
     #[cfg(test)]
     fn forget_all_incoming_packets(&mut self) {
         std::thread::sleep(std::time::Duration::from_millis(100));
         loop {
-            match self.socket.recv_from(&mut self.recv_buffer) {
+            match self.socket.recv_from(&mut self.recv_buffers[0]) {
                 Ok((recv_len, _address)) => {
                     if recv_len == 0 {
                         panic!("Received data too short");
                     }
-                    let received_payload = &self.recv_buffer[..recv_len];
+                    let _received_payload = &self.recv_buffers[0][..recv_len];
                 }
                 Err(e) => {
                     if e.kind() != io::ErrorKind::WouldBlock {
@@ -315,15 +526,27 @@ impl Socket {
     }
 }
 
+// Takes a buffer from the pool of the manager which can hold any datagram that is received.
+fn receive_buffer(manager: &ConnectionManager) -> BufferMut {
+    let mut buffer = manager.buffer_pool().acquire();
+    buffer.resize(manager.config().receive_buffer_max_size, 0);
+    buffer
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
-        net::constants::{ACKED_PACKET_HEADER, FRAGMENT_HEADER_SIZE, STANDARD_HEADER_SIZE},
-        Config, LinkConditioner, Packet, Socket, SocketEvent,
+        net::constants::{
+            ACKED_PACKET_HEADER, DEFAULT_MTU, DEFAULT_ORDERING_STREAM, FRAGMENT_HEADER_SIZE,
+            IPV4_MTU, IPV6_MTU, MAX_FRAGMENT_OVERHEAD, STANDARD_HEADER_SIZE,
+        },
+        Config, ConnectionManager, ErrorKind, InMemoryMetrics, LinkConditioner, OrderingGuarantee,
+        OverflowPolicy, Packet, ReceiveErrorKind, Socket, SocketEvent,
     };
     use std::collections::HashSet;
     use std::net::{SocketAddr, UdpSocket};
-    use std::thread;
+    use std::sync::Arc;
+
     use std::time::{Duration, Instant};
 
     #[test]
@@ -332,6 +555,123 @@ mod tests {
         assert![Socket::bind_any_with_config(Config::default()).is_ok()];
     }
 
+    #[test]
+    fn binding_fails_when_the_os_limits_the_buffer_size() {
+        let config = Config {
+            socket_recv_buffer_size: Some(512 * 1024 * 1024),
+            ..Config::default()
+        };
+
+        match Socket::bind_any_with_config(config) {
+            Err(ErrorKind::SocketOptionError(_)) => {}
+            result => panic!("Expected a socket option error, got {:?}", result.err()),
+        }
+    }
+
+    #[test]
+    fn binding_to_any_ipv6() {
+        let socket = Socket::bind_any_ipv6().unwrap();
+        assert!(socket.local_addr().unwrap().is_ipv6());
+        assert_eq!(
+            socket.manager.config().receive_buffer_max_size,
+            usize::from(IPV6_MTU)
+        );
+
+        let socket = Socket::bind_any_dual_stack().unwrap();
+        assert!(socket.local_addr().unwrap().ip().is_unspecified());
+        assert_eq!(
+            socket.manager.config().receive_buffer_max_size,
+            usize::from(DEFAULT_MTU)
+        );
+        assert_eq!(
+            Socket::bind_any()
+                .unwrap()
+                .manager
+                .config()
+                .receive_buffer_max_size,
+            usize::from(IPV4_MTU)
+        );
+    }
+
+    #[test]
+    fn size_datagrams_of_an_ipv6_socket_for_ipv6() {
+        let mut client = Socket::bind_any_ipv6().unwrap();
+        let server_addr = "[::1]:12417".parse::<SocketAddr>().unwrap();
+        let server = UdpSocket::bind(server_addr).unwrap();
+        server
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+
+        let fragment_size = client.manager.config().fragment_size;
+        assert_eq!(fragment_size, IPV6_MTU - MAX_FRAGMENT_OVERHEAD);
+        assert!(fragment_size > Config::default().fragment_size);
+
+        client
+            .send(Packet::reliable_ordered(
+                server_addr,
+                vec![1; usize::from(fragment_size) * 4],
+                None,
+            ))
+            .unwrap();
+        client.manual_poll(Instant::now());
+
+        let mut buffer = [0; 2048];
+        for _ in 0..4 {
+            let (length, address) = server.recv_from(&mut buffer).unwrap();
+            assert_eq!(address, client.local_addr().unwrap());
+            assert!(length <= usize::from(IPV6_MTU));
+        }
+    }
+
+    #[test]
+    fn dual_stack_socket_sees_ipv4_peers_as_one_connection() {
+        let server_addr = "127.0.0.1:12412".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12413".parse::<SocketAddr>().unwrap();
+        let mapped_client_addr = "[::ffff:127.0.0.1]:12413".parse::<SocketAddr>().unwrap();
+
+        let mut server = Socket::bind_dual_stack_with_config(12412, Config::default()).unwrap();
+        let mut client = Socket::bind(client_addr).unwrap();
+
+        client
+            .send(Packet::unreliable(server_addr, vec![1]))
+            .unwrap();
+        client.manual_poll(Instant::now());
+        std::thread::sleep(Duration::from_millis(100));
+        server.manual_poll(Instant::now());
+
+        assert_eq!(server.recv(), Some(SocketEvent::Connect(client_addr)));
+        assert_eq!(
+            server.recv(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                vec![1]
+            )))
+        );
+
+        // Both forms of the address refer to the same connection
+        server
+            .send(Packet::unreliable(client_addr, vec![2]))
+            .unwrap();
+        server
+            .send(Packet::unreliable(mapped_client_addr, vec![3]))
+            .unwrap();
+        server.manual_poll(Instant::now());
+        assert_eq!(server.connection_count(), 1);
+        assert!(server.connection_stats(mapped_client_addr).is_some());
+
+        std::thread::sleep(Duration::from_millis(100));
+        client.manual_poll(Instant::now());
+        let received: Vec<SocketEvent> = std::iter::from_fn(|| client.recv()).collect();
+        assert!(received.contains(&SocketEvent::Packet(Packet::unreliable(
+            server_addr,
+            vec![2]
+        ))));
+        assert!(received.contains(&SocketEvent::Packet(Packet::unreliable(
+            server_addr,
+            vec![3]
+        ))));
+    }
+
     #[test]
     fn using_sender_and_receiver() {
         let server_addr = "127.0.0.1:12310".parse::<SocketAddr>().unwrap();
@@ -342,13 +682,15 @@ mod tests {
 
         let time = Instant::now();
 
-        let mut sender = client.get_packet_sender();
-        let mut receiver = server.get_event_receiver();
+        let sender = client.get_packet_sender();
+        let receiver = server.get_event_receiver();
 
-        sender.send(Packet::reliable_unordered(
-            server_addr,
-            b"Hello world!".iter().cloned().collect::<Vec<_>>(),
-        ));
+        sender
+            .send(Packet::reliable_unordered(
+                server_addr,
+                b"Hello world!".to_vec(),
+            ))
+            .unwrap();
 
         client.manual_poll(time);
         server.manual_poll(time);
@@ -369,20 +711,26 @@ mod tests {
         let time = Instant::now();
 
         // Send a packet that the server ignores/drops
-        client.send(Packet::reliable_unordered(
-            "127.0.0.1:12335".parse::<SocketAddr>().unwrap(),
-            b"Do not arrive".iter().cloned().collect::<Vec<_>>(),
-        ));
+        client
+            .send(Packet::reliable_unordered(
+                "127.0.0.1:12335".parse::<SocketAddr>().unwrap(),
+                b"Do not arrive".to_vec(),
+            ))
+            .unwrap();
         client.manual_poll(time);
 
         // Drop the inbound packet, this simulates a network error
         server.forget_all_incoming_packets();
 
         // Send a packet that the server receives
-        for id in 0..u8::max_value() {
-            client.send(create_test_packet(id, "127.0.0.1:12335"));
+        for id in 0..u8::MAX {
+            client
+                .send(create_test_packet(id, "127.0.0.1:12335"))
+                .unwrap();
 
-            server.send(create_test_packet(id, "127.0.0.1:12336"));
+            server
+                .send(create_test_packet(id, "127.0.0.1:12336"))
+                .unwrap();
 
             client.manual_poll(time);
             server.manual_poll(time);
@@ -392,7 +740,7 @@ mod tests {
                     return;
                 }
             }
-            while let Some(_) = client.recv() {}
+            while client.recv().is_some() {}
         }
 
         panic!["Did not receive the ignored packet"];
@@ -405,10 +753,12 @@ mod tests {
 
         // Send a bunch of packets to a server
         for _ in 0..3 {
-            client.send(Packet::unreliable(
-                "127.0.0.1:12337".parse::<SocketAddr>().unwrap(),
-                vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
-            ));
+            client
+                .send(Packet::unreliable(
+                    "127.0.0.1:12337".parse::<SocketAddr>().unwrap(),
+                    vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
+                ))
+                .unwrap();
         }
 
         let time = Instant::now();
@@ -416,7 +766,8 @@ mod tests {
         client.manual_poll(time);
         server.manual_poll(time);
 
-        for _ in 0..6 {
+        // A single connect event for the peer, plus the three packets
+        for _ in 0..4 {
             assert![server.recv().is_some()];
         }
         assert![server.recv().is_none()];
@@ -425,10 +776,12 @@ mod tests {
         // packets
         assert_eq![0, server.connection_count()];
 
-        server.send(Packet::unreliable(
-            "127.0.0.1:12338".parse::<SocketAddr>().unwrap(),
-            vec![1],
-        ));
+        server
+            .send(Packet::unreliable(
+                "127.0.0.1:12338".parse::<SocketAddr>().unwrap(),
+                vec![1],
+            ))
+            .unwrap();
 
         server.manual_poll(time);
 
@@ -444,11 +797,13 @@ mod tests {
         let time = Instant::now();
 
         // Send a packet that the server ignores/drops
-        client.send(Packet::reliable_sequenced(
-            "127.0.0.1:12329".parse::<SocketAddr>().unwrap(),
-            b"Do not arrive".iter().cloned().collect::<Vec<_>>(),
-            None,
-        ));
+        client
+            .send(Packet::reliable_sequenced(
+                "127.0.0.1:12329".parse::<SocketAddr>().unwrap(),
+                b"Do not arrive".to_vec(),
+                None,
+            ))
+            .unwrap();
         client.manual_poll(time);
 
         // Drop the inbound packet, this simulates a network error
@@ -456,9 +811,13 @@ mod tests {
 
         // Send a packet that the server receives
         for id in 0..36 {
-            client.send(create_sequenced_packet(id, "127.0.0.1:12329"));
+            client
+                .send(create_sequenced_packet(id, "127.0.0.1:12329"))
+                .unwrap();
 
-            server.send(create_sequenced_packet(id, "127.0.0.1:12330"));
+            server
+                .send(create_sequenced_packet(id, "127.0.0.1:12330"))
+                .unwrap();
 
             client.manual_poll(time);
             server.manual_poll(time);
@@ -468,7 +827,7 @@ mod tests {
                     panic!["Sequenced packet arrived while it should not"];
                 }
             }
-            while let Some(_) = client.recv() {}
+            while client.recv().is_some() {}
         }
     }
 
@@ -480,11 +839,13 @@ mod tests {
         let time = Instant::now();
 
         // Send a packet that the server ignores/drops
-        client.send(Packet::reliable_ordered(
-            "127.0.0.1:12333".parse::<SocketAddr>().unwrap(),
-            b"Do not arrive".iter().cloned().collect::<Vec<_>>(),
-            None,
-        ));
+        client
+            .send(Packet::reliable_ordered(
+                "127.0.0.1:12333".parse::<SocketAddr>().unwrap(),
+                b"Do not arrive".to_vec(),
+                None,
+            ))
+            .unwrap();
         client.manual_poll(time);
 
         // Drop the inbound packet, this simulates a network error
@@ -492,9 +853,13 @@ mod tests {
 
         // Send a packet that the server receives
         for id in 0..36 {
-            client.send(create_ordered_packet(id, "127.0.0.1:12333"));
+            client
+                .send(create_ordered_packet(id, "127.0.0.1:12333"))
+                .unwrap();
 
-            server.send(create_ordered_packet(id, "127.0.0.1:12334"));
+            server
+                .send(create_ordered_packet(id, "127.0.0.1:12334"))
+                .unwrap();
 
             client.manual_poll(time);
             server.manual_poll(time);
@@ -504,7 +869,7 @@ mod tests {
                     return;
                 }
             }
-            while let Some(_) = client.recv() {}
+            while client.recv().is_some() {}
         }
 
         panic!["Did not receive the ignored packet"];
@@ -521,7 +886,9 @@ mod tests {
         let time = Instant::now();
 
         for id in 0..100 {
-            client.send(Packet::reliable_sequenced(server_addr, vec![id], None));
+            client
+                .send(Packet::reliable_sequenced(server_addr, vec![id], None))
+                .unwrap();
             client.manual_poll(time);
         }
 
@@ -531,15 +898,36 @@ mod tests {
 
         while let Some(message) = server.recv() {
             match message {
-                SocketEvent::Connect(connect_event) => {}
+                SocketEvent::Connect(_connect_event) => {}
                 SocketEvent::Packet(packet) => {
                     let byte = packet.payload()[0];
                     assert![!seen.contains(&byte)];
                     seen.insert(byte);
                 }
-                SocketEvent::Timeout(timeout_event) => {
+                SocketEvent::Timeout(_timeout_event) => {
                     panic!["This should not happen, as we've not advanced time"];
                 }
+                SocketEvent::Disconnect(_disconnect_event) => {
+                    panic!["This should not happen, as the client does not disconnect"];
+                }
+                SocketEvent::Evicted(_evicted_event) => {
+                    panic!["This should not happen, as there is a single client"];
+                }
+                SocketEvent::ConnectionRejected(_rejected_event) => {
+                    panic!["This should not happen, as there is no connection limit"];
+                }
+                SocketEvent::AddressChanged { .. } => {
+                    panic!["This should not happen, as the client does not change its address"];
+                }
+                SocketEvent::VersionMismatch(_) => {
+                    panic!["This should not happen, as both use the same protocol"];
+                }
+                SocketEvent::StreamStalled(..) => {
+                    panic!["This should not happen, as we've not sent ordered packets"];
+                }
+                SocketEvent::Error { .. } => {
+                    panic!["This should not happen, as error events are not enabled"];
+                }
             }
         }
 
@@ -552,10 +940,12 @@ mod tests {
         let mut client = Socket::bind("127.0.0.1:12340".parse::<SocketAddr>().unwrap()).unwrap();
 
         for _ in 0..3 {
-            client.send(Packet::unreliable(
-                "127.0.0.1:12339".parse::<SocketAddr>().unwrap(),
-                vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
-            ));
+            client
+                .send(Packet::unreliable(
+                    "127.0.0.1:12339".parse::<SocketAddr>().unwrap(),
+                    vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
+                ))
+                .unwrap();
         }
 
         let time = Instant::now();
@@ -574,10 +964,12 @@ mod tests {
         let mut client = Socket::bind("127.0.0.1:12341".parse::<SocketAddr>().unwrap()).unwrap();
 
         for _ in 0..3 {
-            client.send(Packet::unreliable(
-                "127.0.0.1:12342".parse::<SocketAddr>().unwrap(),
-                vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
-            ));
+            client
+                .send(Packet::unreliable(
+                    "127.0.0.1:12342".parse::<SocketAddr>().unwrap(),
+                    vec![1, 2, 3, 4, 5, 6, 7, 8, 9],
+                ))
+                .unwrap();
         }
 
         let now = Instant::now();
@@ -590,18 +982,19 @@ mod tests {
     }
 
     #[test]
-    fn sending_large_unreliable_packet_should_fail() {
-        let mut server = Socket::bind("127.0.0.1:12370".parse::<SocketAddr>().unwrap()).unwrap();
-
-        assert_eq!(
-            server
-                .send_to(
-                    Packet::unreliable("127.0.0.1:12360".parse().unwrap(), vec![1; 5000]),
-                    Instant::now(),
-                )
-                .is_err(),
-            true
-        );
+    fn sending_unreliable_packet_exceeding_max_fragments_should_fail() {
+        let mut server = Socket::bind_with_config(
+            "127.0.0.1:12370".parse::<SocketAddr>().unwrap(),
+            Config::default(),
+        )
+        .unwrap();
+
+        assert!(server
+            .send_to(
+                Packet::unreliable("127.0.0.1:12360".parse().unwrap(), vec![1; 20000]),
+                Instant::now(),
+            )
+            .is_err());
     }
 
     #[test]
@@ -621,7 +1014,11 @@ mod tests {
 
     #[test]
     fn fragmentation_send_returns_right_size() {
-        let mut server = Socket::bind("127.0.0.1:12372".parse::<SocketAddr>().unwrap()).unwrap();
+        let mut server = Socket::bind_with_config(
+            "127.0.0.1:12372".parse::<SocketAddr>().unwrap(),
+            Config::default(),
+        )
+        .unwrap();
 
         let fragment_packet_size = STANDARD_HEADER_SIZE + FRAGMENT_HEADER_SIZE;
 
@@ -635,6 +1032,17 @@ mod tests {
                 .unwrap(),
             4000 + (fragment_packet_size * 4 + ACKED_PACKET_HEADER) as usize
         );
+
+        // unreliable fragments carry no acknowledgment header.
+        assert_eq!(
+            server
+                .send_to(
+                    Packet::unreliable("127.0.0.1:12362".parse().unwrap(), vec![1; 4000]),
+                    Instant::now(),
+                )
+                .unwrap(),
+            4000 + (fragment_packet_size * 4) as usize
+        );
     }
 
     #[test]
@@ -642,10 +1050,12 @@ mod tests {
         let mut server = Socket::bind("127.0.0.1:12345".parse::<SocketAddr>().unwrap()).unwrap();
         let mut client = Socket::bind("127.0.0.1:12344".parse::<SocketAddr>().unwrap()).unwrap();
 
-        client.send(Packet::unreliable(
-            "127.0.0.1:12345".parse().unwrap(),
-            vec![0, 1, 2],
-        ));
+        client
+            .send(Packet::unreliable(
+                "127.0.0.1:12345".parse().unwrap(),
+                vec![0, 1, 2],
+            ))
+            .unwrap();
 
         let now = Instant::now();
         client.manual_poll(now);
@@ -659,16 +1069,15 @@ mod tests {
 
     #[test]
     fn disconnect_event_occurs() {
-        let mut config = Config::default();
-        config.idle_connection_timeout = Duration::from_millis(1);
-
         let mut server = Socket::bind("127.0.0.1:12347".parse::<SocketAddr>().unwrap()).unwrap();
         let mut client = Socket::bind("127.0.0.1:12346".parse::<SocketAddr>().unwrap()).unwrap();
 
-        client.send(Packet::unreliable(
-            "127.0.0.1:12347".parse().unwrap(),
-            vec![0, 1, 2],
-        ));
+        client
+            .send(Packet::unreliable(
+                "127.0.0.1:12347".parse().unwrap(),
+                vec![0, 1, 2],
+            ))
+            .unwrap();
 
         let now = Instant::now();
         client.manual_poll(now);
@@ -687,10 +1096,12 @@ mod tests {
         );
 
         // Acknowledge the client
-        server.send(Packet::unreliable(
-            "127.0.0.1:12346".parse().unwrap(),
-            vec![],
-        ));
+        server
+            .send(Packet::unreliable(
+                "127.0.0.1:12346".parse().unwrap(),
+                vec![],
+            ))
+            .unwrap();
 
         server.manual_poll(now);
         client.manual_poll(now);
@@ -702,6 +1113,571 @@ mod tests {
         );
     }
 
+    #[test]
+    fn stream_stalled_event_occurs() {
+        let server_addr = "127.0.0.1:12380".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12381".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            ordering_stall_timeout: Some(Duration::from_millis(100)),
+            ..Config::default()
+        };
+
+        let mut server = Socket::bind_with_config(server_addr, config).unwrap();
+        let mut client = Socket::bind(client_addr).unwrap();
+
+        let now = Instant::now();
+
+        // The server only keeps ordering state for clients it has sent to
+        server
+            .send(Packet::unreliable(client_addr, vec![]))
+            .unwrap();
+        server.manual_poll(now);
+
+        // Send a packet that the server ignores/drops
+        client
+            .send(Packet::reliable_ordered(server_addr, vec![1], None))
+            .unwrap();
+        client.manual_poll(now);
+        server.forget_all_incoming_packets();
+
+        client
+            .send(Packet::reliable_ordered(server_addr, vec![2], None))
+            .unwrap();
+        client.manual_poll(now);
+        server.manual_poll(now);
+
+        assert_eq![None, server.recv()];
+
+        server.manual_poll(now + Duration::from_millis(100));
+
+        assert_eq![
+            Some(SocketEvent::StreamStalled(
+                client_addr,
+                DEFAULT_ORDERING_STREAM
+            )),
+            server.recv()
+        ];
+    }
+
+    #[test]
+    fn connection_stats_are_tracked() {
+        let server_addr = "127.0.0.1:12385".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12386".parse::<SocketAddr>().unwrap();
+
+        let mut server = Socket::bind(server_addr).unwrap();
+        let mut client = Socket::bind(client_addr).unwrap();
+
+        let now = Instant::now();
+
+        assert_eq!(server.connection_stats(client_addr), None);
+
+        server
+            .send(Packet::unreliable(client_addr, vec![]))
+            .unwrap();
+        server.manual_poll(now);
+
+        for _ in 0..2 {
+            client
+                .send(Packet::reliable_unordered(server_addr, vec![1; 10]))
+                .unwrap();
+        }
+        client.manual_poll(now);
+        server.manual_poll(now);
+
+        let reliable_packet_size = (STANDARD_HEADER_SIZE + ACKED_PACKET_HEADER) as u64 + 10;
+
+        let server_stats = server.connection_stats(client_addr).unwrap();
+        assert_eq!(server_stats.remote_address, client_addr);
+        assert_eq!(server_stats.packets_sent, 1);
+        assert_eq!(server_stats.bytes_sent, STANDARD_HEADER_SIZE as u64);
+        assert_eq!(server_stats.packets_received, 2);
+        assert_eq!(server_stats.bytes_received, 2 * reliable_packet_size);
+        assert_eq!(
+            server
+                .connections()
+                .iter()
+                .map(|stats| stats.remote_address)
+                .collect::<Vec<_>>(),
+            vec![client_addr]
+        );
+
+        let client_stats = client.connection_stats(server_addr).unwrap();
+        assert_eq!(client_stats.packets_sent, 2);
+        assert_eq!(client_stats.packets_in_flight, 2);
+
+        // The reply of the server acknowledges both packets
+        server
+            .send(Packet::reliable_unordered(client_addr, vec![]))
+            .unwrap();
+        server.manual_poll(now);
+        client.manual_poll(now);
+
+        // The first packet of the server arrived before the client knew the server
+        let client_stats = client.connection_stats(server_addr).unwrap();
+        assert_eq!(client_stats.packets_received, 1);
+        assert_eq!(client_stats.packets_in_flight, 0);
+        assert_eq!(client_stats.packets_resent, 0);
+        assert_eq!(client_stats.outbound_packet_loss, 0.);
+        assert_eq!(client_stats.inbound_packet_loss, 0.);
+    }
+
+    #[test]
+    fn receive_errors_are_reported() {
+        let server_addr = "127.0.0.1:12390".parse::<SocketAddr>().unwrap();
+        let peer_addr = "127.0.0.1:12391".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            emit_error_events: true,
+            ..Default::default()
+        };
+        let mut server = Socket::bind_with_config(server_addr, config).unwrap();
+        let peer = UdpSocket::bind(peer_addr).unwrap();
+
+        let now = Instant::now();
+
+        server.send(Packet::unreliable(peer_addr, vec![])).unwrap();
+        server.manual_poll(now);
+
+        peer.send_to(&[1, 2, 3], server_addr).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        server.manual_poll(now);
+
+        assert_eq!(
+            server.recv(),
+            Some(SocketEvent::Error {
+                addr: Some(peer_addr),
+                kind: ReceiveErrorKind::CouldNotReadHeader("standard".to_owned()),
+            })
+        );
+        assert_eq!(
+            server.connection_stats(peer_addr).unwrap().errors_received,
+            1
+        );
+    }
+
+    #[test]
+    fn flooding_addresses_are_banned() {
+        let server_addr = "127.0.0.1:12414".parse::<SocketAddr>().unwrap();
+        let peer_addr = "127.0.0.1:12415".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            max_datagrams_per_ip: Some(2),
+            ..Default::default()
+        };
+        let mut server = Socket::bind_with_config(server_addr, config).unwrap();
+        let peer = UdpSocket::bind(peer_addr).unwrap();
+        let mut client = ConnectionManager::new(Config::default());
+        client
+            .send(Packet::unreliable(server_addr, vec![1]), Instant::now())
+            .unwrap();
+        let (_, datagram) = client.poll_transmit().unwrap();
+
+        // Only addresses with a connection are banned
+        server.send(Packet::unreliable(peer_addr, vec![0])).unwrap();
+        server.manual_poll(Instant::now());
+        for _ in 0..3 {
+            peer.send_to(&datagram, server_addr).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(100));
+        server.manual_poll(Instant::now());
+
+        let packets = std::iter::from_fn(|| server.recv())
+            .filter(|event| matches!(event, SocketEvent::Packet(_)))
+            .count();
+        assert_eq!(packets, 2);
+        let bans = server.bans();
+        assert_eq!(bans.len(), 1);
+        assert_eq!(bans[0].ip, peer_addr.ip());
+
+        assert!(server.unban(peer_addr.ip()));
+        assert!(server.bans().is_empty());
+    }
+
+    #[test]
+    fn polling_loop_wakes_up_on_activity() {
+        let server_addr = "127.0.0.1:12398".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12399".parse::<SocketAddr>().unwrap();
+
+        let mut server = Socket::bind(server_addr).unwrap();
+        let mut client = Socket::bind(client_addr).unwrap();
+        let (sender, receiver) = (server.get_packet_sender(), server.get_event_receiver());
+        std::thread::spawn(move || server.start_polling());
+
+        // Without connections the loop only wakes up for the queued packet
+        sender
+            .send(Packet::unreliable(client_addr, vec![1]))
+            .unwrap();
+        let start = Instant::now();
+        while client.recv().is_none() {
+            assert!(start.elapsed() < Duration::from_secs(1));
+            std::thread::sleep(Duration::from_millis(1));
+            client.manual_poll(Instant::now());
+        }
+
+        client
+            .send(Packet::unreliable(server_addr, vec![2]))
+            .unwrap();
+        client.manual_poll(Instant::now());
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(1)),
+            Ok(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                vec![2]
+            )))
+        );
+    }
+
+    #[test]
+    fn graceful_shutdown_waits_for_acknowledgments_and_disconnects() {
+        let server_addr = "127.0.0.1:12408".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12409".parse::<SocketAddr>().unwrap();
+
+        let mut server = Socket::bind(server_addr).unwrap();
+        let mut client = Socket::bind(client_addr).unwrap();
+        let (sender, shutdown) = (server.get_packet_sender(), server.get_shutdown_handle());
+        let polling = std::thread::spawn(move || server.start_polling());
+
+        // The client only acknowledges packets on a connection it sent on before
+        client
+            .send(Packet::unreliable(server_addr, vec![]))
+            .unwrap();
+        client.manual_poll(Instant::now());
+        sender
+            .send(Packet::reliable_unordered(client_addr, vec![1]))
+            .unwrap();
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < Duration::from_secs(1));
+            std::thread::sleep(Duration::from_millis(1));
+            client.manual_poll(Instant::now());
+            if let Some(SocketEvent::Packet(_)) = client.recv() {
+                break;
+            }
+        }
+
+        shutdown
+            .shutdown_gracefully(Duration::from_secs(1))
+            .unwrap();
+        assert!(shutdown.is_shutdown_requested());
+        // Only reliable packets carry acknowledgments
+        client
+            .send(Packet::reliable_unordered(server_addr, vec![]))
+            .unwrap();
+        client.manual_poll(Instant::now());
+
+        let stats = polling.join().unwrap();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].remote_address, client_addr);
+        assert_eq!(stats[0].packets_in_flight, 0);
+        assert!(!shutdown.is_shutdown_requested());
+
+        std::thread::sleep(Duration::from_millis(100));
+        client.manual_poll(Instant::now());
+        assert_eq!(client.recv(), Some(SocketEvent::Disconnect(server_addr)));
+        assert_eq!(client.connection_count(), 0);
+    }
+
+    #[test]
+    fn graceful_shutdown_resends_lost_packets_to_a_quiet_peer() {
+        let server_addr = "127.0.0.1:12416".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12417".parse::<SocketAddr>().unwrap();
+
+        let mut server = Socket::bind(server_addr).unwrap();
+        let mut client = Socket::bind(client_addr).unwrap();
+        let (sender, shutdown) = (server.get_packet_sender(), server.get_shutdown_handle());
+        let polling = std::thread::spawn(move || server.start_polling());
+
+        // The packet is lost, and the client never sends anything which would acknowledge it
+        sender
+            .send(Packet::reliable_unordered(client_addr, vec![1]))
+            .unwrap();
+        client.forget_all_incoming_packets();
+
+        shutdown
+            .shutdown_gracefully(Duration::from_millis(500))
+            .unwrap();
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < Duration::from_millis(500));
+            std::thread::sleep(Duration::from_millis(1));
+            client.manual_poll(Instant::now());
+            if let Some(SocketEvent::Packet(packet)) = client.recv() {
+                assert_eq!(packet, Packet::reliable_unordered(server_addr, vec![1]));
+                break;
+            }
+        }
+
+        let stats = polling.join().unwrap();
+        assert_eq!(stats.len(), 1);
+        assert!(stats[0].packets_resent > 0);
+        assert_eq!(stats[0].packets_in_flight, 1);
+    }
+
+    #[test]
+    fn socket_can_be_polled_again_after_shutdown() {
+        let server_addr = "127.0.0.1:12410".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12411".parse::<SocketAddr>().unwrap();
+
+        let mut server = Socket::bind(server_addr).unwrap();
+        let mut client = Socket::bind(client_addr).unwrap();
+        let shutdown = server.get_shutdown_handle();
+        let polling = std::thread::spawn(move || {
+            let stats = server.start_polling();
+            (server, stats)
+        });
+
+        shutdown.shutdown().unwrap();
+        let (mut server, stats) = polling.join().unwrap();
+        assert!(stats.is_empty());
+
+        client
+            .send(Packet::unreliable(server_addr, vec![1]))
+            .unwrap();
+        client.manual_poll(Instant::now());
+        std::thread::sleep(Duration::from_millis(100));
+        server.manual_poll(Instant::now());
+        assert_eq!(server.recv(), Some(SocketEvent::Connect(client_addr)));
+        assert_eq!(
+            server.recv(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                vec![1]
+            )))
+        );
+    }
+
+    #[test]
+    fn next_deadline_is_the_idle_timeout() {
+        let mut socket = Socket::bind("127.0.0.1:12396".parse::<SocketAddr>().unwrap()).unwrap();
+        let remote_addr = "127.0.0.1:12397".parse::<SocketAddr>().unwrap();
+
+        assert_eq!(socket.next_deadline(), None);
+
+        let now = Instant::now();
+        socket
+            .send(Packet::unreliable(remote_addr, vec![]))
+            .unwrap();
+        socket.manual_poll(now);
+
+        assert_eq!(
+            socket.next_deadline(),
+            Some(now + Config::default().idle_connection_timeout)
+        );
+    }
+
+    #[test]
+    fn metrics_are_reported() {
+        let server_addr = "127.0.0.1:12387".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12388".parse::<SocketAddr>().unwrap();
+
+        let mut server = Socket::bind(server_addr).unwrap();
+        let mut client = Socket::bind_with_config(client_addr, Config::default()).unwrap();
+
+        let metrics = Arc::new(InMemoryMetrics::new());
+        server.set_metrics(Some(metrics.clone()));
+
+        let now = Instant::now();
+
+        server
+            .send(Packet::unreliable(client_addr, vec![]))
+            .unwrap();
+        server.manual_poll(now);
+
+        client
+            .send(Packet::reliable_unordered(server_addr, vec![1; 4000]))
+            .unwrap();
+        client.manual_poll(now);
+
+        let garbage = UdpSocket::bind("127.0.0.1:12389").unwrap();
+        garbage.send_to(&[1, 2, 3], server_addr).unwrap();
+
+        std::thread::sleep(Duration::from_millis(100));
+        server.manual_poll(now);
+
+        assert_eq!(metrics.datagrams_sent(), 1);
+        assert_eq!(metrics.bytes_sent(), STANDARD_HEADER_SIZE as u64);
+        // Four fragments of the client and the garbage
+        assert_eq!(metrics.datagrams_received(), 5);
+        assert_eq!(metrics.fragments_reassembled(), 1);
+        assert_eq!(metrics.connections_opened(), 1);
+        assert_eq!(metrics.decode_errors().values().sum::<u64>(), 1);
+    }
+
+    #[test]
+    fn reset_and_close_stream() {
+        let server_addr = "127.0.0.1:12382".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12383".parse::<SocketAddr>().unwrap();
+        let stream = OrderingGuarantee::Ordered(None);
+
+        let mut server = Socket::bind(server_addr).unwrap();
+        let mut client = Socket::bind(client_addr).unwrap();
+
+        let now = Instant::now();
+
+        // The server only keeps ordering state for clients it has sent to
+        server
+            .send(Packet::unreliable(client_addr, vec![]))
+            .unwrap();
+        server.manual_poll(now);
+
+        // Send a packet that the server ignores/drops, the packets behind it are held back
+        client
+            .send(Packet::reliable_ordered(server_addr, vec![1], None))
+            .unwrap();
+        client.manual_poll(now);
+        server.forget_all_incoming_packets();
+
+        client
+            .send(Packet::reliable_ordered(server_addr, vec![2], None))
+            .unwrap();
+        client.manual_poll(now);
+        server.manual_poll(now);
+
+        assert_eq![None, server.recv()];
+        assert_eq!(
+            server
+                .stream_info(client_addr, stream)
+                .map(|info| info.buffered_items),
+            Some(1)
+        );
+
+        // After a reset the server no longer waits for the dropped packet
+        client.reset_stream(server_addr, stream).unwrap();
+        client
+            .send(Packet::reliable_ordered(server_addr, vec![3], None))
+            .unwrap();
+        client.manual_poll(now);
+        server.manual_poll(now);
+
+        assert_eq![
+            Some(SocketEvent::Packet(Packet::reliable_ordered(
+                client_addr,
+                vec![3],
+                Some(DEFAULT_ORDERING_STREAM)
+            ))),
+            server.recv()
+        ];
+        assert_eq!(
+            server
+                .stream_info(client_addr, stream)
+                .map(|info| info.buffered_items),
+            Some(0)
+        );
+
+        // Nothing can be sent on a closed stream
+        client.close_stream(server_addr, stream).unwrap();
+        client
+            .send(Packet::reliable_ordered(server_addr, vec![4], None))
+            .unwrap();
+        client.manual_poll(now);
+        server.manual_poll(now);
+
+        assert_eq![None, server.recv()];
+        assert!(client.stream_info(server_addr, stream).unwrap().closed);
+        assert!(
+            server
+                .stream_info(client_addr, stream)
+                .unwrap()
+                .closed_by_remote
+        );
+
+        assert!(client
+            .reset_stream("127.0.0.1:12384".parse().unwrap(), OrderingGuarantee::None)
+            .is_err());
+        assert!(client
+            .reset_stream(server_addr, OrderingGuarantee::None)
+            .is_err());
+    }
+
+    #[test]
+    fn queues_are_bounded() {
+        let server_addr = "127.0.0.1:12404".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12405".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            event_channel_capacity: Some(2),
+            packet_channel_capacity: Some(2),
+            ..Default::default()
+        };
+        let mut server = Socket::bind_with_config(server_addr, config.clone()).unwrap();
+        let mut client = Socket::bind_with_config(client_addr, config).unwrap();
+
+        for id in 0..2 {
+            client
+                .send(Packet::unreliable(server_addr, vec![id]))
+                .unwrap();
+        }
+        match client.send(Packet::unreliable(server_addr, vec![2])) {
+            Err(ErrorKind::QueueFull(SocketEvent::Packet(packet))) => {
+                assert_eq!(packet.payload(), &[2])
+            }
+            result => panic!("Expected a full queue, got {:?}", result),
+        }
+
+        // Polling empties the queue, the server only has room for the connect event and the first packet
+        client.manual_poll(Instant::now());
+        client
+            .send(Packet::unreliable(server_addr, vec![2]))
+            .unwrap();
+        server.manual_poll(Instant::now());
+
+        assert_eq!(server.recv(), Some(SocketEvent::Connect(client_addr)));
+        assert_eq!(
+            server.recv(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                vec![0]
+            )))
+        );
+        assert_eq!(server.recv(), None);
+    }
+
+    #[test]
+    fn drop_oldest_unreliable_events() {
+        let server_addr = "127.0.0.1:12406".parse::<SocketAddr>().unwrap();
+        let client_addr = "127.0.0.1:12407".parse::<SocketAddr>().unwrap();
+
+        let config = Config {
+            event_channel_capacity: Some(2),
+            channel_overflow_policy: OverflowPolicy::DropOldestUnreliable,
+            ..Default::default()
+        };
+        let mut server = Socket::bind_with_config(server_addr, config).unwrap();
+        let mut client = Socket::bind(client_addr).unwrap();
+
+        // The server only keeps track of clients it has sent to, otherwise every datagram raises a connect event
+        server
+            .send(Packet::unreliable(client_addr, vec![]))
+            .unwrap();
+        server.manual_poll(Instant::now());
+
+        for id in 0..4 {
+            client
+                .send(Packet::unreliable(server_addr, vec![id]))
+                .unwrap();
+        }
+        client.manual_poll(Instant::now());
+        server.manual_poll(Instant::now());
+
+        // The newest packets replaced the older ones
+        assert_eq!(
+            server.recv(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                vec![2]
+            )))
+        );
+        assert_eq!(
+            server.recv(),
+            Some(SocketEvent::Packet(Packet::unreliable(
+                client_addr,
+                vec![3]
+            )))
+        );
+        assert_eq!(server.recv(), None);
+    }
+
     const LOCAL_ADDR: &str = "127.0.0.1:13000";
     const REMOTE_ADDR: &str = "127.0.0.1:14000";
 
@@ -731,7 +1707,7 @@ mod tests {
 
         // Send enough packets to ensure that we must have dropped packets.
         for i in 0..35 {
-            client.send(create_test_packet(i, REMOTE_ADDR));
+            client.send(create_test_packet(i, REMOTE_ADDR)).unwrap();
         }
 
         let mut events = Vec::new();
@@ -747,12 +1723,12 @@ mod tests {
         }
 
         // Ensure that we get the correct number of events to the server.
-        // 35 connect events plus the 35 messages
-        assert_eq!(events.len(), 70);
+        // A single connect event plus the 35 messages
+        assert_eq!(events.len(), 36);
 
         // Finally the server decides to send us a message back. This necessarily will include
         // the ack information for 33 of the sent 35 packets.
-        server.send(create_test_packet(0, LOCAL_ADDR));
+        server.send(create_test_packet(0, LOCAL_ADDR)).unwrap();
 
         server.manual_poll(now);
         client.manual_poll(now);
@@ -763,7 +1739,7 @@ mod tests {
         // This next sent message should end up sending the 2 unacked messages plus the new messages
         // with payload 35
         events.clear();
-        client.send(create_test_packet(35, REMOTE_ADDR));
+        client.send(create_test_packet(35, REMOTE_ADDR)).unwrap();
 
         client.manual_poll(now);
         server.manual_poll(now);
@@ -797,7 +1773,7 @@ mod tests {
 
         let client = UdpSocket::bind(sender).unwrap();
 
-        client.send_to(&bytes, receiver);
+        client.send_to(&bytes, receiver).unwrap();
 
         let time = Instant::now();
         server.manual_poll(time);
@@ -829,17 +1805,21 @@ mod tests {
         // packets
         let mut send_many_packets = |dummy: Option<u8>| {
             for id in 0..100 {
-                client.send(Packet::reliable_unordered(
-                    server_addr,
-                    vec![dummy.unwrap_or(id)],
-                ));
+                client
+                    .send(Packet::reliable_unordered(
+                        server_addr,
+                        vec![dummy.unwrap_or(id)],
+                    ))
+                    .unwrap();
 
-                server.send(Packet::reliable_unordered(client_addr, vec![255]));
+                server
+                    .send(Packet::reliable_unordered(client_addr, vec![255]))
+                    .unwrap();
 
                 client.manual_poll(time);
                 server.manual_poll(time);
 
-                while let Some(_) = client.recv() {}
+                while client.recv().is_some() {}
                 while let Some(event) = server.recv() {
                     match event {
                         SocketEvent::Packet(pkt) => {
@@ -848,12 +1828,33 @@ mod tests {
                         SocketEvent::Timeout(_) => {
                             panic!["Unable to time out, time has not advanced"]
                         }
+                        SocketEvent::Disconnect(_) => {
+                            panic!["Unable to disconnect, no disconnect notices are sent"]
+                        }
+                        SocketEvent::Evicted(_) => {
+                            panic!["Unable to evict, there is a single client"]
+                        }
+                        SocketEvent::ConnectionRejected(_) => {
+                            panic!["Unable to reject, there is no connection limit"]
+                        }
+                        SocketEvent::AddressChanged { .. } => {
+                            panic!["Unable to change the address, the client keeps its socket"]
+                        }
+                        SocketEvent::VersionMismatch(_) => {
+                            panic!["Unable to mismatch, both use the same protocol"]
+                        }
+                        SocketEvent::StreamStalled(..) => {
+                            panic!["Unable to stall, no ordered packets are sent"]
+                        }
+                        SocketEvent::Error { .. } => {
+                            panic!["Unable to raise errors, error events are not enabled"]
+                        }
                         SocketEvent::Connect(_) => {}
                     }
                 }
             }
 
-            return set.len();
+            set.len()
         };
 
         // The first chatting sequence sends packets 0..100 from the client to the server. After
@@ -0,0 +1,230 @@
+//! Creates UDP sockets and applies the socket options of the `Config`.
+
+use crate::{
+    config::Config,
+    error::{ErrorKind, Result, SocketOptionErrorKind},
+};
+use socket2::{Domain, Protocol, SockRef, Type};
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+/// Binds a UDP socket to the first of `addresses` it can bind to, like `UdpSocket::bind`.
+///
+/// This sets the options which only take effect before binding, see `Config::reuse_port`.
+pub fn bind<A: ToSocketAddrs>(addresses: A, config: &Config) -> Result<UdpSocket> {
+    let mut last_error = None;
+    for address in addresses.to_socket_addrs()? {
+        match bind_to(address, false, config) {
+            Ok(socket) => return Ok(socket),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "could not resolve to any addresses",
+        )
+        .into()
+    }))
+}
+
+/// Binds a UDP socket to `address`, a dual-stack socket also receives IPv4 datagrams when `address` is an IPv6 address.
+pub fn bind_to(address: SocketAddr, dual_stack: bool, config: &Config) -> Result<UdpSocket> {
+    let socket = socket2::Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    if dual_stack {
+        socket.set_only_v6(false)?;
+    }
+    if config.reuse_port {
+        set_reuse_port(&socket).map_err(|e| refused("SO_REUSEPORT", &e))?;
+    }
+    socket.bind(&address.into())?;
+    Ok(socket.into())
+}
+
+/// Applies the buffer sizes and the DSCP marking of the config to a bound socket.
+///
+/// Fails with `ErrorKind::SocketOptionError` when the OS refuses a value, or limits a buffer to a smaller size.
+pub fn apply(socket: &UdpSocket, config: &Config) -> Result<()> {
+    let ipv6 = socket.local_addr()?.is_ipv6();
+    let socket = SockRef::from(socket);
+
+    if let Some(size) = config.socket_recv_buffer_size {
+        socket
+            .set_recv_buffer_size(size)
+            .map_err(|e| refused("SO_RCVBUF", &e))?;
+        check_limit("SO_RCVBUF", size, socket.recv_buffer_size()?)?;
+    }
+    if let Some(size) = config.socket_send_buffer_size {
+        socket
+            .set_send_buffer_size(size)
+            .map_err(|e| refused("SO_SNDBUF", &e))?;
+        check_limit("SO_SNDBUF", size, socket.send_buffer_size()?)?;
+    }
+    if let Some(dscp) = config.dscp {
+        if dscp > 63 {
+            return Err(refused(
+                "DSCP",
+                &io::Error::from(io::ErrorKind::InvalidInput),
+            ));
+        }
+        // The DSCP is the upper six bits of the IPv4 TOS and the IPv6 traffic class
+        let traffic_class = u32::from(dscp) << 2;
+        if ipv6 {
+            set_traffic_class_v6(&socket, traffic_class).map_err(|e| refused("IPV6_TCLASS", &e))?;
+        }
+        // IPv6 sockets use the TOS for their IPv4 peers, when they are dual-stack
+        set_tos(&socket, traffic_class).map_err(|e| refused("IP_TOS", &e))?;
+    }
+
+    Ok(())
+}
+
+// Some OSes report a larger size than requested, e.g. Linux doubles it for its bookkeeping, but never a smaller one
+// unless they limited it.
+fn check_limit(option: &'static str, requested: usize, actual: usize) -> Result<()> {
+    if actual < requested {
+        Err(SocketOptionErrorKind::Limited {
+            option,
+            requested,
+            actual,
+        }
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+fn refused(option: &'static str, error: &io::Error) -> ErrorKind {
+    SocketOptionErrorKind::Refused {
+        option,
+        reason: error.kind(),
+    }
+    .into()
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+fn set_reuse_port(socket: &socket2::Socket) -> io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+fn set_reuse_port(_socket: &socket2::Socket) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(any(
+    target_os = "fuchsia",
+    target_os = "redox",
+    target_os = "solaris",
+    target_os = "illumos",
+)))]
+fn set_tos(socket: &SockRef<'_>, tos: u32) -> io::Result<()> {
+    socket.set_tos(tos)
+}
+
+#[cfg(any(
+    target_os = "fuchsia",
+    target_os = "redox",
+    target_os = "solaris",
+    target_os = "illumos",
+))]
+fn set_tos(_socket: &SockRef<'_>, _tos: u32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(target_os = "linux")]
+fn set_traffic_class_v6(socket: &SockRef<'_>, traffic_class: u32) -> io::Result<()> {
+    use std::{mem, os::unix::io::AsRawFd};
+
+    let value = traffic_class as libc::c_int;
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_TCLASS,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_traffic_class_v6(_socket: &SockRef<'_>, _traffic_class: u32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, bind, check_limit};
+    use crate::{error::SocketOptionErrorKind, Config, ErrorKind};
+
+    #[test]
+    fn apply_buffer_sizes_and_dscp() {
+        let config = Config {
+            socket_recv_buffer_size: Some(64 * 1024),
+            socket_send_buffer_size: Some(64 * 1024),
+            dscp: Some(46),
+            ..Config::default()
+        };
+        let socket = bind("127.0.0.1:0", &config).unwrap();
+
+        apply(&socket, &config).unwrap();
+    }
+
+    #[test]
+    fn refuse_invalid_dscp() {
+        let config = Config {
+            dscp: Some(64),
+            ..Config::default()
+        };
+        let socket = bind("127.0.0.1:0", &config).unwrap();
+
+        match apply(&socket, &config) {
+            Err(ErrorKind::SocketOptionError(SocketOptionErrorKind::Refused {
+                option, ..
+            })) => {
+                assert_eq!(option, "DSCP")
+            }
+            result => panic!("Expected the DSCP to be refused, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn report_limited_buffer_sizes() {
+        assert!(check_limit("SO_RCVBUF", 1024, 2048).is_ok());
+        match check_limit("SO_RCVBUF", 4096, 2048) {
+            Err(ErrorKind::SocketOptionError(SocketOptionErrorKind::Limited {
+                requested,
+                actual,
+                ..
+            })) => assert_eq!((requested, actual), (4096, 2048)),
+            result => panic!("Expected the size to be limited, got {:?}", result),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn share_a_port_with_reuse_port() {
+        let config = Config {
+            reuse_port: true,
+            ..Config::default()
+        };
+        let first = bind("127.0.0.1:0", &config).unwrap();
+
+        assert!(bind(first.local_addr().unwrap(), &config).is_ok());
+        assert!(bind(first.local_addr().unwrap(), &Config::default()).is_err());
+    }
+}
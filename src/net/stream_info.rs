@@ -0,0 +1,24 @@
+use crate::packet::OrderingGuarantee;
+
+/// A snapshot of the state of an ordering or sequencing stream of a connection.
+///
+/// Returned by `Socket::stream_info` and `Socket::streams`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StreamInfo {
+    /// The stream this information is about, e.g. `OrderingGuarantee::Ordered(Some(1))`.
+    pub stream: OrderingGuarantee,
+    /// The number of reliable items sent on this stream which are not acknowledged yet.
+    pub items_in_flight: usize,
+    /// The maximal number of items that may be in flight on this stream, the smaller one of `Config::stream_receive_window` and the window the remote endpoint advertised for this stream.
+    ///
+    /// `None` when `Config::stream_receive_window` is not set, then the number of items in flight is not limited.
+    pub receive_window: Option<usize>,
+    /// The number of received items which wait for a missing item, always 0 for sequencing streams.
+    pub buffered_items: usize,
+    /// The number of received bytes which wait for a missing item, always 0 for sequencing streams.
+    pub buffered_bytes: usize,
+    /// Whether we closed this stream, nothing can be sent on it until it is reset.
+    pub closed: bool,
+    /// Whether the remote endpoint closed this stream.
+    pub closed_by_remote: bool,
+}
@@ -1,24 +1,35 @@
 use crate::{
+    compression::Compression,
     config::Config,
-    error::{ErrorKind, PacketErrorKind, Result},
+    error::{DecodingErrorKind, ErrorKind, Result, StreamErrorKind},
     infrastructure::{
-        arranging::{Arranging, ArrangingSystem, OrderingSystem, SequencingSystem},
+        arranging::{Arranging, ArrangingSystem, OrderingStream, OrderingSystem, SequencingSystem},
+        fec::{self, ParityDecoder, ParityEncoder},
         AcknowledgmentHandler, CongestionHandler, Fragmentation, SentPacket,
     },
     net::constants::{
         ACKED_PACKET_HEADER, DEFAULT_ORDERING_STREAM, DEFAULT_SEQUENCING_STREAM,
-        STANDARD_HEADER_SIZE,
+        FRAGMENT_HEADER_SIZE, STANDARD_HEADER_SIZE,
     },
+    net::{
+        channel::PolicySender,
+        negotiation::{Negotiated, HELLO_INTERVAL},
+        ConnectionStats, Features, StreamInfo,
+    },
+    packet::header::{AckedPacketHeader, ParityHeader},
     packet::{
-        DeliveryGuarantee, OrderingGuarantee, Outgoing, OutgoingPacketBuilder, Packet,
-        PacketReader, PacketType, SequenceNumber,
+        DeliveryGuarantee, OrderingGuarantee, Outgoing, OutgoingPacket, OutgoingPacketBuilder,
+        Packet, PacketReader, PacketType, Payload, Redundancy, SequenceNumber, StreamCommand,
     },
+    protocol_version::ProtocolVersion,
     SocketEvent,
 };
 
-use crossbeam_channel::{self, Sender};
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 /// Contains the information about a certain 'virtual connection' over udp.
@@ -28,14 +39,36 @@ pub struct VirtualConnection {
     pub last_heard: Instant,
     /// The address of the remote endpoint
     pub remote_address: SocketAddr,
-
-    ordering_system: OrderingSystem<Box<[u8]>>,
-    sequencing_system: SequencingSystem<Box<[u8]>>,
+    /// The identifier of this connection, which is sent with every packet
+    pub connection_id: u32,
+    remote_connection_id: Option<u32>,
+    protocol_version: u16,
+    negotiated: Option<Negotiated>,
+    hello_sent: Option<Instant>,
+    compression: Option<Compression>,
+
+    ordering_system: OrderingSystem<Payload>,
+    sequencing_system: SequencingSystem<Payload>,
     acknowledge_handler: AcknowledgmentHandler,
     congestion_handler: CongestionHandler,
+    remote_receive_windows: HashMap<u8, u16>,
+    advertised_stream: u8,
 
     config: Config,
     fragmentation: Fragmentation,
+    unreliable_fragmentation: Fragmentation,
+    unreliable_fragment_sequence: SequenceNumber,
+    parity_encoder: ParityEncoder,
+    parity_decoder: ParityDecoder,
+
+    packets_sent: u64,
+    packets_resent: u64,
+    packets_received: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    packets_reassembled: u64,
+    packets_rebuilt: u64,
+    errors_received: u64,
 }
 
 impl VirtualConnection {
@@ -44,15 +77,87 @@ impl VirtualConnection {
         VirtualConnection {
             last_heard: time,
             remote_address: addr,
-            ordering_system: OrderingSystem::new(),
+            connection_id: new_connection_id(),
+            remote_connection_id: None,
+            protocol_version: ProtocolVersion::get_crc16(config.protocol_id),
+            negotiated: None,
+            hello_sent: None,
+            compression: config.compressor.clone().map(|compressor| {
+                Compression::new(
+                    compressor,
+                    config.compression_dictionary.clone(),
+                    config.max_packet_size,
+                )
+            }),
+            ordering_system: OrderingSystem::with_limits(
+                config.max_ordering_buffered_items,
+                config.max_ordering_buffered_bytes,
+            ),
             sequencing_system: SequencingSystem::new(),
             acknowledge_handler: AcknowledgmentHandler::new(),
             congestion_handler: CongestionHandler::new(config),
+            remote_receive_windows: HashMap::new(),
+            advertised_stream: u8::MAX,
             fragmentation: Fragmentation::new(config),
+            unreliable_fragmentation: Fragmentation::new(config),
+            unreliable_fragment_sequence: 0,
+            parity_encoder: ParityEncoder::new(config.parity_group_size),
+            parity_decoder: ParityDecoder::default(),
             config: config.to_owned(),
+            packets_sent: 0,
+            packets_resent: 0,
+            packets_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            packets_reassembled: 0,
+            packets_rebuilt: 0,
+            errors_received: 0,
         }
     }
 
+    /// Returns the identifier the remote endpoint gave its connection with us, once a packet of it was processed.
+    pub fn remote_connection_id(&self) -> Option<u32> {
+        self.remote_connection_id
+    }
+
+    pub(crate) fn set_remote_connection_id(&mut self, id: u32) {
+        self.remote_connection_id = Some(id);
+    }
+
+    /// Returns the wire version and optional features both endpoints settled on, `None` until the hello of the remote endpoint is received.
+    pub fn negotiated(&self) -> Option<Negotiated> {
+        self.negotiated
+    }
+
+    pub(crate) fn set_negotiated(&mut self, negotiated: Negotiated) {
+        self.negotiated = Some(negotiated);
+    }
+
+    /// Replaces the dictionary of the compressor on this connection, returns false if there is no compressor in the config.
+    pub fn set_compression_dictionary(&mut self, dictionary: Option<Arc<[u8]>>) -> bool {
+        match &mut self.compression {
+            Some(compression) => {
+                compression.set_dictionary(dictionary);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether our hello has to be sent (again), which is until the hello of the remote endpoint is received.
+    /// This is only when negotiation is enabled in the config, otherwise hellos are only sent in reply.
+    pub(crate) fn hello_due(&self, time: Instant) -> bool {
+        self.config.negotiate
+            && self.negotiated.is_none()
+            && self
+                .hello_sent
+                .is_none_or(|sent| sent + HELLO_INTERVAL <= time)
+    }
+
+    pub(crate) fn record_hello_sent(&mut self, time: Instant) {
+        self.hello_sent = Some(time);
+    }
+
     /// Returns a [Duration] representing the interval since we last heard from the client
     pub fn last_heard(&self, time: Instant) -> Duration {
         // TODO: Replace with saturating_duration_since once it becomes stable.
@@ -60,22 +165,117 @@ impl VirtualConnection {
         time.duration_since(self.last_heard)
     }
 
+    /// Returns the earliest time at which this connection times out, one of its ordering streams is considered stalled,
+    /// an incomplete unreliable packet is dropped, the open parity group is flushed, or our hello has to be sent again.
+    pub fn next_deadline(&self) -> Instant {
+        let timeout = self.last_heard + self.config.idle_connection_timeout;
+        let deadline = match self
+            .ordering_system
+            .stall_deadline(self.config.ordering_stall_timeout)
+        {
+            Some(stall) if stall < timeout => stall,
+            _ => timeout,
+        };
+        let deadline = match self.unreliable_fragmentation.expiry_deadline() {
+            Some(expiry) if expiry < deadline => expiry,
+            _ => deadline,
+        };
+        let deadline = match self
+            .parity_encoder
+            .deadline(self.config.parity_group_timeout)
+        {
+            Some(flush) if flush < deadline => flush,
+            _ => deadline,
+        };
+        match self.hello_sent {
+            Some(sent) if self.config.negotiate && self.negotiated.is_none() => {
+                deadline.min(sent + HELLO_INTERVAL)
+            }
+            _ => deadline,
+        }
+    }
+
+    // Returns a builder with the standard header for a payload, which is compressed if it shrinks.
+    // Compression is skipped when the remote endpoint negotiated without it.
+    // A datagram in a parity group gets the trailer with its group and its index in the group.
+    fn packet_builder<'a>(
+        &self,
+        payload: &'a [u8],
+        packet_type: PacketType,
+        delivery_guarantee: DeliveryGuarantee,
+        ordering_guarantee: OrderingGuarantee,
+        parity_group: Option<(u16, u8)>,
+    ) -> OutgoingPacketBuilder<'a> {
+        let mut builder = OutgoingPacketBuilder::new(payload);
+        if let Some((group, index)) = parity_group {
+            builder = builder.with_parity_group(group, index);
+        }
+        let compression = self.compression.as_ref().filter(|_| {
+            self.negotiated
+                .is_none_or(|negotiated| negotiated.features.contains(Features::COMPRESSION))
+        });
+        if let Some(compression) = compression {
+            builder = builder.with_compression(compression);
+        }
+
+        builder.with_default_header(
+            packet_type,
+            delivery_guarantee,
+            ordering_guarantee,
+            self.protocol_version,
+            self.connection_id,
+        )
+    }
+
     /// This will pre-process the given buffer to be sent over the network.
     pub fn process_outgoing<'a>(
         &mut self,
-        payload: &'a [u8],
+        payload: &'a Payload,
+        delivery_guarantee: DeliveryGuarantee,
+        ordering_guarantee: OrderingGuarantee,
+        last_item_identifier: Option<SequenceNumber>,
+        time: Instant,
+    ) -> Result<Outgoing<'a>> {
+        self.process_outgoing_with_redundancy(
+            payload,
+            delivery_guarantee,
+            ordering_guarantee,
+            Redundancy::None,
+            last_item_identifier,
+            time,
+        )
+    }
+
+    /// This will pre-process the given buffer to be sent over the network, protected by parities, see `Packet::with_redundancy`.
+    pub fn process_outgoing_with_redundancy<'a>(
+        &mut self,
+        payload: &'a Payload,
         delivery_guarantee: DeliveryGuarantee,
         ordering_guarantee: OrderingGuarantee,
+        redundancy: Redundancy,
         last_item_identifier: Option<SequenceNumber>,
         time: Instant,
     ) -> Result<Outgoing<'a>> {
+        // resent items were already accepted on their stream.
+        if last_item_identifier.is_none() {
+            self.check_stream_can_send(delivery_guarantee, ordering_guarantee)?;
+        }
+
         match delivery_guarantee {
             DeliveryGuarantee::Unreliable => {
-                if payload.len() <= self.config.receive_buffer_max_size {
-                    let mut builder = OutgoingPacketBuilder::new(payload).with_default_header(
+                if payload.len() <= usize::from(self.config.fragment_size) {
+                    let parity_group = if redundancy.parity_count() > 0 {
+                        Some(self.parity_encoder.next_member(time))
+                    } else {
+                        None
+                    };
+
+                    let mut builder = self.packet_builder(
+                        payload,
                         PacketType::Packet,
                         delivery_guarantee,
                         ordering_guarantee,
+                        parity_group,
                     );
 
                     if let OrderingGuarantee::Sequenced(stream_id) = ordering_guarantee {
@@ -84,47 +284,66 @@ impl VirtualConnection {
                             .get_or_create_stream(stream_id.unwrap_or(DEFAULT_SEQUENCING_STREAM))
                             .new_item_identifier();
 
-                        builder = builder.with_sequencing_header(item_identifier as u16, stream_id);
+                        builder = builder.with_sequencing_header(item_identifier, stream_id);
                     };
 
-                    Ok(Outgoing::Packet(builder.build()))
+                    let packet = builder.build();
+                    if parity_group.is_none() {
+                        return Ok(Outgoing::Packet(packet));
+                    }
+
+                    let parities =
+                        self.parity_encoder
+                            .add_member(packet.parity_shard(), redundancy, time);
+                    let outgoing = if parities.is_empty() {
+                        Outgoing::Packet(packet)
+                    } else {
+                        Outgoing::PacketWithParities(packet, self.parity_packets(parities))
+                    };
+
+                    Ok(outgoing)
                 } else {
-                    Err(ErrorKind::PacketError(
-                        PacketErrorKind::ExceededMaxPacketSize,
-                    ))
+                    let outgoing =
+                        self.unreliable_fragments(payload, ordering_guarantee, redundancy)?;
+
+                    Ok(outgoing)
                 }
             }
             DeliveryGuarantee::Reliable => {
                 let payload_length = payload.len() as u16;
 
                 let mut item_identifier_value = None;
+                let (window_stream, window) = self.receive_window();
                 let outgoing = {
                     // spit the packet if the payload length is greater than the allowed fragment size.
                     if payload_length <= self.config.fragment_size {
-                        let mut builder = OutgoingPacketBuilder::new(payload).with_default_header(
+                        let mut builder = self.packet_builder(
+                            payload,
                             PacketType::Packet,
                             delivery_guarantee,
                             ordering_guarantee,
+                            None,
                         );
 
                         builder = builder.with_acknowledgment_header(
                             self.acknowledge_handler.local_sequence_num(),
                             self.acknowledge_handler.remote_sequence_num(),
                             self.acknowledge_handler.ack_bitfield(),
+                            window_stream,
+                            window,
                         );
 
                         if let OrderingGuarantee::Ordered(stream_id) = ordering_guarantee {
-                            let item_identifier = if let Some(item_identifier) =
-                                last_item_identifier
-                            {
-                                item_identifier
-                            } else {
-                                self.ordering_system
-                                    .get_or_create_stream(
-                                        stream_id.unwrap_or(DEFAULT_ORDERING_STREAM),
-                                    )
-                                    .new_item_identifier() as u16
-                            };
+                            let item_identifier =
+                                if let Some(item_identifier) = last_item_identifier {
+                                    item_identifier
+                                } else {
+                                    self.ordering_system
+                                        .get_or_create_stream(
+                                            stream_id.unwrap_or(DEFAULT_ORDERING_STREAM),
+                                        )
+                                        .new_item_identifier()
+                                };
 
                             item_identifier_value = Some(item_identifier);
 
@@ -132,17 +351,16 @@ impl VirtualConnection {
                         };
 
                         if let OrderingGuarantee::Sequenced(stream_id) = ordering_guarantee {
-                            let item_identifier = if let Some(item_identifier) =
-                                last_item_identifier
-                            {
-                                item_identifier
-                            } else {
-                                self.sequencing_system
-                                    .get_or_create_stream(
-                                        stream_id.unwrap_or(DEFAULT_SEQUENCING_STREAM),
-                                    )
-                                    .new_item_identifier() as u16
-                            };
+                            let item_identifier =
+                                if let Some(item_identifier) = last_item_identifier {
+                                    item_identifier
+                                } else {
+                                    self.sequencing_system
+                                        .get_or_create_stream(
+                                            stream_id.unwrap_or(DEFAULT_SEQUENCING_STREAM),
+                                        )
+                                        .new_item_identifier()
+                                };
 
                             item_identifier_value = Some(item_identifier);
 
@@ -151,50 +369,59 @@ impl VirtualConnection {
 
                         Outgoing::Packet(builder.build())
                     } else {
-                        Outgoing::Fragments(
-                            Fragmentation::spit_into_fragments(payload, &self.config)?
-                                .into_iter()
-                                .enumerate()
-                                .map(|(fragment_id, fragment)| {
-                                    let fragments_needed = Fragmentation::fragments_needed(
-                                        payload_length,
-                                        self.config.fragment_size,
-                                    )
-                                        as u8;
-
-                                    let mut builder = OutgoingPacketBuilder::new(fragment)
-                                        .with_default_header(
-                                            PacketType::Fragment,
-                                            delivery_guarantee,
-                                            ordering_guarantee,
-                                        );
-
-                                    builder = builder.with_fragment_header(
+                        let fragments = Fragmentation::spit_into_fragments(payload, &self.config)?;
+                        let parity_groups =
+                            self.fragment_parity_groups(fragments.len(), redundancy);
+
+                        let mut packets: Vec<OutgoingPacket> = fragments
+                            .into_iter()
+                            .enumerate()
+                            .map(|(fragment_id, fragment)| {
+                                let fragments_needed = Fragmentation::fragments_needed(
+                                    payload_length,
+                                    self.config.fragment_size,
+                                ) as u8;
+
+                                let mut builder = self.packet_builder(
+                                    fragment,
+                                    PacketType::Fragment,
+                                    delivery_guarantee,
+                                    ordering_guarantee,
+                                    parity_groups[fragment_id],
+                                );
+
+                                builder = builder.with_fragment_header(
+                                    self.acknowledge_handler.local_sequence_num(),
+                                    fragment_id as u8,
+                                    fragments_needed,
+                                );
+
+                                if fragment_id == 0 {
+                                    builder = builder.with_acknowledgment_header(
                                         self.acknowledge_handler.local_sequence_num(),
-                                        fragment_id as u8,
-                                        fragments_needed,
+                                        self.acknowledge_handler.remote_sequence_num(),
+                                        self.acknowledge_handler.ack_bitfield(),
+                                        window_stream,
+                                        window,
                                     );
+                                }
 
-                                    if fragment_id == 0 {
-                                        builder = builder.with_acknowledgment_header(
-                                            self.acknowledge_handler.local_sequence_num(),
-                                            self.acknowledge_handler.remote_sequence_num(),
-                                            self.acknowledge_handler.ack_bitfield(),
-                                        );
-                                    }
-
-                                    builder.build()
-                                })
-                                .collect(),
-                        )
+                                builder.build()
+                            })
+                            .collect();
+
+                        let parities = self.fragment_parities(&packets, &parity_groups, redundancy);
+                        packets.extend(parities);
+
+                        Outgoing::Fragments(packets)
                     }
                 };
 
                 self.congestion_handler
                     .process_outgoing(self.acknowledge_handler.local_sequence_num(), time);
                 self.acknowledge_handler.process_outgoing(
-                    payload,
-                    ordering_guarantee,
+                    payload.clone(),
+                    Self::stream_of(ordering_guarantee),
                     item_identifier_value,
                 );
 
@@ -203,30 +430,417 @@ impl VirtualConnection {
         }
     }
 
+    // Splits an unreliable payload into fragments, which carry no acknowledgment information.
+    // Every fragment of a sequenced payload carries its arranging header, since any of them can complete the payload.
+    fn unreliable_fragments<'a>(
+        &mut self,
+        payload: &'a [u8],
+        ordering_guarantee: OrderingGuarantee,
+        redundancy: Redundancy,
+    ) -> Result<Outgoing<'a>> {
+        let fragments = Fragmentation::spit_into_fragments(payload, &self.config)?;
+        let fragment_count = fragments.len() as u8;
+        let sequence = self.unreliable_fragment_sequence;
+        self.unreliable_fragment_sequence = sequence.wrapping_add(1);
+
+        let sequencing_header = if let OrderingGuarantee::Sequenced(stream_id) = ordering_guarantee
+        {
+            let item_identifier = self
+                .sequencing_system
+                .get_or_create_stream(stream_id.unwrap_or(DEFAULT_SEQUENCING_STREAM))
+                .new_item_identifier();
+
+            Some((item_identifier, stream_id))
+        } else {
+            None
+        };
+
+        let parity_groups = self.fragment_parity_groups(fragments.len(), redundancy);
+
+        let mut packets: Vec<OutgoingPacket> = fragments
+            .into_iter()
+            .enumerate()
+            .map(|(fragment_id, fragment)| {
+                let mut builder = self
+                    .packet_builder(
+                        fragment,
+                        PacketType::Fragment,
+                        DeliveryGuarantee::Unreliable,
+                        ordering_guarantee,
+                        parity_groups[fragment_id],
+                    )
+                    .with_fragment_header(sequence, fragment_id as u8, fragment_count);
+
+                if let Some((item_identifier, stream_id)) = sequencing_header {
+                    builder = builder.with_sequencing_header(item_identifier, stream_id);
+                }
+
+                builder.build()
+            })
+            .collect();
+
+        let parities = self.fragment_parities(&packets, &parity_groups, redundancy);
+        packets.extend(parities);
+
+        Ok(Outgoing::Fragments(packets))
+    }
+
+    // Returns the parity group and the index in it of each fragment, when the packet has redundancy.
+    // A group holds at most `fec::MAX_GROUP_SIZE` fragments, so more fragments are split over several groups.
+    fn fragment_parity_groups(
+        &mut self,
+        fragment_count: usize,
+        redundancy: Redundancy,
+    ) -> Vec<Option<(u16, u8)>> {
+        if redundancy.parity_count() == 0 {
+            return vec![None; fragment_count];
+        }
+
+        let mut parity_groups = Vec::with_capacity(fragment_count);
+        for first in (0..fragment_count).step_by(fec::MAX_GROUP_SIZE) {
+            let group = self.parity_encoder.new_group();
+            let members = (fragment_count - first).min(fec::MAX_GROUP_SIZE);
+            parity_groups.extend((0..members).map(|index| Some((group, index as u8))));
+        }
+        parity_groups
+    }
+
+    // Returns the packets which carry the parities of each group of fragments, see `fragment_parity_groups`.
+    fn fragment_parities<'a>(
+        &self,
+        packets: &[OutgoingPacket],
+        parity_groups: &[Option<(u16, u8)>],
+        redundancy: Redundancy,
+    ) -> Vec<OutgoingPacket<'a>> {
+        let mut parities = Vec::new();
+        for (packets, parity_groups) in packets
+            .chunks(fec::MAX_GROUP_SIZE)
+            .zip(parity_groups.chunks(fec::MAX_GROUP_SIZE))
+        {
+            if let Some(Some((group, _))) = parity_groups.first() {
+                let shards: Vec<Vec<u8>> =
+                    packets.iter().map(OutgoingPacket::parity_shard).collect();
+                parities.extend(fec::encode(*group, &shards, redundancy));
+            }
+        }
+        self.parity_packets(parities)
+    }
+
+    // Returns the packets which carry the given parities, they are unreliable since a lost parity is not worth resending.
+    fn parity_packets<'a>(
+        &self,
+        parities: Vec<(ParityHeader, Vec<u8>)>,
+    ) -> Vec<OutgoingPacket<'a>> {
+        parities
+            .into_iter()
+            .map(|(header, parity)| {
+                OutgoingPacketBuilder::owned(parity)
+                    .with_default_header(
+                        PacketType::Parity,
+                        DeliveryGuarantee::Unreliable,
+                        OrderingGuarantee::None,
+                        self.protocol_version,
+                        self.connection_id,
+                    )
+                    .with_parity_header(header)
+                    .build()
+            })
+            .collect()
+    }
+
+    /// This will construct a packet, carrying the given command, which resets or closes an ordering or sequencing stream of the remote endpoint.
+    ///
+    /// - `Reset` opens the stream for sending again and makes the remote endpoint skip all items which were sent before.
+    /// - `Close` closes the stream for sending and makes the remote endpoint discard all items it buffered for this stream.
+    ///
+    /// The command tells at which item identifier the stream will continue, `next_identifier` should only be given when resending a command.
+    pub fn process_stream_command(
+        &mut self,
+        command: StreamCommand,
+        stream: OrderingGuarantee,
+        next_identifier: Option<SequenceNumber>,
+        time: Instant,
+    ) -> Result<Outgoing<'static>> {
+        let stream = Self::stream_of(stream);
+        let closed = command == StreamCommand::Close;
+
+        let mut builder = OutgoingPacketBuilder::new(command.payload()).with_default_header(
+            PacketType::StreamControl,
+            DeliveryGuarantee::Reliable,
+            stream,
+            self.protocol_version,
+            self.connection_id,
+        );
+
+        let (window_stream, window) = self.receive_window();
+        builder = builder.with_acknowledgment_header(
+            self.acknowledge_handler.local_sequence_num(),
+            self.acknowledge_handler.remote_sequence_num(),
+            self.acknowledge_handler.ack_bitfield(),
+            window_stream,
+            window,
+        );
+
+        let item_identifier = match stream {
+            OrderingGuarantee::Ordered(Some(stream_id)) => {
+                let stream = self.ordering_system.get_or_create_stream(stream_id);
+                let item_identifier = next_identifier.unwrap_or_else(|| {
+                    stream.set_closed(closed);
+                    stream.next_item_identifier()
+                });
+
+                builder = builder.with_ordering_header(item_identifier, Some(stream_id));
+                item_identifier
+            }
+            OrderingGuarantee::Sequenced(Some(stream_id)) => {
+                let stream = self.sequencing_system.get_or_create_stream(stream_id);
+                let item_identifier = next_identifier.unwrap_or_else(|| {
+                    stream.set_closed(closed);
+                    stream.next_item_identifier()
+                });
+
+                builder = builder.with_sequencing_header(item_identifier, Some(stream_id));
+                item_identifier
+            }
+            _ => return Err(StreamErrorKind::NotAStream.into()),
+        };
+
+        self.congestion_handler
+            .process_outgoing(self.acknowledge_handler.local_sequence_num(), time);
+        self.acknowledge_handler.process_outgoing_with_type(
+            PacketType::StreamControl,
+            command.payload(),
+            stream,
+            Some(item_identifier),
+        );
+
+        Ok(Outgoing::Packet(builder.build()))
+    }
+
+    // Processes the acknowledgment information the remote endpoint sent with a packet.
+    fn process_acknowledgment(&mut self, acked_header: AckedPacketHeader, time: Instant) {
+        let awaiting_ack = self.acknowledge_handler.packets_awaiting_ack();
+
+        self.congestion_handler
+            .process_incoming(acked_header.ack_seq(), time);
+        self.congestion_handler
+            .process_received(acked_header.sequence());
+        self.acknowledge_handler.process_incoming(
+            acked_header.sequence(),
+            acked_header.ack_seq(),
+            acked_header.ack_field(),
+        );
+        self.remote_receive_windows
+            .insert(acked_header.window_stream(), acked_header.window());
+
+        let acknowledged = awaiting_ack - self.acknowledge_handler.packets_awaiting_ack();
+        self.congestion_handler
+            .process_acknowledgments(acknowledged, 0);
+    }
+
+    // Returns an ordering stream and the number of items we are still willing to receive on it, which is advertised to
+    // the remote endpoint. Every call advertises the next stream in turn, so the window of each stream is updated.
+    fn receive_window(&mut self) -> (u8, u16) {
+        let stream_id = self
+            .ordering_system
+            .next_stream_id(self.advertised_stream)
+            .unwrap_or(DEFAULT_ORDERING_STREAM);
+        self.advertised_stream = stream_id;
+
+        let buffered_items = self
+            .ordering_system
+            .get_stream(stream_id)
+            .map_or(0, |stream| stream.buffered_items());
+        let free = self
+            .config
+            .max_ordering_buffered_items
+            .saturating_sub(buffered_items);
+        let window = self
+            .config
+            .stream_receive_window
+            .map_or(free, |window| window.min(free))
+            .min(usize::from(u16::MAX)) as u16;
+
+        (stream_id, window)
+    }
+
     /// This processes the incoming data and returns a packet if the data is complete.
     pub fn process_incoming(
         &mut self,
-        received_data: &[u8],
-        sender: &Sender<SocketEvent>,
+        received_data: &Payload,
+        sender: &PolicySender<SocketEvent>,
         time: Instant,
     ) -> crate::Result<()> {
         self.last_heard = time;
+        self.packets_received += 1;
+        self.bytes_received += received_data.len() as u64;
 
         let mut packet_reader = PacketReader::new(received_data);
 
         let header = packet_reader.read_standard_header()?;
 
-        if !header.is_current_protocol() {
+        if !header.is_current_protocol(self.config.protocol_id) {
             return Err(ErrorKind::ProtocolVersionMismatch);
         }
 
+        if header.packet_type() == PacketType::Parity {
+            let parity_header = packet_reader.read_parity_header()?;
+            let rebuilt = self
+                .parity_decoder
+                .receive_parity(parity_header, packet_reader.read_payload());
+
+            return self.process_rebuilt(rebuilt, sender, time);
+        }
+
+        if header.is_in_parity_group() {
+            let (datagram, group, index) = fec::split_trailer(received_data)
+                .filter(|(datagram, _, _)| datagram.len() >= usize::from(STANDARD_HEADER_SIZE))
+                .ok_or_else(|| ErrorKind::CouldNotReadHeader(String::from("parity trailer")))?;
+            // A datagram which was rebuilt already arrived late, it must not be delivered twice
+            if self.parity_decoder.is_rebuilt(group, index) {
+                return Ok(());
+            }
+
+            let rebuilt = self.parity_decoder.receive_datagram(group, index, datagram);
+            self.process_packet(&received_data.slice_ref(datagram), sender, time)?;
+            return self.process_rebuilt(rebuilt, sender, time);
+        }
+
+        self.process_packet(received_data, sender, time)
+    }
+
+    /// Processes a datagram of a peer without a stored connection, whose standard header was validated already.
+    ///
+    /// Nothing is kept of the datagram, so only payloads which can be delivered on their own are: parities, fragments and
+    /// stream commands are dropped, and of an ordered stream only its first item is delivered.
+    pub(crate) fn process_anonymous(
+        received_data: &Payload,
+        remote_address: SocketAddr,
+        compression: Option<&Compression>,
+        sender: &PolicySender<SocketEvent>,
+    ) -> Result<()> {
+        let header = PacketReader::new(received_data).read_standard_header()?;
+        if header.packet_type() == PacketType::Parity {
+            return Ok(());
+        }
+
+        let received_data = if header.is_in_parity_group() {
+            let (datagram, _, _) = fec::split_trailer(received_data)
+                .filter(|(datagram, _, _)| datagram.len() >= usize::from(STANDARD_HEADER_SIZE))
+                .ok_or_else(|| ErrorKind::CouldNotReadHeader(String::from("parity trailer")))?;
+            received_data.slice_ref(datagram)
+        } else {
+            received_data.clone()
+        };
+
+        let mut packet_reader = PacketReader::new(&received_data);
+        let header = packet_reader.read_standard_header()?;
+        if header.is_stream_control() || header.is_fragment() {
+            return Ok(());
+        }
+
+        let mut header_size = STANDARD_HEADER_SIZE;
+        if header.delivery_guarantee() == DeliveryGuarantee::Reliable {
+            packet_reader.read_acknowledge_header()?;
+            header_size += ACKED_PACKET_HEADER;
+        }
+
+        let ordering_guarantee = match header.ordering_guarantee() {
+            OrderingGuarantee::Sequenced(_) => {
+                let arranging_header =
+                    packet_reader.read_arranging_header(u16::from(header_size))?;
+                OrderingGuarantee::Sequenced(Some(arranging_header.stream_id()))
+            }
+            OrderingGuarantee::Ordered(_)
+                if header.delivery_guarantee() == DeliveryGuarantee::Reliable =>
+            {
+                let arranging_header =
+                    packet_reader.read_arranging_header(u16::from(header_size))?;
+                // The first item of a stream has the identifier 1, a later one can't be delivered in order without it
+                if arranging_header.arranging_id() != 1 {
+                    return Ok(());
+                }
+                OrderingGuarantee::Ordered(Some(arranging_header.stream_id()))
+            }
+            ordering_guarantee => ordering_guarantee,
+        };
+
+        Self::queue_packet(
+            sender,
+            packet_reader.read_shared_payload(&received_data, compression)?,
+            remote_address,
+            header.delivery_guarantee(),
+            ordering_guarantee,
+        )
+    }
+
+    // Processes datagrams which were rebuilt from parities, as if they were received.
+    fn process_rebuilt(
+        &mut self,
+        rebuilt: Vec<Vec<u8>>,
+        sender: &PolicySender<SocketEvent>,
+        time: Instant,
+    ) -> Result<()> {
+        for datagram in rebuilt {
+            self.packets_rebuilt += 1;
+            self.process_packet(&Payload::from(datagram), sender, time)?;
+        }
+
+        Ok(())
+    }
+
+    // Processes a datagram without the trailer of its parity group, whose standard header was validated already.
+    fn process_packet(
+        &mut self,
+        received_data: &Payload,
+        sender: &PolicySender<SocketEvent>,
+        time: Instant,
+    ) -> Result<()> {
+        let mut packet_reader = PacketReader::new(received_data);
+
+        let header = packet_reader.read_standard_header()?;
+
+        if header.is_stream_control() {
+            let acked_header = packet_reader.read_acknowledge_header()?;
+            let arranging_header = packet_reader
+                .read_arranging_header(u16::from(STANDARD_HEADER_SIZE + ACKED_PACKET_HEADER))?;
+
+            let command = match packet_reader.read_payload().first() {
+                Some(command) => StreamCommand::try_from(*command)?,
+                None => return Err(ErrorKind::DecodingError(DecodingErrorKind::StreamCommand)),
+            };
+
+            self.handle_stream_command(
+                command,
+                header.ordering_guarantee(),
+                arranging_header.stream_id(),
+                arranging_header.arranging_id() as usize,
+                sender,
+            )?;
+
+            self.process_acknowledgment(acked_header, time);
+
+            return Ok(());
+        }
+
         match header.delivery_guarantee() {
             DeliveryGuarantee::Unreliable => {
+                if header.is_fragment() {
+                    return self.process_unreliable_fragment(
+                        header.ordering_guarantee(),
+                        packet_reader,
+                        received_data,
+                        sender,
+                        time,
+                    );
+                }
+
                 if let OrderingGuarantee::Sequenced(_id) = header.ordering_guarantee() {
                     let arranging_header =
                         packet_reader.read_arranging_header(u16::from(STANDARD_HEADER_SIZE))?;
 
-                    let payload = packet_reader.read_payload();
+                    let payload = packet_reader
+                        .read_shared_payload(received_data, self.compression.as_ref())?;
 
                     let stream = self
                         .sequencing_system
@@ -249,7 +863,7 @@ impl VirtualConnection {
 
                 Self::queue_packet(
                     sender,
-                    packet_reader.read_payload(),
+                    packet_reader.read_shared_payload(received_data, self.compression.as_ref())?,
                     self.remote_address,
                     header.delivery_guarantee(),
                     header.ordering_guarantee(),
@@ -258,16 +872,18 @@ impl VirtualConnection {
             DeliveryGuarantee::Reliable => {
                 if header.is_fragment() {
                     if let Ok((fragment_header, acked_header)) = packet_reader.read_fragment() {
-                        let payload = packet_reader.read_payload();
+                        let payload = packet_reader
+                            .read_shared_payload(received_data, self.compression.as_ref())?;
 
                         match self
                             .fragmentation
-                            .handle_fragment(fragment_header, &payload)
+                            .handle_fragment(fragment_header, &payload, time)
                         {
                             Ok(Some(payload)) => {
+                                self.packets_reassembled += 1;
                                 Self::queue_packet(
                                     sender,
-                                    payload.into_boxed_slice(),
+                                    payload.into(),
                                     self.remote_address,
                                     header.delivery_guarantee(),
                                     OrderingGuarantee::None,
@@ -278,13 +894,7 @@ impl VirtualConnection {
                         };
 
                         if let Some(acked_header) = acked_header {
-                            self.congestion_handler
-                                .process_incoming(acked_header.sequence());
-                            self.acknowledge_handler.process_incoming(
-                                acked_header.sequence(),
-                                acked_header.ack_seq(),
-                                acked_header.ack_field(),
-                            );
+                            self.process_acknowledgment(acked_header, time);
                         }
                     }
                 } else {
@@ -295,7 +905,8 @@ impl VirtualConnection {
                             STANDARD_HEADER_SIZE + ACKED_PACKET_HEADER,
                         ))?;
 
-                        let payload = packet_reader.read_payload();
+                        let payload = packet_reader
+                            .read_shared_payload(received_data, self.compression.as_ref())?;
 
                         let stream = self
                             .sequencing_system
@@ -317,35 +928,32 @@ impl VirtualConnection {
                             STANDARD_HEADER_SIZE + ACKED_PACKET_HEADER,
                         ))?;
 
-                        let payload = packet_reader.read_payload();
+                        let payload = packet_reader
+                            .read_shared_payload(received_data, self.compression.as_ref())?;
 
                         let stream = self
                             .ordering_system
                             .get_or_create_stream(arranging_header.stream_id());
 
-                        if let Some(packet) =
-                            stream.arrange(arranging_header.arranging_id() as usize, payload)
+                        // a packet which does not fit in the stream is left unacknowledged, so the remote resends it
+                        if stream
+                            .exceeds_limits(arranging_header.arranging_id() as usize, payload.len())
                         {
-                            Self::queue_packet(
-                                sender,
-                                packet,
-                                self.remote_address,
-                                header.delivery_guarantee(),
-                                OrderingGuarantee::Ordered(Some(arranging_header.stream_id())),
-                            )?;
-
-                            while let Some(packet) = stream.iter_mut().next() {
-                                Self::queue_packet(
-                                    sender,
-                                    packet,
-                                    self.remote_address,
-                                    header.delivery_guarantee(),
-                                    OrderingGuarantee::Ordered(Some(arranging_header.stream_id())),
-                                )?;
-                            }
+                            return Ok(());
                         }
+
+                        let packet =
+                            stream.arrange(arranging_header.arranging_id() as usize, payload);
+                        Self::queue_ordered_items(
+                            stream,
+                            packet,
+                            sender,
+                            self.remote_address,
+                            arranging_header.stream_id(),
+                        )?;
                     } else {
-                        let payload = packet_reader.read_payload();
+                        let payload = packet_reader
+                            .read_shared_payload(received_data, self.compression.as_ref())?;
 
                         Self::queue_packet(
                             sender,
@@ -356,23 +964,217 @@ impl VirtualConnection {
                         )?;
                     }
 
-                    self.congestion_handler
-                        .process_incoming(acked_header.sequence());
-                    self.acknowledge_handler.process_incoming(
-                        acked_header.sequence(),
-                        acked_header.ack_seq(),
-                        acked_header.ack_field(),
-                    );
+                    self.process_acknowledgment(acked_header, time);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Reassembles an unreliable payload from its fragments, a payload which misses fragments after the reassembly timeout is dropped.
+    fn process_unreliable_fragment(
+        &mut self,
+        ordering_guarantee: OrderingGuarantee,
+        mut packet_reader: PacketReader,
+        received_data: &Payload,
+        sender: &PolicySender<SocketEvent>,
+        time: Instant,
+    ) -> Result<()> {
+        let fragment_header = packet_reader.read_fragment_header()?;
+        let arranging_header =
+            if let OrderingGuarantee::Sequenced(_) = ordering_guarantee {
+                Some(packet_reader.read_arranging_header(u16::from(
+                    STANDARD_HEADER_SIZE + FRAGMENT_HEADER_SIZE,
+                ))?)
+            } else {
+                None
+            };
+        let payload =
+            packet_reader.read_shared_payload(received_data, self.compression.as_ref())?;
+
+        self.unreliable_fragmentation.drop_expired(time);
+        let payload =
+            match self
+                .unreliable_fragmentation
+                .handle_fragment(fragment_header, &payload, time)?
+            {
+                Some(payload) => Payload::from(payload),
+                None => return Ok(()),
+            };
+        self.packets_reassembled += 1;
+
+        match arranging_header {
+            Some(arranging_header) => {
+                let stream = self
+                    .sequencing_system
+                    .get_or_create_stream(arranging_header.stream_id());
+
+                if let Some(packet) =
+                    stream.arrange(arranging_header.arranging_id() as usize, payload)
+                {
+                    Self::queue_packet(
+                        sender,
+                        packet,
+                        self.remote_address,
+                        DeliveryGuarantee::Unreliable,
+                        OrderingGuarantee::Sequenced(Some(arranging_header.stream_id())),
+                    )?;
+                }
+
+                Ok(())
+            }
+            None => Self::queue_packet(
+                sender,
+                payload,
+                self.remote_address,
+                DeliveryGuarantee::Unreliable,
+                ordering_guarantee,
+            ),
+        }
+    }
+
+    // Applies a command of the remote endpoint to our side of the stream, items which can be read afterwards are queued.
+    fn handle_stream_command(
+        &mut self,
+        command: StreamCommand,
+        ordering_guarantee: OrderingGuarantee,
+        stream_id: u8,
+        next_index: usize,
+        sender: &PolicySender<SocketEvent>,
+    ) -> Result<()> {
+        match ordering_guarantee {
+            OrderingGuarantee::Ordered(_) => {
+                let stream = self.ordering_system.get_or_create_stream(stream_id);
+
+                match command {
+                    StreamCommand::Reset => {
+                        stream.reset(next_index);
+
+                        Self::queue_ordered_items(
+                            stream,
+                            None,
+                            sender,
+                            self.remote_address,
+                            stream_id,
+                        )?;
+                    }
+                    StreamCommand::Close => stream.close_by_remote(next_index),
+                }
+            }
+            OrderingGuarantee::Sequenced(_) => {
+                let stream = self.sequencing_system.get_or_create_stream(stream_id);
+
+                match command {
+                    StreamCommand::Reset => stream.reset(next_index),
+                    StreamCommand::Close => stream.close_by_remote(next_index),
                 }
             }
+            OrderingGuarantee::None => return Err(StreamErrorKind::NotAStream.into()),
         }
 
         Ok(())
     }
 
+    // Returns an error when an item with the given guarantees can't be sent on its stream.
+    fn check_stream_can_send(
+        &self,
+        delivery_guarantee: DeliveryGuarantee,
+        ordering_guarantee: OrderingGuarantee,
+    ) -> Result<()> {
+        if let Some(info) = self.stream_info(ordering_guarantee) {
+            if info.closed {
+                return Err(StreamErrorKind::StreamClosed.into());
+            }
+
+            if delivery_guarantee == DeliveryGuarantee::Reliable
+                && info
+                    .receive_window
+                    .is_some_and(|window| info.items_in_flight >= window)
+            {
+                return Err(StreamErrorKind::ExceededReceiveWindow.into());
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the given guarantee with the default stream filled in, such that it identifies a single stream.
+    fn stream_of(ordering_guarantee: OrderingGuarantee) -> OrderingGuarantee {
+        match ordering_guarantee {
+            OrderingGuarantee::None => OrderingGuarantee::None,
+            OrderingGuarantee::Sequenced(stream_id) => {
+                OrderingGuarantee::Sequenced(Some(stream_id.unwrap_or(DEFAULT_SEQUENCING_STREAM)))
+            }
+            OrderingGuarantee::Ordered(stream_id) => {
+                OrderingGuarantee::Ordered(Some(stream_id.unwrap_or(DEFAULT_ORDERING_STREAM)))
+            }
+        }
+    }
+
+    /// Returns the state of the given ordering or sequencing stream, `None` if the stream was never used.
+    pub fn stream_info(&self, stream: OrderingGuarantee) -> Option<StreamInfo> {
+        let stream = Self::stream_of(stream);
+
+        let (buffered_items, buffered_bytes, closed, closed_by_remote) = match stream {
+            OrderingGuarantee::Ordered(Some(stream_id)) => {
+                let stream = self.ordering_system.get_stream(stream_id)?;
+                (
+                    stream.buffered_items(),
+                    stream.buffered_bytes(),
+                    stream.is_closed(),
+                    stream.is_closed_by_remote(),
+                )
+            }
+            OrderingGuarantee::Sequenced(Some(stream_id)) => {
+                let stream = self.sequencing_system.get_stream(stream_id)?;
+                (0, 0, stream.is_closed(), stream.is_closed_by_remote())
+            }
+            _ => return None,
+        };
+
+        Some(StreamInfo {
+            stream,
+            items_in_flight: self.acknowledge_handler.packets_in_flight(stream),
+            receive_window: self
+                .config
+                .stream_receive_window
+                .map(|window| match stream {
+                    OrderingGuarantee::Ordered(Some(stream_id)) => self
+                        .remote_receive_windows
+                        .get(&stream_id)
+                        .map_or(window, |remote| window.min(usize::from(*remote))),
+                    _ => window,
+                }),
+            buffered_items,
+            buffered_bytes,
+            closed,
+            closed_by_remote,
+        })
+    }
+
+    /// Returns the state of all ordering and sequencing streams used on this connection.
+    pub fn streams(&self) -> Vec<StreamInfo> {
+        let ordered = self
+            .ordering_system
+            .stream_ids()
+            .into_iter()
+            .map(|stream_id| OrderingGuarantee::Ordered(Some(stream_id)));
+        let sequenced = self
+            .sequencing_system
+            .stream_ids()
+            .into_iter()
+            .map(|stream_id| OrderingGuarantee::Sequenced(Some(stream_id)));
+
+        ordered
+            .chain(sequenced)
+            .filter_map(|stream| self.stream_info(stream))
+            .collect()
+    }
+
     fn queue_packet(
-        tx: &Sender<SocketEvent>,
-        payload: Box<[u8]>,
+        tx: &PolicySender<SocketEvent>,
+        payload: Payload,
         remote_addr: SocketAddr,
         delivery: DeliveryGuarantee,
         ordering: OrderingGuarantee,
@@ -386,11 +1188,132 @@ impl VirtualConnection {
         Ok(())
     }
 
+    // Queues `item`, which was just arranged, and the items of the ordered stream which can be read after it.
+    // An item which can't be queued is put back in the stream, it is queued again with the next packet on the stream.
+    fn queue_ordered_items(
+        stream: &mut OrderingStream<Payload>,
+        mut item: Option<Payload>,
+        tx: &PolicySender<SocketEvent>,
+        remote_addr: SocketAddr,
+        stream_id: u8,
+    ) -> Result<()> {
+        while let Some(packet) = item.take().or_else(|| stream.iter_mut().next()) {
+            if let Err(error) = Self::queue_packet(
+                tx,
+                packet.clone(),
+                remote_addr,
+                DeliveryGuarantee::Reliable,
+                OrderingGuarantee::Ordered(Some(stream_id)),
+            ) {
+                stream.put_back(packet);
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the unreliable packets which are still missing fragments after `Config::fragment_reassembly_timeout`.
+    pub fn drop_expired_fragments(&mut self, time: Instant) {
+        self.unreliable_fragmentation.drop_expired(time);
+    }
+
+    /// Returns the packets which carry the parities of the open group of unreliable packets, once it waited
+    /// `Config::parity_group_timeout` for more packets.
+    pub fn flush_parities(&mut self, time: Instant) -> Vec<OutgoingPacket<'static>> {
+        match self
+            .parity_encoder
+            .deadline(self.config.parity_group_timeout)
+        {
+            Some(deadline) if deadline <= time => {}
+            _ => return Vec::new(),
+        }
+
+        let parities = self.parity_encoder.flush();
+        self.parity_packets(parities)
+    }
+
+    /// Returns the ids of the ordering streams that are stalled.
+    ///
+    /// A stream is stalled when it waits longer than `ordering_stall_timeout` for a missing packet, or dropped packets
+    /// because it buffered more than allowed by the config. Each stall is returned only once.
+    pub fn stalled_ordering_streams(&mut self, time: Instant) -> Vec<u8> {
+        self.ordering_system
+            .stalled_streams(time, self.config.ordering_stall_timeout)
+    }
+
     /// This will gather dropped packets from the acknowledgment handler.
     ///
     /// Note that after requesting dropped packets the dropped packets will be removed from this client.
     pub fn gather_dropped_packets(&mut self) -> Vec<SentPacket> {
-        self.acknowledge_handler.dropped_packets()
+        let dropped = self.acknowledge_handler.dropped_packets();
+        self.packets_resent += dropped.len() as u64;
+        self.congestion_handler
+            .process_acknowledgments(0, dropped.len());
+        dropped
+    }
+
+    /// Counts a datagram of `bytes` bytes which was handed over to the transport.
+    pub(crate) fn record_sent(&mut self, bytes: usize) {
+        self.packets_sent += 1;
+        self.bytes_sent += bytes as u64;
+    }
+
+    /// Takes all packets which were not acknowledged yet, to resend them when the remote endpoint dropped them,
+    /// e.g. because it did not validate our address yet.
+    pub fn take_unacknowledged_packets(&mut self) -> Vec<SentPacket> {
+        let unacknowledged = self.acknowledge_handler.take_unacknowledged_packets();
+        self.packets_resent += unacknowledged.len() as u64;
+        unacknowledged
+    }
+
+    /// Returns the number of packets that were reassembled from fragments.
+    pub fn packets_reassembled(&self) -> u64 {
+        self.packets_reassembled
+    }
+
+    /// Counts datagrams from the remote endpoint which could not be processed.
+    pub fn record_receive_error(&mut self, count: u64) {
+        self.errors_received += count;
+    }
+
+    /// Returns the number of datagrams from the remote endpoint which could not be processed.
+    pub fn errors_received(&self) -> u64 {
+        self.errors_received
+    }
+
+    /// Returns a snapshot of the statistics of this connection.
+    pub fn stats(&self, time: Instant) -> ConnectionStats {
+        ConnectionStats {
+            remote_address: self.remote_address,
+            rtt: self.congestion_handler.rtt(),
+            outbound_packet_loss: self.congestion_handler.outbound_loss(),
+            inbound_packet_loss: self.congestion_handler.inbound_loss(),
+            jitter: self.congestion_handler.jitter(),
+            quality: self.congestion_handler.quality(),
+            packets_sent: self.packets_sent,
+            packets_received: self.packets_received,
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            packets_resent: self.packets_resent,
+            packets_in_flight: self.acknowledge_handler.packets_awaiting_ack(),
+            packets_in_reassembly: self.fragmentation.packets_in_reassembly()
+                + self.unreliable_fragmentation.packets_in_reassembly(),
+            packets_reassembled: self.packets_reassembled,
+            packets_rebuilt: self.packets_rebuilt,
+            errors_received: self.errors_received,
+            last_heard: self.last_heard(time),
+            negotiated: self.negotiated,
+        }
+    }
+}
+
+// Returns a random connection identifier, 0 is left for datagrams which do not belong to a connection.
+fn new_connection_id() -> u32 {
+    loop {
+        let id = rand::random();
+        if id != 0 {
+            return id;
+        }
     }
 }
 
@@ -410,12 +1333,15 @@ mod tests {
     use super::VirtualConnection;
     use crate::config::Config;
     use crate::net::constants;
+    use crate::net::{channel::channel, OverflowPolicy};
     use crate::packet::header::{AckedPacketHeader, ArrangingHeader, HeaderWriter, StandardHeader};
-    use crate::packet::{DeliveryGuarantee, OrderingGuarantee, Outgoing, Packet, PacketType};
+    use crate::packet::{
+        DeliveryGuarantee, OrderingGuarantee, Outgoing, Packet, PacketType, Payload, StreamCommand,
+    };
     use crate::protocol_version::ProtocolVersion;
     use crate::SocketEvent;
     use byteorder::{BigEndian, WriteBytesExt};
-    use crossbeam_channel::{unbounded, TryRecvError};
+    use crossbeam_channel::TryRecvError;
     use std::io::Write;
     use std::time::Instant;
 
@@ -425,38 +1351,29 @@ mod tests {
     fn assure_right_fragmentation() {
         let mut protocol_version = Vec::new();
         protocol_version
-            .write_u16::<BigEndian>(ProtocolVersion::get_crc16())
+            .write_u16::<BigEndian>(ProtocolVersion::get_crc16(0))
             .unwrap();
 
-        let standard_header = [protocol_version, vec![1, 1, 2]].concat();
+        let standard_header = [protocol_version, vec![1, 1, 2, 0, 0, 0, 0]].concat();
 
-        let acked_header = vec![1, 0, 0, 2, 0, 0, 0, 3];
-        let first_fragment = vec![0, 1, 1, 3];
-        let second_fragment = vec![0, 1, 2, 3];
-        let third_fragment = vec![0, 1, 3, 3];
+        let acked_header = vec![1, 0, 0, 2, 0, 0, 0, 3, 1, 0, 4];
+        let first_fragment = vec![0, 1, 0, 3];
+        let second_fragment = vec![0, 1, 1, 3];
+        let third_fragment = vec![0, 1, 2, 3];
 
-        let (tx, rx) = unbounded::<SocketEvent>();
+        let (tx, rx) = channel::<SocketEvent>(None, OverflowPolicy::Error);
 
         let mut connection = create_virtual_connection();
         connection
             .process_incoming(
-                [standard_header.as_slice(), acked_header.as_slice()]
-                    .concat()
-                    .as_slice(),
-                &tx,
-                Instant::now(),
-            )
-            .unwrap();
-        assert!(rx.try_recv().is_err());
-        connection
-            .process_incoming(
-                [
+                &[
                     standard_header.as_slice(),
                     first_fragment.as_slice(),
+                    acked_header.as_slice(),
                     &PAYLOAD,
                 ]
                 .concat()
-                .as_slice(),
+                .into(),
                 &tx,
                 Instant::now(),
             )
@@ -464,13 +1381,13 @@ mod tests {
         assert!(rx.try_recv().is_err());
         connection
             .process_incoming(
-                [
+                &[
                     standard_header.as_slice(),
                     second_fragment.as_slice(),
                     &PAYLOAD,
                 ]
                 .concat()
-                .as_slice(),
+                .into(),
                 &tx,
                 Instant::now(),
             )
@@ -478,13 +1395,13 @@ mod tests {
         assert!(rx.try_recv().is_err());
         connection
             .process_incoming(
-                [
+                &[
                     standard_header.as_slice(),
                     third_fragment.as_slice(),
                     &PAYLOAD,
                 ]
                 .concat()
-                .as_slice(),
+                .into(),
                 &tx,
                 Instant::now(),
             )
@@ -507,7 +1424,7 @@ mod tests {
     fn expect_fragmentation() {
         let mut connection = create_virtual_connection();
 
-        let buffer = vec![1; 4000];
+        let buffer = Payload::from(vec![1; 4000]);
 
         let outgoing = connection
             .process_outgoing(
@@ -520,18 +1437,149 @@ mod tests {
             .unwrap();
 
         match outgoing {
-            Outgoing::Packet(_) => panic!("Expected fragment got packet"),
             Outgoing::Fragments(fragments) => {
                 assert_eq!(fragments.len(), 4);
             }
+            _ => panic!("Expected fragments"),
+        }
+    }
+
+    #[test]
+    fn reassemble_unreliable_sequenced_fragments() {
+        let mut sender = create_virtual_connection();
+        let mut receiver = create_virtual_connection();
+        let (tx, rx) = channel::<SocketEvent>(None, OverflowPolicy::Error);
+        let time = Instant::now();
+
+        let payload: Vec<u8> = (0..4000).map(|index| index as u8).collect();
+        let buffer = Payload::from(payload.clone());
+        let fragments = match sender
+            .process_outgoing(
+                &buffer,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::Sequenced(Some(1)),
+                None,
+                time,
+            )
+            .unwrap()
+        {
+            Outgoing::Fragments(fragments) => fragments,
+            _ => panic!("Expected fragments"),
+        };
+        assert_eq!(fragments.len(), 4);
+
+        for fragment in fragments.iter().rev() {
+            assert!(rx.try_recv().is_err());
+            receiver
+                .process_incoming(&fragment.contents().to_vec().into(), &tx, time)
+                .unwrap();
         }
+
+        assert_eq!(
+            rx.try_recv(),
+            Ok(SocketEvent::Packet(Packet::unreliable_sequenced(
+                get_fake_addr(),
+                payload,
+                Some(1),
+            )))
+        );
+        assert_eq!(receiver.packets_reassembled(), 1);
+    }
+
+    #[test]
+    fn drop_unreliable_fragments_after_reassembly_timeout() {
+        let mut sender = create_virtual_connection();
+        let mut receiver = create_virtual_connection();
+        let (tx, rx) = channel::<SocketEvent>(None, OverflowPolicy::Error);
+        let time = Instant::now();
+        let timeout = Config::default().fragment_reassembly_timeout;
+
+        let payload = Payload::from(vec![1; 2000]);
+        let mut send = || match sender
+            .process_outgoing(
+                &payload,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::None,
+                None,
+                time,
+            )
+            .unwrap()
+        {
+            Outgoing::Fragments(fragments) => fragments
+                .iter()
+                .map(|fragment| Payload::from(fragment.contents().to_vec()))
+                .collect::<Vec<Payload>>(),
+            _ => panic!("Expected fragments"),
+        };
+        let stale = send();
+        let fresh = send();
+
+        receiver.process_incoming(&stale[0], &tx, time).unwrap();
+        receiver
+            .process_incoming(&fresh[0], &tx, time + timeout)
+            .unwrap();
+        receiver
+            .process_incoming(&stale[1], &tx, time + timeout)
+            .unwrap();
+        assert!(rx.try_recv().is_err());
+
+        receiver
+            .process_incoming(&fresh[1], &tx, time + timeout)
+            .unwrap();
+        assert_eq!(
+            rx.try_recv(),
+            Ok(SocketEvent::Packet(Packet::unreliable(
+                get_fake_addr(),
+                vec![1; 2000]
+            )))
+        );
+        assert_eq!(receiver.packets_reassembled(), 1);
+    }
+
+    #[test]
+    fn fragment_unreliable_packets_larger_than_the_fragment_size() {
+        let mut sender = create_virtual_connection();
+        let mut receiver = create_virtual_connection();
+        let (tx, _rx) = channel::<SocketEvent>(None, OverflowPolicy::Error);
+        let time = Instant::now();
+        let config = Config::default();
+
+        // the payload fits in the receive buffer, but not with its headers.
+        let payload = Payload::from(vec![1; config.receive_buffer_max_size]);
+        let fragments = match sender
+            .process_outgoing(
+                &payload,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::None,
+                None,
+                time,
+            )
+            .unwrap()
+        {
+            Outgoing::Fragments(fragments) => fragments
+                .iter()
+                .map(|fragment| Payload::from(fragment.contents().to_vec()))
+                .collect::<Vec<Payload>>(),
+            _ => panic!("Expected fragments"),
+        };
+        assert!(fragments
+            .iter()
+            .all(|fragment| fragment.len() <= config.receive_buffer_max_size));
+
+        // the incomplete packet is dropped by the timers of the connection.
+        receiver.process_incoming(&fragments[0], &tx, time).unwrap();
+        let expiry = time + config.fragment_reassembly_timeout;
+        assert_eq!(receiver.next_deadline(), expiry);
+        receiver.drop_expired_fragments(expiry);
+        assert_eq!(receiver.stats(expiry).packets_in_reassembly, 0);
+        assert!(receiver.next_deadline() > expiry);
     }
 
     #[test]
     fn assure_correct_outgoing_processing() {
         let mut connection = create_virtual_connection();
 
-        let buffer = vec![1; 1000];
+        let buffer = Payload::from(vec![1; 1000]);
 
         connection
             .process_outgoing(
@@ -750,6 +1798,281 @@ mod tests {
         );
     }
 
+    #[test]
+    fn leave_ordered_packets_overflowing_the_stream_unacknowledged() {
+        let (tx, rx) = channel::<SocketEvent>(None, OverflowPolicy::Error);
+        let config = Config {
+            max_ordering_buffered_items: 1,
+            ..Config::default()
+        };
+        let mut sender = create_virtual_connection();
+        let mut receiver = VirtualConnection::new(get_fake_addr(), &config, Instant::now());
+
+        let packets: Vec<Vec<u8>> = (0..3)
+            .map(|_| {
+                match sender
+                    .process_outgoing(
+                        &Payload::from(&PAYLOAD[..]),
+                        DeliveryGuarantee::Reliable,
+                        OrderingGuarantee::Ordered(Some(1)),
+                        None,
+                        Instant::now(),
+                    )
+                    .unwrap()
+                {
+                    Outgoing::Packet(packet) => packet.contents().to_vec(),
+                    _ => panic!("Expected a single packet"),
+                }
+            })
+            .collect();
+
+        // the second item is buffered, the third one does not fit anymore and is not acknowledged.
+        for packet in &packets[1..] {
+            receiver
+                .process_incoming(&packet.as_slice().into(), &tx, Instant::now())
+                .unwrap();
+        }
+        assert!(rx.try_recv().is_err());
+        assert_eq!(receiver.acknowledge_handler.remote_sequence_num(), 1);
+
+        receiver
+            .process_incoming(&packets[0].as_slice().into(), &tx, Instant::now())
+            .unwrap();
+        assert_eq!(rx.try_iter().count(), 2);
+
+        // the resent item is delivered now that the gap is filled.
+        receiver
+            .process_incoming(&packets[2].as_slice().into(), &tx, Instant::now())
+            .unwrap();
+        assert_eq!(rx.try_iter().count(), 1);
+        assert_eq!(receiver.acknowledge_handler.remote_sequence_num(), 2);
+    }
+
+    #[test]
+    fn keep_ordered_items_which_do_not_fit_in_the_channel() {
+        let (tx, rx) = channel::<SocketEvent>(Some(2), OverflowPolicy::Error);
+        let mut sender = create_virtual_connection();
+        let mut receiver = create_virtual_connection();
+
+        let packets: Vec<Vec<u8>> = (1..=3u8)
+            .map(|item| {
+                match sender
+                    .process_outgoing(
+                        &Payload::from(vec![item]),
+                        DeliveryGuarantee::Reliable,
+                        OrderingGuarantee::Ordered(Some(1)),
+                        None,
+                        Instant::now(),
+                    )
+                    .unwrap()
+                {
+                    Outgoing::Packet(packet) => packet.contents().to_vec(),
+                    _ => panic!("Expected a single packet"),
+                }
+            })
+            .collect();
+
+        for packet in &packets[1..] {
+            receiver
+                .process_incoming(&packet.as_slice().into(), &tx, Instant::now())
+                .unwrap();
+        }
+
+        // filling the gap delivers more items than fit in the channel, the packet is not acknowledged.
+        assert!(receiver
+            .process_incoming(&packets[0].as_slice().into(), &tx, Instant::now())
+            .is_err());
+        assert_eq!(receiver.acknowledge_handler.remote_sequence_num(), 2);
+        assert_eq!(receiver.acknowledge_handler.ack_bitfield() & 0b11, 0b01);
+        let mut payloads: Vec<Vec<u8>> = rx
+            .try_iter()
+            .map(|event| match event {
+                SocketEvent::Packet(packet) => packet.payload().to_vec(),
+                _ => panic!("Expected a packet"),
+            })
+            .collect();
+
+        // the remaining item is delivered when the packet is resent.
+        receiver
+            .process_incoming(&packets[0].as_slice().into(), &tx, Instant::now())
+            .unwrap();
+        payloads.extend(rx.try_iter().map(|event| match event {
+            SocketEvent::Packet(packet) => packet.payload().to_vec(),
+            _ => panic!("Expected a packet"),
+        }));
+
+        assert_eq!(payloads, vec![vec![1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn reject_items_exceeding_stream_receive_window() {
+        let config = Config {
+            stream_receive_window: Some(2),
+            ..Config::default()
+        };
+        let mut connection = VirtualConnection::new(get_fake_addr(), &config, Instant::now());
+        let ordered = OrderingGuarantee::Ordered(Some(1));
+
+        for _ in 0..2 {
+            assert!(connection
+                .process_outgoing(
+                    &Payload::from(&PAYLOAD[..]),
+                    DeliveryGuarantee::Reliable,
+                    ordered,
+                    None,
+                    Instant::now()
+                )
+                .is_ok());
+        }
+
+        assert!(connection
+            .process_outgoing(
+                &Payload::from(&PAYLOAD[..]),
+                DeliveryGuarantee::Reliable,
+                ordered,
+                None,
+                Instant::now()
+            )
+            .is_err());
+        // resending an item and sending on another stream is still possible.
+        assert!(connection
+            .process_outgoing(
+                &Payload::from(&PAYLOAD[..]),
+                DeliveryGuarantee::Reliable,
+                ordered,
+                Some(1),
+                Instant::now()
+            )
+            .is_ok());
+        assert!(connection
+            .process_outgoing(
+                &Payload::from(&PAYLOAD[..]),
+                DeliveryGuarantee::Reliable,
+                OrderingGuarantee::Ordered(Some(2)),
+                None,
+                Instant::now()
+            )
+            .is_ok());
+
+        assert_eq!(connection.stream_info(ordered).unwrap().items_in_flight, 3);
+        assert_eq!(connection.streams().len(), 2);
+    }
+
+    #[test]
+    fn close_stream_of_remote_endpoint() {
+        let (tx, _rx) = channel::<SocketEvent>(None, OverflowPolicy::Error);
+        let mut connection = create_virtual_connection();
+        let mut remote = create_virtual_connection();
+        let sequenced = OrderingGuarantee::Sequenced(Some(1));
+
+        let outgoing = connection
+            .process_stream_command(StreamCommand::Close, sequenced, None, Instant::now())
+            .unwrap();
+
+        match outgoing {
+            Outgoing::Packet(packet) => remote
+                .process_incoming(&packet.contents().into(), &tx, Instant::now())
+                .unwrap(),
+            _ => panic!("Expected a single packet"),
+        }
+
+        assert!(connection.stream_info(sequenced).unwrap().closed);
+        assert!(connection
+            .process_outgoing(
+                &Payload::from(&PAYLOAD[..]),
+                DeliveryGuarantee::Unreliable,
+                sequenced,
+                None,
+                Instant::now()
+            )
+            .is_err());
+        assert!(remote.stream_info(sequenced).unwrap().closed_by_remote);
+    }
+
+    #[test]
+    fn honour_the_receive_window_of_the_remote_endpoint() {
+        let (tx, _rx) = channel::<SocketEvent>(None, OverflowPolicy::Error);
+        let config = Config {
+            max_ordering_buffered_items: 4,
+            stream_receive_window: Some(1024),
+            ..Config::default()
+        };
+        let mut connection = VirtualConnection::new(get_fake_addr(), &config, Instant::now());
+        let mut remote = VirtualConnection::new(get_fake_addr(), &config, Instant::now());
+        let ordered = OrderingGuarantee::Ordered(Some(1));
+
+        let packets: Vec<Vec<u8>> = (0..4)
+            .map(|_| {
+                match connection
+                    .process_outgoing(
+                        &Payload::from(&PAYLOAD[..]),
+                        DeliveryGuarantee::Reliable,
+                        ordered,
+                        None,
+                        Instant::now(),
+                    )
+                    .unwrap()
+                {
+                    Outgoing::Packet(packet) => packet.contents().to_vec(),
+                    _ => panic!("Expected a single packet"),
+                }
+            })
+            .collect();
+
+        // the first item is lost, so the remote endpoint buffers the other three.
+        for packet in &packets[1..] {
+            remote
+                .process_incoming(&packet.as_slice().into(), &tx, Instant::now())
+                .unwrap();
+        }
+        assert_eq!(remote.stream_info(ordered).unwrap().buffered_items, 3);
+
+        match remote
+            .process_outgoing(
+                &Payload::from(&PAYLOAD[..]),
+                DeliveryGuarantee::Reliable,
+                OrderingGuarantee::Ordered(Some(2)),
+                None,
+                Instant::now(),
+            )
+            .unwrap()
+        {
+            Outgoing::Packet(packet) => connection
+                .process_incoming(&packet.contents().into(), &tx, Instant::now())
+                .unwrap(),
+            _ => panic!("Expected a single packet"),
+        }
+
+        let info = connection.stream_info(ordered).unwrap();
+        assert_eq!(info.receive_window, Some(1));
+        assert_eq!(info.items_in_flight, 1);
+        assert!(connection
+            .process_outgoing(
+                &Payload::from(&PAYLOAD[..]),
+                DeliveryGuarantee::Reliable,
+                ordered,
+                None,
+                Instant::now()
+            )
+            .is_err());
+
+        // the stalled stream does not throttle the other streams.
+        let other = OrderingGuarantee::Ordered(Some(2));
+        assert!(connection
+            .process_outgoing(
+                &Payload::from(&PAYLOAD[..]),
+                DeliveryGuarantee::Reliable,
+                other,
+                None,
+                Instant::now()
+            )
+            .is_ok());
+        assert_eq!(
+            connection.stream_info(other).unwrap().receive_window,
+            Some(1024)
+        );
+    }
+
     /// ======= helper functions =========
     fn create_virtual_connection() -> VirtualConnection {
         VirtualConnection::new(get_fake_addr(), &Config::default(), Instant::now())
@@ -770,12 +2093,18 @@ mod tests {
         let mut packet = Vec::new();
 
         // configure the right header based on specified guarantees.
-        let header = StandardHeader::new(delivery, ordering, PacketType::Packet);
+        let header = StandardHeader::new(
+            ProtocolVersion::get_crc16(0),
+            delivery,
+            ordering,
+            PacketType::Packet,
+            0,
+        );
         header.parse(&mut packet).unwrap();
 
         if let OrderingGuarantee::Sequenced(val) = ordering {
             if delivery == DeliveryGuarantee::Reliable {
-                let ack_header = AckedPacketHeader::new(1, 2, 3);
+                let ack_header = AckedPacketHeader::new(1, 2, 3, 4, 5);
                 ack_header.parse(&mut packet).unwrap();
             }
 
@@ -785,7 +2114,7 @@ mod tests {
 
         if let OrderingGuarantee::Ordered(val) = ordering {
             if delivery == DeliveryGuarantee::Reliable {
-                let ack_header = AckedPacketHeader::new(1, 2, 3);
+                let ack_header = AckedPacketHeader::new(1, 2, 3, 4, 5);
                 let order_header = ArrangingHeader::new(order_id, val.unwrap());
                 ack_header.parse(&mut packet).unwrap();
                 order_header.parse(&mut packet).unwrap();
@@ -794,17 +2123,17 @@ mod tests {
 
         if let OrderingGuarantee::None = ordering {
             if delivery == DeliveryGuarantee::Reliable {
-                let ack_header = AckedPacketHeader::new(1, 2, 3);
+                let ack_header = AckedPacketHeader::new(1, 2, 3, 4, 5);
                 ack_header.parse(&mut packet).unwrap();
             }
         }
 
         packet.write_all(&PAYLOAD).unwrap();
 
-        let (tx, rx) = unbounded::<SocketEvent>();
+        let (tx, rx) = channel::<SocketEvent>(None, OverflowPolicy::Error);
 
         connection
-            .process_incoming(packet.as_slice(), &tx, Instant::now())
+            .process_incoming(&packet.into(), &tx, Instant::now())
             .unwrap();
 
         let event = rx.try_recv();
@@ -824,20 +2153,26 @@ mod tests {
         let mut packet = Vec::new();
 
         // configure the right header based on specified guarantees.
-        let header = StandardHeader::new(delivery, OrderingGuarantee::None, PacketType::Packet);
+        let header = StandardHeader::new(
+            ProtocolVersion::get_crc16(0),
+            delivery,
+            OrderingGuarantee::None,
+            PacketType::Packet,
+            0,
+        );
         header.parse(&mut packet).unwrap();
 
         if delivery == DeliveryGuarantee::Reliable {
-            let ack_header = AckedPacketHeader::new(1, 2, 3);
+            let ack_header = AckedPacketHeader::new(1, 2, 3, 4, 5);
             ack_header.parse(&mut packet).unwrap();
         }
 
         packet.write_all(&PAYLOAD).unwrap();
 
-        let (tx, rx) = unbounded::<SocketEvent>();
+        let (tx, rx) = channel::<SocketEvent>(None, OverflowPolicy::Error);
 
         connection
-            .process_incoming(packet.as_slice(), &tx, Instant::now())
+            .process_incoming(&packet.into(), &tx, Instant::now())
             .unwrap();
 
         let event = rx.try_recv();
@@ -853,7 +2188,7 @@ mod tests {
     ) {
         let mut connection = create_virtual_connection();
 
-        let buffer = vec![1; 500];
+        let buffer = Payload::from(vec![1; 500]);
 
         let outgoing = connection
             .process_outgoing(&buffer, delivery, ordering, None, Instant::now())
@@ -863,7 +2198,7 @@ mod tests {
             Outgoing::Packet(packet) => {
                 assert_eq!(packet.contents().len() - buffer.len(), expected_header_size);
             }
-            Outgoing::Fragments(_) => panic!("Expected packet got fragment"),
+            _ => panic!("Expected a single packet"),
         }
     }
 }
@@ -6,11 +6,15 @@ mod enums;
 mod outgoing;
 mod packet_reader;
 mod packet_structure;
+mod payload;
 
-pub use self::enums::{DeliveryGuarantee, OrderingGuarantee, PacketType};
+pub use self::enums::{
+    DeliveryGuarantee, OrderingGuarantee, PacketType, Redundancy, StreamCommand,
+};
 pub use self::outgoing::{Outgoing, OutgoingPacket, OutgoingPacketBuilder};
 pub use self::packet_reader::PacketReader;
 pub use self::packet_structure::Packet;
+pub use self::payload::{BufferMut, BufferPool, Payload};
 
 pub type SequenceNumber = u16;
 
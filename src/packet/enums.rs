@@ -37,9 +37,10 @@ impl TryFrom<u8> for DeliveryGuarantee {
 }
 
 /// Enum to specify how a packet should be arranged.
-#[derive(Copy, Clone, Debug, PartialOrd, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Default, PartialOrd, PartialEq, Eq, Hash)]
 pub enum OrderingGuarantee {
     /// No arranging will be done.
+    #[default]
     None,
     /// Packets will be arranged in sequence.
     Sequenced(Option<u8>),
@@ -47,12 +48,6 @@ pub enum OrderingGuarantee {
     Ordered(Option<u8>),
 }
 
-impl Default for OrderingGuarantee {
-    fn default() -> Self {
-        OrderingGuarantee::None
-    }
-}
-
 impl EnumConverter for OrderingGuarantee {
     type Enum = OrderingGuarantee;
 
@@ -81,6 +76,49 @@ impl TryFrom<u8> for OrderingGuarantee {
     }
 }
 
+/// Enum to specify how many parities protect a packet, which rebuild lost datagrams without a retransmit.
+///
+/// Parities are computed over a group of datagrams: the fragments of a fragmented packet, or a number of unreliable packets
+/// in a row, see `Config::parity_group_size`. Each parity costs a datagram as large as the largest one of its group.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Redundancy {
+    /// No parities are sent.
+    #[default]
+    None,
+    /// A single XOR parity is sent per group, which rebuilds one lost datagram of the group.
+    Xor,
+    /// The given number of Reed-Solomon parities are sent per group, which rebuild as many lost datagrams of the group.
+    ReedSolomon(u8),
+}
+
+impl Redundancy {
+    /// The maximal number of parities of a group.
+    pub const MAX_PARITIES: u8 = 128;
+
+    /// Returns the number of parities which are sent per group.
+    pub fn parity_count(self) -> u8 {
+        match self {
+            Redundancy::None => 0,
+            Redundancy::Xor => 1,
+            Redundancy::ReedSolomon(parities) => parities.min(Redundancy::MAX_PARITIES),
+        }
+    }
+
+    /// Returns true if the parities are Reed-Solomon parities.
+    pub fn is_reed_solomon(self) -> bool {
+        matches!(self, Redundancy::ReedSolomon(_))
+    }
+
+    /// Returns the redundancy with the most parities, `self` when both have as many.
+    pub fn max(self, other: Redundancy) -> Redundancy {
+        if other.parity_count() > self.parity_count() {
+            other
+        } else {
+            self
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
 /// Id to identify a certain packet type.
 pub enum PacketType {
@@ -88,6 +126,26 @@ pub enum PacketType {
     Packet = 0,
     /// Fragment of a full packet
     Fragment = 1,
+    /// Command that controls an ordering or sequencing stream
+    StreamControl = 2,
+    /// Notice that the sender closed the connection, it carries the identifier the sender gave the connection
+    Disconnect = 3,
+    /// Reply to a new peer which was not accepted, e.g. because the maximal number of connections was reached.
+    /// It carries the identifier of the connection of the peer, which was read from the rejected datagram
+    ConnectionRejected = 4,
+    /// Reply to a datagram from an unvalidated address, which carries a cookie the peer has to return
+    Challenge = 5,
+    /// Returns the cookie of a challenge, which proves the peer receives datagrams at its address
+    ///
+    /// A peer whose address changed mixes the identifier we gave the connection into the cookie, which proves the connection is its own
+    ChallengeResponse = 6,
+    /// Reply to a packet of another protocol version or application protocol id, it carries the protocol version of the receiver
+    /// and the identifier of its connection
+    VersionMismatch = 7,
+    /// Advertises the wire versions and optional features the sender supports, when a connection is set up
+    Hello = 8,
+    /// Parity of a group of datagrams, which rebuilds lost datagrams of the group without a retransmit
+    Parity = 9,
 }
 
 impl EnumConverter for PacketType {
@@ -104,15 +162,61 @@ impl TryFrom<u8> for PacketType {
         match value {
             0 => Ok(PacketType::Packet),
             1 => Ok(PacketType::Fragment),
+            2 => Ok(PacketType::StreamControl),
+            3 => Ok(PacketType::Disconnect),
+            4 => Ok(PacketType::ConnectionRejected),
+            5 => Ok(PacketType::Challenge),
+            6 => Ok(PacketType::ChallengeResponse),
+            7 => Ok(PacketType::VersionMismatch),
+            8 => Ok(PacketType::Hello),
+            9 => Ok(PacketType::Parity),
             _ => Err(ErrorKind::DecodingError(DecodingErrorKind::PacketType)),
         }
     }
 }
 
+#[derive(Copy, Clone, Debug, PartialOrd, PartialEq)]
+/// Command which is sent with a `PacketType::StreamControl` packet.
+pub enum StreamCommand {
+    /// Skip all items before the given item identifier and continue from there
+    Reset = 0,
+    /// Discard all buffered items, the sender will not send any new items until it resets the stream
+    Close = 1,
+}
+
+impl StreamCommand {
+    /// Returns the payload of a `PacketType::StreamControl` packet which carries this command.
+    pub fn payload(&self) -> &'static [u8] {
+        match self {
+            StreamCommand::Reset => &[0],
+            StreamCommand::Close => &[1],
+        }
+    }
+}
+
+impl EnumConverter for StreamCommand {
+    type Enum = StreamCommand;
+
+    fn to_u8(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl TryFrom<u8> for StreamCommand {
+    type Error = ErrorKind;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(StreamCommand::Reset),
+            1 => Ok(StreamCommand::Close),
+            _ => Err(ErrorKind::DecodingError(DecodingErrorKind::StreamCommand)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::packet::{
-        enums::{DeliveryGuarantee, OrderingGuarantee, PacketType},
+        enums::{DeliveryGuarantee, OrderingGuarantee, PacketType, StreamCommand},
         EnumConverter,
     };
     use std::convert::TryFrom;
@@ -155,6 +259,14 @@ mod tests {
     fn assure_parsing_packet_id() {
         let packet = PacketType::Packet;
         let fragment = PacketType::Fragment;
+        let stream_control = PacketType::StreamControl;
+        let disconnect = PacketType::Disconnect;
+        let rejected = PacketType::ConnectionRejected;
+        let challenge = PacketType::Challenge;
+        let challenge_response = PacketType::ChallengeResponse;
+        let version_mismatch = PacketType::VersionMismatch;
+        let hello = PacketType::Hello;
+        let parity = PacketType::Parity;
         assert_eq!(
             PacketType::Packet,
             PacketType::try_from(packet.to_u8()).unwrap()
@@ -163,5 +275,51 @@ mod tests {
             PacketType::Fragment,
             PacketType::try_from(fragment.to_u8()).unwrap()
         );
+        assert_eq!(
+            PacketType::StreamControl,
+            PacketType::try_from(stream_control.to_u8()).unwrap()
+        );
+        assert_eq!(
+            PacketType::Disconnect,
+            PacketType::try_from(disconnect.to_u8()).unwrap()
+        );
+        assert_eq!(
+            PacketType::ConnectionRejected,
+            PacketType::try_from(rejected.to_u8()).unwrap()
+        );
+        assert_eq!(
+            PacketType::Challenge,
+            PacketType::try_from(challenge.to_u8()).unwrap()
+        );
+        assert_eq!(
+            PacketType::ChallengeResponse,
+            PacketType::try_from(challenge_response.to_u8()).unwrap()
+        );
+        assert_eq!(
+            PacketType::VersionMismatch,
+            PacketType::try_from(version_mismatch.to_u8()).unwrap()
+        );
+        assert_eq!(
+            PacketType::Hello,
+            PacketType::try_from(hello.to_u8()).unwrap()
+        );
+        assert_eq!(
+            PacketType::Parity,
+            PacketType::try_from(parity.to_u8()).unwrap()
+        );
+    }
+
+    #[test]
+    fn assure_parsing_stream_command() {
+        let reset = StreamCommand::Reset;
+        let close = StreamCommand::Close;
+        assert_eq!(
+            StreamCommand::Reset,
+            StreamCommand::try_from(reset.to_u8()).unwrap()
+        );
+        assert_eq!(
+            StreamCommand::Close,
+            StreamCommand::try_from(close.to_u8()).unwrap()
+        );
     }
 }
@@ -13,17 +13,31 @@ pub struct AckedPacketHeader {
     ack_seq: u16,
     // this is an bitfield of all last 32 acknowledged packages
     ack_field: u32,
+    // this is the ordering stream whose receive window is advertised.
+    window_stream: u8,
+    // this is the number of items the sender of this header is still willing to receive on that stream.
+    window: u16,
 }
 
 impl AckedPacketHeader {
     /// When we compose packet headers, the local sequence becomes the sequence number of the packet, and the remote sequence becomes the ack.
     /// The ack bitfield is calculated by looking into a queue of up to 33 packets, containing sequence numbers in the range [remote sequence - 32, remote sequence].
     /// We set bit n (in [1,32]) in ack bits to 1 if the sequence number remote sequence - n is in the received queue.
-    pub fn new(seq_num: u16, last_seq: u16, bit_field: u32) -> AckedPacketHeader {
+    ///
+    /// The window advertises how many more items we are willing to receive on the ordering stream `window_stream`.
+    pub fn new(
+        seq_num: u16,
+        last_seq: u16,
+        bit_field: u32,
+        window_stream: u8,
+        window: u16,
+    ) -> AckedPacketHeader {
         AckedPacketHeader {
             seq: seq_num,
             ack_seq: last_seq,
             ack_field: bit_field,
+            window_stream,
+            window,
         }
     }
 
@@ -42,6 +56,16 @@ impl AckedPacketHeader {
     pub fn ack_seq(&self) -> u16 {
         self.ack_seq
     }
+
+    /// Get the ordering stream whose receive window is advertised.
+    pub fn window_stream(&self) -> u8 {
+        self.window_stream
+    }
+
+    /// Get the number of items the remote host is still willing to receive on `window_stream`.
+    pub fn window(&self) -> u16 {
+        self.window
+    }
 }
 
 impl HeaderWriter for AckedPacketHeader {
@@ -51,6 +75,8 @@ impl HeaderWriter for AckedPacketHeader {
         buffer.write_u16::<BigEndian>(self.seq)?;
         buffer.write_u16::<BigEndian>(self.ack_seq)?;
         buffer.write_u32::<BigEndian>(self.ack_field)?;
+        buffer.write_u8(self.window_stream)?;
+        buffer.write_u16::<BigEndian>(self.window)?;
         Ok(())
     }
 }
@@ -62,11 +88,15 @@ impl HeaderReader for AckedPacketHeader {
         let seq = rdr.read_u16::<BigEndian>()?;
         let ack_seq = rdr.read_u16::<BigEndian>()?;
         let ack_field = rdr.read_u32::<BigEndian>()?;
+        let window_stream = rdr.read_u8()?;
+        let window = rdr.read_u16::<BigEndian>()?;
 
         Ok(AckedPacketHeader {
             seq,
             ack_seq,
             ack_field,
+            window_stream,
+            window,
         })
     }
 
@@ -84,18 +114,20 @@ mod tests {
     #[test]
     fn serialize() {
         let mut buffer = Vec::new();
-        let header = AckedPacketHeader::new(1, 2, 3);
-        header.parse(&mut buffer).is_ok();
+        let header = AckedPacketHeader::new(1, 2, 3, 4, 5);
+        header.parse(&mut buffer).unwrap();
 
         assert_eq!(buffer[1], 1);
         assert_eq!(buffer[3], 2);
         assert_eq!(buffer[7], 3);
+        assert_eq!(buffer[8], 4);
+        assert_eq!(buffer[10], 5);
         assert_eq!(buffer.len() as u8, AckedPacketHeader::size());
     }
 
     #[test]
     fn deserialize() {
-        let buffer = vec![0, 1, 0, 2, 0, 0, 0, 3];
+        let buffer = vec![0, 1, 0, 2, 0, 0, 0, 3, 4, 0, 5];
 
         let mut cursor = Cursor::new(buffer.as_slice());
 
@@ -104,6 +136,8 @@ mod tests {
         assert_eq!(header.sequence(), 1);
         assert_eq!(header.ack_seq(), 2);
         assert_eq!(header.ack_field(), 3);
+        assert_eq!(header.window_stream(), 4);
+        assert_eq!(header.window(), 5);
     }
 
     #[test]
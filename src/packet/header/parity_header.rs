@@ -0,0 +1,117 @@
+use super::{HeaderReader, HeaderWriter};
+use crate::error::Result;
+use crate::net::constants::PARITY_HEADER_SIZE;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::io::Cursor;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// This header represents the header of a parity packet, which rebuilds lost datagrams of its group.
+pub struct ParityHeader {
+    group: u16,
+    data_count: u8,
+    index: u8,
+    reed_solomon: bool,
+}
+
+impl ParityHeader {
+    /// Create new parity header for the parity with the given index, of a group of `data_count` datagrams.
+    pub fn new(group: u16, data_count: u8, index: u8, reed_solomon: bool) -> Self {
+        ParityHeader {
+            group,
+            data_count,
+            index,
+            reed_solomon,
+        }
+    }
+
+    /// Get the group this parity protects.
+    pub fn group(&self) -> u16 {
+        self.group
+    }
+
+    /// Get the number of datagrams in the group.
+    pub fn data_count(&self) -> u8 {
+        self.data_count
+    }
+
+    /// Get the index of this parity in the group.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
+    /// Returns true if this is a Reed-Solomon parity, false if it is a XOR parity.
+    pub fn is_reed_solomon(&self) -> bool {
+        self.reed_solomon
+    }
+}
+
+impl HeaderWriter for ParityHeader {
+    type Output = Result<()>;
+
+    fn parse(&self, buffer: &mut Vec<u8>) -> Self::Output {
+        buffer.write_u16::<BigEndian>(self.group)?;
+        buffer.write_u8(self.data_count)?;
+        buffer.write_u8(self.index)?;
+        buffer.write_u8(self.reed_solomon as u8)?;
+
+        Ok(())
+    }
+}
+
+impl HeaderReader for ParityHeader {
+    type Header = Result<ParityHeader>;
+
+    fn read(rdr: &mut Cursor<&[u8]>) -> Self::Header {
+        let group = rdr.read_u16::<BigEndian>()?;
+        let data_count = rdr.read_u8()?;
+        let index = rdr.read_u8()?;
+        let reed_solomon = rdr.read_u8()? != 0;
+
+        Ok(ParityHeader {
+            group,
+            data_count,
+            index,
+            reed_solomon,
+        })
+    }
+
+    /// Get the size of this header.
+    fn size() -> u8 {
+        PARITY_HEADER_SIZE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::constants::PARITY_HEADER_SIZE;
+    use crate::packet::header::{HeaderReader, HeaderWriter, ParityHeader};
+    use std::io::Cursor;
+
+    #[test]
+    fn serialize() {
+        let mut buffer = Vec::new();
+        let header = ParityHeader::new(258, 3, 1, true);
+        header.parse(&mut buffer).unwrap();
+
+        assert_eq!(buffer, [1, 2, 3, 1, 1]);
+    }
+
+    #[test]
+    fn deserialize() {
+        let buffer = vec![1, 2, 3, 1, 0];
+
+        let mut cursor = Cursor::new(buffer.as_slice());
+
+        let header = ParityHeader::read(&mut cursor).unwrap();
+
+        assert_eq!(header.group(), 258);
+        assert_eq!(header.data_count(), 3);
+        assert_eq!(header.index(), 1);
+        assert!(!header.is_reed_solomon());
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(ParityHeader::size(), PARITY_HEADER_SIZE);
+    }
+}
@@ -7,32 +7,58 @@ use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use std::convert::TryFrom;
 use std::io::Cursor;
 
+/// The bit of the packet type which flags a compressed payload.
+const COMPRESSED_FLAG: u8 = 0x80;
+/// The bit of the packet type which flags a datagram in a parity group, which ends in a trailer with the group.
+const PARITY_GROUP_FLAG: u8 = 0x40;
+
 #[derive(Copy, Clone, Debug)]
 /// This header will be included in each packet, and contains some basic information.
 pub struct StandardHeader {
     protocol_version: u16,
     packet_type: PacketType,
+    compressed: bool,
+    parity_group: bool,
     delivery_guarantee: DeliveryGuarantee,
     ordering_guarantee: OrderingGuarantee,
+    connection_id: u32,
 }
 
 impl StandardHeader {
     /// Create new heartbeat header.
+    ///
+    /// `protocol_version` is the crc16 of the protocol version and the application protocol id, see `ProtocolVersion::get_crc16`.
     pub fn new(
+        protocol_version: u16,
         delivery_guarantee: DeliveryGuarantee,
         ordering_guarantee: OrderingGuarantee,
         packet_type: PacketType,
+        connection_id: u32,
     ) -> Self {
         StandardHeader {
-            protocol_version: ProtocolVersion::get_crc16(),
+            protocol_version,
             delivery_guarantee,
             ordering_guarantee,
             packet_type,
+            compressed: false,
+            parity_group: false,
+            connection_id,
         }
     }
 
+    /// Flags whether the payload is compressed, which is written in the highest bit of the packet type.
+    pub fn with_compressed(mut self, compressed: bool) -> Self {
+        self.compressed = compressed;
+        self
+    }
+
+    /// Flags whether the datagram is in a parity group, which is written in the second highest bit of the packet type.
+    pub fn with_parity_group(mut self, parity_group: bool) -> Self {
+        self.parity_group = parity_group;
+        self
+    }
+
     /// Returns the protocol version
-    #[cfg(test)]
     pub fn protocol_version(&self) -> u16 {
         self.protocol_version
     }
@@ -48,28 +74,49 @@ impl StandardHeader {
     }
 
     /// Returns the PacketType
-    #[cfg(test)]
     pub fn packet_type(&self) -> PacketType {
         self.packet_type
     }
 
+    /// Returns the identifier the sender gave its connection, 0 if the sender has no connection with us
+    pub fn connection_id(&self) -> u32 {
+        self.connection_id
+    }
+
+    /// Returns true if the payload is compressed, see `Config::compressor`
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Returns true if the datagram is in a parity group and ends in a trailer with the group, see `Packet::with_redundancy`
+    pub fn is_in_parity_group(&self) -> bool {
+        self.parity_group
+    }
+
     /// Returns true if the packet is a fragment, false if not
     pub fn is_fragment(&self) -> bool {
         self.packet_type == PacketType::Fragment
     }
 
-    /// Checks if the protocol version in the packet is a valid version
-    pub fn is_current_protocol(&self) -> bool {
-        ProtocolVersion::valid_version(self.protocol_version)
+    /// Returns true if the packet is a stream command, false if not
+    pub fn is_stream_control(&self) -> bool {
+        self.packet_type == PacketType::StreamControl
+    }
+
+    /// Checks if the protocol version in the packet is a valid version for the given application protocol id
+    pub fn is_current_protocol(&self, protocol_id: u64) -> bool {
+        ProtocolVersion::valid_version(self.protocol_version, protocol_id)
     }
 }
 
 impl Default for StandardHeader {
     fn default() -> Self {
         StandardHeader::new(
+            ProtocolVersion::get_crc16(0),
             DeliveryGuarantee::Unreliable,
             OrderingGuarantee::None,
             PacketType::Packet,
+            0,
         )
     }
 }
@@ -79,9 +126,16 @@ impl HeaderWriter for StandardHeader {
 
     fn parse(&self, buffer: &mut Vec<u8>) -> Self::Output {
         buffer.write_u16::<BigEndian>(self.protocol_version)?;
-        buffer.write_u8(self.packet_type.to_u8())?;
+        let compressed_flag = if self.compressed { COMPRESSED_FLAG } else { 0 };
+        let parity_group_flag = if self.parity_group {
+            PARITY_GROUP_FLAG
+        } else {
+            0
+        };
+        buffer.write_u8(self.packet_type.to_u8() | compressed_flag | parity_group_flag)?;
         buffer.write_u8(self.delivery_guarantee.to_u8())?;
         buffer.write_u8(self.ordering_guarantee.to_u8())?;
+        buffer.write_u32::<BigEndian>(self.connection_id)?;
         Ok(())
     }
 }
@@ -94,12 +148,16 @@ impl HeaderReader for StandardHeader {
         let packet_id = rdr.read_u8()?;
         let delivery_guarantee_id = rdr.read_u8()?;
         let order_guarantee_id = rdr.read_u8()?;
+        let connection_id = rdr.read_u32::<BigEndian>()?;
 
         let header = StandardHeader {
             protocol_version,
-            packet_type: PacketType::try_from(packet_id)?,
+            packet_type: PacketType::try_from(packet_id & !(COMPRESSED_FLAG | PARITY_GROUP_FLAG))?,
+            compressed: packet_id & COMPRESSED_FLAG != 0,
+            parity_group: packet_id & PARITY_GROUP_FLAG != 0,
             delivery_guarantee: DeliveryGuarantee::try_from(delivery_guarantee_id)?,
             ordering_guarantee: OrderingGuarantee::try_from(order_guarantee_id)?,
+            connection_id,
         };
 
         Ok(header)
@@ -122,21 +180,24 @@ mod tests {
     fn serialize() {
         let mut buffer = Vec::new();
         let header = StandardHeader::new(
+            1,
             DeliveryGuarantee::Unreliable,
             OrderingGuarantee::Sequenced(None),
             PacketType::Packet,
+            7,
         );
-        header.parse(&mut buffer).is_ok();
+        header.parse(&mut buffer).unwrap();
 
-        // [0 .. 3] protocol version
+        assert_eq!(buffer[0..2], [0, 1]);
         assert_eq!(buffer[2], PacketType::Packet.to_u8());
         assert_eq!(buffer[3], DeliveryGuarantee::Unreliable.to_u8());
         assert_eq!(buffer[4], OrderingGuarantee::Sequenced(None).to_u8());
+        assert_eq!(buffer[5..9], [0, 0, 0, 7]);
     }
 
     #[test]
     fn deserialize() {
-        let buffer = vec![0, 1, 0, 1, 1];
+        let buffer = vec![0, 1, 0, 1, 1, 0, 0, 1, 2];
 
         let mut cursor = Cursor::new(buffer.as_slice());
 
@@ -149,6 +210,48 @@ mod tests {
             header.ordering_guarantee(),
             OrderingGuarantee::Sequenced(None)
         );
+        assert_eq!(header.connection_id(), 258);
+    }
+
+    #[test]
+    fn compressed_flag() {
+        let mut buffer = Vec::new();
+        let header = StandardHeader::new(
+            1,
+            DeliveryGuarantee::Reliable,
+            OrderingGuarantee::None,
+            PacketType::Fragment,
+            7,
+        )
+        .with_compressed(true);
+        header.parse(&mut buffer).unwrap();
+        assert_eq!(buffer[2], 0x80 | PacketType::Fragment.to_u8());
+
+        let header = StandardHeader::read(&mut Cursor::new(buffer.as_slice())).unwrap();
+        assert!(header.is_compressed());
+        assert!(!header.is_in_parity_group());
+        assert_eq!(header.packet_type(), PacketType::Fragment);
+    }
+
+    #[test]
+    fn parity_group_flag() {
+        let mut buffer = Vec::new();
+        let header = StandardHeader::new(
+            1,
+            DeliveryGuarantee::Unreliable,
+            OrderingGuarantee::None,
+            PacketType::Packet,
+            7,
+        )
+        .with_compressed(true)
+        .with_parity_group(true);
+        header.parse(&mut buffer).unwrap();
+        assert_eq!(buffer[2], 0xc0 | PacketType::Packet.to_u8());
+
+        let header = StandardHeader::read(&mut Cursor::new(buffer.as_slice())).unwrap();
+        assert!(header.is_compressed());
+        assert!(header.is_in_parity_group());
+        assert_eq!(header.packet_type(), PacketType::Packet);
     }
 
     #[test]
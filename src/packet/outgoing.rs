@@ -1,28 +1,66 @@
 use crate::{
-    net::constants::{DEFAULT_ORDERING_STREAM, DEFAULT_SEQUENCING_STREAM},
+    compression::Compression,
+    infrastructure::fec,
+    net::constants::{
+        ACKED_PACKET_HEADER, ARRANGING_PACKET_HEADER, DEFAULT_ORDERING_STREAM,
+        DEFAULT_SEQUENCING_STREAM, FRAGMENT_HEADER_SIZE, STANDARD_HEADER_SIZE,
+    },
     packet::{
         header::{
-            AckedPacketHeader, ArrangingHeader, FragmentHeader, HeaderWriter, StandardHeader,
+            AckedPacketHeader, ArrangingHeader, FragmentHeader, HeaderWriter, ParityHeader,
+            StandardHeader,
         },
         DeliveryGuarantee, OrderingGuarantee, PacketType,
     },
 };
+use std::borrow::Cow;
 
 /// Builder that could be used to construct an outgoing laminar packet.
 pub struct OutgoingPacketBuilder<'p> {
     header: Vec<u8>,
-    payload: &'p [u8],
+    payload: Cow<'p, [u8]>,
+    trailer: Vec<u8>,
+    compressed: bool,
 }
 
 impl<'p> OutgoingPacketBuilder<'p> {
     /// Construct a new builder from the given `payload`.
     pub fn new(payload: &'p [u8]) -> OutgoingPacketBuilder<'p> {
+        OutgoingPacketBuilder::with_payload(Cow::Borrowed(payload))
+    }
+
+    /// Construct a new builder which owns the given `payload`, e.g. a parity which is computed when sending.
+    pub fn owned(payload: Vec<u8>) -> OutgoingPacketBuilder<'p> {
+        OutgoingPacketBuilder::with_payload(Cow::Owned(payload))
+    }
+
+    fn with_payload(payload: Cow<'p, [u8]>) -> OutgoingPacketBuilder<'p> {
+        // Allocate room for every header at once instead of growing the header for each one
+        let max_header_size = STANDARD_HEADER_SIZE
+            + FRAGMENT_HEADER_SIZE.max(ARRANGING_PACKET_HEADER)
+            + ACKED_PACKET_HEADER;
+
         OutgoingPacketBuilder {
-            header: Vec::new(),
+            header: Vec::with_capacity(max_header_size as usize),
             payload,
+            trailer: Vec::new(),
+            compressed: false,
         }
     }
 
+    /// This will compress the payload, unless it does not shrink.
+    ///
+    /// # Remark
+    /// - Has to be called before `with_default_header`, which flags the compressed payload in the standard header.
+    pub fn with_compression(mut self, compression: &Compression) -> Self {
+        if let Some(compressed) = compression.compress(&self.payload) {
+            self.payload = Cow::Owned(compressed);
+            self.compressed = true;
+        }
+
+        self
+    }
+
     /// This will add the `FragmentHeader` to the header.
     pub fn with_fragment_header(mut self, packet_seq: u16, id: u8, num_fragments: u8) -> Self {
         let header = FragmentHeader::new(packet_seq, id, num_fragments);
@@ -34,14 +72,46 @@ impl<'p> OutgoingPacketBuilder<'p> {
         self
     }
 
+    /// This will add the [`ParityHeader`](./headers/parity_header) to the header.
+    pub fn with_parity_header(mut self, header: ParityHeader) -> Self {
+        header
+            .parse(&mut self.header)
+            .expect("Could not write parity header to buffer");
+
+        self
+    }
+
+    /// This will append the trailer of a datagram in a parity group, with the group and the index of the datagram in it.
+    ///
+    /// # Remark
+    /// - Has to be called before `with_default_header`, which flags the trailer in the standard header.
+    pub fn with_parity_group(mut self, group: u16, index: u8) -> Self {
+        fec::write_trailer(&mut self.trailer, group, index);
+
+        self
+    }
+
     /// This will add the [`StandardHeader`](./headers/standard_header) to the header.
+    ///
+    /// `protocol_version` is the crc16 of the protocol version and the application protocol id, see `ProtocolVersion::get_crc16`.
+    /// `connection_id` identifies the connection of the sender, so it can be found when its address changes.
     pub fn with_default_header(
         mut self,
         packet_type: PacketType,
         delivery_guarantee: DeliveryGuarantee,
         ordering_guarantee: OrderingGuarantee,
+        protocol_version: u16,
+        connection_id: u32,
     ) -> Self {
-        let header = StandardHeader::new(delivery_guarantee, ordering_guarantee, packet_type);
+        let header = StandardHeader::new(
+            protocol_version,
+            delivery_guarantee,
+            ordering_guarantee,
+            packet_type,
+            connection_id,
+        )
+        .with_compressed(self.compressed)
+        .with_parity_group(!self.trailer.is_empty());
         header
             .parse(&mut self.header)
             .expect("Could not write default header to buffer");
@@ -55,8 +125,10 @@ impl<'p> OutgoingPacketBuilder<'p> {
         seq_num: u16,
         last_seq: u16,
         bit_field: u32,
+        window_stream: u8,
+        window: u16,
     ) -> Self {
-        let header = AckedPacketHeader::new(seq_num, last_seq, bit_field);
+        let header = AckedPacketHeader::new(seq_num, last_seq, bit_field, window_stream, window);
         header
             .parse(&mut self.header)
             .expect("Could not write acknowledgment header to buffer");
@@ -99,6 +171,7 @@ impl<'p> OutgoingPacketBuilder<'p> {
         OutgoingPacket {
             header: self.header,
             payload: self.payload,
+            trailer: self.trailer,
         }
     }
 }
@@ -106,7 +179,8 @@ impl<'p> OutgoingPacketBuilder<'p> {
 /// Packet that that contains data which is ready to be sent to a remote endpoint.
 pub struct OutgoingPacket<'p> {
     header: Vec<u8>,
-    payload: &'p [u8],
+    payload: Cow<'p, [u8]>,
+    trailer: Vec<u8>,
 }
 
 impl<'p> OutgoingPacket<'p> {
@@ -114,12 +188,26 @@ impl<'p> OutgoingPacket<'p> {
     ///
     /// # Remark
     /// - Until here we could use a reference to the outgoing data but here we need to do a hard copy.
-    /// Because the header could vary in size but should be in front of the payload provided by the user.
+    ///   Because the header could vary in size but should be in front of the payload provided by the user.
+    /// - Datagrams are written to pooled buffers with `write_contents`, this is only used to inspect packets in tests.
+    #[cfg(test)]
     pub fn contents(&self) -> Box<[u8]> {
-        [self.header.as_slice(), &self.payload]
+        [self.header.as_slice(), &self.payload, &self.trailer]
             .concat()
             .into_boxed_slice()
     }
+
+    /// Writes the contents of this packet to the end of `buffer`, e.g. a pooled buffer, without allocating a new one.
+    pub fn write_contents(&self, buffer: &mut Vec<u8>) {
+        buffer.extend_from_slice(&self.header);
+        buffer.extend_from_slice(&self.payload);
+        buffer.extend_from_slice(&self.trailer);
+    }
+
+    /// Returns the contents of this packet without the trailer of its parity group, which the parities of the group are computed over.
+    pub fn parity_shard(&self) -> Vec<u8> {
+        [self.header.as_slice(), &self.payload].concat()
+    }
 }
 
 /// Enum for storing different kinds of outgoing types with data.
@@ -127,16 +215,23 @@ pub enum Outgoing<'a> {
     /// Represents a single packet.
     Packet(OutgoingPacket<'a>),
     /// Represents a packet that is fragmented and thus contains more than one `OutgoingPacket`.
+    /// The parities of the fragments follow them, when the packet has redundancy.
     Fragments(Vec<OutgoingPacket<'a>>),
+    /// Represents a packet which completes a parity group, followed by the parities of the group.
+    PacketWithParities(OutgoingPacket<'a>, Vec<OutgoingPacket<'a>>),
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::packet::PacketType;
-    use crate::packet::{DeliveryGuarantee, OrderingGuarantee, OutgoingPacketBuilder};
+    use crate::compression::{Compression, Lz4Compressor};
+    use crate::net::constants::STANDARD_HEADER_SIZE;
+    use crate::packet::{
+        DeliveryGuarantee, EnumConverter, OrderingGuarantee, OutgoingPacketBuilder, PacketType,
+    };
+    use std::sync::Arc;
 
     fn test_payload() -> Vec<u8> {
-        return "test".as_bytes().to_vec();
+        "test".as_bytes().to_vec()
     }
 
     #[test]
@@ -170,10 +265,10 @@ mod tests {
         let payload = test_payload();
 
         let outgoing = OutgoingPacketBuilder::new(&payload)
-            .with_acknowledgment_header(1, 2, 3)
+            .with_acknowledgment_header(1, 2, 3, 4, 5)
             .build();
 
-        let expected: Vec<u8> = [vec![0, 1, 0, 2, 0, 0, 0, 3], test_payload()]
+        let expected: Vec<u8> = [vec![0, 1, 0, 2, 0, 0, 0, 3, 4, 0, 5], test_payload()]
             .concat()
             .to_vec();
 
@@ -189,14 +284,72 @@ mod tests {
                 PacketType::Packet,
                 DeliveryGuarantee::Reliable,
                 OrderingGuarantee::Sequenced(None),
+                1,
+                7,
             )
             .build();
 
-        let expected: Vec<u8> = [vec![0, 1, 1], test_payload()].concat().to_vec();
+        let expected: Vec<u8> = [vec![0, 1, 1, 0, 0, 0, 7], test_payload()]
+            .concat()
+            .to_vec();
 
         assert_eq!(
             outgoing.contents()[2..outgoing.contents().len()].to_vec(),
             expected
         );
     }
+
+    #[test]
+    fn assure_creation_parity_group_trailer() {
+        let payload = test_payload();
+
+        let outgoing = OutgoingPacketBuilder::new(&payload)
+            .with_parity_group(258, 3)
+            .with_default_header(
+                PacketType::Packet,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::None,
+                1,
+                7,
+            )
+            .build();
+
+        let contents = outgoing.contents();
+        assert_eq!(contents[2], 0x40 | PacketType::Packet.to_u8());
+        assert_eq!(contents[contents.len() - 3..], [1, 2, 3]);
+        assert_eq!(outgoing.parity_shard()[..], contents[..contents.len() - 3]);
+    }
+
+    #[test]
+    fn assure_compression_of_payloads_which_shrink() {
+        let compression = Compression::new(Arc::new(Lz4Compressor), None, 1024);
+        let payload = vec![1; 100];
+
+        let outgoing = OutgoingPacketBuilder::new(&payload)
+            .with_compression(&compression)
+            .with_default_header(
+                PacketType::Packet,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::None,
+                1,
+                7,
+            )
+            .build();
+        assert_eq!(outgoing.contents()[2], 0x80 | PacketType::Packet.to_u8());
+        assert!(outgoing.contents().len() < STANDARD_HEADER_SIZE as usize + payload.len());
+
+        let payload = test_payload();
+        let outgoing = OutgoingPacketBuilder::new(&payload)
+            .with_compression(&compression)
+            .with_default_header(
+                PacketType::Packet,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::None,
+                1,
+                7,
+            )
+            .build();
+        assert_eq!(outgoing.contents()[2], PacketType::Packet.to_u8());
+        assert_eq!(outgoing.contents()[9..], payload[..]);
+    }
 }
@@ -1,7 +1,10 @@
+use crate::compression::Compression;
+use crate::error::DecodingErrorKind;
 use crate::net::constants::STANDARD_HEADER_SIZE;
 use crate::packet::header::{
-    AckedPacketHeader, ArrangingHeader, FragmentHeader, HeaderReader, StandardHeader,
+    AckedPacketHeader, ArrangingHeader, FragmentHeader, HeaderReader, ParityHeader, StandardHeader,
 };
+use crate::packet::Payload;
 use crate::{ErrorKind, Result};
 
 use std::io::Cursor;
@@ -14,6 +17,7 @@ use std::io::Cursor;
 pub struct PacketReader<'s> {
     buffer: &'s [u8],
     cursor: Cursor<&'s [u8]>,
+    compressed: bool,
 }
 
 impl<'s> PacketReader<'s> {
@@ -22,6 +26,7 @@ impl<'s> PacketReader<'s> {
         PacketReader {
             buffer,
             cursor: Cursor::new(buffer),
+            compressed: false,
         }
     }
 
@@ -33,7 +38,9 @@ impl<'s> PacketReader<'s> {
         self.cursor.set_position(0);
 
         if self.can_read(StandardHeader::size()) {
-            StandardHeader::read(&mut self.cursor)
+            let header = StandardHeader::read(&mut self.cursor)?;
+            self.compressed = header.is_compressed();
+            Ok(header)
         } else {
             Err(ErrorKind::CouldNotReadHeader(String::from("standard")))
         }
@@ -74,8 +81,8 @@ impl<'s> PacketReader<'s> {
     ///
     /// # Remark
     /// - Notice that this will continue on the position of last read header;
-    /// e.g. when reading `StandardHeader` the position of the underlying `Cursor` will be at the end where it left of,
-    /// when calling this function afterward it will read the `FragmentHeader` from there on.
+    ///   e.g. when reading `StandardHeader` the position of the underlying `Cursor` will be at the end where it left of,
+    ///   when calling this function afterward it will read the `FragmentHeader` from there on.
     /// - Note that only the first fragment of a sequence contains acknowledgment information that's why `AckedPacketHeader` is optional.
     pub fn read_fragment(&mut self) -> Result<(FragmentHeader, Option<AckedPacketHeader>)> {
         if self.can_read(FragmentHeader::size()) {
@@ -93,42 +100,96 @@ impl<'s> PacketReader<'s> {
         }
     }
 
+    /// Read the `FragmentHeader` of an unreliable fragment from the underlying buffer, which carries no acknowledgment information.
+    ///
+    /// # Remark
+    /// - Notice that this will continue on the position of last read header.
+    pub fn read_fragment_header(&mut self) -> Result<FragmentHeader> {
+        if self.can_read(FragmentHeader::size()) {
+            FragmentHeader::read(&mut self.cursor)
+        } else {
+            Err(ErrorKind::CouldNotReadHeader(String::from("fragment")))
+        }
+    }
+
+    /// Read the `ParityHeader` from the underlying buffer.
+    ///
+    /// # Remark
+    /// - Will change the position to the location of `ParityHeader`
+    pub fn read_parity_header(&mut self) -> Result<ParityHeader> {
+        // parity header comes after standard header.
+        self.cursor.set_position(u64::from(STANDARD_HEADER_SIZE));
+
+        if self.can_read(ParityHeader::size()) {
+            ParityHeader::read(&mut self.cursor)
+        } else {
+            Err(ErrorKind::CouldNotReadHeader(String::from("parity")))
+        }
+    }
+
     /// Read the payload` from the underlying buffer.
     ///
     /// # Remark
     /// - Notice that this will continue on the position of last read header;
-    /// e.g. when reading `StandardHeader` the position of the underlying `Cursor` will be at the end where it left of,
-    /// when calling this function afterward it will read all the bytes from there on.
-    pub fn read_payload(&self) -> Box<[u8]> {
-        self.buffer[self.cursor.position() as usize..self.buffer.len()]
-            .to_vec()
-            .into_boxed_slice()
+    ///   e.g. when reading `StandardHeader` the position of the underlying `Cursor` will be at the end where it left of,
+    ///   when calling this function afterward it will read all the bytes from there on.
+    pub fn read_payload(&self) -> &'s [u8] {
+        &self.buffer[self.cursor.position() as usize..self.buffer.len()]
+    }
+
+    /// Read the payload like `read_payload`, as a payload sharing the buffer of `received`, which has to be the buffer of this reader.
+    ///
+    /// # Remark
+    /// - A payload flagged as compressed in the `StandardHeader` is decompressed into a new buffer,
+    ///   this fails when there is no `compression` or the payload is corrupt.
+    pub fn read_shared_payload(
+        &self,
+        received: &Payload,
+        compression: Option<&Compression>,
+    ) -> Result<Payload> {
+        let payload = self.read_payload();
+        if !self.compressed {
+            return Ok(received.slice_ref(payload));
+        }
+
+        compression
+            .and_then(|compression| compression.decompress(payload))
+            .map(Payload::from)
+            .ok_or(ErrorKind::DecodingError(DecodingErrorKind::Compression))
     }
 
     // checks if a given length of bytes could be read with the buffer.
     fn can_read(&self, length: u8) -> bool {
-        (self.buffer.len() - self.cursor.position() as usize) >= length as usize
+        self.buffer
+            .len()
+            .saturating_sub(self.cursor.position() as usize)
+            >= length as usize
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::compression::{Compression, Lz4Compressor};
     use crate::packet::header::{AckedPacketHeader, HeaderReader, StandardHeader};
-    use crate::packet::{DeliveryGuarantee, OrderingGuarantee, PacketReader, PacketType};
+    use crate::packet::{
+        DeliveryGuarantee, OrderingGuarantee, OutgoingPacketBuilder, PacketReader, PacketType,
+        Payload,
+    };
+    use std::sync::Arc;
 
     #[test]
     fn can_read_bytes() {
         let buffer = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
 
         let reader = PacketReader::new(buffer.as_slice());
-        assert_eq!(reader.can_read(buffer.len() as u8), true);
-        assert_eq!(reader.can_read((buffer.len() + 1) as u8), false);
+        assert!(reader.can_read(buffer.len() as u8));
+        assert!(!reader.can_read((buffer.len() + 1) as u8));
     }
 
     #[test]
     fn assure_read_standard_header() {
         // standard header
-        let reliable_ordered_payload: Vec<u8> = vec![vec![0, 1, 0, 1, 2]].concat();
+        let reliable_ordered_payload: Vec<u8> = [vec![0, 1, 0, 1, 2, 0, 0, 0, 0]].concat();
 
         let mut reader = PacketReader::new(reliable_ordered_payload.as_slice());
 
@@ -149,8 +210,11 @@ mod tests {
     #[test]
     fn assure_read_acknowledgment_header() {
         // standard header, acked header
-        let reliable_ordered_payload: Vec<u8> =
-            vec![vec![0, 1, 0, 1, 2], vec![0, 1, 0, 2, 0, 0, 0, 3]].concat();
+        let reliable_ordered_payload: Vec<u8> = [
+            vec![0, 1, 0, 1, 2, 0, 0, 0, 0],
+            vec![0, 1, 0, 2, 0, 0, 0, 3, 1, 0, 4],
+        ]
+        .concat();
 
         let mut reader = PacketReader::new(reliable_ordered_payload.as_slice());
 
@@ -159,15 +223,16 @@ mod tests {
         assert_eq!(acked_header.sequence(), 1);
         assert_eq!(acked_header.ack_seq(), 2);
         assert_eq!(acked_header.ack_field(), 3);
+        assert_eq!(acked_header.window(), 4);
     }
 
     #[test]
     fn assure_read_fragment_header() {
         // standard header, acked header, arranging header
-        let reliable_ordered_payload: Vec<u8> = vec![
-            vec![0, 1, 0, 1, 2],
+        let reliable_ordered_payload: Vec<u8> = [
+            vec![0, 1, 0, 1, 2, 0, 0, 0, 0],
             vec![0, 1, 0, 3],
-            vec![0, 1, 0, 2, 0, 0, 0, 3],
+            vec![0, 1, 0, 2, 0, 0, 0, 3, 1, 0, 4],
         ]
         .concat();
 
@@ -190,6 +255,7 @@ mod tests {
         assert_eq!(acked_header.unwrap().sequence(), 1);
         assert_eq!(acked_header.unwrap().ack_seq(), 2);
         assert_eq!(acked_header.unwrap().ack_field(), 3);
+        assert_eq!(acked_header.unwrap().window(), 4);
 
         assert_eq!(fragment_header.sequence(), 1);
         assert_eq!(fragment_header.id(), 0);
@@ -199,7 +265,8 @@ mod tests {
     #[test]
     fn assure_read_unreliable_sequenced_header() {
         // standard header, arranging header
-        let reliable_ordered_payload: Vec<u8> = vec![vec![0, 1, 0, 1, 2], vec![0, 1, 2]].concat();
+        let reliable_ordered_payload: Vec<u8> =
+            [vec![0, 1, 0, 1, 2, 0, 0, 0, 0], vec![0, 1, 2]].concat();
 
         let mut reader = PacketReader::new(reliable_ordered_payload.as_slice());
 
@@ -214,9 +281,9 @@ mod tests {
     #[test]
     fn assure_read_reliable_ordered_header() {
         // standard header, acked header, arranging header
-        let reliable_ordered_payload: Vec<u8> = vec![
-            vec![0, 1, 0, 1, 2],
-            vec![0, 1, 0, 2, 0, 0, 0, 3],
+        let reliable_ordered_payload: Vec<u8> = [
+            vec![0, 1, 0, 1, 2, 0, 0, 0, 0],
+            vec![0, 1, 0, 2, 0, 0, 0, 3, 1, 0, 4],
             vec![0, 1, 2],
         ]
         .concat();
@@ -242,6 +309,7 @@ mod tests {
         assert_eq!(acked_header.sequence(), 1);
         assert_eq!(acked_header.ack_seq(), 2);
         assert_eq!(acked_header.ack_field(), 3);
+        assert_eq!(acked_header.window(), 4);
 
         assert_eq!(arranging_header.arranging_id(), 1);
         assert_eq!(arranging_header.stream_id(), 2);
@@ -250,8 +318,11 @@ mod tests {
     #[test]
     fn assure_read_reliable_unordered_header() {
         // standard header, acked header, arranging header
-        let reliable_ordered_payload: Vec<u8> =
-            vec![vec![0, 1, 0, 1, 2], vec![0, 1, 0, 2, 0, 0, 0, 3]].concat();
+        let reliable_ordered_payload: Vec<u8> = [
+            vec![0, 1, 0, 1, 2, 0, 0, 0, 0],
+            vec![0, 1, 0, 2, 0, 0, 0, 3, 1, 0, 4],
+        ]
+        .concat();
         let mut reader = PacketReader::new(reliable_ordered_payload.as_slice());
 
         let standard_header = reader.read_standard_header().unwrap();
@@ -271,15 +342,44 @@ mod tests {
         assert_eq!(acked_header.sequence(), 1);
         assert_eq!(acked_header.ack_seq(), 2);
         assert_eq!(acked_header.ack_field(), 3);
+        assert_eq!(acked_header.window(), 4);
     }
 
     #[test]
     fn expect_read_error() {
         // standard header (with one corrupt byte)
-        let reliable_ordered_payload: Vec<u8> = vec![vec![0, 1, 0, 1]].concat();
+        let reliable_ordered_payload: Vec<u8> = [vec![0, 1, 0, 1]].concat();
 
         let mut reader = PacketReader::new(reliable_ordered_payload.as_slice());
 
         assert!(reader.read_standard_header().is_err());
     }
+
+    #[test]
+    fn assure_read_compressed_payload() {
+        let compression = Compression::new(Arc::new(Lz4Compressor), None, 1024);
+        let mut buffer = Vec::new();
+        OutgoingPacketBuilder::new(&[5; 200])
+            .with_compression(&compression)
+            .with_default_header(
+                PacketType::Packet,
+                DeliveryGuarantee::Unreliable,
+                OrderingGuarantee::None,
+                1,
+                7,
+            )
+            .build()
+            .write_contents(&mut buffer);
+        let received = Payload::from(buffer);
+
+        let mut reader = PacketReader::new(&received);
+        assert!(reader.read_standard_header().unwrap().is_compressed());
+        assert_eq!(
+            &*reader
+                .read_shared_payload(&received, Some(&compression))
+                .unwrap(),
+            &[5; 200][..]
+        );
+        assert!(reader.read_shared_payload(&received, None).is_err());
+    }
 }
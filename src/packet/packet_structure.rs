@@ -1,4 +1,4 @@
-use crate::packet::{DeliveryGuarantee, OrderingGuarantee};
+use crate::packet::{DeliveryGuarantee, OrderingGuarantee, Payload, Redundancy};
 use std::net::SocketAddr;
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -7,28 +7,30 @@ use std::net::SocketAddr;
 ///
 /// | Reliability Type                 | Packet Drop | Packet Duplication | Packet Order  | Packet Fragmentation |Packet Delivery|
 /// | :-------------:                  | :-------------: | :-------------:    | :-------------:  | :-------------:    | :-------------:
-/// |       **Unreliable Unordered**   |       Yes       |       Yes          |      No          |      No             |       No
+/// |       **Unreliable Unordered**   |       Yes       |       Yes          |      No          |      Yes             |       No
 /// |       **Reliable Unordered**     |       No        |      No            |      No          |      Yes             |       Yes
 /// |       **Reliable Ordered**       |       No        |      No            |      Ordered |      Yes             |       Yes
-/// |       **Sequenced**              |       Yes       |      No            |      Sequenced |      No |       No
+/// |       **Sequenced**              |       Yes       |      No            |      Sequenced |      Yes |       No
 ///
 /// You are able to send packets with any the above guarantees.
 pub struct Packet {
     /// the endpoint from where it came
     addr: SocketAddr,
     /// the raw payload of the packet
-    payload: Box<[u8]>,
+    payload: Payload,
     /// defines on how the packet will be delivered.
     delivery: DeliveryGuarantee,
     /// defines on how the packet will be ordered.
     ordering: OrderingGuarantee,
+    /// defines how many parities protect the packet.
+    redundancy: Redundancy,
 }
 
 impl Packet {
     /// Create a new packet by passing the receiver, data, and guarantees on how this packet should be delivered.
     pub(crate) fn new(
         addr: SocketAddr,
-        payload: Box<[u8]>,
+        payload: Payload,
         delivery: DeliveryGuarantee,
         ordering: OrderingGuarantee,
     ) -> Packet {
@@ -37,6 +39,7 @@ impl Packet {
             payload,
             delivery,
             ordering,
+            redundancy: Redundancy::None,
         }
     }
 
@@ -48,15 +51,16 @@ impl Packet {
     ///
     /// | Packet Drop     | Packet Duplication | Packet Order     | Packet Fragmentation | Packet Delivery |
     /// | :-------------: | :-------------:    | :-------------:  | :-------------:      | :-------------: |
-    /// |       Yes       |        Yes         |      No          |      No              |       No        |
+    /// |       Yes       |        Yes         |      No          |      Yes             |       No        |
     ///
-    /// Basically just bare UDP. The packet may or may not be delivered.
+    /// Basically just bare UDP. The packet may or may not be delivered, a fragmented packet is dropped as a whole when one of its fragments is lost.
     pub fn unreliable(addr: SocketAddr, payload: Vec<u8>) -> Packet {
         Packet {
             addr,
-            payload: payload.into_boxed_slice(),
+            payload: payload.into(),
             delivery: DeliveryGuarantee::Unreliable,
             ordering: OrderingGuarantee::None,
+            redundancy: Redundancy::None,
         }
     }
 
@@ -68,7 +72,7 @@ impl Packet {
     ///
     /// | Packet Drop     | Packet Duplication | Packet Order     | Packet Fragmentation | Packet Delivery |
     /// | :-------------: | :-------------:    | :-------------:  | :-------------:      | :-------------: |
-    /// |       Yes       |        Yes         |      Sequenced          |      No              |       No        |
+    /// |       Yes       |        Yes         |      Sequenced          |      Yes             |       No        |
     ///
     /// Basically just bare UDP, free to be dropped, but has some sequencing to it so that only the newest packets are kept.
     pub fn unreliable_sequenced(
@@ -78,9 +82,10 @@ impl Packet {
     ) -> Packet {
         Packet {
             addr,
-            payload: payload.into_boxed_slice(),
+            payload: payload.into(),
             delivery: DeliveryGuarantee::Unreliable,
             ordering: OrderingGuarantee::Sequenced(stream_id),
+            redundancy: Redundancy::None,
         }
     }
 
@@ -97,9 +102,10 @@ impl Packet {
     pub fn reliable_unordered(addr: SocketAddr, payload: Vec<u8>) -> Packet {
         Packet {
             addr,
-            payload: payload.into_boxed_slice(),
+            payload: payload.into(),
             delivery: DeliveryGuarantee::Reliable,
             ordering: OrderingGuarantee::None,
+            redundancy: Redundancy::None,
         }
     }
 
@@ -120,9 +126,10 @@ impl Packet {
     pub fn reliable_ordered(addr: SocketAddr, payload: Vec<u8>, stream_id: Option<u8>) -> Packet {
         Packet {
             addr,
-            payload: payload.into_boxed_slice(),
+            payload: payload.into(),
             delivery: DeliveryGuarantee::Reliable,
             ordering: OrderingGuarantee::Ordered(stream_id),
+            redundancy: Redundancy::None,
         }
     }
 
@@ -144,17 +151,34 @@ impl Packet {
     pub fn reliable_sequenced(addr: SocketAddr, payload: Vec<u8>, stream_id: Option<u8>) -> Packet {
         Packet {
             addr,
-            payload: payload.into_boxed_slice(),
+            payload: payload.into(),
             delivery: DeliveryGuarantee::Reliable,
             ordering: OrderingGuarantee::Sequenced(stream_id),
+            redundancy: Redundancy::None,
         }
     }
 
+    /// Protects this packet with parities, which let the remote endpoint rebuild it when it gets lost, without a retransmit.
+    ///
+    /// A fragmented packet is protected by parities over its fragments, in groups of at most 128 fragments. Unreliable packets
+    /// are protected together with the next ones which have redundancy, the parities are sent after the last packet of the
+    /// group or once `Config::parity_group_timeout` passed, see `Config::parity_group_size`. Other packets are sent without
+    /// parities.
+    pub fn with_redundancy(mut self, redundancy: Redundancy) -> Packet {
+        self.redundancy = redundancy;
+        self
+    }
+
     /// Returns the payload of this packet.
     pub fn payload(&self) -> &[u8] {
         &self.payload
     }
 
+    /// Returns the payload of this packet as a reference-counted `Payload`, which can be kept without copying it.
+    pub fn shared_payload(&self) -> &Payload {
+        &self.payload
+    }
+
     /// Returns the address of this packet.
     ///
     /// # Remark
@@ -173,11 +197,16 @@ impl Packet {
     pub fn order_guarantee(&self) -> OrderingGuarantee {
         self.ordering
     }
+
+    /// Returns the [`Redundancy`](./enum.Redundancy.html) of this packet.
+    pub fn redundancy(&self) -> Redundancy {
+        self.redundancy
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::packet::{DeliveryGuarantee, OrderingGuarantee, Packet};
+    use crate::packet::{DeliveryGuarantee, OrderingGuarantee, Packet, Redundancy};
     use std::net::SocketAddr;
 
     #[test]
@@ -239,8 +268,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn assure_redundancy() {
+        let packet = Packet::unreliable(test_addr(), test_payload());
+        assert_eq!(packet.redundancy(), Redundancy::None);
+
+        let packet = packet.with_redundancy(Redundancy::ReedSolomon(2));
+        assert_eq!(packet.redundancy(), Redundancy::ReedSolomon(2));
+        assert_eq!(packet.payload(), test_payload().as_slice());
+    }
+
     fn test_payload() -> Vec<u8> {
-        return "test".as_bytes().to_vec();
+        "test".as_bytes().to_vec()
     }
 
     fn test_addr() -> SocketAddr {
@@ -0,0 +1,275 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut, Range},
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+};
+
+/// A reference-counted view into a buffer holding packet data.
+///
+/// Cloning or slicing a payload does not copy the data. When the buffer came from a `BufferPool`,
+/// it is returned to the pool once the last payload referring to it is dropped.
+#[derive(Clone)]
+pub struct Payload {
+    buffer: Arc<Buffer>,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Debug)]
+struct Buffer {
+    data: Vec<u8>,
+    pool: Weak<Pool>,
+}
+
+#[derive(Debug)]
+struct Pool {
+    free: Mutex<Vec<Arc<Buffer>>>,
+    max_buffers: usize,
+    buffer_capacity: usize,
+}
+
+impl Payload {
+    fn new(buffer: Arc<Buffer>) -> Payload {
+        let end = buffer.data.len();
+        Payload {
+            buffer,
+            start: 0,
+            end,
+        }
+    }
+
+    /// Returns a payload for the given range of this payload, sharing the same buffer.
+    ///
+    /// # Panics
+    /// When the range is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> Payload {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "Range {:?} is out of bounds of a payload of {} bytes.",
+            range,
+            self.len()
+        );
+
+        Payload {
+            buffer: self.buffer.clone(),
+            start: self.start + range.start,
+            end: self.start + range.end,
+        }
+    }
+
+    /// Returns a payload for `subset`, which has to be a slice of this payload, sharing the same buffer.
+    ///
+    /// # Panics
+    /// When `subset` is not contained in this payload.
+    pub fn slice_ref(&self, subset: &[u8]) -> Payload {
+        let start = (subset.as_ptr() as usize).wrapping_sub(self.as_ptr() as usize);
+        assert!(
+            start <= self.len() && subset.len() <= self.len() - start,
+            "The slice is not part of this payload."
+        );
+
+        self.slice(start..start + subset.len())
+    }
+}
+
+impl Deref for Payload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buffer.data[self.start..self.end]
+    }
+}
+
+impl AsRef<[u8]> for Payload {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Drop for Payload {
+    fn drop(&mut self) {
+        // There are no weak references to buffers, so this is the last payload referring to it
+        if Arc::strong_count(&self.buffer) == 1 {
+            if let Some(pool) = self.buffer.pool.upgrade() {
+                pool.release(self.buffer.clone());
+            }
+        }
+    }
+}
+
+impl PartialEq for Payload {
+    fn eq(&self, other: &Payload) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Payload {}
+
+impl fmt::Debug for Payload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(data: Vec<u8>) -> Payload {
+        Payload::new(Arc::new(Buffer {
+            data,
+            pool: Weak::new(),
+        }))
+    }
+}
+
+impl From<Box<[u8]>> for Payload {
+    fn from(data: Box<[u8]>) -> Payload {
+        Payload::from(data.into_vec())
+    }
+}
+
+impl From<&[u8]> for Payload {
+    fn from(data: &[u8]) -> Payload {
+        Payload::from(data.to_vec())
+    }
+}
+
+/// A buffer from a `BufferPool` which can still be written to, `freeze` turns it into a `Payload`.
+#[derive(Debug)]
+pub struct BufferMut(Payload);
+
+impl BufferMut {
+    /// Turns the buffer into a payload of all bytes written to it.
+    pub fn freeze(self) -> Payload {
+        let BufferMut(mut payload) = self;
+        payload.end = payload.buffer.data.len();
+        payload
+    }
+}
+
+impl Deref for BufferMut {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0.buffer.data
+    }
+}
+
+impl DerefMut for BufferMut {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut Arc::get_mut(&mut (self.0).buffer)
+            .expect("A buffer which is written to is not shared.")
+            .data
+    }
+}
+
+impl AsMut<[u8]> for BufferMut {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+/// A pool of buffers which are reused for received and sent datagrams, so steady-state operation does not allocate per packet.
+///
+/// Cloning the pool returns a handle to the same buffers.
+#[derive(Clone, Debug)]
+pub struct BufferPool {
+    pool: Arc<Pool>,
+}
+
+impl BufferPool {
+    /// Creates a pool which keeps at most `max_buffers` unused buffers, new buffers are allocated with `buffer_capacity` bytes.
+    pub fn new(max_buffers: usize, buffer_capacity: usize) -> BufferPool {
+        BufferPool {
+            pool: Arc::new(Pool {
+                free: Mutex::new(Vec::with_capacity(max_buffers)),
+                max_buffers,
+                buffer_capacity,
+            }),
+        }
+    }
+
+    /// Takes an empty buffer from the pool, or allocates one if there is none left.
+    pub fn acquire(&self) -> BufferMut {
+        let buffer = self.pool.free_buffers().pop();
+        let mut buffer = BufferMut(Payload::new(buffer.unwrap_or_else(|| {
+            Arc::new(Buffer {
+                data: Vec::with_capacity(self.pool.buffer_capacity),
+                pool: Arc::downgrade(&self.pool),
+            })
+        })));
+        buffer.clear();
+        buffer
+    }
+
+    /// Returns the number of unused buffers in the pool.
+    pub fn available(&self) -> usize {
+        self.pool.free_buffers().len()
+    }
+}
+
+impl Pool {
+    fn free_buffers(&self) -> MutexGuard<'_, Vec<Arc<Buffer>>> {
+        // The list of buffers stays valid even if a thread panicked while holding the lock
+        self.free.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn release(&self, buffer: Arc<Buffer>) {
+        let mut free = self.free_buffers();
+        if free.len() < self.max_buffers {
+            free.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferPool, Payload};
+
+    #[test]
+    fn reuse_buffers_after_the_last_payload_is_dropped() {
+        let pool = BufferPool::new(2, 16);
+
+        let mut buffer = pool.acquire();
+        buffer.extend_from_slice(&[1, 2, 3, 4]);
+        let payload = buffer.freeze();
+        let slice = payload.slice(1..3);
+        assert_eq!(pool.available(), 0);
+
+        drop(payload);
+        assert_eq!(&*slice, &[2, 3]);
+        assert_eq!(pool.available(), 0);
+
+        drop(slice);
+        assert_eq!(pool.available(), 1);
+        assert!(pool.acquire().is_empty());
+    }
+
+    #[test]
+    fn keep_at_most_max_buffers() {
+        let pool = BufferPool::new(1, 16);
+
+        let first = pool.acquire().freeze();
+        let second = pool.acquire().freeze();
+        drop(first);
+        drop(second);
+
+        assert_eq!(pool.available(), 1);
+    }
+
+    #[test]
+    fn slice_ref_shares_the_buffer() {
+        let payload = Payload::from(vec![1, 2, 3, 4, 5]);
+        let slice = payload.slice_ref(&payload[2..4]);
+
+        assert_eq!(slice, Payload::from(vec![3, 4]));
+        assert_eq!(slice.slice_ref(&slice[1..]), Payload::from(vec![4]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn slice_ref_of_other_data_panics() {
+        let payload = Payload::from(vec![1, 2, 3]);
+        let other = vec![1, 2, 3];
+
+        payload.slice_ref(&other);
+    }
+}
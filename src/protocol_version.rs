@@ -1,14 +1,19 @@
-use crc::crc16;
+use crc::{crc16, crc32};
 use lazy_static::lazy_static;
 
 pub use crate::net::constants::PROTOCOL_VERSION;
 
 lazy_static! {
-    // The CRC16 of the current protocol version.
+    // The CRC16 of the current protocol version, which the application protocol id is mixed into.
     static ref VERSION_CRC16: u16 = crc16::checksum_x25(PROTOCOL_VERSION.as_bytes());
+    // The CRC32 of the current protocol version, which the application protocol id is mixed into.
+    static ref VERSION_CRC32: u32 = crc32::checksum_ieee(PROTOCOL_VERSION.as_bytes());
 }
 
 /// Wrapper to provide some functions to perform with the current protocol version.
+///
+/// The application protocol id of the config, see `Config::protocol_id`, is mixed into all of them.
+/// So endpoints only accept each other's packets when both the laminar protocol and the application protocol match.
 pub struct ProtocolVersion;
 
 impl ProtocolVersion {
@@ -19,16 +24,39 @@ impl ProtocolVersion {
         PROTOCOL_VERSION
     }
 
-    /// This will return the crc16 from the current protocol version.
+    /// This will return the crc16 from the current protocol version followed by the application protocol id.
     #[inline]
-    pub fn get_crc16() -> u16 {
-        *VERSION_CRC16
+    pub fn get_crc16(protocol_id: u64) -> u16 {
+        crc16::update(
+            *VERSION_CRC16,
+            &crc16::X25_TABLE,
+            &protocol_id.to_be_bytes(),
+        )
     }
 
-    /// Validate a crc16 with the current protocol version and return the results.
+    /// Returns the CRC32 of the current protocol version followed by the application protocol id, which is mixed into checksums.
     #[inline]
-    pub fn valid_version(protocol_version_crc16: u16) -> bool {
-        protocol_version_crc16 == ProtocolVersion::get_crc16()
+    pub fn checksum_tag(protocol_id: u64) -> u32 {
+        crc32::update(
+            *VERSION_CRC32,
+            &crc32::IEEE_TABLE,
+            &protocol_id.to_be_bytes(),
+        )
+    }
+
+    /// Returns the CRC32 of `data` with `tag` mixed in, see `Config::checksum` and `checksum_tag`.
+    ///
+    /// The tag is mixed in with xor, so a datagram of another version fails the checksum like a corrupt one, while
+    /// `checksum(data, received_checksum)` returns the tag its sender mixed in.
+    #[inline]
+    pub fn checksum(data: &[u8], tag: u32) -> u32 {
+        crc32::checksum_ieee(data) ^ tag
+    }
+
+    /// Validate a crc16 with the current protocol version and the application protocol id, and return the results.
+    #[inline]
+    pub fn valid_version(protocol_version_crc16: u16, protocol_id: u64) -> bool {
+        protocol_version_crc16 == ProtocolVersion::get_crc16(protocol_id)
     }
 }
 
@@ -39,19 +67,50 @@ mod test {
 
     #[test]
     fn valid_version() {
-        let protocol_id = crc16::checksum_x25(PROTOCOL_VERSION.as_bytes());
-        assert!(ProtocolVersion::valid_version(protocol_id));
+        let mixed = [PROTOCOL_VERSION.as_bytes(), &7u64.to_be_bytes()].concat();
+        let protocol_id = crc16::checksum_x25(&mixed);
+        assert!(ProtocolVersion::valid_version(protocol_id, 7));
     }
 
     #[test]
     fn not_valid_version() {
         let protocol_id = crc16::checksum_x25("not-laminar".as_bytes());
-        assert!(!ProtocolVersion::valid_version(protocol_id));
+        assert!(!ProtocolVersion::valid_version(protocol_id, 0));
+    }
+
+    #[test]
+    fn not_valid_application_protocol() {
+        let protocol_id = ProtocolVersion::get_crc16(1);
+        assert!(ProtocolVersion::valid_version(protocol_id, 1));
+        assert!(!ProtocolVersion::valid_version(protocol_id, 2));
+    }
+
+    #[test]
+    fn checksum_tag() {
+        let mixed = [PROTOCOL_VERSION.as_bytes(), &7u64.to_be_bytes()].concat();
+        assert_eq!(
+            ProtocolVersion::checksum_tag(7),
+            crc32::checksum_ieee(&mixed)
+        );
+        assert_ne!(
+            ProtocolVersion::checksum_tag(7),
+            ProtocolVersion::checksum_tag(8)
+        );
+    }
+
+    #[test]
+    fn recover_the_tag_of_a_checksum() {
+        let tag = ProtocolVersion::checksum_tag(7);
+        let checksum = ProtocolVersion::checksum(&[1, 2, 3], tag);
+
+        assert_eq!(ProtocolVersion::checksum(&[1, 2, 3], checksum), tag);
+        assert_ne!(ProtocolVersion::checksum(&[1, 2, 4], checksum), tag);
     }
 
     #[test]
     fn get_crc16() {
-        assert_eq!(ProtocolVersion::get_crc16(), *VERSION_CRC16);
+        let mixed = [PROTOCOL_VERSION.as_bytes(), &0u64.to_be_bytes()].concat();
+        assert_eq!(ProtocolVersion::get_crc16(0), crc16::checksum_x25(&mixed));
     }
 
     #[test]
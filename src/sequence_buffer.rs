@@ -68,6 +68,14 @@ impl<T: Clone + Default> SequenceBuffer<T> {
         false
     }
 
+    /// Returns the number of entries currently stored.
+    pub fn entry_count(&self) -> usize {
+        self.entry_sequences
+            .iter()
+            .filter(|sequence| sequence.is_some())
+            .count()
+    }
+
     /// Removes an entry from the sequence buffer
     pub fn remove(&mut self, sequence_num: SequenceNumber) {
         if self.exists(sequence_num) {
@@ -77,6 +85,25 @@ impl<T: Clone + Default> SequenceBuffer<T> {
         }
     }
 
+    /// Returns an iterator over the stored entries.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entry_sequences
+            .iter()
+            .zip(self.entries.iter())
+            .filter(|(sequence, _)| sequence.is_some())
+            .map(|(_, entry)| entry)
+    }
+
+    /// Removes all entries for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(&T) -> bool) {
+        for index in 0..self.entry_sequences.len() {
+            if self.entry_sequences[index].is_some() && !keep(&self.entries[index]) {
+                self.entries[index] = T::default();
+                self.entry_sequences[index] = None;
+            }
+        }
+    }
+
     // Advances the sequence number while removing older entries.
     fn advance_sequence(&mut self, sequence_num: SequenceNumber) {
         if sequence_greater_than(sequence_num.wrapping_add(1), self.sequence_num) {
@@ -137,13 +164,13 @@ mod tests {
         assert!(sequence_less_than(32769, 0));
 
         // In this case, 0 is greater than u16 max because we're likely at the wrapping case
-        assert!(sequence_greater_than(0, u16::max_value()));
+        assert!(sequence_greater_than(0, u16::MAX));
     }
 
     #[test]
     fn max_sequence_number_should_not_exist_by_default() {
         let buffer: SequenceBuffer<DataStub> = SequenceBuffer::with_capacity(2);
-        assert!(!buffer.exists(u16::max_value()));
+        assert!(!buffer.exists(u16::MAX));
     }
 
     #[test]
@@ -176,6 +203,18 @@ mod tests {
         assert!(!buffer.exists(0));
     }
 
+    #[test]
+    fn retain_in_buffer_test() {
+        let mut buffer = SequenceBuffer::with_capacity(4);
+        buffer.insert(0, 10u8);
+        buffer.insert(1, 11u8);
+        buffer.retain(|entry| *entry != 10);
+        assert!(!buffer.exists(0));
+        assert!(buffer.exists(1));
+        assert_eq!(buffer.entry_count(), 1);
+        assert_eq!(buffer.iter().collect::<Vec<_>>(), vec![&11]);
+    }
+
     #[test]
     fn insert_into_buffer_old_entry_test() {
         let mut buffer = SequenceBuffer::with_capacity(8);
@@ -218,9 +257,9 @@ mod tests {
         assert!(!buffer.exists(2));
 
         // Insert respects boundary wrap. Both of these would be earlier than 11
-        buffer.insert(u16::max_value(), DataStub);
+        buffer.insert(u16::MAX, DataStub);
         buffer.insert(0, DataStub);
-        assert!(!buffer.exists(u16::max_value()));
+        assert!(!buffer.exists(u16::MAX));
         assert!(!buffer.exists(0));
 
         assert_eq!(count_entries(&buffer), 1);
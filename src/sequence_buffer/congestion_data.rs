@@ -4,6 +4,7 @@ use std::time::Instant;
 #[derive(Clone)]
 /// This contains the information required to reassemble fragments.
 pub struct CongestionData {
+    #[allow(dead_code)]
     pub sequence: SequenceNumber,
     pub sending_time: Instant,
 }
@@ -1,5 +1,5 @@
-use crate::net::constants::MAX_FRAGMENTS_DEFAULT;
 use crate::packet::SequenceNumber;
+use std::time::Instant;
 
 #[derive(Clone)]
 /// This contains the information required to reassemble fragments.
@@ -8,17 +8,26 @@ pub struct ReassemblyData {
     pub num_fragments_received: u8,
     pub num_fragments_total: u8,
     pub buffer: Vec<u8>,
-    pub fragments_received: [bool; MAX_FRAGMENTS_DEFAULT as usize],
+    pub fragments_received: Vec<bool>,
+    pub fragment_lengths: Vec<usize>,
+    pub started: Option<Instant>,
 }
 
 impl ReassemblyData {
-    pub fn new(sequence: SequenceNumber, num_fragments_total: u8, prealloc: usize) -> Self {
+    pub fn new(
+        sequence: SequenceNumber,
+        num_fragments_total: u8,
+        prealloc: usize,
+        started: Instant,
+    ) -> Self {
         Self {
             sequence,
             num_fragments_received: 0,
             num_fragments_total,
             buffer: Vec::with_capacity(prealloc),
-            fragments_received: [false; MAX_FRAGMENTS_DEFAULT as usize],
+            fragments_received: vec![false; usize::from(num_fragments_total)],
+            fragment_lengths: vec![0; usize::from(num_fragments_total)],
+            started: Some(started),
         }
     }
 }
@@ -30,7 +39,9 @@ impl Default for ReassemblyData {
             num_fragments_received: 0,
             num_fragments_total: 0,
             buffer: Vec::with_capacity(1024),
-            fragments_received: [false; MAX_FRAGMENTS_DEFAULT as usize],
+            fragments_received: Vec::new(),
+            fragment_lengths: Vec::new(),
+            started: None,
         }
     }
 }
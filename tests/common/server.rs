@@ -1,5 +1,5 @@
 use crossbeam_channel::{Receiver, Sender, TryIter};
-use laminar::{Config, Packet, Socket, SocketEvent, ThroughputMonitoring};
+use laminar::{Packet, Socket, SocketEvent, ThroughputMonitoring};
 
 use log::error;
 use std::net::SocketAddr;
@@ -143,7 +143,7 @@ impl ServerHandle {
     }
 
     /// Iterate over the events that have happened on the server.
-    pub fn iter_events(&self) -> TryIter<ServerEvent> {
+    pub fn iter_events(&self) -> TryIter<'_, ServerEvent> {
         self.events_rx.try_iter()
     }
 
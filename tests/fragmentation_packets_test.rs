@@ -3,9 +3,13 @@ mod common;
 #[cfg(feature = "tester")]
 use common::{client_addr, Client, Server, ServerEvent};
 
+#[cfg(feature = "tester")]
 use laminar::{DeliveryGuarantee, OrderingGuarantee, Packet};
+#[cfg(feature = "tester")]
 use log::debug;
+#[cfg(feature = "tester")]
 use std::net::SocketAddr;
+#[cfg(feature = "tester")]
 use std::{thread, time::Duration};
 
 #[test]